@@ -1,6 +1,8 @@
 use std::convert::Infallible;
 use std::fmt::{self, Display};
 
+use askama_escape::EscapeError;
+
 pub type Result<I, E = Error> = ::std::result::Result<I, E>;
 
 /// askama error type
@@ -10,6 +12,25 @@ pub type Result<I, E = Error> = ::std::result::Result<I, E>;
 /// If the feature `serde_json` is enabled an
 /// additional error variant `Json` is added.
 ///
+/// # Matching on errors
+///
+/// With `error_context = true`, the errors raised while rendering a template are
+/// wrapped in `Context`, use [`Error::without_context()`] to get the raised error,
+/// whether the option is enabled or not. An error returned by a
+/// filter is wrapped in `Filter`, and is also its [`source()`].
+///
+/// ```
+/// # use askama::Error;
+/// fn is_bad_input(err: &Error) -> bool {
+///     match err.without_context() {
+///         Error::Filter { name: "validate", error } => matches!(**error, Error::Custom(_)),
+///         _ => false,
+///     }
+/// }
+/// ```
+///
+/// [`source()`]: std::error::Error::source
+///
 /// # Why not `failure`/`error-chain`?
 ///
 /// Error from `error-chain` are not `Sync` which
@@ -35,6 +56,89 @@ pub enum Error {
     /// json conversion error
     #[cfg(feature = "serde_json")]
     Json(::serde_json::Error),
+
+    /// the escaper rejected a value
+    Escape(EscapeError),
+
+    /// an error returned by a filter
+    Filter {
+        /// the name of the filter
+        name: &'static str,
+        /// the error returned by the filter
+        error: Box<Error>,
+    },
+
+    /// an error annotated with the place in the template where it was raised,
+    /// only returned by templates with `error_context = true`
+    Context(Box<ErrorContext>),
+}
+
+/// The place in a template where a rendering [`Error`] was raised
+#[non_exhaustive]
+#[derive(Debug)]
+pub struct ErrorContext {
+    /// the path of the template, relative to its templates directory
+    pub template: &'static str,
+    /// the block that was being rendered, if any
+    pub block: Option<&'static str>,
+    /// the source of the expression or filter call that failed
+    pub expr: &'static str,
+    /// the error that was raised
+    pub error: Error,
+}
+
+impl Error {
+    /// Returns the place in the template where the error was raised, if known
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match self {
+            Error::Context(ctx) => Some(ctx),
+            _ => None,
+        }
+    }
+
+    /// Returns the error that was raised, without the context in which it happened
+    pub fn without_context(&self) -> &Error {
+        match self {
+            Error::Context(ctx) => &ctx.error,
+            err => err,
+        }
+    }
+
+    /// Used by the generated code to mark an error as returned by the filter `name`. An error
+    /// that already has a context was raised in a nested template, so it's kept as is.
+    #[doc(hidden)]
+    #[cold]
+    pub fn in_filter(self, name: &'static str) -> Self {
+        match self {
+            Error::Context(_) => self,
+            error => Error::Filter {
+                name,
+                error: Box::new(error),
+            },
+        }
+    }
+
+    /// Used by the generated code to annotate an error with the place where it was raised.
+    /// If the error already has a context, it was raised in a nested expression, so the
+    /// existing context is kept.
+    #[doc(hidden)]
+    #[cold]
+    pub fn with_context(
+        self,
+        template: &'static str,
+        block: Option<&'static str>,
+        expr: &'static str,
+    ) -> Self {
+        match self {
+            Error::Context(_) => self,
+            error => Error::Context(Box::new(ErrorContext {
+                template,
+                block,
+                expr,
+                error,
+            })),
+        }
+    }
 }
 
 impl std::error::Error for Error {
@@ -44,6 +148,9 @@ impl std::error::Error for Error {
             Error::Custom(ref err) => Some(err.as_ref()),
             #[cfg(feature = "serde_json")]
             Error::Json(ref err) => Some(err),
+            Error::Escape(ref err) => Some(err),
+            Error::Filter { ref error, .. } => Some(error.as_ref()),
+            Error::Context(ref ctx) => Some(&ctx.error),
         }
     }
 }
@@ -55,16 +162,38 @@ impl Display for Error {
             Error::Custom(err) => write!(formatter, "{err}"),
             #[cfg(feature = "serde_json")]
             Error::Json(err) => write!(formatter, "json conversion error: {err}"),
+            Error::Escape(err) => write!(formatter, "escaping error: {err}"),
+            Error::Filter { name, error } => write!(formatter, "filter `{name}`: {error}"),
+            Error::Context(ctx) => write!(formatter, "{ctx}"),
         }
     }
 }
 
+impl Display for ErrorContext {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(formatter, "{} (in template `{}`", self.error, self.template)?;
+        if let Some(block) = self.block {
+            write!(formatter, ", block `{block}`")?;
+        }
+        write!(formatter, ", expression `{}`)", self.expr)
+    }
+}
+
 impl From<fmt::Error> for Error {
     fn from(err: fmt::Error) -> Self {
         Error::Fmt(err)
     }
 }
 
+impl From<EscapeError> for Error {
+    fn from(err: EscapeError) -> Self {
+        match err {
+            EscapeError::Fmt(err) => Error::Fmt(err),
+            err => Error::Escape(err),
+        }
+    }
+}
+
 #[cfg(feature = "serde_json")]
 impl From<::serde_json::Error> for Error {
     fn from(err: ::serde_json::Error) -> Self {
@@ -81,9 +210,83 @@ impl From<Infallible> for Error {
 
 #[cfg(test)]
 mod tests {
+    use std::fmt;
+
+    use askama_escape::EscapeError;
+
     use super::Error;
 
     #[allow(dead_code)]
     trait AssertSendSyncStatic: Send + Sync + 'static {}
     impl AssertSendSyncStatic for Error {}
+
+    #[test]
+    fn context() {
+        let err = Error::from(fmt::Error).with_context("index.html", Some("content"), "user.name");
+        assert_eq!(
+            err.to_string(),
+            "formatting error: an error occurred when formatting an argument \
+             (in template `index.html`, block `content`, expression `user.name`)",
+        );
+        assert!(matches!(err.without_context(), Error::Fmt(_)));
+
+        // The innermost context is kept.
+        let err = err.with_context("base.html", None, "page");
+        let ctx = err.context().unwrap();
+        assert_eq!(ctx.template, "index.html");
+        assert_eq!(ctx.block, Some("content"));
+        assert_eq!(ctx.expr, "user.name");
+
+        let err = Error::from(fmt::Error).with_context("index.html", None, "a|b");
+        assert_eq!(
+            err.to_string(),
+            "formatting error: an error occurred when formatting an argument \
+             (in template `index.html`, expression `a|b`)",
+        );
+    }
+
+    #[test]
+    fn filter() {
+        let err = Error::Custom("bad".into()).in_filter("check").with_context(
+            "index.html",
+            None,
+            "a|check",
+        );
+        assert_eq!(
+            err.to_string(),
+            "filter `check`: bad (in template `index.html`, expression `a|check`)"
+        );
+        match err.without_context() {
+            Error::Filter { name, error } => {
+                assert_eq!(*name, "check");
+                assert!(matches!(**error, Error::Custom(_)));
+            }
+            err => panic!("unexpected error: {err:?}"),
+        }
+        let source = std::error::Error::source(err.without_context()).unwrap();
+        assert_eq!(source.to_string(), "bad");
+
+        // An error raised in a nested template keeps its context.
+        let err = Error::from(fmt::Error)
+            .with_context("child.html", None, "x")
+            .in_filter("render");
+        assert_eq!(err.context().unwrap().template, "child.html");
+        assert!(matches!(err.without_context(), Error::Fmt(_)));
+    }
+    #[test]
+    fn escape() {
+        let err = Error::from(EscapeError::Invalid("NUL characters are not allowed"));
+        assert_eq!(
+            err.to_string(),
+            "escaping error: NUL characters are not allowed"
+        );
+        let source = std::error::Error::source(&err).unwrap();
+        assert_eq!(source.to_string(), "NUL characters are not allowed");
+
+        // A failing writer is reported as a formatting error.
+        assert!(matches!(
+            Error::from(EscapeError::Fmt(fmt::Error)),
+            Error::Fmt(_)
+        ));
+    }
 }
@@ -0,0 +1,111 @@
+use std::fmt;
+
+use base64::alphabet;
+use base64::display::Base64Display;
+use base64::engine::general_purpose::{GeneralPurpose, GeneralPurposeConfig};
+use base64::engine::{DecodePaddingMode, Engine};
+
+use crate::error::{Error, Result};
+
+// Padding is optional when decoding, so tokens stripped of their trailing `=` still work.
+const STANDARD: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+const URL_SAFE: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::URL_SAFE,
+    GeneralPurposeConfig::new()
+        .with_encode_padding(false)
+        .with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+fn engine(alphabet: &str) -> Result<&'static GeneralPurpose> {
+    match alphabet {
+        "standard" => Ok(&STANDARD),
+        "url" => Ok(&URL_SAFE),
+        _ => Err(Error::Custom(
+            format!("unknown base64 alphabet {alphabet:?}, expected \"standard\" or \"url\"")
+                .into(),
+        )),
+    }
+}
+
+/// Encode bytes or a string as base64 (requires `base64` feature)
+///
+/// The `alphabet` argument selects between the `"standard"` alphabet (the default,
+/// `+` and `/`, padded with `=`) and the URL-safe `"url"` alphabet (`-` and `_`,
+/// without padding).
+///
+/// The output is escaped like any other value, but none of the characters above
+/// are changed by the HTML escaper. In URLs, `+`, `/` and `=` have a meaning of
+/// their own, so use the `"url"` alphabet for query strings and path segments.
+///
+/// ```html
+/// <img src="data:image/png;base64,{{ icon|b64encode }}">
+/// <a href="/confirm?token={{ token|b64encode("url") }}">Confirm</a>
+/// ```
+#[inline]
+pub fn b64encode<T: AsRef<[u8]>>(value: T, alphabet: &str) -> Result<impl fmt::Display> {
+    Ok(Base64Encode(value, engine(alphabet)?))
+}
+
+struct Base64Encode<T>(T, &'static GeneralPurpose);
+
+impl<T: AsRef<[u8]>> fmt::Display for Base64Encode<T> {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Base64Display::new(self.0.as_ref(), self.1).fmt(f)
+    }
+}
+
+/// Decode a base64 encoded string (requires `base64` feature)
+///
+/// The `alphabet` argument is the same as for [`b64encode`]. Trailing padding is
+/// optional. An error is returned if the input is not valid base64 or does not
+/// decode to valid UTF-8.
+pub fn b64decode<T: AsRef<[u8]>>(value: T, alphabet: &str) -> Result<String> {
+    let bytes = engine(alphabet)?
+        .decode(value.as_ref())
+        .map_err(|err| Error::Custom(Box::new(err)))?;
+    String::from_utf8(bytes).map_err(|err| Error::Custom(Box::new(err)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_b64encode() {
+        let encode = |value: &[u8], alphabet| b64encode(value, alphabet).unwrap().to_string();
+        assert_eq!(encode(b"", "standard"), "");
+        assert_eq!(encode(b"f", "standard"), "Zg==");
+        assert_eq!(encode(b"fo", "standard"), "Zm8=");
+        assert_eq!(encode(b"foo", "standard"), "Zm9v");
+        assert_eq!(encode(b"\xfb\xff\xbf", "standard"), "+/+/");
+        assert_eq!(encode(b"\xfb\xff\xbf", "url"), "-_-_");
+        assert_eq!(encode(b"fo", "url"), "Zm8");
+        assert_eq!(b64encode("Hi!", "standard").unwrap().to_string(), "SGkh");
+        assert_eq!(
+            b64encode(vec![0u8, 1, 2], "standard").unwrap().to_string(),
+            "AAEC"
+        );
+        assert!(b64encode("foo", "base32").is_err());
+    }
+
+    #[test]
+    fn test_b64decode() {
+        assert_eq!(b64decode("", "standard").unwrap(), "");
+        assert_eq!(b64decode("Zm8=", "standard").unwrap(), "fo");
+        assert_eq!(b64decode("Zm8", "standard").unwrap(), "fo");
+        assert_eq!(b64decode("Zm8", "url").unwrap(), "fo");
+        assert_eq!(b64decode("Zm8=", "url").unwrap(), "fo");
+        assert_eq!(b64decode("w6lj", "standard").unwrap(), "éc");
+        assert!(b64decode("+/+/", "url").is_err());
+        assert!(b64decode("-_-_", "standard").is_err());
+        assert!(b64decode("Zm9v!", "standard").is_err());
+        // valid base64, invalid UTF-8
+        assert!(b64decode("+/+/", "standard").is_err());
+        assert!(b64decode("Zm9v", "base32").is_err());
+    }
+}
@@ -5,10 +5,17 @@
 //! For more information, read the [book](https://djc.github.io/askama/filters.html).
 #![allow(clippy::trivially_copy_pass_by_ref)]
 
+use std::borrow::Borrow;
 use std::cell::Cell;
+use std::collections::HashSet;
 use std::convert::Infallible;
 use std::fmt::{self, Write};
+use std::hash::Hash;
 
+#[cfg(feature = "base64")]
+mod base64;
+#[cfg(feature = "base64")]
+pub use self::base64::{b64decode, b64encode};
 #[cfg(feature = "serde-json")]
 mod json;
 #[cfg(feature = "serde-json")]
@@ -250,6 +257,206 @@ pub fn paragraphbreaks(s: impl ToString) -> Result<impl fmt::Display, Infallible
     paragraphbreaks(s.to_string())
 }
 
+/// Removes HTML/XML tags and comments, and collapses the remaining whitespace
+///
+/// Runs of whitespace become a single space, and leading and trailing whitespace is removed, so
+/// the result can be used as a plain-text preview of the content. A `<` that doesn't start a
+/// tag, like in `a < b`, is kept. If `decode` is `true`, HTML entities (`&amp;`, `&lt;`, `&#39;`,
+/// …) are decoded too. In templates, the `decode` argument is optional.
+///
+/// ```ignore
+/// {{ "<p>Hello <b>world</b>!</p>"|striptags }}    -> Hello world!
+/// {{ "<p>Fish &amp; chips</p>"|striptags(true) }} -> Fish & chips
+/// ```
+#[inline]
+pub fn striptags(s: impl ToString, decode: bool) -> Result<impl fmt::Display, Infallible> {
+    fn striptags(s: String, decode: bool) -> Result<String, Infallible> {
+        let mut text = String::with_capacity(s.len());
+        let mut rest = s.as_str();
+        while let Some(start) = rest.find('<') {
+            text.push_str(&rest[..start]);
+            let tag = &rest[start..];
+            let end = match tag[1..].chars().next() {
+                _ if tag.starts_with("<!--") => tag.find("-->").map(|end| end + 3),
+                Some(c) if c.is_alphabetic() || matches!(c, '/' | '!' | '?') => {
+                    tag.find('>').map(|end| end + 1)
+                }
+                _ => None,
+            };
+            match end {
+                Some(end) => rest = &tag[end..],
+                None => {
+                    text.push('<');
+                    rest = &tag[1..];
+                }
+            }
+        }
+        text.push_str(rest);
+
+        let mut output = String::with_capacity(text.len());
+        for word in text.split_whitespace() {
+            if !output.is_empty() {
+                output.push(' ');
+            }
+            output.push_str(word);
+        }
+        if decode {
+            output = decode_entities(&output);
+        }
+        Ok(output)
+    }
+    striptags(s.to_string(), decode)
+}
+
+// Decodes the named entities of the characters escaped by `Html`, `&nbsp;`, and numeric character
+// references. Anything else is kept as-is.
+fn decode_entities(s: &str) -> String {
+    let mut output = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find('&') {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest.find(';').and_then(|end| {
+            let c = match &rest[1..end] {
+                "amp" => '&',
+                "lt" => '<',
+                "gt" => '>',
+                "quot" => '"',
+                "apos" => '\'',
+                "nbsp" => '\u{a0}',
+                entity => {
+                    let code = match entity.strip_prefix('#')? {
+                        hex if hex.starts_with(['x', 'X']) => u32::from_str_radix(&hex[1..], 16),
+                        dec => dec.parse(),
+                    };
+                    char::from_u32(code.ok()?)?
+                }
+            };
+            Some((c, end + 1))
+        });
+        match decoded {
+            Some((c, len)) => {
+                output.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                output.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+/// Splits a string into the substrings separated by `separator`
+///
+/// The substrings borrow the string, and can be consumed by a `for` loop or another filter:
+///
+/// ```ignore
+/// {% for field in csv_line|split(",") %}<td>{{ field }}</td>{% endfor %}
+/// {{ path|split("/")|join(" › ") }}
+/// ```
+#[inline]
+pub fn split<'a, 'b, S, P>(
+    s: &'a S,
+    separator: &'b P,
+) -> Result<std::str::Split<'a, &'b str>, Infallible>
+where
+    S: AsRef<str> + ?Sized,
+    P: AsRef<str> + ?Sized,
+{
+    Ok(s.as_ref().split(separator.as_ref()))
+}
+
+/// Wraps the lines of a text so they are at most `width` characters long
+///
+/// Existing line breaks and the indentation of the lines are kept, and the words of each line
+/// are separated by a single space. If `break_long_words` is `true`, words longer than `width`
+/// are split, otherwise they are put on a line of their own. In templates, `width` defaults to
+/// `79` and `break_long_words` to `true`:
+///
+/// ```ignore
+/// {{ body|wordwrap(72) }}
+/// {{ body|wordwrap(40, false) }}
+/// ```
+///
+/// Returns an error if `width` is `0`.
+#[inline]
+pub fn wordwrap(
+    s: impl ToString,
+    width: usize,
+    break_long_words: bool,
+) -> Result<impl fmt::Display> {
+    fn wordwrap(s: String, width: usize, break_long_words: bool) -> Result<String> {
+        if width == 0 {
+            return Err(crate::Error::Custom(
+                "the width of `wordwrap` must be greater than 0".into(),
+            ));
+        }
+
+        // the inserted line breaks are the ones of the text
+        let newline = match s.contains("\r\n") {
+            true => "\r\n",
+            false => "\n",
+        };
+        let mut output = String::with_capacity(s.len() + s.len() / width);
+        for (index, line) in s.split('\n').enumerate() {
+            if index > 0 {
+                output.push('\n');
+            }
+            let (line, cr) = match line.strip_suffix('\r') {
+                Some(line) => (line, "\r"),
+                None => (line, ""),
+            };
+            let indent = &line[..line.len() - line.trim_start().len()];
+            output.push_str(indent);
+            let mut len = indent.chars().count();
+            let mut has_word = false;
+            for mut word in line.split_whitespace() {
+                let mut word_len = word.chars().count();
+                if has_word {
+                    if len + 1 + word_len <= width {
+                        output.push(' ');
+                        output.push_str(word);
+                        len += 1 + word_len;
+                        continue;
+                    }
+                    // a word which has to be split anyway starts on the current line
+                    if break_long_words && word_len > width && len + 1 < width {
+                        output.push(' ');
+                        len += 1;
+                    } else {
+                        output.push_str(newline);
+                        len = 0;
+                    }
+                }
+                while break_long_words && len + word_len > width {
+                    if len < width {
+                        let head_len = width - len;
+                        let (head, tail) = word.split_at(
+                            word.char_indices()
+                                .nth(head_len)
+                                .map_or(word.len(), |(i, _)| i),
+                        );
+                        output.push_str(head);
+                        word = tail;
+                        word_len -= head_len;
+                    }
+                    output.push_str(newline);
+                    len = 0;
+                }
+                output.push_str(word);
+                len += word_len;
+                has_word = true;
+            }
+            output.push_str(cr);
+        }
+        Ok(output)
+    }
+    wordwrap(s.to_string(), width, break_long_words)
+}
+
 /// Converts to lowercase
 #[inline]
 pub fn lower(s: impl ToString) -> Result<impl fmt::Display, Infallible> {
@@ -459,6 +666,282 @@ where
     }
 }
 
+/// Splits the items of `iterable` into chunks of `size` items
+///
+/// The last chunk may be shorter, unless a `fill` value is given, which is used for the missing
+/// items. This is useful to lay out items in a grid with nested loops:
+///
+/// ```ignore
+/// {% for row in items|batch(3, "-") %}
+///   {% for item in row %}{{ item }}{% endfor %}
+/// {% endfor %}
+/// ```
+///
+/// In templates, the fill value is optional. It has the type of the items, so it's borrowed if
+/// the items are borrowed. Returns an error if `size` is `0`.
+#[inline]
+pub fn batch<I>(iterable: I, size: usize, fill: Option<I::Item>) -> Result<Batch<I::IntoIter>>
+where
+    I: IntoIterator,
+    I::Item: Clone,
+{
+    if size == 0 {
+        return Err(crate::Error::Custom(
+            "the size of a batch must be greater than 0".into(),
+        ));
+    }
+    Ok(Batch {
+        iter: iterable.into_iter(),
+        size,
+        fill,
+    })
+}
+
+/// The iterator returned by the [`batch`] filter, which yields the chunks as `Vec`s.
+#[derive(Debug, Clone)]
+pub struct Batch<I: Iterator> {
+    iter: I,
+    size: usize,
+    fill: Option<I::Item>,
+}
+
+impl<I> Iterator for Batch<I>
+where
+    I: Iterator,
+    I::Item: Clone,
+{
+    type Item = Vec<I::Item>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut chunk = Vec::with_capacity(self.size);
+        chunk.extend(self.iter.by_ref().take(self.size));
+        if chunk.is_empty() {
+            return None;
+        }
+        if let Some(fill) = &self.fill {
+            chunk.resize(self.size, fill.clone());
+        }
+        Some(chunk)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let chunks = |len: usize| len / self.size + (len % self.size != 0) as usize;
+        let (lower, upper) = self.iter.size_hint();
+        (chunks(lower), upper.map(chunks))
+    }
+}
+
+/// Splits the items of `iterable` into `count` columns of (roughly) the same length
+///
+/// The first columns get one more item than the others if the items can't be split evenly. If a
+/// `fill` value is given, it's added to the shorter columns, so that all columns have the same
+/// length. This is useful for multi-column layouts:
+///
+/// ```ignore
+/// {% for column in items|slice(3) %}
+///   <ul>{% for item in column %}<li>{{ item }}</li>{% endfor %}</ul>
+/// {% endfor %}
+/// ```
+///
+/// In templates, the fill value is optional, and the filter can also be called `columns`.
+/// Returns an error if `count` is `0`.
+#[inline]
+pub fn slice<I>(iterable: I, count: usize, fill: Option<I::Item>) -> Result<Slice<I::Item>>
+where
+    I: IntoIterator,
+    I::Item: Clone,
+{
+    if count == 0 {
+        return Err(crate::Error::Custom(
+            "the number of slices must be greater than 0".into(),
+        ));
+    }
+    let items: Vec<_> = iterable.into_iter().collect();
+    Ok(Slice {
+        per_slice: items.len() / count,
+        with_extra: items.len() % count,
+        items: items.into_iter(),
+        count,
+        index: 0,
+        fill,
+    })
+}
+
+/// The iterator returned by the [`slice`] filter, which yields the columns as `Vec`s.
+#[derive(Debug, Clone)]
+pub struct Slice<T> {
+    items: std::vec::IntoIter<T>,
+    count: usize,
+    // the number of items of the shorter columns
+    per_slice: usize,
+    // the number of columns with an extra item
+    with_extra: usize,
+    index: usize,
+    fill: Option<T>,
+}
+
+impl<T: Clone> Iterator for Slice<T> {
+    type Item = Vec<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index == self.count {
+            return None;
+        }
+        let extra = self.index < self.with_extra;
+        let mut column = Vec::with_capacity(self.per_slice + 1);
+        column.extend(self.items.by_ref().take(self.per_slice + extra as usize));
+        if let (false, true, Some(fill)) = (extra, self.with_extra > 0, &self.fill) {
+            column.push(fill.clone());
+        }
+        self.index += 1;
+        Some(column)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.count - self.index;
+        (len, Some(len))
+    }
+}
+
+/// Sorts the `(key, value)` pairs of a map by key
+///
+/// Iterating over a `HashMap` yields its entries in an arbitrary order, which changes from one
+/// run to the next. This filter collects them and sorts them by key, so they are rendered in a
+/// reproducible order:
+///
+/// ```ignore
+/// {% for (name, score) in scores|dictsort %}{{ name }}: {{ score }}{% endfor %}
+/// ```
+///
+/// Any iterable of pairs whose keys implement [`Ord`] can be sorted. In templates, the filter can
+/// also be called `items`.
+#[inline]
+pub fn dictsort<I, K, V>(map: I) -> Result<std::vec::IntoIter<(K, V)>, Infallible>
+where
+    I: IntoIterator<Item = (K, V)>,
+    K: Ord,
+{
+    let mut items: Vec<_> = map.into_iter().collect();
+    items.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Ok(items.into_iter())
+}
+
+/// Removes the duplicate items of `iterable`, keeping the first occurrence of each item
+///
+/// Two items are duplicates if the keys returned by `key` for them are equal. In templates, the
+/// key extractor is optional, and defaults to (a clone of) the item itself:
+///
+/// ```ignore
+/// {{ tags|unique|join(", ") }}
+/// {{ tags|unique(|tag| tag.to_lowercase())|join(", ") }}
+/// ```
+#[inline]
+pub fn unique<I, K, F>(iterable: I, key: F) -> Result<Unique<I::IntoIter, K, F>, Infallible>
+where
+    I: IntoIterator,
+    K: Eq + Hash,
+    F: FnMut(&I::Item) -> K,
+{
+    Ok(Unique {
+        iter: iterable.into_iter(),
+        key,
+        seen: HashSet::new(),
+    })
+}
+
+/// The iterator returned by the [`unique`] filter.
+#[derive(Debug, Clone)]
+pub struct Unique<I, K, F> {
+    iter: I,
+    key: F,
+    seen: HashSet<K>,
+}
+
+impl<I, K, F> Iterator for Unique<I, K, F>
+where
+    I: Iterator,
+    K: Eq + Hash,
+    F: FnMut(&I::Item) -> K,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let item = self.iter.next()?;
+            if self.seen.insert((self.key)(&item)) {
+                return Some(item);
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.iter.size_hint();
+        ((lower > 0) as usize, upper)
+    }
+}
+
+/// Returns the first item of `iterable`, or `None` if it's empty
+///
+/// Unlike `items[0]`, it doesn't panic if there are no items:
+///
+/// ```ignore
+/// {{ tags|first|unwrap_or("untagged") }}
+/// ```
+#[inline]
+pub fn first<I: IntoIterator>(iterable: I) -> Result<Option<I::Item>, Infallible> {
+    Ok(iterable.into_iter().next())
+}
+
+/// Returns the last item of `iterable`, or `None` if it's empty
+///
+/// ```ignore
+/// {% if let Some(post) = posts|last %}Last post: {{ post.title }}{% endif %}
+/// ```
+#[inline]
+pub fn last<I: IntoIterator>(iterable: I) -> Result<Option<I::Item>, Infallible> {
+    Ok(iterable.into_iter().last())
+}
+
+/// Keeps the items of `iterable` for which `predicate` returns `true`
+///
+/// In templates, the predicate is built from the name of an attribute of the items, which is
+/// either a `bool`, or is compared with a value:
+///
+/// ```ignore
+/// {% for user in users|selectattr("is_admin") %}…{% endfor %}
+/// {% for user in users|selectattr("age", ">=", 18) %}…{% endfor %}
+/// ```
+#[inline]
+pub fn selectattr<I, F>(
+    iterable: I,
+    predicate: F,
+) -> Result<impl Iterator<Item = I::Item>, Infallible>
+where
+    I: IntoIterator,
+    F: FnMut(&I::Item) -> bool,
+{
+    Ok(iterable.into_iter().filter(predicate))
+}
+
+/// Removes the items of `iterable` for which `predicate` returns `true`
+///
+/// This is the opposite of [`selectattr`]:
+///
+/// ```ignore
+/// {% for user in users|rejectattr("is_admin") %}…{% endfor %}
+/// ```
+#[inline]
+pub fn rejectattr<I, F>(
+    iterable: I,
+    mut predicate: F,
+) -> Result<impl Iterator<Item = I::Item>, Infallible>
+where
+    I: IntoIterator,
+    F: FnMut(&I::Item) -> bool,
+{
+    Ok(iterable.into_iter().filter(move |item| !predicate(item)))
+}
+
 #[cfg(feature = "num-traits")]
 /// Absolute value
 pub fn abs<T>(number: T) -> Result<T>
@@ -484,6 +967,55 @@ pub fn capitalize(s: impl ToString) -> Result<impl fmt::Display, Infallible> {
     capitalize(s.to_string())
 }
 
+/// Uppercase the first character of a value, leaving all others untouched.
+///
+/// Unlike [`capitalize`], the rest of the value is not lowercased, so acronyms and names keep
+/// their case. With the `unicode-segmentation` feature, the whole first user-perceived character
+/// (grapheme cluster) is uppercased. If `locale` is Turkish (`"tr"`) or Azerbaijani (`"az"`),
+/// a leading `i` is uppercased to a dotted `İ`. In templates, the locale argument is optional.
+///
+/// ```ignore
+/// {{ "iOS app"|capitalize_first }}       -> IOS app
+/// {{ "istanbul"|capitalize_first("tr") }} -> İstanbul
+/// ```
+#[inline]
+pub fn capitalize_first(s: impl ToString, locale: &str) -> Result<impl fmt::Display, Infallible> {
+    fn capitalize_first(s: String, locale: &str) -> Result<String, Infallible> {
+        let first = first_grapheme(&s);
+        if first.is_empty() {
+            return Ok(s);
+        }
+
+        let dotted_i = matches!(locale.get(..2), Some("tr" | "az"))
+            && matches!(locale.as_bytes().get(2), None | Some(b'-' | b'_'));
+        let mut output = String::with_capacity(s.len());
+        for c in first.chars() {
+            match c {
+                'i' if dotted_i => output.push('İ'),
+                c => output.extend(c.to_uppercase()),
+            }
+        }
+        output.push_str(&s[first.len()..]);
+        Ok(output)
+    }
+    capitalize_first(s.to_string(), locale)
+}
+
+#[cfg(feature = "unicode-segmentation")]
+fn first_grapheme(s: &str) -> &str {
+    unicode_segmentation::UnicodeSegmentation::graphemes(s, true)
+        .next()
+        .unwrap_or_default()
+}
+
+#[cfg(not(feature = "unicode-segmentation"))]
+fn first_grapheme(s: &str) -> &str {
+    match s.chars().next() {
+        Some(c) => &s[..c.len_utf8()],
+        None => "",
+    }
+}
+
 /// Centers the value in a field of a given width
 #[inline]
 pub fn center(src: impl fmt::Display, width: usize) -> Result<impl fmt::Display, Infallible> {
@@ -506,16 +1038,84 @@ impl<T: fmt::Display> fmt::Display for Center<T> {
 }
 
 /// Count the words in that string.
+///
+/// With the `unicode-segmentation` feature, words are found using the Unicode word boundaries
+/// rules, otherwise the string is split on whitespace.
 #[inline]
 pub fn wordcount(s: impl ToString) -> Result<usize, Infallible> {
     fn wordcount(s: String) -> Result<usize, Infallible> {
-        Ok(s.split_whitespace().count())
+        Ok(count_words(&s))
     }
     wordcount(s.to_string())
 }
 
+#[cfg(feature = "unicode-segmentation")]
+fn count_words(s: &str) -> usize {
+    use unicode_segmentation::UnicodeSegmentation;
+
+    s.unicode_words().count()
+}
+
+#[cfg(not(feature = "unicode-segmentation"))]
+fn count_words(s: &str) -> usize {
+    s.split_whitespace().count()
+}
+
+/// Count the characters in that string.
+///
+/// With the `unicode-segmentation` feature, the user-perceived characters (grapheme clusters)
+/// are counted, otherwise the `char`s are counted.
+#[inline]
+pub fn char_count(s: impl ToString) -> Result<usize, Infallible> {
+    fn char_count(s: String) -> Result<usize, Infallible> {
+        #[cfg(feature = "unicode-segmentation")]
+        let count = unicode_segmentation::UnicodeSegmentation::graphemes(s.as_str(), true).count();
+        #[cfg(not(feature = "unicode-segmentation"))]
+        let count = s.chars().count();
+        Ok(count)
+    }
+    char_count(s.to_string())
+}
+
+/// Estimate the time needed to read that string, given a reading speed in words per minute.
+///
+/// The returned value displays the number of minutes, rounded up. Its fields give access to
+/// the number of words and minutes.
+#[inline]
+pub fn reading_time(s: impl ToString, wpm: usize) -> Result<ReadingTime, Infallible> {
+    fn reading_time(s: String, wpm: usize) -> Result<ReadingTime, Infallible> {
+        let words = count_words(&s);
+        let wpm = wpm.max(1);
+        Ok(ReadingTime {
+            words,
+            // `usize::div_ceil()` needs rust 1.73
+            minutes: words / wpm + (words % wpm != 0) as usize,
+        })
+    }
+    reading_time(s.to_string(), wpm)
+}
+
+/// The result of the [`reading_time`] filter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReadingTime {
+    /// The number of words in the text.
+    pub words: usize,
+    /// The estimated reading time, in minutes.
+    pub minutes: usize,
+}
+
+impl fmt::Display for ReadingTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.minutes.fmt(f)
+    }
+}
+
 /// Return a title cased version of the value. Words will start with uppercase letters, all
 /// remaining characters are lowercase.
+///
+/// Words are separated by whitespace, hyphens and dashes, and opening brackets, so
+/// `"jean-luc (the captain)"` becomes `"Jean-Luc (The Captain)"`. Apostrophes don't start a new
+/// word: `"don't"` becomes `"Don't"`, not `"Don'T"`.
 #[inline]
 pub fn title(s: impl ToString) -> Result<String, Infallible> {
     let s = s.to_string();
@@ -525,7 +1125,7 @@ pub fn title(s: impl ToString) -> Result<String, Infallible> {
     // change the size of a char, "breaking" the char indices.
     let mut output = String::with_capacity(s.len());
     for c in s.chars() {
-        if c.is_whitespace() {
+        if is_title_separator(c) {
             output.push(c);
             need_capitalization = true;
         } else if need_capitalization {
@@ -544,6 +1144,231 @@ pub fn title(s: impl ToString) -> Result<String, Infallible> {
     Ok(output)
 }
 
+// Whether `c` separates two words for [`title`]
+fn is_title_separator(c: char) -> bool {
+    c.is_whitespace()
+        || matches!(
+            c,
+            '-' | '\u{2010}'..='\u{2015}' | '\u{2212}' | '(' | '[' | '{' | '<' | '/' | '"'
+        )
+}
+
+/// Rounds a number to `precision` decimal places
+///
+/// `mode` is `"common"` to round half away from zero, `"ceil"` to round up, or `"floor"` to
+/// round down. A negative precision rounds to tens, hundreds, etc. In templates, the precision
+/// defaults to `0` and the mode to `"common"`:
+///
+/// ```ignore
+/// {{ 2.675|round(2) }}           -> 2.68
+/// {{ 2.671|round(2, "ceil") }}   -> 2.68
+/// {{ 2.679|round(0, "floor") }}  -> 2
+/// ```
+#[inline]
+pub fn round(value: impl Number, precision: i32, mode: &str) -> Result<f64> {
+    let round = match mode {
+        "common" => f64::round,
+        "ceil" => f64::ceil,
+        "floor" => f64::floor,
+        _ => {
+            return Err(crate::Error::Custom(
+                format!(
+                    "unknown rounding mode `{mode}`, expected `\"common\"`, `\"ceil\"` or \
+                     `\"floor\"`"
+                )
+                .into(),
+            ));
+        }
+    };
+    let value = value.to_f64();
+    // Scaling by a factor that's not exactly representable, like `1e-1`, would add rounding
+    // errors, so a negative precision divides by its (exact) inverse.
+    Ok(if precision >= 0 {
+        let factor = 10f64.powi(precision);
+        round(value * factor) / factor
+    } else {
+        let factor = 10f64.powi(-precision);
+        round(value / factor) * factor
+    })
+}
+
+/// Formats a number in a short, human-readable form, like `1.2k` or `3.4M`
+///
+/// The number is rounded to one decimal place, and the suffixes are `k` (thousands), `M`
+/// (millions), `B` (billions) and `T` (trillions).
+///
+/// ```ignore
+/// {{ 999|humanize }}       -> 999
+/// {{ 1234|humanize }}      -> 1.2k
+/// {{ 3_400_000|humanize }} -> 3.4M
+/// ```
+#[inline]
+pub fn humanize(number: impl Number) -> Result<impl fmt::Display, Infallible> {
+    const SUFFIXES: [&str; 5] = ["", "k", "M", "B", "T"];
+
+    let number = number.to_f64();
+    let mut scaled = number.abs();
+    let mut exponent = 0;
+    while scaled >= 1000.0 && exponent < SUFFIXES.len() - 1 {
+        scaled /= 1000.0;
+        exponent += 1;
+    }
+    let mut rounded = (scaled * 10.0).round() / 10.0;
+    // e.g. 999_950 is rounded to 1000k, which is 1M
+    if rounded >= 1000.0 && exponent < SUFFIXES.len() - 1 {
+        rounded = (scaled / 100.0).round() / 10.0;
+        exponent += 1;
+    }
+    let sign = match number.is_sign_negative() && rounded != 0.0 {
+        true => "-",
+        false => "",
+    };
+    let suffix = SUFFIXES[exponent];
+    Ok(match rounded.fract() == 0.0 {
+        true => format!("{sign}{rounded:.0}{suffix}"),
+        false => format!("{sign}{rounded:.1}{suffix}"),
+    })
+}
+
+/// Inserts `separator` between the groups of three digits of the integer part of a number
+///
+/// The number is formatted with its `Display` implementation first, so it can be an integer, a
+/// float or a number already formatted as a string. In templates, the separator defaults to `,`.
+///
+/// ```ignore
+/// {{ 1234567|intcomma }}       -> 1,234,567
+/// {{ -1234.5|intcomma(" ") }}  -> -1 234.5
+/// ```
+#[inline]
+pub fn intcomma(
+    number: impl fmt::Display,
+    separator: impl fmt::Display,
+) -> Result<impl fmt::Display, Infallible> {
+    let number = number.to_string();
+    let separator = separator.to_string();
+    let start = match number.starts_with(['-', '+']) {
+        true => 1,
+        false => 0,
+    };
+    let digits = number[start..]
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(number.len() - start);
+
+    let mut output = String::with_capacity(number.len() + digits / 3 * separator.len());
+    output.push_str(&number[..start]);
+    for (index, digit) in number[start..start + digits].char_indices() {
+        if index > 0 && (digits - index) % 3 == 0 {
+            output.push_str(&separator);
+        }
+        output.push(digit);
+    }
+    output.push_str(&number[start + digits..]);
+    Ok(output)
+}
+
+/// A number, as accepted by the [`round`] filter
+pub trait Number {
+    /// Converts the number to an `f64`, possibly losing precision
+    fn to_f64(&self) -> f64;
+}
+
+macro_rules! impl_number {
+    ($($ty:ty),*) => {
+        $(
+            impl Number for $ty {
+                #[inline]
+                fn to_f64(&self) -> f64 {
+                    *self as f64
+                }
+            }
+        )*
+    };
+}
+
+impl_number!(f32, f64, i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+
+impl<T: Number + ?Sized> Number for &T {
+    #[inline]
+    fn to_f64(&self) -> f64 {
+        T::to_f64(self)
+    }
+}
+
+/// An `Option` or a `Result`, as accepted by the [`ok`], [`unwrap_or`] and [`map_or`] filters
+pub trait FilterOption {
+    /// The type of the `Some` or `Ok` value
+    type Value: ?Sized;
+
+    /// Returns a reference to the `Some` or `Ok` value, if any
+    fn filter_option(&self) -> Option<&Self::Value>;
+}
+
+impl<T> FilterOption for Option<T> {
+    type Value = T;
+
+    #[inline]
+    fn filter_option(&self) -> Option<&T> {
+        self.as_ref()
+    }
+}
+
+impl<T, E> FilterOption for std::result::Result<T, E> {
+    type Value = T;
+
+    #[inline]
+    fn filter_option(&self) -> Option<&T> {
+        self.as_ref().ok()
+    }
+}
+
+impl<O: FilterOption + ?Sized> FilterOption for &O {
+    type Value = O::Value;
+
+    #[inline]
+    fn filter_option(&self) -> Option<&O::Value> {
+        O::filter_option(self)
+    }
+}
+
+/// Converts an `Option` or a `Result` to an `Option` referencing its value, discarding the error
+#[inline]
+pub fn ok<O: FilterOption + ?Sized>(value: &O) -> Result<Option<&O::Value>, Infallible> {
+    Ok(value.filter_option())
+}
+
+/// Returns a reference to the value of an `Option` or a `Result`, or to `default`
+///
+/// The value can also be borrowed as the type of `default`, so a reference to a value can be
+/// replaced by a value, like the item returned by [`first`]:
+///
+/// ```ignore
+/// {{ user.nickname|unwrap_or(user.name) }}
+/// {{ tags|first|unwrap_or("untagged") }}
+/// ```
+#[inline]
+pub fn unwrap_or<'a, O, D>(value: &'a O, default: &'a D) -> Result<&'a D, Infallible>
+where
+    O: FilterOption + ?Sized,
+    O::Value: Borrow<D>,
+    D: ?Sized,
+{
+    Ok(value.filter_option().map_or(default, Borrow::borrow))
+}
+
+/// Calls `f` with the value of an `Option` or a `Result`, or returns `default`
+///
+/// ```ignore
+/// {{ user.nickname|map_or(0, String::len) }}
+/// ```
+#[inline]
+pub fn map_or<'a, O, U, F>(value: &'a O, default: U, f: F) -> Result<U, Infallible>
+where
+    O: FilterOption + ?Sized,
+    F: FnOnce(&'a O::Value) -> U,
+{
+    Ok(value.filter_option().map_or(default, f))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -799,6 +1624,60 @@ mod tests {
         assert_eq!(capitalize("ßß").unwrap().to_string(), "SSß".to_string());
     }
 
+    #[test]
+    fn test_capitalize_first() {
+        assert_eq!(capitalize_first("foo", "").unwrap().to_string(), "Foo");
+        assert_eq!(capitalize_first("", "").unwrap().to_string(), "");
+        assert_eq!(
+            capitalize_first("fOO bAR", "").unwrap().to_string(),
+            "FOO bAR"
+        );
+        assert_eq!(capitalize_first("ßß", "").unwrap().to_string(), "SSß");
+        assert_eq!(
+            capitalize_first("istanbul", "").unwrap().to_string(),
+            "Istanbul"
+        );
+        assert_eq!(
+            capitalize_first("istanbul", "tr").unwrap().to_string(),
+            "İstanbul"
+        );
+        assert_eq!(
+            capitalize_first("izmir", "az-AZ").unwrap().to_string(),
+            "İzmir"
+        );
+        assert_eq!(
+            capitalize_first("izmir", "tra").unwrap().to_string(),
+            "Izmir"
+        );
+        assert_eq!(capitalize_first("ılık", "tr").unwrap().to_string(), "Ilık");
+        #[cfg(feature = "unicode-segmentation")]
+        assert_eq!(
+            capitalize_first("e\u{301}te\u{301}", "")
+                .unwrap()
+                .to_string(),
+            "E\u{301}te\u{301}"
+        );
+    }
+
+    #[test]
+    fn test_option_filters() {
+        let some = Some(String::from("nick"));
+        let none: Option<String> = None;
+        let name = String::from("name");
+        assert_eq!(unwrap_or(&some, &name).unwrap(), "nick");
+        assert_eq!(unwrap_or(&none, &name).unwrap(), "name");
+        assert_eq!(map_or(&some, 0, String::len).unwrap(), 4);
+        assert_eq!(map_or(&&none, 0, String::len).unwrap(), 0);
+        assert_eq!(ok(&some).unwrap().map(String::as_str), Some("nick"));
+
+        let ok_value: std::result::Result<u32, &str> = Ok(1);
+        let err: std::result::Result<u32, &str> = Err("error");
+        assert_eq!(*unwrap_or(&ok_value, &0).unwrap(), 1);
+        assert_eq!(*unwrap_or(&err, &0).unwrap(), 0);
+        assert_eq!(ok(&err).unwrap(), None);
+        assert!(map_or(&ok_value, false, |v| *v == 1).unwrap());
+    }
+
     #[test]
     fn test_center() {
         assert_eq!(center("f", 3).unwrap().to_string(), " f ".to_string());
@@ -823,6 +1702,312 @@ mod tests {
         assert_eq!(wordcount("foo  bar").unwrap(), 2);
     }
 
+    #[cfg(feature = "unicode-segmentation")]
+    #[test]
+    fn test_wordcount_unicode() {
+        assert_eq!(wordcount("Hello, world!").unwrap(), 2);
+        assert_eq!(wordcount("« Ça va ? »").unwrap(), 2);
+        assert_eq!(wordcount("foo - bar").unwrap(), 2);
+    }
+
+    #[test]
+    fn test_char_count() {
+        assert_eq!(char_count("").unwrap(), 0);
+        assert_eq!(char_count("foo").unwrap(), 3);
+        assert_eq!(char_count("été").unwrap(), 3);
+    }
+
+    #[cfg(feature = "unicode-segmentation")]
+    #[test]
+    fn test_char_count_graphemes() {
+        assert_eq!(char_count("e\u{301}te\u{301}").unwrap(), 3);
+        assert_eq!(char_count("🇫🇷").unwrap(), 1);
+    }
+
+    #[test]
+    fn test_reading_time() {
+        let rt = reading_time("", 200).unwrap();
+        assert_eq!((rt.words, rt.minutes), (0, 0));
+        let rt = reading_time("foo bar", 200).unwrap();
+        assert_eq!((rt.words, rt.minutes), (2, 1));
+        assert_eq!(reading_time("foo bar baz", 2).unwrap().to_string(), "2");
+        assert_eq!(reading_time("foo bar", 2).unwrap().to_string(), "1");
+        assert_eq!(reading_time("foo bar", 0).unwrap().minutes, 2);
+        assert_eq!(reading_time("foo bar", usize::MAX).unwrap().minutes, 1);
+    }
+
+    #[test]
+    fn test_striptags() {
+        assert_eq!(striptags("", false).unwrap().to_string(), "");
+        assert_eq!(
+            striptags("<p>Hello <b>world</b>!</p>", false)
+                .unwrap()
+                .to_string(),
+            "Hello world!"
+        );
+        assert_eq!(
+            striptags("<h1 class=\"title\">A\n  title</h1>\n<p>Text</p>", false)
+                .unwrap()
+                .to_string(),
+            "A title Text"
+        );
+        assert_eq!(
+            striptags("a<!-- <b>comment</b> -->b <br/>c", false)
+                .unwrap()
+                .to_string(),
+            "ab c"
+        );
+        assert_eq!(
+            striptags("1 < 2 <3 and <b>x</b>", false)
+                .unwrap()
+                .to_string(),
+            "1 < 2 <3 and x"
+        );
+        assert_eq!(
+            striptags("<p>unclosed <b", false).unwrap().to_string(),
+            "unclosed <b"
+        );
+        assert_eq!(
+            striptags("<p>Fish &amp; chips &lt;3</p>", false)
+                .unwrap()
+                .to_string(),
+            "Fish &amp; chips &lt;3"
+        );
+        assert_eq!(
+            striptags("<p>Fish &amp; chips &lt;3</p>", true)
+                .unwrap()
+                .to_string(),
+            "Fish & chips <3"
+        );
+        assert_eq!(
+            striptags("&#39;&#x41;&quot;&nbsp;&unknown; & &#xFFFFFFFF; &amp", true)
+                .unwrap()
+                .to_string(),
+            "'A\"\u{a0}&unknown; & &#xFFFFFFFF; &amp"
+        );
+    }
+
+    #[test]
+    fn test_batch() {
+        let chunks = |iter: Batch<std::ops::Range<i32>>| iter.collect::<Vec<_>>();
+        assert_eq!(
+            chunks(batch(0..7, 3, None).unwrap()),
+            vec![vec![0, 1, 2], vec![3, 4, 5], vec![6]]
+        );
+        assert_eq!(
+            chunks(batch(0..7, 3, Some(-1)).unwrap()),
+            vec![vec![0, 1, 2], vec![3, 4, 5], vec![6, -1, -1]]
+        );
+        assert_eq!(
+            chunks(batch(0..6, 3, Some(-1)).unwrap()),
+            vec![vec![0, 1, 2], vec![3, 4, 5]]
+        );
+        assert!(chunks(batch(0..0, 3, Some(-1)).unwrap()).is_empty());
+        assert_eq!(batch(0..7, 3, None).unwrap().size_hint(), (3, Some(3)));
+        assert_eq!(batch(0..6, 2, None).unwrap().size_hint(), (3, Some(3)));
+        assert!(batch(0..7, 0, None).is_err());
+
+        let items = vec!["a", "b", "c"];
+        assert_eq!(
+            batch(&items, 2, Some(&"-")).unwrap().collect::<Vec<_>>(),
+            vec![vec![&"a", &"b"], vec![&"c", &"-"]]
+        );
+    }
+
+    #[test]
+    fn test_slice() {
+        let columns = |iter: Slice<i32>| iter.collect::<Vec<_>>();
+        assert_eq!(
+            columns(slice(0..7, 3, None).unwrap()),
+            vec![vec![0, 1, 2], vec![3, 4], vec![5, 6]]
+        );
+        assert_eq!(
+            columns(slice(0..7, 3, Some(-1)).unwrap()),
+            vec![vec![0, 1, 2], vec![3, 4, -1], vec![5, 6, -1]]
+        );
+        assert_eq!(
+            columns(slice(0..6, 3, Some(-1)).unwrap()),
+            vec![vec![0, 1], vec![2, 3], vec![4, 5]]
+        );
+        assert_eq!(
+            columns(slice(0..1, 3, None).unwrap()),
+            vec![vec![0], Vec::<i32>::new(), Vec::new()]
+        );
+        assert_eq!(
+            columns(slice(0..0, 2, Some(-1)).unwrap()),
+            vec![Vec::<i32>::new(), Vec::new()]
+        );
+        assert_eq!(slice(0..7, 3, None).unwrap().size_hint(), (3, Some(3)));
+        assert!(slice(0..7, 0, None).is_err());
+    }
+
+    #[test]
+    fn test_dictsort() {
+        let map: std::collections::HashMap<_, _> =
+            [("c", 3), ("a", 1), ("b", 2)].into_iter().collect();
+        assert_eq!(
+            dictsort(&map).unwrap().collect::<Vec<_>>(),
+            vec![(&"a", &1), (&"b", &2), (&"c", &3)]
+        );
+        assert_eq!(
+            dictsort(vec![(2, 'x'), (1, 'y'), (2, 'z')])
+                .unwrap()
+                .collect::<Vec<_>>(),
+            vec![(1, 'y'), (2, 'x'), (2, 'z')]
+        );
+    }
+
+    #[test]
+    fn test_unique() {
+        let tags = ["rust", "Web", "rust", "web", "cli"];
+        assert_eq!(
+            unique(&tags, |tag| *tag).unwrap().collect::<Vec<_>>(),
+            vec![&"rust", &"Web", &"web", &"cli"]
+        );
+        assert_eq!(
+            unique(&tags, |tag| tag.to_lowercase())
+                .unwrap()
+                .collect::<Vec<_>>(),
+            vec![&"rust", &"Web", &"cli"]
+        );
+        assert_eq!(
+            unique(0..10, |i| i % 3).unwrap().collect::<Vec<_>>(),
+            vec![0, 1, 2]
+        );
+        assert_eq!(unique(0..10, |i| *i).unwrap().size_hint(), (1, Some(10)));
+    }
+
+    #[test]
+    fn test_first_last() {
+        let items = vec!["a", "b", "c"];
+        assert_eq!(first(&items).unwrap(), Some(&"a"));
+        assert_eq!(last(&items).unwrap(), Some(&"c"));
+        assert_eq!(first(0..0).unwrap(), None);
+        assert_eq!(last(0..0).unwrap(), None);
+        assert_eq!(last(1..=3).unwrap(), Some(3));
+    }
+
+    #[test]
+    fn test_selectattr_rejectattr() {
+        let items = [1, 2, 3, 4, 5];
+        assert_eq!(
+            selectattr(&items, |i| *i % 2 == 1)
+                .unwrap()
+                .collect::<Vec<_>>(),
+            vec![&1, &3, &5]
+        );
+        assert_eq!(
+            rejectattr(&items, |i| *i % 2 == 1)
+                .unwrap()
+                .collect::<Vec<_>>(),
+            vec![&2, &4]
+        );
+    }
+
+    #[test]
+    fn test_round() {
+        assert_eq!(round(2.5, 0, "common").unwrap(), 3.0);
+        assert_eq!(round(-2.5, 0, "common").unwrap(), -3.0);
+        assert_eq!(round(1.23456, 2, "common").unwrap(), 1.23);
+        assert_eq!(round(2.71328, 2, "ceil").unwrap(), 2.72);
+        assert_eq!(round(3.149, 1, "floor").unwrap(), 3.1);
+        assert_eq!(round(-2.71, 1, "floor").unwrap(), -2.8);
+        assert_eq!(round(1234.5, -2, "common").unwrap(), 1200.0);
+        assert_eq!(round(1250, -2, "common").unwrap(), 1300.0);
+        for value in &[7u8, 8] {
+            assert_eq!(round(value, 0, "common").unwrap(), *value as f64);
+        }
+        assert_eq!(round(2.5f32, 0, "floor").unwrap(), 2.0);
+        assert!(round(2.5, 0, "even").is_err());
+    }
+
+    #[test]
+    fn test_split() {
+        fn split(s: &str, separator: &str) -> Vec<String> {
+            super::split(s, separator)
+                .unwrap()
+                .map(str::to_owned)
+                .collect()
+        }
+        assert_eq!(split("a,b,,c", ","), vec!["a", "b", "", "c"]);
+        assert_eq!(split("", ","), vec![""]);
+        assert_eq!(split("a -- b", " -- "), vec!["a", "b"]);
+        assert_eq!(
+            super::split(&String::from("x y"), &String::from(" "))
+                .unwrap()
+                .collect::<Vec<_>>(),
+            vec!["x", "y"]
+        );
+    }
+
+    #[test]
+    fn test_wordwrap() {
+        let wrap = |s: &str, width, break_long_words| {
+            wordwrap(s, width, break_long_words).unwrap().to_string()
+        };
+        assert_eq!(wrap("", 10, true), "");
+        assert_eq!(
+            wrap("the quick brown fox jumps", 10, true),
+            "the quick\nbrown fox\njumps"
+        );
+        assert_eq!(wrap("the  quick\tbrown", 20, true), "the quick brown");
+        assert_eq!(
+            wrap("one two\n\nthree four five", 9, true),
+            "one two\n\nthree\nfour five"
+        );
+        assert_eq!(
+            wrap("  indented text here", 12, true),
+            "  indented\ntext here"
+        );
+        assert_eq!(wrap("a\r\nb c d", 3, true), "a\r\nb c\r\nd");
+        assert_eq!(
+            wrap("see https://example.com/a/long/path", 10, true),
+            "see https:\n//example.\ncom/a/long\n/path"
+        );
+        assert_eq!(
+            wrap("see https://example.com/a/long/path ok", 10, false),
+            "see\nhttps://example.com/a/long/path\nok"
+        );
+        assert_eq!(wrap("ééééé", 2, true), "éé\néé\né");
+        assert!(wordwrap("text", 0, true).is_err());
+    }
+
+    #[test]
+    fn test_humanize() {
+        let humanize = |n: f64| humanize(n).unwrap().to_string();
+        assert_eq!(humanize(0.0), "0");
+        assert_eq!(humanize(999.0), "999");
+        assert_eq!(humanize(12.34), "12.3");
+        assert_eq!(humanize(1000.0), "1k");
+        assert_eq!(humanize(1234.0), "1.2k");
+        assert_eq!(humanize(999_949.0), "999.9k");
+        assert_eq!(humanize(999_950.0), "1M");
+        assert_eq!(humanize(3_450_000.0), "3.5M");
+        assert_eq!(humanize(-3_400_000.0), "-3.4M");
+        assert_eq!(humanize(-0.01), "0");
+        assert_eq!(humanize(7.1e9), "7.1B");
+        assert_eq!(humanize(2e15), "2000T");
+        assert_eq!(super::humanize(&1_500u64).unwrap().to_string(), "1.5k");
+    }
+
+    #[test]
+    fn test_intcomma() {
+        let intcomma = |n: &dyn fmt::Display| intcomma(n, ",").unwrap().to_string();
+        assert_eq!(intcomma(&0), "0");
+        assert_eq!(intcomma(&999), "999");
+        assert_eq!(intcomma(&1000), "1,000");
+        assert_eq!(intcomma(&1_234_567), "1,234,567");
+        assert_eq!(intcomma(&-123_456), "-123,456");
+        assert_eq!(intcomma(&1234.5), "1,234.5");
+        assert_eq!(intcomma(&u64::MAX), "18,446,744,073,709,551,615");
+        assert_eq!(intcomma(&"+12345 items"), "+12,345 items");
+        assert_eq!(intcomma(&"n/a"), "n/a");
+        assert_eq!(
+            super::intcomma(1_234_567, "\u{202f}").unwrap().to_string(),
+            "1\u{202f}234\u{202f}567"
+        );
+    }
+
     #[test]
     fn test_title() {
         assert_eq!(&title("").unwrap(), "");
@@ -833,6 +2018,12 @@ mod tests {
         assert_eq!(&title("foo  bar ").unwrap(), "Foo  Bar ");
         assert_eq!(&title("fOO").unwrap(), "Foo");
         assert_eq!(&title("fOo BaR").unwrap(), "Foo Bar");
+        assert_eq!(&title("jean-luc picard").unwrap(), "Jean-Luc Picard");
+        assert_eq!(&title("paris–roubaix").unwrap(), "Paris–Roubaix");
+        assert_eq!(&title("DON'T STOP").unwrap(), "Don't Stop");
+        assert_eq!(&title("l’été (in paris)").unwrap(), "L’été (In Paris)");
+        assert_eq!(&title("\"quoted\" and/or").unwrap(), "\"Quoted\" And/Or");
+        assert_eq!(&title("éCOLE ΣΟΦΙΑ").unwrap(), "École Σοφια");
     }
 
     #[test]
@@ -1,4 +1,13 @@
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
 use std::iter::{Enumerate, Peekable};
+use std::ops::{Bound, Deref, RangeBounds};
+
+use askama_escape::{EscapeError, Escaper};
+
+use crate::{DynTemplate, Error, Result, Template};
 
 pub struct TemplateLoop<I>
 where
@@ -40,9 +49,780 @@ where
     }
 }
 
+/// Iterator used by `for` loops whose body accesses `loop.length`.
+///
+/// Iterators that report an exact [`Iterator::size_hint`] (e.g. slices, ranges or any
+/// [`ExactSizeIterator`]) are passed through as-is; any other iterator is collected first, so
+/// that its length is known before the first iteration.
+pub enum LoopLength<I>
+where
+    I: Iterator,
+{
+    Exact(I),
+    Collected(std::vec::IntoIter<I::Item>),
+}
+
+impl<I> LoopLength<I>
+where
+    I: Iterator,
+{
+    /// Returns the iterator to loop over, and the number of items it will yield.
+    #[inline]
+    pub fn new(iter: I) -> (Self, usize) {
+        match iter.size_hint() {
+            (lower, Some(upper)) if lower == upper => (LoopLength::Exact(iter), lower),
+            _ => {
+                let items: Vec<_> = iter.collect();
+                let length = items.len();
+                (LoopLength::Collected(items.into_iter()), length)
+            }
+        }
+    }
+}
+
+impl<I> Iterator for LoopLength<I>
+where
+    I: Iterator,
+{
+    type Item = I::Item;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            LoopLength::Exact(iter) => iter.next(),
+            LoopLength::Collected(iter) => iter.next(),
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self {
+            LoopLength::Exact(iter) => iter.size_hint(),
+            LoopLength::Collected(iter) => iter.size_hint(),
+        }
+    }
+}
+
+/// Renders a `{% for … recursive %}` loop over `iter`.
+///
+/// `body` renders the loop over the iterable it is given, and calls the function it gets as
+/// last argument for every `loop(…)` in its body, to render the loop again over other items.
+/// The mutable variables updated by the loop are moved in and out of `body` as `state`, since
+/// the closure rendering the loop can be called again while it runs.
+#[inline]
+pub fn recursive_loop<W, S, T, F>(writer: &mut W, state: S, iter: T, body: F) -> Result<S>
+where
+    W: fmt::Write + ?Sized,
+    F: Fn(&mut W, S, T, &dyn Fn(&mut W, S, T) -> Result<S>) -> Result<S>,
+{
+    fn recurse<W, S, T, F>(writer: &mut W, state: S, iter: T, body: &F) -> Result<S>
+    where
+        W: fmt::Write + ?Sized,
+        F: Fn(&mut W, S, T, &dyn Fn(&mut W, S, T) -> Result<S>) -> Result<S>,
+    {
+        body(writer, state, iter, &|writer, state, iter| {
+            recurse(writer, state, iter, body)
+        })
+    }
+
+    recurse(writer, state, iter, &body)
+}
+
+/// Renders a recursive macro called with `args`.
+///
+/// `body` renders the macro with the arguments it is given, and calls the function it gets as
+/// last argument for every call of the macro in its body. Like in [`recursive_loop`], the
+/// mutable variables updated by the macro are moved in and out of `body` as `state`. An error
+/// is returned if more than `limit` calls are nested.
+#[inline]
+pub fn recursive_macro<W, S, T, F>(
+    writer: &mut W,
+    state: S,
+    args: T,
+    name: &'static str,
+    limit: usize,
+    body: F,
+) -> Result<S>
+where
+    W: fmt::Write + ?Sized,
+    F: Fn(&mut W, S, T, &dyn Fn(&mut W, S, T) -> Result<S>) -> Result<S>,
+{
+    fn recurse<W, S, T, F>(
+        writer: &mut W,
+        state: S,
+        args: T,
+        name: &'static str,
+        depth: usize,
+        limit: usize,
+        body: &F,
+    ) -> Result<S>
+    where
+        W: fmt::Write + ?Sized,
+        F: Fn(&mut W, S, T, &dyn Fn(&mut W, S, T) -> Result<S>) -> Result<S>,
+    {
+        if depth > limit {
+            return Err(Error::Custom(
+                format!("macro `{name}` exceeded the recursion limit of {limit}").into(),
+            ));
+        }
+        body(writer, state, args, &|writer, state, args| {
+            recurse(writer, state, args, name, depth + 1, limit, body)
+        })
+    }
+
+    recurse(writer, state, args, name, 0, limit, &body)
+}
+
 #[derive(Copy, Clone)]
 pub struct LoopItem {
     pub index: usize,
     pub first: bool,
     pub last: bool,
 }
+
+/// Wrapper used by the generated code to iterate over a borrowed value in a `for` loop.
+///
+/// The generated code calls `(&&Iterable(&value)).askama_iter()`, so that values whose
+/// reference implements [`IntoIterator`] are picked up by [`IterableRef`] before falling back
+/// to [`IterableDeref`], which iterates over the target of smart pointers like `Arc<Vec<T>>`,
+/// `Rc<[T]>`, `Box<[T]>` or `Cow<'_, [T]>`.
+pub struct Iterable<'a, T: ?Sized>(pub &'a T);
+
+/// Iterates over `&T`.
+pub trait IterableRef<'a> {
+    type IntoIter: Iterator;
+
+    fn askama_iter(&self) -> Self::IntoIter;
+}
+
+impl<'a, T: ?Sized> IterableRef<'a> for &Iterable<'a, T>
+where
+    &'a T: IntoIterator,
+{
+    type IntoIter = <&'a T as IntoIterator>::IntoIter;
+
+    #[inline]
+    fn askama_iter(&self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+/// Iterates over `&T::Target`.
+pub trait IterableDeref<'a> {
+    type IntoIter: Iterator;
+
+    fn askama_iter(&self) -> Self::IntoIter;
+}
+
+impl<'a, T: Deref + ?Sized> IterableDeref<'a> for Iterable<'a, T>
+where
+    &'a T::Target: IntoIterator,
+{
+    type IntoIter = <&'a T::Target as IntoIterator>::IntoIter;
+
+    #[inline]
+    fn askama_iter(&self) -> Self::IntoIter {
+        (&**self.0).into_iter()
+    }
+}
+
+/// Wrapper used by the generated code to render the value of an expression.
+///
+/// The generated code calls `(&&Render(&value)).askama_render(..)`, so that values implementing
+/// [`Template`] are picked up by [`RenderTemplate`] before falling back to [`RenderDisplay`]
+/// for everything else that implements [`fmt::Display`]. Both write the value directly into the
+/// writer of the template.
+pub struct Render<'a, T: ?Sized>(pub &'a T);
+
+/// Renders a nested [`Template`] directly into the parent's writer.
+pub trait RenderTemplate {
+    fn askama_render<W, E>(&self, writer: &mut W, escaper: E, mime_type: &str) -> Result<()>
+    where
+        W: fmt::Write + ?Sized,
+        E: Escaper;
+
+    /// Like `askama_render()`, but through the non-generic [`render_template()`], for templates
+    /// generated with `codegen = "size"`.
+    fn askama_render_dyn<E>(
+        &self,
+        writer: &mut dyn fmt::Write,
+        escaper: E,
+        mime_type: &str,
+    ) -> Result<()>
+    where
+        E: Escaper;
+}
+
+impl<T: Template + ?Sized> RenderTemplate for &Render<'_, T> {
+    /// If the nested template has the same MIME type as its parent, its output is already
+    /// escaped correctly and is written as is. Otherwise it is escaped with the parent's escaper.
+    #[inline]
+    fn askama_render<W, E>(&self, writer: &mut W, escaper: E, mime_type: &str) -> Result<()>
+    where
+        W: fmt::Write + ?Sized,
+        E: Escaper,
+    {
+        if T::MIME_TYPE == mime_type {
+            self.0.render_into(writer)
+        } else {
+            let mut writer = EscapingWriter::new(writer, &escaper);
+            let result = self.0.render_into(&mut writer);
+            writer.check(result)
+        }
+    }
+
+    #[inline]
+    fn askama_render_dyn<E>(
+        &self,
+        writer: &mut dyn fmt::Write,
+        escaper: E,
+        mime_type: &str,
+    ) -> Result<()>
+    where
+        E: Escaper,
+    {
+        render_template(writer, &self.0, &escaper, T::MIME_TYPE != mime_type)
+    }
+}
+
+/// The already rendered body of a `{% component %}` block, or of a `{% set %}` block assignment.
+///
+/// The body is rendered and escaped by the template containing the block, so a `Slot` used in an
+/// expression of a template with the same MIME type is written as is, and is never escaped a
+/// second time. In a template with another MIME type, it is escaped like a nested template.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Slot {
+    body: String,
+    mime_type: &'static str,
+}
+
+impl Slot {
+    #[doc(hidden)]
+    pub fn new(body: String, mime_type: &'static str) -> Self {
+        Self { body, mime_type }
+    }
+
+    /// Returns the rendered body.
+    pub fn as_str(&self) -> &str {
+        &self.body
+    }
+
+    /// Returns `true` if the rendered body is empty.
+    pub fn is_empty(&self) -> bool {
+        self.body.is_empty()
+    }
+}
+
+impl fmt::Display for Slot {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.body)
+    }
+}
+
+impl RenderTemplate for &Render<'_, Slot> {
+    #[inline]
+    fn askama_render<W, E>(&self, writer: &mut W, escaper: E, mime_type: &str) -> Result<()>
+    where
+        W: fmt::Write + ?Sized,
+        E: Escaper,
+    {
+        if self.0.mime_type == mime_type {
+            return Ok(writer.write_str(&self.0.body)?);
+        }
+        let mut writer = EscapingWriter::new(writer, &escaper);
+        let result = fmt::Write::write_str(&mut writer, &self.0.body).map_err(Error::from);
+        writer.check(result)
+    }
+
+    #[inline]
+    fn askama_render_dyn<E>(
+        &self,
+        writer: &mut dyn fmt::Write,
+        escaper: E,
+        mime_type: &str,
+    ) -> Result<()>
+    where
+        E: Escaper,
+    {
+        if self.0.mime_type == mime_type {
+            return write_str(writer, &self.0.body);
+        }
+        render_display(writer, self.0, &escaper)
+    }
+}
+
+/// Renders any [`fmt::Display`] value, escaping it with the template's escaper.
+pub trait RenderDisplay {
+    fn askama_render<W, E>(&self, writer: &mut W, escaper: E, mime_type: &str) -> Result<()>
+    where
+        W: fmt::Write + ?Sized,
+        E: Escaper;
+
+    /// Like `askama_render()`, but through the non-generic [`render_display()`], for templates
+    /// generated with `codegen = "size"`.
+    fn askama_render_dyn<E>(
+        &self,
+        writer: &mut dyn fmt::Write,
+        escaper: E,
+        mime_type: &str,
+    ) -> Result<()>
+    where
+        E: Escaper;
+}
+
+impl<T: fmt::Display + ?Sized> RenderDisplay for Render<'_, T> {
+    #[inline]
+    fn askama_render<W, E>(&self, writer: &mut W, escaper: E, _mime_type: &str) -> Result<()>
+    where
+        W: fmt::Write + ?Sized,
+        E: Escaper,
+    {
+        let mut writer = EscapingWriter::new(writer, &escaper);
+        let result =
+            fmt::Write::write_fmt(&mut writer, format_args!("{}", self.0)).map_err(Error::from);
+        writer.check(result)
+    }
+
+    #[inline]
+    fn askama_render_dyn<E>(
+        &self,
+        writer: &mut dyn fmt::Write,
+        escaper: E,
+        _mime_type: &str,
+    ) -> Result<()>
+    where
+        E: Escaper,
+    {
+        render_display(writer, &self.0, &escaper)
+    }
+}
+
+// The runtime helpers of the templates generated with `codegen = "size"`. They are never inlined,
+// so their code exists only once in the binary, shared by every template and every type of value.
+
+/// Writes a literal of a template.
+#[inline(never)]
+pub fn write_str(writer: &mut dyn fmt::Write, s: &str) -> Result<()> {
+    Ok(writer.write_str(s)?)
+}
+
+/// Renders a value, escaping it with `escaper`.
+#[inline(never)]
+pub fn render_display<E: Escaper>(
+    writer: &mut dyn fmt::Write,
+    value: &dyn fmt::Display,
+    escaper: &E,
+) -> Result<()> {
+    let mut writer = EscapingWriter::new(writer, escaper);
+    let result = fmt::Write::write_fmt(&mut writer, format_args!("{value}")).map_err(Error::from);
+    writer.check(result)
+}
+
+/// Renders a nested template, escaping its output with `escaper` if `escape` is `true`.
+#[inline(never)]
+pub fn render_template<E: Escaper>(
+    writer: &mut dyn fmt::Write,
+    template: &dyn DynTemplate,
+    escaper: &E,
+    escape: bool,
+) -> Result<()> {
+    if !escape {
+        return template.dyn_render_into(writer);
+    }
+    let mut writer = EscapingWriter::new(writer, escaper);
+    let result = template.dyn_render_into(&mut writer);
+    writer.check(result)
+}
+
+/// The value of an optional chain like `user?.address?.city` rendered in a `{{ }}` block, which
+/// renders nothing if a link of the chain is `None`.
+pub struct OptionalChain<T>(pub Option<T>);
+
+impl<T: fmt::Display> fmt::Display for OptionalChain<T> {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.0 {
+            Some(value) => value.fmt(f),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Escapes everything written to it, and remembers why the escaper failed, if it did.
+struct EscapingWriter<'a, W: ?Sized, E> {
+    writer: &'a mut W,
+    escaper: &'a E,
+    error: Option<EscapeError>,
+}
+
+impl<'a, W: ?Sized, E> EscapingWriter<'a, W, E> {
+    #[inline]
+    fn new(writer: &'a mut W, escaper: &'a E) -> Self {
+        Self {
+            writer,
+            escaper,
+            error: None,
+        }
+    }
+
+    /// Replaces the `fmt::Error` returned when the escaper failed by the actual error. A nested
+    /// template may already have added the context in which the error happened.
+    #[inline]
+    fn check(self, result: Result<()>) -> Result<()> {
+        match (result, self.error) {
+            (Err(Error::Fmt(_)), Some(err)) => Err(err.into()),
+            (Err(Error::Context(mut ctx)), Some(err)) if matches!(ctx.error, Error::Fmt(_)) => {
+                ctx.error = err.into();
+                Err(Error::Context(ctx))
+            }
+            (result, _) => result,
+        }
+    }
+}
+
+impl<W: fmt::Write + ?Sized, E: Escaper> fmt::Write for EscapingWriter<'_, W, E> {
+    #[inline]
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.escaper
+            .try_write_escaped(&mut *self.writer, s)
+            .map_err(|err| {
+                self.error = Some(err);
+                fmt::Error
+            })
+    }
+}
+
+/// Wraps any writer so it can be used as a `&mut dyn fmt::Write`.
+///
+/// Used by templates generated with `codegen = "size"`.
+pub struct DynWriter<'a, W: ?Sized>(pub &'a mut W);
+
+impl<W: fmt::Write + ?Sized> fmt::Write for DynWriter<'_, W> {
+    #[inline]
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.write_str(s)
+    }
+
+    #[inline]
+    fn write_char(&mut self, c: char) -> fmt::Result {
+        self.0.write_char(c)
+    }
+}
+
+/// Adapts a [`bytes::BufMut`] to [`fmt::Write`], used by [`Template::write_into_buf`].
+///
+/// [`Template::write_into_buf`]: crate::Template::write_into_buf
+#[cfg(feature = "bytes")]
+pub(crate) struct BufMutWriter<'a, B: ?Sized>(pub(crate) &'a mut B);
+
+#[cfg(feature = "bytes")]
+impl<B: bytes::BufMut + ?Sized> fmt::Write for BufMutWriter<'_, B> {
+    #[inline]
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        // `put_slice()` panics if the buffer is full
+        if self.0.remaining_mut() < s.len() {
+            return Err(fmt::Error);
+        }
+        self.0.put_slice(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// The size of an image, read at compile time by `image_dims("path/to/image.png")`
+///
+/// It is displayed as the `width` and `height` attributes of an `<img>` tag, e.g.
+/// `width=120 height=40`.
+#[cfg(feature = "image-dims")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageDims {
+    /// the width of the image in pixels
+    pub width: usize,
+    /// the height of the image in pixels
+    pub height: usize,
+}
+
+#[cfg(feature = "image-dims")]
+impl fmt::Display for ImageDims {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "width={} height={}", self.width, self.height)
+    }
+}
+
+/// Implements the `in` and `not in` operators of templates.
+///
+/// Like [`HashSet::contains()`], the item can be any borrowed form of the element type, e.g. a
+/// `&str` for a `Vec<String>`. The item of a string can be a `char` or a string.
+pub trait Contains<Q: ?Sized> {
+    fn askama_contains(&self, item: &Q) -> bool;
+}
+
+impl<T: Borrow<Q>, Q: PartialEq + ?Sized> Contains<Q> for [T] {
+    #[inline]
+    fn askama_contains(&self, item: &Q) -> bool {
+        self.iter().any(|value| value.borrow() == item)
+    }
+}
+
+impl<K, V, Q, S> Contains<Q> for HashMap<K, V, S>
+where
+    K: Borrow<Q> + Hash + Eq,
+    Q: Hash + Eq + ?Sized,
+    S: BuildHasher,
+{
+    #[inline]
+    fn askama_contains(&self, item: &Q) -> bool {
+        self.contains_key(item)
+    }
+}
+
+impl<T, Q, S> Contains<Q> for HashSet<T, S>
+where
+    T: Borrow<Q> + Hash + Eq,
+    Q: Hash + Eq + ?Sized,
+    S: BuildHasher,
+{
+    #[inline]
+    fn askama_contains(&self, item: &Q) -> bool {
+        self.contains(item)
+    }
+}
+
+impl<K: Borrow<Q> + Ord, V, Q: Ord + ?Sized> Contains<Q> for BTreeMap<K, V> {
+    #[inline]
+    fn askama_contains(&self, item: &Q) -> bool {
+        self.contains_key(item)
+    }
+}
+
+impl<T: Borrow<Q> + Ord, Q: Ord + ?Sized> Contains<Q> for BTreeSet<T> {
+    #[inline]
+    fn askama_contains(&self, item: &Q) -> bool {
+        self.contains(item)
+    }
+}
+
+impl Contains<str> for str {
+    #[inline]
+    fn askama_contains(&self, item: &str) -> bool {
+        self.contains(item)
+    }
+}
+
+impl Contains<&str> for str {
+    #[inline]
+    fn askama_contains(&self, item: &&str) -> bool {
+        self.contains(item)
+    }
+}
+
+impl Contains<String> for str {
+    #[inline]
+    fn askama_contains(&self, item: &String) -> bool {
+        self.contains(item.as_str())
+    }
+}
+
+impl Contains<char> for str {
+    #[inline]
+    fn askama_contains(&self, item: &char) -> bool {
+        self.contains(*item)
+    }
+}
+
+/// Implements indexing with a negative number literal in templates, like `{{ items[-1] }}` for the
+/// last item.
+pub trait GetItem {
+    type Item;
+
+    /// Returns the item at `index`, counted from the end if `index` is negative.
+    ///
+    /// # Panics
+    ///
+    /// Like indexing, this panics if `index` is out of bounds.
+    fn askama_get_item(&self, index: isize) -> &Self::Item;
+}
+
+impl<T> GetItem for [T] {
+    type Item = T;
+
+    #[inline]
+    #[track_caller]
+    fn askama_get_item(&self, index: isize) -> &T {
+        let len = self.len();
+        let position = match usize::try_from(index) {
+            Ok(index) => Some(index),
+            Err(_) => len.checked_sub(index.unsigned_abs()),
+        };
+        match position.and_then(|position| self.get(position)) {
+            Some(item) => item,
+            None => panic!("index {index} is out of bounds for a length of {len}"),
+        }
+    }
+}
+
+/// Implements slicing with a range in templates, like `{{ items[1..4] }}` or `{{ name[..5] }}`.
+///
+/// Unlike indexing in Rust, slicing never panics: the bounds of the range are clamped to the
+/// length of the value, and a string is sliced by characters instead of bytes, so it is never
+/// split in the middle of a character.
+pub trait Slice {
+    fn askama_slice<R: RangeBounds<usize>>(&self, range: R) -> &Self;
+}
+
+impl<T> Slice for [T] {
+    #[inline]
+    fn askama_slice<R: RangeBounds<usize>>(&self, range: R) -> &Self {
+        let (start, end) = slice_bounds(&range, self.len());
+        &self[start..end]
+    }
+}
+
+impl Slice for str {
+    fn askama_slice<R: RangeBounds<usize>>(&self, range: R) -> &Self {
+        let (start, end) = slice_bounds(&range, self.chars().count());
+        let offset = |index| {
+            self.char_indices()
+                .nth(index)
+                .map_or(self.len(), |(i, _)| i)
+        };
+        &self[offset(start)..offset(end)]
+    }
+}
+
+// Returns the start and end of `range`, clamped to `0..=len`.
+fn slice_bounds<R: RangeBounds<usize>>(range: &R, len: usize) -> (usize, usize) {
+    let end = match range.end_bound() {
+        Bound::Included(&end) => end.saturating_add(1),
+        Bound::Excluded(&end) => end,
+        Bound::Unbounded => len,
+    }
+    .min(len);
+    let start = match range.start_bound() {
+        Bound::Included(&start) => start,
+        Bound::Excluded(&start) => start.saturating_add(1),
+        Bound::Unbounded => 0,
+    }
+    .min(end);
+    (start, end)
+}
+
+/// A tuple or a sequence whose items can be spread into the arguments of a macro call, like
+/// `{% call menu(*items) %}`, where the `I`th argument is the `I`th item.
+pub trait SpreadItem<const I: usize> {
+    type Item: ?Sized;
+
+    fn spread_item(&self) -> Option<&Self::Item>;
+}
+
+impl<T, const I: usize> SpreadItem<I> for [T] {
+    type Item = T;
+
+    #[inline]
+    fn spread_item(&self) -> Option<&T> {
+        self.get(I)
+    }
+}
+
+impl<T, const N: usize, const I: usize> SpreadItem<I> for [T; N] {
+    type Item = T;
+
+    #[inline]
+    fn spread_item(&self) -> Option<&T> {
+        self.get(I)
+    }
+}
+
+impl<T, const I: usize> SpreadItem<I> for Vec<T> {
+    type Item = T;
+
+    #[inline]
+    fn spread_item(&self) -> Option<&T> {
+        self.get(I)
+    }
+}
+
+impl<T: SpreadItem<I> + ?Sized, const I: usize> SpreadItem<I> for &T {
+    type Item = T::Item;
+
+    #[inline]
+    fn spread_item(&self) -> Option<&T::Item> {
+        T::spread_item(self)
+    }
+}
+
+macro_rules! impl_spread_item {
+    ($tuple:tt $($index:tt $item:ident)*) => {
+        $(impl_spread_item!(@impl $tuple $index $item);)*
+    };
+    (@impl ($($ty:ident),*) $index:tt $item:ident) => {
+        impl<$($ty),*> SpreadItem<$index> for ($($ty,)*) {
+            type Item = $item;
+
+            #[inline]
+            fn spread_item(&self) -> Option<&$item> {
+                Some(&self.$index)
+            }
+        }
+    };
+}
+
+impl_spread_item!((A) 0 A);
+impl_spread_item!((A, B) 0 A 1 B);
+impl_spread_item!((A, B, C) 0 A 1 B 2 C);
+impl_spread_item!((A, B, C, D) 0 A 1 B 2 C 3 D);
+impl_spread_item!((A, B, C, D, E) 0 A 1 B 2 C 3 D 4 E);
+impl_spread_item!((A, B, C, D, E, F) 0 A 1 B 2 C 3 D 4 E 5 F);
+impl_spread_item!((A, B, C, D, E, F, G) 0 A 1 B 2 C 3 D 4 E 5 F 6 G);
+impl_spread_item!((A, B, C, D, E, F, G, H) 0 A 1 B 2 C 3 D 4 E 5 F 6 G 7 H);
+
+/// Returns the `I`th item of a value spread into the arguments of a macro call, or an error if
+/// it has too few items.
+#[inline]
+pub fn spread_item<const I: usize, T: SpreadItem<I> + ?Sized>(value: &T) -> Result<&T::Item> {
+    value.spread_item().ok_or_else(|| {
+        Error::Custom(format!("too few items to spread into macro argument #{}", I + 1).into())
+    })
+}
+
+/// Returns `value`, so the compiler checks it has the type hinted in the template.
+#[inline]
+pub fn type_hint<T: ?Sized>(value: &T) -> &T {
+    value
+}
+
+#[cfg(feature = "serde")]
+#[doc(hidden)]
+pub use serde;
+
+/// Serializes the rendered output of `template` as a string.
+///
+/// Used by templates generated with `serialize = "string"`.
+#[cfg(feature = "serde")]
+pub fn serialize_string<T, S>(template: &T, serializer: S) -> std::result::Result<S::Ok, S::Error>
+where
+    T: Template + ?Sized,
+    S: serde::Serializer,
+{
+    let html = template.render().map_err(serde::ser::Error::custom)?;
+    serializer.serialize_str(&html)
+}
+
+/// Serializes the rendered output of `template` as an object with the fields `html` and
+/// `mime_type`.
+///
+/// Used by templates generated with `serialize = "object"`.
+#[cfg(feature = "serde")]
+pub fn serialize_object<T, S>(template: &T, serializer: S) -> std::result::Result<S::Ok, S::Error>
+where
+    T: Template + ?Sized,
+    S: serde::Serializer,
+{
+    use serde::ser::SerializeStruct;
+
+    let html = template.render().map_err(serde::ser::Error::custom)?;
+    let mut object = serializer.serialize_struct("Template", 2)?;
+    object.serialize_field("html", &html)?;
+    object.serialize_field("mime_type", T::MIME_TYPE)?;
+    object.end()
+}
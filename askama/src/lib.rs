@@ -49,8 +49,8 @@
 //!   web framework integrations use it to determine the content type.
 //!   Cannot be used together with `path`.
 //! * `print` (as `print = "code"`): enable debugging by printing nothing
-//!   (`none`), the parsed syntax tree (`ast`), the generated code (`code`)
-//!   or `all` for both. The requested data will be printed to stdout at
+//!   (`none`), the parsed syntax tree (`ast`), the generated code (`code`),
+//!   the whitespace control decisions (`whitespace`) or `all` of them. The requested data will be printed to stdout at
 //!   compile time.
 //! * `escape` (as `escape = "none"`): override the template's extension used for
 //!   the purpose of determining the escaper for this template. See the section
@@ -67,13 +67,18 @@ pub mod filters;
 pub mod helpers;
 
 use std::fmt;
+use std::rc::Rc;
+use std::sync::Arc;
 
 pub use askama_derive::Template;
-pub use askama_escape::{Html, MarkupDisplay, Text};
+pub use askama_escape::{EscapeError, Escaper, Html, MarkupDisplay, Text};
 
 #[doc(hidden)]
 pub use crate as shared;
-pub use crate::error::{Error, Result};
+pub use crate::error::{Error, ErrorContext, Result};
+#[cfg(feature = "image-dims")]
+pub use crate::helpers::ImageDims;
+pub use crate::helpers::Slot;
 
 /// Main `Template` trait; implementations are generally derived
 ///
@@ -96,14 +101,33 @@ pub trait Template: fmt::Display {
         writer.write_fmt(format_args!("{self}"))
     }
 
+    /// Renders the template to the given `buf` byte buffer, e.g. a [`bytes::BytesMut`]
+    ///
+    /// Fails with a formatting error if `buf` cannot grow to hold the rendered template.
+    #[cfg(feature = "bytes")]
+    #[inline]
+    fn write_into_buf(&self, buf: &mut (impl bytes::BufMut + ?Sized)) -> Result<()> {
+        self.render_into(&mut helpers::BufMutWriter(buf))
+    }
+
+    /// Helper method which allocates a new [`bytes::BytesMut`] and renders into it
+    ///
+    /// The result can be frozen into [`bytes::Bytes`] to be used as a response body.
+    #[cfg(feature = "bytes")]
+    fn render_bytes(&self) -> Result<bytes::BytesMut> {
+        let mut buf = bytes::BytesMut::with_capacity(Self::SIZE_HINT);
+        self.write_into_buf(&mut buf)?;
+        Ok(buf)
+    }
+
     /// The template's extension, if provided
     const EXTENSION: Option<&'static str>;
 
     /// Provides a rough estimate of the expanded length of the rendered template. Larger
     /// values result in higher memory usage but fewer reallocations. Smaller values result in the
-    /// opposite. This value only affects [`render`]. It does not take effect when calling
-    /// [`render_into`], [`write_into`], the [`fmt::Display`] implementation, or the blanket
-    /// [`ToString::to_string`] implementation.
+    /// opposite. This value only affects [`render`] and `render_bytes`. It does not take effect
+    /// when calling [`render_into`], [`write_into`], the [`fmt::Display`] implementation, or the
+    /// blanket [`ToString::to_string`] implementation.
     ///
     /// [`render`]: Template::render
     /// [`render_into`]: Template::render_into
@@ -137,6 +161,218 @@ impl<T: Template + ?Sized> Template for &T {
     const MIME_TYPE: &'static str = T::MIME_TYPE;
 }
 
+/// Renders one of several variants of a template, selected at runtime by a key
+///
+/// Implemented by `#[derive(Template)]` when the struct has `#[template_variant]` attributes,
+/// each mapping a key to the path of a template rendering the same struct:
+///
+/// ```ignore
+/// #[derive(Template)]
+/// #[template(path = "light/page.html")]
+/// #[template_variant(key = "Theme::Dark", path = "dark/page.html")]
+/// struct Page<'a> { title: &'a str }
+///
+/// let html = page.render_variant(&theme)?;
+/// ```
+///
+/// Keys without a variant render the main template.
+pub trait TemplateVariants<K: ?Sized>: Template {
+    /// Renders the variant selected by `key` to the given `writer` fmt buffer
+    fn render_variant_into(
+        &self,
+        key: &K,
+        writer: &mut (impl std::fmt::Write + ?Sized),
+    ) -> Result<()>;
+
+    /// Provides a rough estimate of the expanded length of the variant selected by `key`
+    fn variant_size_hint(key: &K) -> usize {
+        let _ = key;
+        Self::SIZE_HINT
+    }
+
+    /// Helper method which allocates a new `String` and renders the variant selected by `key`
+    /// into it
+    fn render_variant(&self, key: &K) -> Result<String> {
+        let mut buf = String::new();
+        let _ = buf.try_reserve(Self::variant_size_hint(key));
+        self.render_variant_into(key, &mut buf)?;
+        Ok(buf)
+    }
+}
+
+/// Dynamic values available to all templates as `globals.name`
+///
+/// Set `globals_type` in the `[general]` section of `askama.toml` to the path of a type
+/// implementing this trait. In templates, `globals.name` then refers to the field or method
+/// `name` of the value returned by [`Globals::globals()`], unless a constant `name` is declared
+/// in the `[globals]` table of the configuration file:
+///
+/// ```ignore
+/// pub struct Site {
+///     pub build_hash: String,
+/// }
+///
+/// impl askama::Globals for Site {
+///     fn globals() -> &'static Self {
+///         static SITE: OnceLock<Site> = OnceLock::new();
+///         SITE.get_or_init(|| Site { build_hash: env::var("BUILD_HASH").unwrap_or_default() })
+///     }
+/// }
+/// ```
+pub trait Globals: 'static {
+    /// Returns the values
+    fn globals() -> &'static Self;
+}
+
+macro_rules! impl_for_smart_pointer {
+    ($($ty:ident),+ $(,)?) => { $(
+        impl<T: Template + ?Sized> Template for $ty<T> {
+            #[inline]
+            fn render_into(&self, writer: &mut (impl std::fmt::Write + ?Sized)) -> Result<()> {
+                T::render_into(self, writer)
+            }
+
+            #[inline]
+            fn render(&self) -> Result<String> {
+                T::render(self)
+            }
+
+            #[inline]
+            fn write_into(
+                &self,
+                writer: &mut (impl std::io::Write + ?Sized),
+            ) -> std::io::Result<()> {
+                T::write_into(self, writer)
+            }
+
+            const EXTENSION: Option<&'static str> = T::EXTENSION;
+
+            const SIZE_HINT: usize = T::SIZE_HINT;
+
+            const MIME_TYPE: &'static str = T::MIME_TYPE;
+        }
+    )+ };
+}
+
+impl_for_smart_pointer!(Box, Rc, Arc);
+
+/// One of two templates, e.g. to return either a full page or a fragment from the same handler
+///
+/// [`EXTENSION`] and [`MIME_TYPE`] are taken from `L`. Both templates must have the same
+/// [`MIME_TYPE`], which is checked at compile time when the `Either` is rendered.
+/// [`SIZE_HINT`] is the larger of both size hints.
+///
+/// ```compile_fail
+/// # use askama::{Either, Template};
+/// # macro_rules! template {
+/// #     ($ty:ident, $mime:literal) => {
+/// #         struct $ty;
+/// #         impl Template for $ty {
+/// #             fn render_into(&self, _: &mut (impl std::fmt::Write + ?Sized)) -> askama::Result<()> {
+/// #                 Ok(())
+/// #             }
+/// #             const EXTENSION: Option<&'static str> = None;
+/// #             const SIZE_HINT: usize = 0;
+/// #             const MIME_TYPE: &'static str = $mime;
+/// #         }
+/// #         impl std::fmt::Display for $ty {
+/// #             fn fmt(&self, _: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+/// #                 Ok(())
+/// #             }
+/// #         }
+/// #     };
+/// # }
+/// template!(Page, "text/html; charset=utf-8");
+/// template!(Text, "text/plain; charset=utf-8");
+///
+/// // error: both templates of an `Either` must have the same MIME type
+/// let page: Either<Page, Text> = Either::Right(Text);
+/// page.render().unwrap();
+/// ```
+///
+/// [`EXTENSION`]: Template::EXTENSION
+/// [`MIME_TYPE`]: Template::MIME_TYPE
+/// [`SIZE_HINT`]: Template::SIZE_HINT
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Either<L, R> {
+    Left(L),
+    Right(R),
+}
+
+impl<L: Template, R: Template> Either<L, R> {
+    const SAME_MIME_TYPE: () = assert!(
+        str_eq(L::MIME_TYPE, R::MIME_TYPE),
+        "both templates of an `Either` must have the same MIME type"
+    );
+}
+
+const fn str_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+impl<L: Template, R: Template> Template for Either<L, R> {
+    #[inline]
+    fn render_into(&self, writer: &mut (impl std::fmt::Write + ?Sized)) -> Result<()> {
+        let () = Self::SAME_MIME_TYPE;
+        match self {
+            Either::Left(t) => t.render_into(writer),
+            Either::Right(t) => t.render_into(writer),
+        }
+    }
+
+    #[inline]
+    fn render(&self) -> Result<String> {
+        let () = Self::SAME_MIME_TYPE;
+        match self {
+            Either::Left(t) => t.render(),
+            Either::Right(t) => t.render(),
+        }
+    }
+
+    #[inline]
+    fn write_into(&self, writer: &mut (impl std::io::Write + ?Sized)) -> std::io::Result<()> {
+        let () = Self::SAME_MIME_TYPE;
+        match self {
+            Either::Left(t) => t.write_into(writer),
+            Either::Right(t) => t.write_into(writer),
+        }
+    }
+
+    const EXTENSION: Option<&'static str> = L::EXTENSION;
+
+    const SIZE_HINT: usize = if L::SIZE_HINT > R::SIZE_HINT {
+        L::SIZE_HINT
+    } else {
+        R::SIZE_HINT
+    };
+
+    const MIME_TYPE: &'static str = {
+        let () = Self::SAME_MIME_TYPE;
+        L::MIME_TYPE
+    };
+}
+
+impl<L: fmt::Display, R: fmt::Display> fmt::Display for Either<L, R> {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Either::Left(t) => t.fmt(f),
+            Either::Right(t) => t.fmt(f),
+        }
+    }
+}
+
 /// Object-safe wrapper trait around [`Template`] implementers
 ///
 /// This trades reduced performance (mostly due to writing into `dyn Write`) for object safety.
@@ -238,4 +474,82 @@ mod tests {
         test.dyn_write_into(&mut vec).unwrap();
         assert_eq!(vec, vec![b't', b'e', b's', b't']);
     }
+
+    #[test]
+    fn wrappers() {
+        struct Test(&'static str);
+        impl Template for Test {
+            fn render_into(&self, writer: &mut (impl std::fmt::Write + ?Sized)) -> Result<()> {
+                Ok(writer.write_str(self.0)?)
+            }
+
+            const EXTENSION: Option<&'static str> = Some("txt");
+
+            const SIZE_HINT: usize = 4;
+
+            const MIME_TYPE: &'static str = "text/plain; charset=utf-8";
+        }
+
+        impl fmt::Display for Test {
+            #[inline]
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.render_into(f).map_err(|_| fmt::Error {})
+            }
+        }
+
+        fn render<T: Template>(t: &T) -> String {
+            t.render().unwrap()
+        }
+
+        assert_eq!(render(&&Test("ref")), "ref");
+        assert_eq!(render(&Box::new(Test("box"))), "box");
+        assert_eq!(render(&Rc::new(Test("rc"))), "rc");
+        assert_eq!(render(&Arc::new(Test("arc"))), "arc");
+
+        let left: Either<Test, Box<Test>> = Either::Left(Test("left"));
+        let right: Either<Test, Box<Test>> = Either::Right(Box::new(Test("right")));
+        assert_eq!(render(&left), "left");
+        assert_eq!(render(&right), "right");
+        assert_eq!(right.to_string(), "right");
+
+        let mut vec = Vec::new();
+        left.write_into(&mut vec).unwrap();
+        assert_eq!(vec, b"left");
+    }
+
+    #[cfg(feature = "bytes")]
+    #[test]
+    fn bytes() {
+        struct Test;
+        impl Template for Test {
+            fn render_into(&self, writer: &mut (impl std::fmt::Write + ?Sized)) -> Result<()> {
+                Ok(writer.write_str("hello")?)
+            }
+
+            const EXTENSION: Option<&'static str> = Some("txt");
+
+            const SIZE_HINT: usize = 2;
+
+            const MIME_TYPE: &'static str = "text/plain; charset=utf-8";
+        }
+
+        impl fmt::Display for Test {
+            #[inline]
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.render_into(f).map_err(|_| fmt::Error {})
+            }
+        }
+
+        assert_eq!(&Test.render_bytes().unwrap()[..], b"hello");
+
+        let mut buf = bytes::BytesMut::from("> ");
+        Test.write_into_buf(&mut buf).unwrap();
+        assert_eq!(&buf[..], b"> hello");
+
+        let mut array = [0; 4];
+        assert!(matches!(
+            Test.write_into_buf(&mut &mut array[..]),
+            Err(Error::Fmt(_))
+        ));
+    }
 }
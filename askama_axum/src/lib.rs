@@ -17,7 +17,7 @@ pub fn into_response<T: ?Sized + askama::Template>(tmpl: &T) -> Response {
 
 /// Try to render a [`Template`] into a [`Response`].
 pub fn try_into_response<T: ?Sized + askama::Template>(tmpl: &T) -> Result<Response, Error> {
-    let value = tmpl.render()?.into();
+    let value = tmpl.render_bytes()?.freeze().into();
     Response::builder()
         .header(
             http::header::CONTENT_TYPE,
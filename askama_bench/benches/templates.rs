@@ -0,0 +1,118 @@
+use askama::Template;
+use criterion::{criterion_group, criterion_main, Criterion};
+
+criterion_main!(benches);
+criterion_group!(benches, functions);
+
+fn functions(c: &mut Criterion) {
+    let loops = Loops::new(100);
+    c.bench_function("Loops", |b| b.iter(|| loops.render().unwrap()));
+
+    let page = Page::new(100);
+    c.bench_function("Inheritance", |b| b.iter(|| page.render().unwrap()));
+
+    let escaping = Escaping::new(100);
+    c.bench_function("Escaping", |b| b.iter(|| escaping.render().unwrap()));
+    c.bench_function("Escaping into Vec", |b| {
+        let mut buf = Vec::with_capacity(Escaping::SIZE_HINT);
+        b.iter(|| {
+            buf.clear();
+            escaping.write_into(&mut buf).unwrap();
+        })
+    });
+
+    let cards = Cards::new(100);
+    c.bench_function("Nested templates", |b| b.iter(|| cards.render().unwrap()));
+}
+
+/// Nested loops using the loop variables
+#[derive(Template)]
+#[template(path = "loops.html")]
+struct Loops {
+    rows: Vec<Vec<usize>>,
+}
+
+impl Loops {
+    fn new(size: usize) -> Self {
+        Self {
+            rows: (0..size).map(|_| (0..size).collect()).collect(),
+        }
+    }
+}
+
+/// A page four levels deep in a template hierarchy, calling `super()`
+#[derive(Template)]
+#[template(path = "page.html")]
+struct Page {
+    title: &'static str,
+    section: &'static str,
+    year: u16,
+    paragraphs: Vec<String>,
+}
+
+impl Page {
+    fn new(size: usize) -> Self {
+        Self {
+            title: "Benchmarks",
+            section: "blog",
+            year: 2024,
+            paragraphs: (0..size)
+                .map(|i| format!("Paragraph number {i}, without anything to escape."))
+                .collect(),
+        }
+    }
+}
+
+/// Text where most characters need to be escaped
+#[derive(Template)]
+#[template(path = "escaping.html")]
+struct Escaping {
+    comments: Vec<Comment>,
+}
+
+struct Comment {
+    author: String,
+    text: String,
+}
+
+impl Escaping {
+    fn new(size: usize) -> Self {
+        Self {
+            comments: (0..size)
+                .map(|i| Comment {
+                    author: format!("\"user\" <{i}@example.com>"),
+                    text: r#"<script>alert('1 & 2 > "3"');</script>"#.repeat(4),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Nested templates rendered in a loop
+#[derive(Template)]
+#[template(path = "cards.html")]
+struct Cards {
+    cards: Vec<Card>,
+}
+
+#[derive(Template)]
+#[template(path = "card.html")]
+struct Card {
+    id: usize,
+    title: String,
+    text: String,
+}
+
+impl Cards {
+    fn new(size: usize) -> Self {
+        Self {
+            cards: (0..size)
+                .map(|id| Card {
+                    id,
+                    title: format!("Card {id}"),
+                    text: "Some text & a few <characters> to escape.".to_owned(),
+                })
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,39 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::process::Command;
+use std::{env, fs, io};
+
+// Identifies this build of the derive macro in the keys of the cache of generated code, so the
+// code generated by another compiler, or by a changed copy of this crate (e.g. a path or git
+// dependency) with the same version, is not reused. Without `rerun-if-changed`, cargo runs this
+// script again whenever a file of the package changes.
+fn main() {
+    let mut hasher = DefaultHasher::new();
+    let rustc = env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
+    if let Ok(output) = Command::new(rustc).arg("-vV").output() {
+        output.stdout.hash(&mut hasher);
+    }
+    if let Err(err) = hash_dir(Path::new("src"), &mut hasher) {
+        panic!("cannot read the sources of askama_derive: {err}");
+    }
+    println!(
+        "cargo:rustc-env=ASKAMA_DERIVE_BUILD={:016x}",
+        hasher.finish()
+    );
+}
+
+fn hash_dir(dir: &Path, hasher: &mut DefaultHasher) -> io::Result<()> {
+    let mut entries = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.path());
+    for entry in entries {
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            hash_dir(&path, hasher)?;
+        } else {
+            path.hash(hasher);
+            fs::read(&path)?.hash(hasher);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,158 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::{env, process};
+
+use quote::ToTokens;
+
+use crate::config::{Config, TemplateLookup};
+
+/// An entry of the cache of generated code, enabled with `cache_dir` in the configuration.
+///
+/// An entry is identified by everything the generated code depends on, except for the template
+/// files: the derive input, the configuration file and the version, build and features of this
+/// crate. The build is identified by the compiler version and the sources of this crate, see
+/// `build.rs`.
+/// It stores a hash of each file the code depends on, i.e. the files it passes to `include_bytes!()`
+/// (the templates and e.g. the images read by `image_dims()`), and the templates found by each
+/// lookup of a template name, so it is only reused while none of the files changed and the
+/// lookups still find the same templates, e.g. a template added to a directory searched first
+/// doesn't shadow the one found before.
+pub(crate) struct CacheEntry {
+    path: PathBuf,
+}
+
+impl CacheEntry {
+    pub(crate) fn new(dir: &Path, ast: &syn::DeriveInput, config: &str) -> Self {
+        let mut hasher = DefaultHasher::new();
+        env!("CARGO_PKG_VERSION").hash(&mut hasher);
+        env!("ASKAMA_DERIVE_BUILD").hash(&mut hasher);
+        FEATURES.hash(&mut hasher);
+        env::var("CARGO_MANIFEST_DIR").ok().hash(&mut hasher);
+        ast.to_token_stream().to_string().hash(&mut hasher);
+        config.hash(&mut hasher);
+        Self {
+            path: dir.join(format!("{:016x}.rs", hasher.finish())),
+        }
+    }
+
+    /// Returns the cached code, unless there is none, a file it was generated from changed since
+    /// or a template lookup of `config` finds another template.
+    pub(crate) fn get(&self, config: &Config<'_>) -> Option<String> {
+        // The number of files, a line with the hash and path of each file, the number of lookups,
+        // three lines with the name, the path looked up from and the path found of each lookup
+        // (empty if there is none), and the code
+        let entry = fs::read_to_string(&self.path).ok()?;
+        let mut lines = entry.split_inclusive('\n');
+        let mut next_line = || lines.next()?.strip_suffix('\n');
+        for _ in 0..next_line()?.parse::<usize>().ok()? {
+            let (hash, path) = next_line()?.split_once(' ')?;
+            let data = config.files.read(Path::new(path)).ok()?;
+            if hash != format!("{:016x}", hash_data(&data)) {
+                return None;
+            }
+        }
+        for _ in 0..next_line()?.parse::<usize>().ok()? {
+            let name = next_line()?;
+            let start_at = Some(next_line()?).filter(|path| !path.is_empty());
+            let path = Some(next_line()?).filter(|path| !path.is_empty());
+            let found = config.find_template(name, start_at.map(Path::new)).ok();
+            if found.as_deref() != path.map(Path::new) {
+                return None;
+            }
+        }
+        Some(lines.collect())
+    }
+
+    /// Stores `code`, generated with the template lookups of `config`.
+    ///
+    /// The cache is only an optimization, so failing to write it is not an error.
+    pub(crate) fn store(&self, code: &str, config: &Config<'_>) {
+        let Some(paths) = included_files(code) else {
+            return;
+        };
+        let mut entry = format!("{}\n", paths.len());
+        for path in paths {
+            let Ok(data) = config.files.read(Path::new(&path)) else {
+                return;
+            };
+            if path.contains('\n') {
+                return;
+            }
+            entry.push_str(&format!("{:016x} {path}\n", hash_data(&data)));
+        }
+        let lookups = config.lookups.borrow();
+        entry.push_str(&format!("{}\n", lookups.len()));
+        for TemplateLookup {
+            name,
+            start_at,
+            found,
+        } in lookups.iter()
+        {
+            let (Some(start_at), Some(found)) = (
+                start_at.as_deref().map_or(Some(""), Path::to_str),
+                found.as_deref().map_or(Some(""), Path::to_str),
+            ) else {
+                return;
+            };
+            if [name.as_str(), start_at, found]
+                .iter()
+                .any(|s| s.contains('\n'))
+            {
+                return;
+            }
+            entry.push_str(&format!("{name}\n{start_at}\n{found}\n"));
+        }
+        entry.push_str(code);
+
+        // Write to a temporary file first, so an entry is never read while it's incomplete.
+        let tmp = self.path.with_extension(format!("{}.tmp", process::id()));
+        let stored = self
+            .path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(&tmp, entry))
+            .and_then(|_| fs::rename(&tmp, &self.path));
+        if stored.is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+    }
+}
+
+fn hash_data(data: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    data.hash(&mut hasher);
+    hasher.finish()
+}
+
+// Returns the paths passed to `include_bytes!()` in the generated `code`, which are all the files
+// the code depends on.
+fn included_files(code: &str) -> Option<Vec<String>> {
+    const INCLUDE: &str = "include_bytes ! (";
+
+    let mut paths = Vec::new();
+    for (start, _) in code.match_indices(INCLUDE) {
+        let rest = &code[start + INCLUDE.len()..];
+        let path = syn::parse_str::<syn::LitStr>(rest.split_once(')')?.0).ok()?;
+        if !paths.contains(&path.value()) {
+            paths.push(path.value());
+        }
+    }
+    Some(paths)
+}
+
+// The features changing the generated code.
+const FEATURES: &[bool] = &[
+    cfg!(feature = "base64"),
+    cfg!(feature = "humansize"),
+    cfg!(feature = "image-dims"),
+    cfg!(feature = "urlencode"),
+    cfg!(feature = "serde-json"),
+    cfg!(feature = "num-traits"),
+    cfg!(feature = "with-serde"),
+    cfg!(feature = "with-actix-web"),
+    cfg!(feature = "with-axum"),
+    cfg!(feature = "with-rocket"),
+    cfg!(feature = "with-warp"),
+];
@@ -1,7 +1,9 @@
+use std::cell::RefCell;
 use std::collections::{BTreeMap, HashSet};
+use std::env;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
-use std::{env, fs};
+use std::str::FromStr;
 
 #[cfg(feature = "serde")]
 use serde::Deserialize;
@@ -9,21 +11,67 @@ use serde::Deserialize;
 use crate::{CompileError, CRATE};
 use parser::expr::TWO_PLUS_CHAR_OPS;
 use parser::node::Whitespace;
-use parser::Syntax;
+use parser::{FileSource, FileSystem, Syntax};
 
 #[derive(Debug)]
 pub(crate) struct Config<'a> {
     pub(crate) dirs: Vec<PathBuf>,
     pub(crate) syntaxes: BTreeMap<String, Syntax<'a>>,
     pub(crate) default_syntax: &'a str,
+    // Directories whose templates are parsed with the given syntax instead of the default one
+    pub(crate) syntax_dirs: Vec<(PathBuf, &'a str)>,
     pub(crate) escapers: Vec<(HashSet<String>, String)>,
     pub(crate) whitespace: WhitespaceHandling,
+    pub(crate) codegen: Codegen,
+    // Whether the indentation of block bodies is removed from their literals
+    pub(crate) dedent: bool,
+    // Whether the first line break after a block tag is removed
+    pub(crate) trim_blocks: bool,
+    // Whether the indentation before a block tag starting a line is removed
+    pub(crate) lstrip_blocks: bool,
+    // How line endings in template files are handled
+    pub(crate) newlines: Newlines,
+    // Constants available to all templates as `globals.name`, as Rust literals
+    pub(crate) globals: BTreeMap<String, String>,
+    // Type implementing `askama::Globals`, providing all other `globals.name` values
+    pub(crate) globals_type: Option<&'a str>,
+    // Directory where the generated code is cached, if any
+    pub(crate) cache_dir: Option<PathBuf>,
+    // How many calls of a recursive macro can be nested when rendering
+    pub(crate) macro_recursion_limit: usize,
+    // How many iterations a `while` loop can run when rendering, if limited
+    pub(crate) while_iteration_limit: Option<usize>,
+    // Whether rendering errors are wrapped in `Error::Context`
+    pub(crate) error_context: bool,
+    // The lookups made by `find_template()`, so the cache can check they would still find the
+    // same templates
+    pub(crate) lookups: RefCell<Vec<TemplateLookup>>,
+    // The files templates are read from
+    pub(crate) files: Box<dyn FileSource>,
+}
+
+/// A lookup of the template `name` by [`Config::find_template()`], from the template at
+/// `start_at`, which found the template at `found`.
+#[derive(Debug, PartialEq)]
+pub(crate) struct TemplateLookup {
+    pub(crate) name: String,
+    pub(crate) start_at: Option<PathBuf>,
+    pub(crate) found: Option<PathBuf>,
 }
 
 impl<'a> Config<'a> {
     pub(crate) fn new(
         s: &'a str,
         template_whitespace: Option<&str>,
+    ) -> std::result::Result<Config<'a>, CompileError> {
+        Self::with_files(s, template_whitespace, Box::new(FileSystem))
+    }
+
+    /// Like [`Config::new()`], but finding and reading the templates in `files`.
+    pub(crate) fn with_files(
+        s: &'a str,
+        template_whitespace: Option<&str>,
+        files: Box<dyn FileSource>,
     ) -> std::result::Result<Config<'a>, CompileError> {
         let root = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
         let default_dirs = vec![root.join("templates")];
@@ -37,36 +85,45 @@ impl<'a> Config<'a> {
             RawConfig::from_toml_str(s)?
         };
 
-        let (dirs, default_syntax, mut whitespace) = match raw.general {
-            Some(General {
-                dirs,
-                default_syntax,
-                whitespace,
-            }) => (
-                dirs.map_or(default_dirs, |v| {
-                    v.into_iter().map(|dir| root.join(dir)).collect()
-                }),
-                default_syntax.unwrap_or(DEFAULT_SYNTAX_NAME),
-                whitespace,
-            ),
-            None => (
-                default_dirs,
-                DEFAULT_SYNTAX_NAME,
-                WhitespaceHandling::default(),
-            ),
-        };
+        let General {
+            dirs,
+            default_syntax,
+            mut whitespace,
+            codegen,
+            dedent,
+            trim_blocks,
+            lstrip_blocks,
+            newlines,
+            globals_type,
+            cache_dir,
+            macro_recursion_limit,
+            while_iteration_limit,
+            error_context,
+        } = raw.general.unwrap_or_default();
+        let dirs = dirs.map_or(default_dirs, |v| {
+            v.into_iter().map(|dir| root.join(dir)).collect()
+        });
+        let default_syntax = default_syntax.unwrap_or(DEFAULT_SYNTAX_NAME);
+        let cache_dir = cache_dir.map(|dir| root.join(dir));
         if let Some(template_whitespace) = template_whitespace {
             whitespace = match template_whitespace {
                 "suppress" => WhitespaceHandling::Suppress,
                 "minimize" => WhitespaceHandling::Minimize,
+                "linebreak" => WhitespaceHandling::Linebreak,
                 "preserve" => WhitespaceHandling::Preserve,
                 s => return Err(format!("invalid value for `whitespace`: \"{s}\"").into()),
             };
         }
 
+        let mut syntax_dirs = Vec::new();
         if let Some(raw_syntaxes) = raw.syntax {
-            for raw_s in raw_syntaxes {
+            for mut raw_s in raw_syntaxes {
                 let name = raw_s.name;
+                for syntax_dir in raw_s.dirs.take().unwrap_or_default() {
+                    for dir in &dirs {
+                        syntax_dirs.push((dir.join(syntax_dir), name));
+                    }
+                }
 
                 if syntaxes
                     .insert(name.to_string(), raw_s.try_into()?)
@@ -98,30 +155,77 @@ impl<'a> Config<'a> {
             escapers.push((str_set(extensions), format!("{CRATE}{path}")));
         }
 
+        let mut globals = BTreeMap::new();
+        for (name, value) in raw.globals.unwrap_or_default() {
+            globals.insert(name.to_string(), value.to_literal(name)?);
+        }
+
         Ok(Config {
             dirs,
             syntaxes,
             default_syntax,
+            syntax_dirs,
             escapers,
             whitespace,
+            codegen,
+            dedent,
+            trim_blocks,
+            lstrip_blocks,
+            newlines,
+            globals,
+            globals_type,
+            cache_dir,
+            macro_recursion_limit: macro_recursion_limit.unwrap_or(DEFAULT_MACRO_RECURSION_LIMIT),
+            while_iteration_limit,
+            error_context,
+            lookups: RefCell::default(),
+            files,
         })
     }
 
+    /// Returns the syntax configured for the directory containing the template at `path`, if
+    /// any. If several configured directories contain it, the innermost one is used.
+    pub(crate) fn find_syntax(&self, path: &Path) -> Option<&Syntax<'a>> {
+        self.syntax_dirs
+            .iter()
+            .filter(|(dir, _)| path.starts_with(dir))
+            .max_by_key(|(dir, _)| dir.components().count())
+            .map(|(_, name)| &self.syntaxes[*name])
+    }
+
     pub(crate) fn find_template(
         &self,
         path: &str,
         start_at: Option<&Path>,
+    ) -> std::result::Result<Rc<Path>, CompileError> {
+        let found = self.lookup_template(path, start_at);
+        let lookup = TemplateLookup {
+            name: path.to_owned(),
+            start_at: start_at.map(Path::to_owned),
+            found: found.as_ref().ok().map(|found| found.to_path_buf()),
+        };
+        let mut lookups = self.lookups.borrow_mut();
+        if !lookups.contains(&lookup) {
+            lookups.push(lookup);
+        }
+        found
+    }
+
+    fn lookup_template(
+        &self,
+        path: &str,
+        start_at: Option<&Path>,
     ) -> std::result::Result<Rc<Path>, CompileError> {
         if let Some(root) = start_at {
             let relative = root.with_file_name(path);
-            if relative.exists() {
+            if self.files.exists(&relative) {
                 return Ok(relative.into());
             }
         }
 
         for dir in &self.dirs {
             let rooted = dir.join(path);
-            if rooted.exists() {
+            if self.files.exists(&rooted) {
                 return Ok(rooted.into());
             }
         }
@@ -146,6 +250,7 @@ impl<'a> TryInto<Syntax<'a>> for RawSyntax<'a> {
             expr_end: self.expr_end.unwrap_or(default.expr_end),
             comment_start: self.comment_start.unwrap_or(default.comment_start),
             comment_end: self.comment_end.unwrap_or(default.comment_end),
+            line_statement_prefix: self.line_statement_prefix,
         };
 
         for (s, kind) in [
@@ -181,6 +286,17 @@ impl<'a> TryInto<Syntax<'a>> for RawSyntax<'a> {
             }
         }
 
+        if let Some(prefix) = syntax.line_statement_prefix {
+            if prefix.is_empty() {
+                return Err("line_statement_prefix may not be empty".into());
+            } else if prefix.chars().any(|c| c.is_whitespace()) {
+                return Err(format!(
+                    "line_statement_prefix may not contain whitespace: {prefix:?}"
+                )
+                .into());
+            }
+        }
+
         Ok(syntax)
     }
 }
@@ -192,6 +308,8 @@ struct RawConfig<'a> {
     general: Option<General<'a>>,
     syntax: Option<Vec<RawSyntax<'a>>>,
     escaper: Option<Vec<RawEscaper<'a>>>,
+    #[cfg_attr(feature = "serde", serde(borrow))]
+    globals: Option<BTreeMap<&'a str, RawGlobal>>,
 }
 
 impl RawConfig<'_> {
@@ -220,6 +338,9 @@ pub(crate) enum WhitespaceHandling {
     /// If there is a newline character, the preserved character in the trimmed characters, it will
     /// the one preserved.
     Minimize,
+    /// It'll replace the whitespace characters before and after the jinja blocks with a single
+    /// newline character if they contain one, and remove them otherwise.
+    Linebreak,
 }
 
 impl From<WhitespaceHandling> for Whitespace {
@@ -228,28 +349,113 @@ impl From<WhitespaceHandling> for Whitespace {
             WhitespaceHandling::Suppress => Whitespace::Suppress,
             WhitespaceHandling::Preserve => Whitespace::Preserve,
             WhitespaceHandling::Minimize => Whitespace::Minimize,
+            WhitespaceHandling::Linebreak => Whitespace::Linebreak,
+        }
+    }
+}
+
+/// How the code rendering the template is generated.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
+#[cfg_attr(feature = "serde", serde(field_identifier, rename_all = "lowercase"))]
+pub(crate) enum Codegen {
+    /// The default behaviour. The rendering code is generic over the writer, so it's generated
+    /// again for every type of writer the template is rendered into.
+    #[default]
+    Speed,
+    /// The rendering code always writes into a `&mut dyn Write`, so it's generated only once,
+    /// whatever the type of the writer. Its literals are written from a table, and both literals
+    /// and expressions are written by runtime helpers shared between all templates.
+    Size,
+}
+
+/// How line endings in template files are handled.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
+#[cfg_attr(feature = "serde", serde(field_identifier, rename_all = "lowercase"))]
+pub(crate) enum Newlines {
+    /// The default behaviour. Line endings are kept as they are in the template files.
+    #[default]
+    Preserve,
+    /// `\r\n` line endings are replaced by `\n`, e.g. for templates edited on Windows.
+    Lf,
+}
+
+impl FromStr for Codegen {
+    type Err = CompileError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "speed" => Ok(Self::Speed),
+            "size" => Ok(Self::Size),
+            s => Err(format!("invalid value for `codegen`: \"{s}\"").into()),
         }
     }
 }
 
 #[cfg_attr(feature = "serde", derive(Deserialize))]
+#[derive(Default)]
 struct General<'a> {
     #[cfg_attr(feature = "serde", serde(borrow))]
     dirs: Option<Vec<&'a str>>,
     default_syntax: Option<&'a str>,
     #[cfg_attr(feature = "serde", serde(default))]
     whitespace: WhitespaceHandling,
+    #[cfg_attr(feature = "serde", serde(default))]
+    codegen: Codegen,
+    #[cfg_attr(feature = "serde", serde(default))]
+    dedent: bool,
+    #[cfg_attr(feature = "serde", serde(default))]
+    trim_blocks: bool,
+    #[cfg_attr(feature = "serde", serde(default))]
+    lstrip_blocks: bool,
+    #[cfg_attr(feature = "serde", serde(default))]
+    newlines: Newlines,
+    globals_type: Option<&'a str>,
+    cache_dir: Option<&'a str>,
+    macro_recursion_limit: Option<usize>,
+    while_iteration_limit: Option<usize>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    error_context: bool,
 }
 
 #[cfg_attr(feature = "serde", derive(Deserialize))]
 struct RawSyntax<'a> {
     name: &'a str,
+    #[cfg_attr(feature = "serde", serde(borrow))]
+    dirs: Option<Vec<&'a str>>,
     block_start: Option<&'a str>,
     block_end: Option<&'a str>,
     expr_start: Option<&'a str>,
     expr_end: Option<&'a str>,
     comment_start: Option<&'a str>,
     comment_end: Option<&'a str>,
+    line_statement_prefix: Option<&'a str>,
+}
+
+/// A constant declared in the `[globals]` table.
+#[cfg_attr(feature = "serde", derive(Deserialize))]
+#[cfg_attr(feature = "serde", serde(untagged))]
+#[cfg_attr(not(feature = "serde"), allow(dead_code))]
+enum RawGlobal {
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+    String(String),
+}
+
+impl RawGlobal {
+    fn to_literal(&self, name: &str) -> Result<String, CompileError> {
+        Ok(match self {
+            Self::Bool(b) => b.to_string(),
+            Self::Integer(i) => proc_macro2::Literal::i64_unsuffixed(*i).to_string(),
+            Self::Float(f) if f.is_finite() => proc_macro2::Literal::f64_unsuffixed(*f).to_string(),
+            Self::Float(_) => {
+                return Err(format!("global `{name}` must be a finite number").into());
+            }
+            Self::String(s) => proc_macro2::Literal::string(s).to_string(),
+        })
+    }
 }
 
 #[cfg_attr(feature = "serde", derive(Deserialize))]
@@ -260,6 +466,7 @@ struct RawEscaper<'a> {
 
 pub(crate) fn read_config_file(
     config_path: Option<&str>,
+    files: &dyn FileSource,
 ) -> std::result::Result<String, CompileError> {
     let root = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
     let filename = match config_path {
@@ -267,8 +474,9 @@ pub(crate) fn read_config_file(
         None => root.join(CONFIG_FILE_NAME),
     };
 
-    if filename.exists() {
-        fs::read_to_string(&filename)
+    if files.exists(&filename) {
+        files
+            .read_to_string(&filename)
             .map_err(|_| format!("unable to read {:?}", filename.to_str().unwrap()).into())
     } else if config_path.is_some() {
         Err(format!("`{}` does not exist", root.display()).into())
@@ -284,16 +492,30 @@ where
     vals.iter().map(|s| s.to_string()).collect()
 }
 
+/// Reads the template file at `tpl_path` from `files`, without its byte order mark and final
+/// newline.
 #[allow(clippy::match_wild_err_arm)]
-pub(crate) fn get_template_source(tpl_path: &Path) -> std::result::Result<String, CompileError> {
-    match fs::read_to_string(tpl_path) {
+pub(crate) fn get_template_source(
+    files: &dyn FileSource,
+    tpl_path: &Path,
+    newlines: Newlines,
+) -> std::result::Result<String, CompileError> {
+    match files.read_to_string(tpl_path) {
         Err(_) => Err(format!(
             "unable to open template file '{}'",
             tpl_path.to_str().unwrap()
         )
         .into()),
         Ok(mut source) => {
-            if source.ends_with('\n') {
+            if source.starts_with('\u{feff}') {
+                source.drain(..'\u{feff}'.len_utf8());
+            }
+            if newlines == Newlines::Lf && source.contains("\r\n") {
+                source = source.replace("\r\n", "\n");
+            }
+            if source.ends_with("\r\n") {
+                source.truncate(source.len() - 2);
+            } else if source.ends_with('\n') {
                 let _ = source.pop();
             }
             Ok(source)
@@ -303,6 +525,7 @@ pub(crate) fn get_template_source(tpl_path: &Path) -> std::result::Result<String
 
 static CONFIG_FILE_NAME: &str = "askama.toml";
 static DEFAULT_SYNTAX_NAME: &str = "default";
+static DEFAULT_MACRO_RECURSION_LIMIT: usize = 64;
 static DEFAULT_ESCAPERS: &[(&[&str], &str)] = &[
     (&["html", "htm", "svg", "xml"], "::Html"),
     (&["md", "none", "txt", "typ", "yml", ""], "::Text"),
@@ -315,13 +538,56 @@ mod tests {
     use std::path::{Path, PathBuf};
 
     use super::*;
+    use crate::tests::in_memory_files;
 
     #[test]
     fn get_source() {
         let path = Config::new("", None)
             .and_then(|config| config.find_template("b.html", None))
             .unwrap();
-        assert_eq!(get_template_source(&path).unwrap(), "bar");
+        assert_eq!(
+            get_template_source(&FileSystem, &path, Newlines::Preserve).unwrap(),
+            "bar"
+        );
+    }
+
+    #[test]
+    fn in_memory_templates() {
+        let files = in_memory_files(&[("templates/a.html", "in memory"), ("tpl/b.html", "tpl")]);
+        let config = Config::with_files("", None, files).unwrap();
+        assert!(config.find_template("b.html", None).is_err());
+        let path = config.find_template("a.html", None).unwrap();
+        assert_eq!(
+            get_template_source(&*config.files, &path, Newlines::Preserve).unwrap(),
+            "in memory"
+        );
+        // The real file is read from the file system.
+        assert_eq!(
+            get_template_source(&FileSystem, &path, Newlines::Preserve).unwrap(),
+            "foo"
+        );
+    }
+
+    #[test]
+    fn bom_and_crlf() {
+        let files = in_memory_files(&[
+            ("templates/bom.html", "\u{feff}<p>\r\n  a\r\n</p>\r\n"),
+            ("templates/lf.html", "b\n"),
+        ]);
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("templates/bom.html");
+        assert_eq!(
+            get_template_source(&*files, &path, Newlines::Preserve).unwrap(),
+            "<p>\r\n  a\r\n</p>"
+        );
+        assert_eq!(
+            get_template_source(&*files, &path, Newlines::Lf).unwrap(),
+            "<p>\n  a\n</p>"
+        );
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("templates/lf.html");
+        assert_eq!(
+            get_template_source(&*files, &path, Newlines::Lf).unwrap(),
+            "b"
+        );
     }
 
     #[test]
@@ -418,6 +684,44 @@ mod tests {
         assert_eq!(bar.comment_end, default_syntax.comment_end);
     }
 
+    #[cfg(feature = "config")]
+    #[test]
+    fn syntax_dirs() {
+        let raw_config = r#"
+        [general]
+        dirs = ["tpl"]
+
+        [[syntax]]
+        name = "foo"
+        block_start = "{<"
+        dirs = ["legacy"]
+
+        [[syntax]]
+        name = "bar"
+        expr_start = "{!"
+        dirs = ["legacy/bar"]
+        "#;
+
+        let root = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap()).join("tpl");
+        let config = Config::new(raw_config, None).unwrap();
+        assert_eq!(
+            config
+                .find_syntax(&root.join("legacy/a.html"))
+                .unwrap()
+                .block_start,
+            "{<"
+        );
+        assert_eq!(
+            config
+                .find_syntax(&root.join("legacy/bar/a.html"))
+                .unwrap()
+                .expr_start,
+            "{!"
+        );
+        assert!(config.find_syntax(&root.join("a.html")).is_none());
+        assert!(config.find_syntax(&root.join("legacy-a.html")).is_none());
+    }
+
     #[cfg(feature = "config")]
     #[test]
     fn add_syntax_two() {
@@ -516,6 +820,17 @@ mod tests {
             config.unwrap_err().msg,
             r#"a delimiter may not be the prefix of another delimiter: "{{" vs "{{$""#,
         );
+
+        let raw_config = r##"
+        [[syntax]]
+        name = "line_statement_ws"
+        line_statement_prefix = "# "
+        "##;
+        let config = Config::new(raw_config, None);
+        assert_eq!(
+            config.unwrap_err().msg,
+            r##"line_statement_prefix may not contain whitespace: "# ""##,
+        );
     }
 
     #[cfg(feature = "config")]
@@ -648,4 +963,171 @@ mod tests {
             panic!("Config::new should have return an error");
         }
     }
+
+    #[cfg(feature = "config")]
+    #[test]
+    fn test_error_context_parsing() {
+        let config = Config::new(r#""#, None).unwrap();
+        assert!(!config.error_context);
+
+        let config = Config::new(
+            r#"
+            [general]
+            error_context = true
+            "#,
+            None,
+        )
+        .unwrap();
+        assert!(config.error_context);
+    }
+
+    #[cfg(feature = "config")]
+    #[test]
+    fn test_dedent_parsing() {
+        let config = Config::new(r#""#, None).unwrap();
+        assert!(!config.dedent);
+
+        let config = Config::new(
+            r#"
+            [general]
+            dedent = true
+            "#,
+            None,
+        )
+        .unwrap();
+        assert!(config.dedent);
+    }
+
+    #[cfg(feature = "config")]
+    #[test]
+    fn test_trim_blocks_parsing() {
+        let config = Config::new(r#""#, None).unwrap();
+        assert!(!config.trim_blocks);
+        assert!(!config.lstrip_blocks);
+
+        let config = Config::new(
+            r#"
+            [general]
+            trim_blocks = true
+            lstrip_blocks = true
+            "#,
+            None,
+        )
+        .unwrap();
+        assert!(config.trim_blocks);
+        assert!(config.lstrip_blocks);
+    }
+
+    #[cfg(feature = "config")]
+    #[test]
+    fn test_macro_recursion_limit_parsing() {
+        let config = Config::new(r#""#, None).unwrap();
+        assert_eq!(config.macro_recursion_limit, 64);
+
+        let config = Config::new(
+            r#"
+            [general]
+            macro_recursion_limit = 8
+            "#,
+            None,
+        )
+        .unwrap();
+        assert_eq!(config.macro_recursion_limit, 8);
+    }
+
+    #[cfg(feature = "config")]
+    #[test]
+    fn test_while_iteration_limit_parsing() {
+        let config = Config::new(r#""#, None).unwrap();
+        assert_eq!(config.while_iteration_limit, None);
+
+        let config = Config::new(
+            r#"
+            [general]
+            while_iteration_limit = 1000
+            "#,
+            None,
+        )
+        .unwrap();
+        assert_eq!(config.while_iteration_limit, Some(1000));
+    }
+
+    #[cfg(feature = "config")]
+    #[test]
+    fn test_globals_parsing() {
+        let config = Config::new(r#""#, None).unwrap();
+        assert!(config.globals.is_empty());
+        assert_eq!(config.globals_type, None);
+
+        let config = Config::new(
+            r#"
+            [general]
+            globals_type = "crate::Site"
+
+            [globals]
+            name = "Askama \"docs\""
+            year = 2024
+            ratio = 0.5
+            beta = false
+            "#,
+            None,
+        )
+        .unwrap();
+        assert_eq!(config.globals_type, Some("crate::Site"));
+        assert_eq!(config.globals["name"], r#""Askama \"docs\"""#);
+        assert_eq!(config.globals["year"], "2024");
+        assert_eq!(config.globals["ratio"], "0.5");
+        assert_eq!(config.globals["beta"], "false");
+
+        let config = Config::new("[globals]\nlist = [1, 2]", None);
+        assert!(config.is_err());
+    }
+
+    #[cfg(feature = "config")]
+    #[test]
+    fn test_newlines_parsing() {
+        let config = Config::new(r#""#, None).unwrap();
+        assert_eq!(config.newlines, Newlines::Preserve);
+
+        let config = Config::new("[general]\nnewlines = \"lf\"", None).unwrap();
+        assert_eq!(config.newlines, Newlines::Lf);
+
+        assert!(Config::new("[general]\nnewlines = \"crlf\"", None).is_err());
+    }
+
+    #[cfg(feature = "config")]
+    #[test]
+    fn test_codegen_parsing() {
+        let config = Config::new(r#""#, None).unwrap();
+        assert_eq!(config.codegen, Codegen::Speed);
+
+        let config = Config::new(
+            r#"
+            [general]
+            codegen = "size"
+            "#,
+            None,
+        )
+        .unwrap();
+        assert_eq!(config.codegen, Codegen::Size);
+
+        assert!(Config::new(
+            r#"
+            [general]
+            codegen = "small"
+            "#,
+            None,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_codegen_from_str() {
+        assert_eq!("speed".parse::<Codegen>().unwrap(), Codegen::Speed);
+        assert_eq!("size".parse::<Codegen>().unwrap(), Codegen::Size);
+        assert_eq!(
+            "small".parse::<Codegen>().unwrap_err().msg,
+            "invalid value for `codegen`: \"small\""
+        );
+    }
 }
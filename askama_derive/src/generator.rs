@@ -1,20 +1,21 @@
 use std::borrow::Cow;
 use std::collections::hash_map::{Entry, HashMap};
+use std::collections::{BTreeMap, BTreeSet};
 use std::ops::Deref;
 use std::path::Path;
 use std::rc::Rc;
-use std::{cmp, hash, mem, str};
+use std::{cmp, hash, mem, ptr, str};
 
-use crate::config::WhitespaceHandling;
+use crate::config::{Codegen, WhitespaceHandling};
 use crate::heritage::{Context, Heritage};
-use crate::input::{Source, TemplateInput};
+use crate::input::{Print, Serialize, Source, TemplateInput};
 use crate::{CompileError, CRATE};
 
 use parser::node::{
-    Call, Comment, CondTest, FilterBlock, If, Include, Let, Lit, Loop, Match, Target, Whitespace,
-    Ws,
+    Call, Caller, Comment, Component, CondTest, Embed, FilterBlock, If, Include, Let, Lit, Loop,
+    Macro, Match, Once, SetBlock, Target, While, Whitespace, With, Ws,
 };
-use parser::{Expr, Filter, Node};
+use parser::{Expr, Filter, FormatPart, Node, StrLit, Test};
 use quote::quote;
 
 pub(crate) struct Generator<'a> {
@@ -29,16 +30,66 @@ pub(crate) struct Generator<'a> {
     // Suffix whitespace from the previous literal. Will be flushed to the
     // output buffer unless suppressed by whitespace suppression on the next
     // non-literal.
-    next_ws: Option<&'a str>,
+    next_ws: Option<Cow<'a, str>>,
+    // The literal text preceding `next_ws`, if any, to report whitespace decisions
+    next_ws_after: &'a str,
+    // Whether `next_ws` starts a line, for `lstrip_blocks`
+    next_ws_line_start: bool,
+    // The handling of the whitespace after the tag preceding `next_ws`, which is combined with
+    // the one of the tag following it
+    next_ws_source: WsSource,
     // Whitespace suppression from the previous non-literal. Will be used to
     // determine whether to flush prefix whitespace from the next literal.
-    skip_ws: WhitespaceHandling,
+    skip_ws: WsSource,
     // If currently in a block, this will contain the name of a potential parent block
     super_block: Option<(&'a str, usize)>,
     // Buffer for writable
     buf_writable: WritableBuffer<'a>,
     // Counter for write! hash named arguments
     named: usize,
+    // Local filters used by the template, with the number of arguments they are called with
+    local_filters: BTreeMap<String, usize>,
+    // Local tests used by the template, from the `tests` module
+    local_tests: BTreeSet<String>,
+    // Whether expressions are wrapped in markers showing their source, for `render_debug()`
+    debug_markers: bool,
+    // The template and block currently being generated, used to annotate rendering errors
+    location: Location<'a>,
+    // With `dedent = true`, the number of indentation characters removed from the start of every
+    // line of the literals in the current block body
+    dedent: usize,
+    // With `dedent = true`, the indentation of the lines of the current block body in the template
+    body_indent: usize,
+    // Whether whitespace decisions are reported, for `print = "whitespace"`
+    print_ws: bool,
+    // The whitespace decisions reported so far, one line each
+    pub(crate) ws_reports: Vec<String>,
+    // The `once` blocks of the render body, identified by their address, in the order of their
+    // `__askama_once_{index}` flags
+    once_blocks: Vec<*const ()>,
+    // If the innermost loop is recursive, so its body can call `loop(…)`, the mutable variables
+    // moved in and out of the closure rendering it, as returned by `recursive_state()`
+    recursive_loop: Option<String>,
+    // Whether the code is generated in the body of a loop, where blocks see the loop variables
+    in_loop: bool,
+    // The recursive macros being rendered, identified by their address, whose calls render them
+    // again with `_macro_recurse{index}`, with the mutable variables moved in and out of them
+    macro_stack: Vec<(*const (), String)>,
+    // The value whose fields are the variables that aren't local, `self` unless the template is
+    // included with a context
+    root: Option<String>,
+    // The name of the `macro_rules!` rendering the body of the `{% call %}` block of the innermost
+    // macro, and its number of arguments, so the macro can call `caller(…)`
+    caller: Option<(String, usize)>,
+    // The code of the constants defined with `{% const %}` by the rendered template and the
+    // templates it extends, which is inlined where they are used
+    consts: HashMap<&'a str, String>,
+    // Whether the render body writes into a `&mut dyn Write`, with `codegen = "size"`, so its
+    // literals and expressions are written by the shared runtime helpers
+    dyn_writer: bool,
+    // With `dyn_writer`, the literals of the render body, written from the `__ASKAMA_LITERALS`
+    // table by their index
+    literals: Vec<String>,
 }
 
 impl<'a> Generator<'a> {
@@ -54,22 +105,64 @@ impl<'a> Generator<'a> {
             heritage,
             locals,
             next_ws: None,
-            skip_ws: WhitespaceHandling::Preserve,
+            next_ws_after: "",
+            next_ws_line_start: false,
+            next_ws_source: WsSource::NoTag,
+            skip_ws: WsSource::NoTag,
             super_block: None,
             buf_writable: WritableBuffer {
                 discard: input.block.is_some(),
                 ..Default::default()
             },
             named: 0,
+            local_filters: BTreeMap::new(),
+            local_tests: BTreeSet::new(),
+            debug_markers: false,
+            location: Location {
+                template: Rc::from(""),
+                block: None,
+            },
+            dedent: 0,
+            body_indent: 0,
+            print_ws: matches!(input.print, Print::Whitespace | Print::All),
+            ws_reports: Vec::new(),
+            once_blocks: Vec::new(),
+            recursive_loop: None,
+            in_loop: false,
+            macro_stack: Vec::new(),
+            root: None,
+            caller: None,
+            consts: HashMap::new(),
+            dyn_writer: false,
+            literals: Vec::new(),
         }
     }
 
     // Takes a Context and generates the relevant implementations.
-    pub(crate) fn build(mut self, ctx: &Context<'a>) -> Result<String, CompileError> {
+    pub(crate) fn build(&mut self, ctx: &Context<'a>) -> Result<String, CompileError> {
         let mut buf = Buffer::new(0);
 
+        if self.input.render_debug {
+            let mut debug = Self::new(
+                self.input,
+                self.contexts,
+                self.heritage,
+                MapChain::default(),
+            );
+            debug.debug_markers = true;
+            debug.print_ws = false;
+            debug.impl_render_debug(ctx, &mut buf)?;
+        }
+
         self.impl_template(ctx, &mut buf)?;
         self.impl_display(&mut buf)?;
+        if !self.input.variants.is_empty() {
+            self.impl_template_variants(&mut buf)?;
+        }
+
+        if self.input.serialize != Serialize::None {
+            self.impl_serialize(&mut buf)?;
+        }
 
         #[cfg(feature = "with-actix-web")]
         self.impl_actix_web_responder(&mut buf)?;
@@ -83,12 +176,202 @@ impl<'a> Generator<'a> {
         Ok(buf.buf)
     }
 
+    // Generate the method rendering the `index`th `#[template_variant]` of the struct.
+    pub(crate) fn build_variant(
+        &mut self,
+        ctx: &Context<'a>,
+        index: usize,
+    ) -> Result<String, CompileError> {
+        let mut buf = Buffer::new(0);
+        let (impl_generics, ty_generics, where_clause) = self.input.ast.generics.split_for_impl();
+        let ident = &self.input.ast.ident;
+        buf.writeln(&format!(
+            "{} {{",
+            quote!(impl #impl_generics #ident #ty_generics #where_clause),
+        ))?;
+        buf.writeln("#[doc(hidden)]")?;
+        self.dyn_writer = self.input.codegen == Codegen::Size;
+        let writer = match self.dyn_writer {
+            true => "dyn ::std::fmt::Write",
+            false => "(impl ::std::fmt::Write + ?Sized)",
+        };
+        buf.write(&format!(
+            "fn __askama_render_variant_{index}(&self, writer: &mut {writer}) -> "
+        ));
+        buf.write(CRATE);
+        buf.writeln("::Result<()> {")?;
+        let size_hint = self.write_render_body(ctx, &mut buf)?;
+        buf.writeln("}")?;
+        buf.writeln("#[doc(hidden)]")?;
+        buf.writeln(&format!(
+            "const __ASKAMA_VARIANT_{index}_SIZE_HINT: ::std::primitive::usize = {size_hint};"
+        ))?;
+        buf.writeln("}")?;
+        Ok(buf.buf)
+    }
+
+    // Implement `TemplateVariants`, dispatching on the key to the method generated for each
+    // variant by `build_variant()`, or to `render_into()` for any other key.
+    fn impl_template_variants(&mut self, buf: &mut Buffer) -> Result<(), CompileError> {
+        let key_type = &self.input.variants[0].key_type;
+        self.write_header(buf, &format!("{CRATE}::TemplateVariants<{key_type}>"), None)?;
+        buf.write(&format!(
+            "fn render_variant_into(&self, key: &{key_type}, writer: &mut (impl ::std::fmt::Write + ?Sized)) -> "
+        ));
+        buf.write(CRATE);
+        buf.writeln("::Result<()> {")?;
+        buf.writeln("#[allow(unreachable_patterns)]")?;
+        buf.writeln("match key {")?;
+        let writer = match self.input.codegen {
+            Codegen::Speed => "writer".to_owned(),
+            Codegen::Size => format!("&mut {CRATE}::helpers::DynWriter(writer)"),
+        };
+        for (index, variant) in self.input.variants.iter().enumerate() {
+            buf.writeln(&format!(
+                "{} => self.__askama_render_variant_{index}({writer}),",
+                variant.key
+            ))?;
+        }
+        buf.writeln(&format!(
+            "_ => {CRATE}::Template::render_into(self, writer),"
+        ))?;
+        buf.writeln("}")?;
+        buf.writeln("}")?;
+
+        buf.writeln(&format!(
+            "fn variant_size_hint(key: &{key_type}) -> ::std::primitive::usize {{"
+        ))?;
+        buf.writeln("#[allow(unreachable_patterns)]")?;
+        buf.writeln("match key {")?;
+        for (index, variant) in self.input.variants.iter().enumerate() {
+            buf.writeln(&format!(
+                "{} => Self::__ASKAMA_VARIANT_{index}_SIZE_HINT,",
+                variant.key
+            ))?;
+        }
+        buf.writeln(&format!("_ => <Self as {CRATE}::Template>::SIZE_HINT,"))?;
+        buf.writeln("}")?;
+        buf.writeln("}")?;
+        buf.writeln("}")
+    }
+
     // Implement `Template` for the given context struct.
     fn impl_template(&mut self, ctx: &Context<'a>, buf: &mut Buffer) -> Result<(), CompileError> {
-        self.write_header(buf, &format!("{CRATE}::Template"), None)?;
-        buf.write("fn render_into(&self, writer: &mut (impl ::std::fmt::Write + ?Sized)) -> ");
+        let size_hint = match self.input.codegen {
+            Codegen::Speed => {
+                self.write_header(buf, &format!("{CRATE}::Template"), None)?;
+                buf.write(
+                    "fn render_into(&self, writer: &mut (impl ::std::fmt::Write + ?Sized)) -> ",
+                );
+                buf.write(CRATE);
+                buf.writeln("::Result<()> {")?;
+                let size_hint = self.write_render_body(ctx, buf)?;
+                buf.writeln("}")?;
+                size_hint
+            }
+            Codegen::Size => {
+                // The template is rendered by a non-generic method, so its code is only
+                // generated once, whatever the type of the writer.
+                let (impl_generics, ty_generics, where_clause) =
+                    self.input.ast.generics.split_for_impl();
+                let ident = &self.input.ast.ident;
+                buf.writeln(&format!(
+                    "{} {{",
+                    quote!(impl #impl_generics #ident #ty_generics #where_clause),
+                ))?;
+                buf.writeln("#[doc(hidden)]")?;
+                buf.write("fn __askama_render_into(&self, writer: &mut dyn ::std::fmt::Write) -> ");
+                buf.write(CRATE);
+                buf.writeln("::Result<()> {")?;
+                self.dyn_writer = true;
+                let size_hint = self.write_render_body(ctx, buf)?;
+                self.dyn_writer = false;
+                buf.writeln("}")?;
+                buf.writeln("}")?;
+
+                self.write_header(buf, &format!("{CRATE}::Template"), None)?;
+                buf.writeln("#[inline]")?;
+                buf.write(
+                    "fn render_into(&self, writer: &mut (impl ::std::fmt::Write + ?Sized)) -> ",
+                );
+                buf.write(CRATE);
+                buf.writeln("::Result<()> {")?;
+                buf.writeln(&format!(
+                    "self.__askama_render_into(&mut {CRATE}::helpers::DynWriter(writer))"
+                ))?;
+                buf.writeln("}")?;
+                size_hint
+            }
+        };
+
+        buf.writeln("const EXTENSION: ::std::option::Option<&'static ::std::primitive::str> = ")?;
+        buf.writeln(&format!("{:?}", self.input.extension()))?;
+        buf.writeln(";")?;
+
+        buf.writeln("const SIZE_HINT: ::std::primitive::usize = ")?;
+        buf.writeln(&format!("{size_hint}"))?;
+        buf.writeln(";")?;
+
+        buf.writeln("const MIME_TYPE: &'static ::std::primitive::str = ")?;
+        buf.writeln(&format!("{:?}", &self.input.mime_type))?;
+        buf.writeln(";")?;
+
+        buf.writeln("}")?;
+        Ok(())
+    }
+
+    // Implement `render_debug()`, which renders the template with every expression wrapped in
+    // markers showing its source and its value.
+    fn impl_render_debug(
+        &mut self,
+        ctx: &Context<'a>,
+        buf: &mut Buffer,
+    ) -> Result<(), CompileError> {
+        let (impl_generics, ty_generics, where_clause) = self.input.ast.generics.split_for_impl();
+        let ident = &self.input.ast.ident;
+        buf.writeln(&format!(
+            "{} {{",
+            quote!(impl #impl_generics #ident #ty_generics #where_clause),
+        ))?;
+
+        buf.writeln(
+            "/// Renders the template, wrapping every expression in `[[source = value]]` markers.",
+        )?;
+        buf.writeln("#[allow(dead_code)]")?;
+        buf.write("pub fn render_debug(&self) -> ");
+        buf.write(CRATE);
+        buf.writeln("::Result<::std::string::String> {")?;
+        buf.writeln("let mut buf = ::std::string::String::new();")?;
+        buf.writeln("self.__askama_render_debug_into(&mut buf)?;")?;
+        buf.write(CRATE);
+        buf.writeln("::Result::Ok(buf)")?;
+        buf.writeln("}")?;
+
+        buf.write(
+            "fn __askama_render_debug_into(&self, writer: &mut (impl ::std::fmt::Write + ?Sized)) -> ",
+        );
         buf.write(CRATE);
         buf.writeln("::Result<()> {")?;
+        self.write_render_body(ctx, buf)?;
+        buf.writeln("}")?;
+
+        buf.writeln("}")
+    }
+
+    // Writes the body of the function rendering the template into `writer`.
+    fn write_render_body(
+        &mut self,
+        ctx: &Context<'a>,
+        buf: &mut Buffer,
+    ) -> Result<usize, CompileError> {
+        // Chained comparisons like `0 <= x < 10` are desugared to `0 <= x && x < 10`.
+        buf.writeln("#![allow(clippy::manual_range_contains)]")?;
+        buf.writeln(&format!(
+            "#[allow(unused_imports)] use {CRATE}::helpers::{{\
+                Contains as _, GetItem as _, IterableDeref as _, IterableRef as _, \
+                RenderDisplay as _, RenderTemplate as _, Slice as _\
+            }};"
+        ))?;
 
         // Make sure the compiler understands that the generated code depends on the template files.
         for path in self.contexts.keys() {
@@ -98,7 +381,7 @@ impl<'a> Generator<'a> {
                 Source::Source(_) => **path != self.input.path,
             };
             if path_is_valid {
-                let canonical_path = path.canonicalize().unwrap();
+                let canonical_path = self.input.config.files.canonicalize(path).unwrap();
                 let include_path = canonical_path.to_str().unwrap();
                 buf.writeln(
                     &quote! {
@@ -109,31 +392,55 @@ impl<'a> Generator<'a> {
             }
         }
 
+        self.once_blocks.clear();
+        self.collect_consts(ctx)?;
+        let mut body = Buffer::new(buf.indent);
         let size_hint = if let Some(heritage) = self.heritage {
-            self.handle(heritage.root, heritage.root.nodes, buf, AstLevel::Top)
+            self.handle(heritage.root, heritage.root.nodes, &mut body, AstLevel::Top)
         } else {
-            self.handle(ctx, ctx.nodes, buf, AstLevel::Top)
+            self.handle(ctx, ctx.nodes, &mut body, AstLevel::Top)
         }?;
 
+        // Import the local filters used by the template up front: if one of them doesn't exist,
+        // the user gets a single "unresolved import" error naming the filter, instead of errors
+        // in the middle of the generated code.
+        for name in self.local_filters.keys() {
+            buf.writeln(&format!("use filters::{name} as __askama_filter_{name};"))?;
+        }
+        // Calling a filter through a function requiring it to take as many arguments as the
+        // template passes gives an error naming the filter if the counts don't match.
+        for (name, &count) in &self.local_filters {
+            let args = (0..count)
+                .map(|i| format!("A{i}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            buf.writeln(&format!(
+                "#[inline(always)] fn __askama_filter_args_{name}\
+                 <F: ::core::ops::FnOnce({args}) -> R, {args}, R>(filter: F) -> F {{ filter }}"
+            ))?;
+        }
+        for name in &self.local_tests {
+            buf.writeln(&format!("use tests::{name} as __askama_test_{name};"))?;
+        }
+        for id in 0..self.once_blocks.len() {
+            buf.writeln(&format!(
+                "let __askama_once_{id} = ::core::cell::Cell::new(false);"
+            ))?;
+        }
+        buf.buf.push_str(&body.buf);
+
+        if self.dyn_writer {
+            buf.writeln("const __ASKAMA_LITERALS: &[&::std::primitive::str] = &[")?;
+            for lit in mem::take(&mut self.literals) {
+                buf.writeln(&format!("{lit:#?},"))?;
+            }
+            buf.writeln("];")?;
+        }
+
         self.flush_ws(Ws(None, None));
         buf.write(CRATE);
         buf.writeln("::Result::Ok(())")?;
-        buf.writeln("}")?;
-
-        buf.writeln("const EXTENSION: ::std::option::Option<&'static ::std::primitive::str> = ")?;
-        buf.writeln(&format!("{:?}", self.input.extension()))?;
-        buf.writeln(";")?;
-
-        buf.writeln("const SIZE_HINT: ::std::primitive::usize = ")?;
-        buf.writeln(&format!("{size_hint}"))?;
-        buf.writeln(";")?;
-
-        buf.writeln("const MIME_TYPE: &'static ::std::primitive::str = ")?;
-        buf.writeln(&format!("{:?}", &self.input.mime_type))?;
-        buf.writeln(";")?;
-
-        buf.writeln("}")?;
-        Ok(())
+        Ok(size_hint)
     }
 
     // Implement `Display` for the given context struct.
@@ -147,6 +454,28 @@ impl<'a> Generator<'a> {
         buf.writeln("}")
     }
 
+    // Implement `serde::Serialize`, serializing the rendered output.
+    fn impl_serialize(&mut self, buf: &mut Buffer) -> Result<(), CompileError> {
+        if cfg!(not(feature = "with-serde")) {
+            return Err(
+                "the `serialize` attribute requires the `serde` feature to be enabled".into(),
+            );
+        }
+
+        let helper = match self.input.serialize {
+            Serialize::Object => "serialize_object",
+            _ => "serialize_string",
+        };
+        self.write_header(buf, &format!("{CRATE}::helpers::serde::Serialize"), None)?;
+        buf.writeln(&format!(
+            "fn serialize<S: {CRATE}::helpers::serde::Serializer>(&self, serializer: S) \
+             -> ::std::result::Result<S::Ok, S::Error> {{"
+        ))?;
+        buf.writeln(&format!("{CRATE}::helpers::{helper}(self, serializer)"))?;
+        buf.writeln("}")?;
+        buf.writeln("}")
+    }
+
     // Implement Actix-web's `Responder`.
     #[cfg(feature = "with-actix-web")]
     fn impl_actix_web_responder(&mut self, buf: &mut Buffer) -> Result<(), CompileError> {
@@ -252,8 +581,9 @@ impl<'a> Generator<'a> {
         buf: &mut Buffer,
         level: AstLevel,
     ) -> Result<usize, CompileError> {
+        let prev_template = mem::replace(&mut self.location.template, Rc::clone(&ctx.name));
         let mut size_hint = 0;
-        for n in nodes {
+        for (index, n) in nodes.iter().enumerate() {
             match *n {
                 Node::Lit(ref lit) => {
                     self.visit_lit(lit);
@@ -261,32 +591,66 @@ impl<'a> Generator<'a> {
                 Node::Comment(ref comment) => {
                     self.write_comment(comment);
                 }
-                Node::Expr(ws, ref val) => {
-                    self.write_expr(ws, val);
+                Node::Expr(ws, Expr::Call(ref callee, ref args))
+                    if **callee == Expr::Var("loop") =>
+                {
+                    self.write_recursive_call(buf, ws, args)?;
+                }
+                Node::Expr(ws, Expr::Call(ref callee, ref args))
+                    if **callee == Expr::Var("caller") && self.caller.is_some() =>
+                {
+                    self.write_caller_call(buf, ws, args)?;
                 }
+                Node::Expr(ws, ref val) => match self.find_macro_filter(ctx, val)? {
+                    Some((name, args)) => {
+                        size_hint += self.write_macro_call(ctx, buf, ws, None, name, args, None)?;
+                    }
+                    None => self.write_expr(ws, val),
+                },
                 Node::Let(ref l) => {
                     self.write_let(buf, l)?;
                 }
+                Node::SetBlock(ref set) => {
+                    self.write_set_block(ctx, buf, set, tag_indent(nodes, index))?;
+                }
+                Node::With(ref with) => {
+                    size_hint += self.write_with(ctx, buf, with, tag_indent(nodes, index))?;
+                }
                 Node::If(ref i) => {
-                    size_hint += self.write_if(ctx, buf, i)?;
+                    size_hint += self.write_if(ctx, buf, i, tag_indent(nodes, index))?;
                 }
                 Node::Match(ref m) => {
-                    size_hint += self.write_match(ctx, buf, m)?;
+                    size_hint += self.write_match(ctx, buf, m, tag_indent(nodes, index))?;
                 }
                 Node::Loop(ref loop_block) => {
-                    size_hint += self.write_loop(ctx, buf, loop_block)?;
+                    size_hint += self.write_loop(ctx, buf, loop_block, tag_indent(nodes, index))?;
+                }
+                Node::While(ref w) => {
+                    size_hint += self.write_while(ctx, buf, w, tag_indent(nodes, index))?;
                 }
                 Node::BlockDef(ref b) => {
-                    size_hint += self.write_block(ctx, buf, Some(b.name), Ws(b.ws1.0, b.ws2.1))?;
+                    let ws = Ws(b.ws1.0, b.ws2.1);
+                    size_hint += self.write_block(ctx, buf, BlockCall::Def(b.name), ws)?;
                 }
                 Node::Include(ref i) => {
                     size_hint += self.handle_include(ctx, buf, i)?;
                 }
+                Node::Embed(ref embed) => {
+                    size_hint += self.write_embed(ctx, buf, embed)?;
+                }
                 Node::Call(ref call) => {
                     size_hint += self.write_call(ctx, buf, call)?;
                 }
                 Node::FilterBlock(ref filter) => {
-                    size_hint += self.write_filter_block(ctx, buf, filter)?;
+                    size_hint +=
+                        self.write_filter_block(ctx, buf, filter, tag_indent(nodes, index))?;
+                }
+                Node::Component(ref component) => {
+                    size_hint +=
+                        self.write_component(ctx, buf, component, tag_indent(nodes, index))?;
+                }
+                Node::Once(ref once) => {
+                    size_hint += self.write_once(ctx, buf, once, tag_indent(nodes, index))?;
                 }
                 Node::Macro(ref m) => {
                     if level != AstLevel::Top {
@@ -306,6 +670,18 @@ impl<'a> Generator<'a> {
                     }
                     self.handle_ws(i.ws);
                 }
+                Node::FromImport(ref i) => {
+                    if level != AstLevel::Top {
+                        return Err("import blocks only allowed at the top level".into());
+                    }
+                    self.handle_ws(i.ws);
+                }
+                Node::Const(ref c) => {
+                    if level != AstLevel::Top {
+                        return Err("const blocks only allowed at the top level".into());
+                    }
+                    self.handle_ws(c.ws);
+                }
                 Node::Extends(_) => {
                     if level != AstLevel::Top {
                         return Err("extend blocks only allowed at the top level".into());
@@ -323,25 +699,159 @@ impl<'a> Generator<'a> {
                     self.write_buf_writable(buf)?;
                     buf.writeln("continue;")?;
                 }
+                Node::Do(ws, ref expr) => {
+                    self.handle_ws(ws);
+                    self.write_buf_writable(buf)?;
+                    let mut expr_buf = Buffer::new(0);
+                    self.visit_expr(&mut expr_buf, expr)?;
+                    buf.writeln(&format!("let _ = {};", expr_buf.buf))?;
+                }
             }
         }
 
         if AstLevel::Top == level {
             // Handle any pending whitespace.
             if self.next_ws.is_some() {
-                self.flush_ws(Ws(Some(self.skip_ws.into()), None));
+                self.flush_ws_from(self.skip_ws);
             }
 
             size_hint += self.write_buf_writable(buf)?;
         }
+        self.location.template = prev_template;
+        Ok(size_hint)
+    }
+
+    // Handles the body of a block tag whose line is indented by `tag_indent` characters in the
+    // template. With `dedent = true`, the literals of the body are dedented by the additional
+    // indentation of the body, so that they line up with the tag in the output.
+    fn handle_body(
+        &mut self,
+        ctx: &Context<'a>,
+        nodes: &'a [Node<'_>],
+        buf: &mut Buffer,
+        tag_indent: Option<usize>,
+    ) -> Result<usize, CompileError> {
+        if !self.input.dedent {
+            return self.handle(ctx, nodes, buf, AstLevel::Nested);
+        }
+
+        let tag_indent = tag_indent.unwrap_or(self.body_indent);
+        let body_indent = body_indent(nodes).unwrap_or(tag_indent);
+        let prev_dedent = self.dedent;
+        let prev_body_indent = mem::replace(&mut self.body_indent, body_indent);
+        self.dedent += body_indent.saturating_sub(tag_indent);
+        let size_hint = self.handle(ctx, nodes, buf, AstLevel::Nested)?;
+        self.dedent = prev_dedent;
+        self.body_indent = prev_body_indent;
         Ok(size_hint)
     }
 
+    // Writes the condition of an `if` or `while`, binding the variables of its pattern if any.
+    fn write_cond_test(
+        &mut self,
+        buf: &mut Buffer,
+        cond: &'a CondTest<'_>,
+    ) -> Result<(), CompileError> {
+        let CondTest { target, expr } = cond;
+        if let Some(target) = target {
+            let mut expr_buf = Buffer::new(0);
+            buf.write("let ");
+            // If this is a chain condition, then we need to declare the variable after the
+            // left expression has been handled but before the right expression is handled
+            // but this one should have access to the let-bound variable.
+            match expr {
+                Expr::BinOp(op, ref left, ref right) if *op == "||" || *op == "&&" => {
+                    self.visit_expr(&mut expr_buf, left)?;
+                    self.visit_target(buf, true, true, target);
+                    expr_buf.write(&format!(" {op} "));
+                    self.visit_expr(&mut expr_buf, right)?;
+                }
+                _ => {
+                    self.visit_expr(&mut expr_buf, expr)?;
+                    self.visit_target(buf, true, true, target);
+                }
+            }
+            buf.write(" = &");
+            buf.write(&expr_buf.buf);
+        } else {
+            // The following syntax `*(&(...) as &bool)` is used to
+            // trigger Rust's automatic dereferencing, to coerce
+            // e.g. `&&&&&bool` to `bool`. First `&(...) as &bool`
+            // coerces e.g. `&&&bool` to `&bool`. Then `*(&bool)`
+            // finally dereferences it to `bool`.
+            buf.write("*(&(");
+            let expr_code = self.visit_expr_root(expr)?;
+            buf.write(&expr_code);
+            buf.write(") as &bool)");
+        }
+        Ok(())
+    }
+
+    // With `while_iteration_limit`, the iterations are counted, and rendering fails when the
+    // loop would run once more than the limit.
+    fn write_while(
+        &mut self,
+        ctx: &Context<'a>,
+        buf: &mut Buffer,
+        w: &'a While<'_>,
+        tag_indent: Option<usize>,
+    ) -> Result<usize, CompileError> {
+        self.handle_ws(w.ws1);
+        let flushed = self.write_buf_writable(buf)?;
+        self.locals.push();
+
+        let limit = self.input.config.while_iteration_limit;
+        let counter = match limit {
+            Some(_) => {
+                let mut id = 0;
+                while self
+                    .locals
+                    .get(&Cow::Owned(format!("__askama_while{id}")))
+                    .is_some()
+                {
+                    id += 1;
+                }
+                let counter = format!("__askama_while{id}");
+                self.locals.insert_with_default(Cow::Owned(counter.clone()));
+                buf.writeln("{")?;
+                buf.writeln(&format!("let mut {counter}: usize = 0;"))?;
+                Some(counter)
+            }
+            None => None,
+        };
+
+        buf.write("while ");
+        self.write_cond_test(buf, &w.cond)?;
+        buf.writeln(" {")?;
+        if let (Some(limit), Some(counter)) = (limit, &counter) {
+            let context = self.error_context("while");
+            buf.writeln(&format!(
+                "if {counter} == {limit} {{
+                    return ::core::result::Result::Err({CRATE}::shared::Error::Custom(\
+                    ::core::convert::Into::into(\
+                    \"`while` loop exceeded the iteration limit of {limit}\"))){context};
+                }}
+                {counter} += 1;"
+            ))?;
+        }
+
+        let body_size = self.handle_body(ctx, &w.nodes, buf, tag_indent)?;
+        self.handle_ws(w.ws2);
+        let flushed = flushed + self.write_buf_writable(buf)?;
+        buf.writeln("}")?;
+        if counter.is_some() {
+            buf.writeln("}")?;
+        }
+        self.locals.pop();
+        Ok(flushed + body_size * 2)
+    }
+
     fn write_if(
         &mut self,
         ctx: &Context<'a>,
         buf: &mut Buffer,
         i: &'a If<'_>,
+        tag_indent: Option<usize>,
     ) -> Result<usize, CompileError> {
         let mut flushed = 0;
         let mut arm_sizes = Vec::new();
@@ -355,44 +865,29 @@ impl<'a> Generator<'a> {
 
             self.locals.push();
             let mut arm_size = 0;
-            if let Some(CondTest { target, expr }) = &cond.cond {
+            let mut skip_body = false;
+            if let Some(cond_test) = &cond.cond {
                 if i == 0 {
                     buf.write("if ");
                 } else {
                     buf.dedent()?;
                     buf.write("} else if ");
                 }
-
-                if let Some(target) = target {
-                    let mut expr_buf = Buffer::new(0);
-                    buf.write("let ");
-                    // If this is a chain condition, then we need to declare the variable after the
-                    // left expression has been handled but before the right expression is handled
-                    // but this one should have access to the let-bound variable.
-                    match expr {
-                        Expr::BinOp(op, ref left, ref right) if *op == "||" || *op == "&&" => {
-                            self.visit_expr(&mut expr_buf, left)?;
-                            self.visit_target(buf, true, true, target);
-                            expr_buf.write(&format!(" {op} "));
-                            self.visit_expr(&mut expr_buf, right)?;
-                        }
-                        _ => {
-                            self.visit_expr(&mut expr_buf, expr)?;
-                            self.visit_target(buf, true, true, target);
-                        }
+                match self.defined_test(cond_test) {
+                    // The body of a branch that can't be taken isn't generated, so it can use
+                    // variables that don't exist.
+                    Some((_, false, Defined::No) | (_, true, Defined::Yes)) => {
+                        buf.write("false");
+                        skip_body = true;
                     }
-                    buf.write(" = &");
-                    buf.write(&expr_buf.buf);
-                } else {
-                    // The following syntax `*(&(...) as &bool)` is used to
-                    // trigger Rust's automatic dereferencing, to coerce
-                    // e.g. `&&&&&bool` to `bool`. First `&(...) as &bool`
-                    // coerces e.g. `&&&bool` to `&bool`. Then `*(&bool)`
-                    // finally dereferences it to `bool`.
-                    buf.write("*(&(");
-                    let expr_code = self.visit_expr_root(expr)?;
-                    buf.write(&expr_code);
-                    buf.write(") as &bool)");
+                    // In `{% if var is defined %}`, `var` is the value of the optional field.
+                    Some((var, false, Defined::Optional)) => {
+                        let value = self.resolve_var(var);
+                        buf.write("let ::core::option::Option::Some(");
+                        self.visit_target(buf, true, true, &Target::Name(var));
+                        buf.write(&format!(") = &({value})"));
+                    }
+                    _ => self.write_cond_test(buf, cond_test)?,
                 }
             } else {
                 buf.dedent()?;
@@ -402,7 +897,9 @@ impl<'a> Generator<'a> {
 
             buf.writeln(" {")?;
 
-            arm_size += self.handle(ctx, &cond.nodes, buf, AstLevel::Nested)?;
+            if !skip_body {
+                arm_size += self.handle_body(ctx, &cond.nodes, buf, tag_indent)?;
+            }
             arm_sizes.push(arm_size);
         }
         self.handle_ws(i.ws);
@@ -423,6 +920,7 @@ impl<'a> Generator<'a> {
         ctx: &Context<'a>,
         buf: &mut Buffer,
         m: &'a Match<'a>,
+        tag_indent: Option<usize>,
     ) -> Result<usize, CompileError> {
         let Match {
             ws1,
@@ -436,7 +934,7 @@ impl<'a> Generator<'a> {
         let mut arm_sizes = Vec::new();
 
         let expr_code = self.visit_expr_root(expr)?;
-        buf.writeln(&format!("match &{expr_code} {{"))?;
+        buf.writeln(&format!("match &({expr_code}) {{"))?;
 
         let mut arm_size = 0;
         for (i, arm) in arms.iter().enumerate() {
@@ -451,9 +949,16 @@ impl<'a> Generator<'a> {
 
             self.locals.push();
             self.visit_target(buf, true, true, &arm.target);
+            if let Some(guard) = &arm.guard {
+                // Like the condition of `if`, see `write_if()`
+                buf.write(" if *(&(");
+                let guard_code = self.visit_expr_root(guard)?;
+                buf.write(&guard_code);
+                buf.write(") as &bool)");
+            }
             buf.writeln(" => {")?;
 
-            arm_size = self.handle(ctx, &arm.nodes, buf, AstLevel::Nested)?;
+            arm_size = self.handle_body(ctx, &arm.nodes, buf, tag_indent)?;
         }
 
         self.handle_ws(ws2);
@@ -472,6 +977,7 @@ impl<'a> Generator<'a> {
         ctx: &Context<'a>,
         buf: &mut Buffer,
         loop_block: &'a Loop<'_>,
+        tag_indent: Option<usize>,
     ) -> Result<usize, CompileError> {
         self.handle_ws(loop_block.ws1);
         self.locals.push();
@@ -482,26 +988,36 @@ impl<'a> Generator<'a> {
 
         let flushed = self.write_buf_writable(buf)?;
         buf.writeln("{")?;
+        let state = loop_block.recursive.then(|| self.recursive_state());
+        if let Some((state, pattern)) = &state {
+            // The loop is rendered by a closure, which renders it again for every `loop(…)`.
+            buf.writeln(&format!(
+                "{}{CRATE}::helpers::recursive_loop(writer, {state}, &({expr_code}), \
+                 |writer, {pattern}, _loop_iter, _loop_recurse| {{",
+                assign_state(state),
+            ))?;
+        }
         if has_else_nodes {
             buf.writeln("let mut _did_loop = false;")?;
         }
         match loop_block.iter {
+            _ if loop_block.recursive => buf.writeln("let _iter = _loop_iter.into_iter();"),
             Expr::Range(_, _, _) => buf.writeln(&format!("let _iter = {expr_code};")),
             Expr::Array(..) => buf.writeln(&format!("let _iter = {expr_code}.iter();")),
+            Expr::Map(..) => buf.writeln(&format!("let _iter = {expr_code}.into_iter();")),
             // If `iter` is a call then we assume it's something that returns
             // an iterator. If not then the user can explicitly add the needed
             // call without issues.
             Expr::Call(..) | Expr::Index(..) => {
                 buf.writeln(&format!("let _iter = ({expr_code}).into_iter();"))
             }
-            // If accessing `self` then it most likely needs to be
-            // borrowed, to prevent an attempt of moving.
-            _ if expr_code.starts_with("self.") => {
-                buf.writeln(&format!("let _iter = (&{expr_code}).into_iter();"))
-            }
-            // If accessing a field then it most likely needs to be
-            // borrowed, to prevent an attempt of moving.
-            Expr::Attr(..) => buf.writeln(&format!("let _iter = (&{expr_code}).into_iter();")),
+            // If accessing `self` or a field then it most likely needs to be
+            // borrowed, to prevent an attempt of moving. Smart pointers like
+            // `Arc<Vec<T>>` are iterated through their target.
+            _ if expr_code.starts_with("self.") || matches!(loop_block.iter, Expr::Attr(..)) => buf
+                .writeln(&format!(
+                    "let _iter = (&&{CRATE}::helpers::Iterable(&{expr_code})).askama_iter();"
+                )),
             // Otherwise, we borrow `iter` assuming that it implements `IntoIterator`.
             _ => buf.writeln(&format!("let _iter = ({expr_code}).into_iter();")),
         }?;
@@ -515,6 +1031,12 @@ impl<'a> Generator<'a> {
             self.locals.pop();
         }
 
+        if self.uses_loop_length(&loop_block.body, self.heritage) {
+            buf.writeln(&format!(
+                "let (_iter, _loop_length) = {CRATE}::helpers::LoopLength::new(_iter);"
+            ))?;
+        }
+
         self.locals.push();
         buf.write("for (");
         self.visit_target(buf, true, true, &loop_block.var);
@@ -525,7 +1047,15 @@ impl<'a> Generator<'a> {
         if has_else_nodes {
             buf.writeln("_did_loop = true;")?;
         }
-        let mut size_hint1 = self.handle(ctx, &loop_block.body, buf, AstLevel::Nested)?;
+        let prev_recursive = mem::replace(
+            &mut self.recursive_loop,
+            state.as_ref().map(|(state, _)| state.clone()),
+        );
+        let prev_in_loop = mem::replace(&mut self.in_loop, true);
+        let size_hint1 = self.handle_body(ctx, &loop_block.body, buf, tag_indent);
+        self.recursive_loop = prev_recursive;
+        self.in_loop = prev_in_loop;
+        let mut size_hint1 = size_hint1?;
         self.handle_ws(loop_block.ws2);
         size_hint1 += self.write_buf_writable(buf)?;
         self.locals.pop();
@@ -535,7 +1065,7 @@ impl<'a> Generator<'a> {
         if has_else_nodes {
             buf.writeln("if !_did_loop {")?;
             self.locals.push();
-            size_hint2 = self.handle(ctx, &loop_block.else_nodes, buf, AstLevel::Nested)?;
+            size_hint2 = self.handle_body(ctx, &loop_block.else_nodes, buf, tag_indent)?;
             self.handle_ws(loop_block.ws3);
             size_hint2 += self.write_buf_writable(buf)?;
             self.locals.pop();
@@ -545,12 +1075,192 @@ impl<'a> Generator<'a> {
             size_hint2 = self.write_buf_writable(buf)?;
         }
 
+        if let Some((state, _)) = &state {
+            buf.writeln(&format!("{CRATE}::Result::Ok({state})"))?;
+            buf.writeln("})?;")?;
+        }
         buf.writeln("}")?;
         self.locals.pop();
 
         Ok(flushed + ((size_hint1 * 3) + size_hint2) / 2)
     }
 
+    // Returns `true` if `loop.length` of the enclosing loop is accessed in `nodes`.
+    //
+    // The bodies of nested loops are not visited, because `loop` refers to the nested loop there.
+    // The definitions overriding the blocks in `nodes` are looked up in `heritage`, and the
+    // included templates are visited too.
+    fn uses_loop_length(&self, nodes: &[Node<'_>], heritage: Option<&Heritage<'a>>) -> bool {
+        nodes.iter().any(|node| match node {
+            Node::Expr(_, expr) | Node::Do(_, expr) => expr_uses_loop_length(expr),
+            Node::Call(call) => {
+                call.args.iter().any(expr_uses_loop_length)
+                    || call.caller.as_ref().map_or(false, |caller| {
+                        self.uses_loop_length(&caller.nodes, heritage)
+                    })
+            }
+            Node::Let(l) => l.val.as_ref().map_or(false, expr_uses_loop_length),
+            Node::Const(c) => expr_uses_loop_length(&c.value),
+            Node::If(i) => i.branches.iter().any(|cond| {
+                cond.cond
+                    .as_ref()
+                    .map_or(false, |test| expr_uses_loop_length(&test.expr))
+                    || self.uses_loop_length(&cond.nodes, heritage)
+            }),
+            Node::Match(m) => {
+                expr_uses_loop_length(&m.expr)
+                    || m.arms.iter().any(|arm| {
+                        arm.guard.as_ref().map_or(false, expr_uses_loop_length)
+                            || self.uses_loop_length(&arm.nodes, heritage)
+                    })
+            }
+            Node::Loop(l) => {
+                expr_uses_loop_length(&l.iter)
+                    || l.cond.as_ref().map_or(false, expr_uses_loop_length)
+                    || self.uses_loop_length(&l.else_nodes, heritage)
+            }
+            Node::While(w) => {
+                expr_uses_loop_length(&w.cond.expr) || self.uses_loop_length(&w.nodes, heritage)
+            }
+            Node::BlockDef(b) => {
+                self.uses_loop_length(&b.nodes, heritage)
+                    || heritage
+                        .and_then(|heritage| heritage.blocks.get(b.name))
+                        .map_or(false, |defs| {
+                            defs.iter()
+                                .any(|(_, def)| self.uses_loop_length(&def.nodes, heritage))
+                        })
+            }
+            Node::FilterBlock(f) => {
+                f.filters.arguments.iter().any(expr_uses_loop_length)
+                    || self.uses_loop_length(&f.nodes, heritage)
+            }
+            Node::Component(c) => {
+                c.args.iter().any(|(_, expr)| expr_uses_loop_length(expr))
+                    || self.uses_loop_length(&c.nodes, heritage)
+            }
+            Node::Include(i) => {
+                i.expr.as_ref().map_or(false, expr_uses_loop_length)
+                    || i.context.as_ref().map_or(false, expr_uses_loop_length)
+                    || i.paths
+                        .iter()
+                        .any(|path| self.include_uses_loop_length(path, heritage))
+            }
+            Node::Once(o) => self.uses_loop_length(&o.nodes, heritage),
+            Node::Embed(e) => self.uses_loop_length(&e.nodes, heritage),
+            Node::SetBlock(b) => self.uses_loop_length(&b.nodes, heritage),
+            Node::With(w) => {
+                w.vars.iter().any(|(_, expr)| expr_uses_loop_length(expr))
+                    || self.uses_loop_length(&w.nodes, heritage)
+            }
+            Node::Lit(_)
+            | Node::Comment(_)
+            | Node::Extends(_)
+            | Node::Import(_)
+            | Node::FromImport(_)
+            | Node::Macro(_)
+            | Node::Raw(_)
+            | Node::Break(_)
+            | Node::Continue(_) => false,
+        })
+    }
+
+    // Like `uses_loop_length()`, for the template at `path` included in the current template.
+    fn include_uses_loop_length(&self, path: &str, heritage: Option<&Heritage<'a>>) -> bool {
+        // An unknown template is reported when the include is generated.
+        let Ok(path) = self
+            .input
+            .config
+            .find_template(path, Some(&self.input.path))
+        else {
+            return false;
+        };
+        let Some(ctx) = self.contexts.get(&path) else {
+            return false;
+        };
+        if ctx.blocks.is_empty() && ctx.extends.is_none() {
+            return self.uses_loop_length(ctx.nodes, heritage);
+        }
+        let included = Heritage::new(ctx, self.contexts);
+        let included = match heritage {
+            Some(outer) => included.with_outer(outer),
+            None => included,
+        };
+        self.uses_loop_length(included.root.nodes, Some(&included))
+    }
+
+    // Renders the innermost recursive loop again, over the items of `args[0]`.
+    fn write_recursive_call(
+        &mut self,
+        buf: &mut Buffer,
+        ws: Ws,
+        args: &[Expr<'_>],
+    ) -> Result<(), CompileError> {
+        let Some(state) = self.recursive_loop.clone() else {
+            return Err(
+                "`loop(…)` can only be called in the body of a recursive `for` loop".into(),
+            );
+        };
+        let [arg] = args else {
+            return Err("`loop(…)` expects exactly one argument".into());
+        };
+
+        self.handle_expr_ws(ws);
+        self.write_buf_writable(buf)?;
+        let mut expr_buf = Buffer::new(0);
+        self.visit_expr(&mut expr_buf, arg)?;
+        buf.writeln(&format!(
+            "{}_loop_recurse(writer, {state}, &({}))?;",
+            assign_state(&state),
+            expr_buf.buf
+        ))
+    }
+
+    // Renders the body of the `{% call %}` block of the innermost macro, with `args`.
+    fn write_caller_call(
+        &mut self,
+        buf: &mut Buffer,
+        ws: Ws,
+        args: &[Expr<'_>],
+    ) -> Result<(), CompileError> {
+        let Some((name, count)) = self.caller.clone() else {
+            return Err(
+                "`caller(…)` can only be called in a macro with a `{% call %}` body".into(),
+            );
+        };
+        if args.len() != count {
+            return Err(format!(
+                "`caller(…)` expected {count} argument{}, found {}",
+                if count != 1 { "s" } else { "" },
+                args.len()
+            )
+            .into());
+        }
+
+        self.handle_expr_ws(ws);
+        self.write_buf_writable(buf)?;
+        buf.writeln("{")?;
+        let mut values = vec!["writer".to_owned()];
+        for (index, arg) in args.iter().enumerate() {
+            let mut expr_buf = Buffer::new(0);
+            self.visit_expr(&mut expr_buf, arg)?;
+            match arg {
+                // Like the arguments of a macro, variables are passed as they are so they are
+                // not moved.
+                Expr::Var(_) | Expr::Attr(_, _) => values.push(expr_buf.buf),
+                _ => {
+                    buf.writeln(&format!(
+                        "let __askama_caller_arg{index} = ({});",
+                        expr_buf.buf
+                    ))?;
+                    values.push(format!("__askama_caller_arg{index}"));
+                }
+            }
+        }
+        buf.writeln(&format!("{name}!({});", values.join(", ")))?;
+        buf.writeln("}")
+    }
+
     fn write_call(
         &mut self,
         ctx: &Context<'a>,
@@ -562,101 +1272,371 @@ impl<'a> Generator<'a> {
             scope,
             name,
             ref args,
+            ref caller,
         } = *call;
-        if name == "super" {
-            return self.write_block(ctx, buf, None, ws);
+        if let Some(levels) = super_levels(name) {
+            return self.write_block(ctx, buf, BlockCall::Super(levels, args), ws);
         }
+        self.write_macro_call(ctx, buf, ws, scope, name, args, caller.as_ref())
+    }
 
-        let (def, own_ctx) = match scope {
-            Some(s) => {
-                let path = ctx.imports.get(s).ok_or_else(|| {
-                    CompileError::from(format!("no import found for scope {s:?}"))
-                })?;
-                let mctx = self
+    // Defines the `macro_rules!` rendering the body of a `{% call %}` block, and returns its name.
+    //
+    // The body is rendered by a macro instead of being inlined where the called macro uses
+    // `caller(…)`: thanks to the hygiene of `macro_rules!`, the variables of the body are the ones
+    // of the call site, not the ones the called macro defines with the same names.
+    fn write_caller(
+        &mut self,
+        ctx: &Context<'a>,
+        buf: &mut Buffer,
+        ws: Ws,
+        caller: &'a Caller<'_>,
+    ) -> Result<(String, usize), CompileError> {
+        let mut name = String::new();
+        for id in 0.. {
+            name = format!("__askama_caller{id}");
+            if self.locals.get(&Cow::Borrowed(name.as_str())).is_none() {
+                break;
+            }
+        }
+        // Reserve the name, so a `{% call %}` block in the body uses another one: the metavariables
+        // of the nested `macro_rules!` must not be replaced by the ones of this one.
+        self.locals
+            .insert(Cow::Owned(name.clone()), LocalMeta::initialized());
+
+        self.locals.push();
+        // The writer is passed too: `caller(…)` can be called in a closure with its own writer,
+        // e.g. in a recursive loop.
+        let mut params = vec![format!("${name}_writer:ident")];
+        for (index, &arg) in caller.args.iter().enumerate() {
+            let param = format!("${name}_{index}");
+            params.push(format!("{param}:expr"));
+            self.locals.insert(
+                Cow::Borrowed(normalize_identifier(arg)),
+                LocalMeta::with_ref(format!("({param})")),
+            );
+        }
+        buf.writeln(&format!("macro_rules! {name} {{"))?;
+        buf.writeln(&format!("({}) => {{{{", params.join(", ")))?;
+        buf.writeln(&format!("let writer = &mut *${name}_writer;"))?;
+        self.prepare_ws(ws);
+        let mut size_hint = self.handle_body(ctx, &caller.nodes, buf, None)?;
+        self.flush_ws(caller.ws);
+        size_hint += self.write_buf_writable(buf)?;
+        buf.writeln("}};")?;
+        buf.writeln("}")?;
+        self.locals.pop();
+        Ok((name, size_hint))
+    }
+
+    // Returns the macro and its arguments if `expr` uses a macro of `ctx` as a filter, like
+    // `{{ value|badge }}`, which is rendered like `{% call badge(value) %}`.
+    fn find_macro_filter<'e>(
+        &self,
+        ctx: &Context<'a>,
+        expr: &'e Expr<'e>,
+    ) -> Result<Option<(&'e str, &'e [Expr<'e>])>, CompileError> {
+        let Expr::Filter(Filter { name, arguments }) = expr else {
+            return Ok(None);
+        };
+        let mut inner = arguments.first();
+        while let Some(Expr::Filter(filter)) = inner {
+            if ctx.macros.contains_key(filter.name) || ctx.imported_macros.contains_key(filter.name)
+            {
+                return Err(format!(
+                    "macro {:?} can only be used as the last filter of an expression",
+                    filter.name
+                )
+                .into());
+            }
+            inner = filter.arguments.first();
+        }
+        let is_macro = ctx.macros.contains_key(name) || ctx.imported_macros.contains_key(name);
+        Ok(is_macro.then_some((*name, &arguments[..])))
+    }
+
+    // Returns the macro called `name` in `scope`, and the context it is defined in.
+    fn find_macro<'c>(
+        &self,
+        ctx: &'c Context<'a>,
+        scope: Option<&str>,
+        name: &str,
+    ) -> Result<(&'a Macro<'a>, &'c Context<'a>), CompileError> {
+        match scope {
+            Some(s) => {
+                let path = ctx.imports.get(s).ok_or_else(|| {
+                    CompileError::from(format!("no import found for scope {s:?}"))
+                })?;
+                let mctx = self
                     .contexts
                     .get(path)
                     .ok_or_else(|| CompileError::from(format!("context for {path:?} not found")))?;
                 let def = mctx.macros.get(name).ok_or_else(|| {
                     CompileError::from(format!("macro {name:?} not found in scope {s:?}"))
                 })?;
-                (def, mctx)
+                Ok((def, mctx))
             }
-            None => {
-                let def = ctx
-                    .macros
-                    .get(name)
-                    .ok_or_else(|| CompileError::from(format!("macro {name:?} not found")))?;
-                (def, ctx)
+            None => match ctx.macros.get(name) {
+                Some(def) => Ok((def, ctx)),
+                None => {
+                    let (path, name) = ctx
+                        .imported_macros
+                        .get(name)
+                        .ok_or_else(|| CompileError::from(format!("macro {name:?} not found")))?;
+                    let mctx = self.contexts.get(path).ok_or_else(|| {
+                        CompileError::from(format!("context for {path:?} not found"))
+                    })?;
+                    let def = mctx.macros.get(name).ok_or_else(|| {
+                        CompileError::from(format!("macro {name:?} not found in {:?}", mctx.name))
+                    })?;
+                    Ok((def, mctx))
+                }
+            },
+        }
+    }
+
+    // Whether the body of `def`, defined in `ctx`, calls `def` again, directly or through the
+    // macros it calls.
+    fn is_recursive_macro(&self, ctx: &Context<'a>, def: &'a Macro<'a>) -> bool {
+        let mut visited = vec![def as *const Macro<'_>];
+        let mut nested = vec![(ctx, &def.nodes[..])];
+        while let Some((ctx, nodes)) = nested.pop() {
+            for node in nodes {
+                let (scope, name) = match node {
+                    Node::Call(call) => {
+                        if let Some(caller) = &call.caller {
+                            nested.push((ctx, &caller.nodes));
+                        }
+                        (call.scope, call.name)
+                    }
+                    Node::Expr(_, expr) => match self.find_macro_filter(ctx, expr) {
+                        Ok(Some((name, _))) => (None, name),
+                        _ => continue,
+                    },
+                    Node::If(i) => {
+                        for cond in &i.branches {
+                            nested.push((ctx, &cond.nodes));
+                        }
+                        continue;
+                    }
+                    Node::Loop(l) => {
+                        nested.push((ctx, &l.body));
+                        nested.push((ctx, &l.else_nodes));
+                        continue;
+                    }
+                    Node::While(w) => {
+                        nested.push((ctx, &w.nodes));
+                        continue;
+                    }
+                    Node::Match(m) => {
+                        for arm in &m.arms {
+                            nested.push((ctx, &arm.nodes));
+                        }
+                        continue;
+                    }
+                    Node::FilterBlock(f) => {
+                        nested.push((ctx, &f.nodes));
+                        continue;
+                    }
+                    Node::Component(c) => {
+                        nested.push((ctx, &c.nodes));
+                        continue;
+                    }
+                    Node::Once(o) => {
+                        nested.push((ctx, &o.nodes));
+                        continue;
+                    }
+                    Node::Embed(e) => {
+                        nested.push((ctx, &e.nodes));
+                        continue;
+                    }
+                    Node::SetBlock(b) => {
+                        nested.push((ctx, &b.nodes));
+                        continue;
+                    }
+                    Node::With(w) => {
+                        nested.push((ctx, &w.nodes));
+                        continue;
+                    }
+                    _ => continue,
+                };
+                if scope.is_none() && super_levels(name).is_some() {
+                    continue;
+                }
+                // Unknown macros are reported when the call is rendered.
+                let Ok((callee, callee_ctx)) = self.find_macro(ctx, scope, name) else {
+                    continue;
+                };
+                if ptr::eq(callee, def) {
+                    return true;
+                }
+                if !visited.contains(&(callee as *const Macro<'_>)) {
+                    visited.push(callee);
+                    nested.push((callee_ctx, &callee.nodes));
+                }
             }
-        };
+        }
+        false
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn write_macro_call(
+        &mut self,
+        ctx: &Context<'a>,
+        buf: &mut Buffer,
+        ws: Ws,
+        scope: Option<&str>,
+        name: &str,
+        args: &[Expr<'_>],
+        caller: Option<&'a Caller<'_>>,
+    ) -> Result<usize, CompileError> {
+        let (def, own_ctx) = self.find_macro(ctx, scope, name)?;
+        let args = self.spread_macro_arguments(name, def, args)?;
+        let exprs = macro_arguments(name, def, &args)?;
+
+        // A call of a recursive macro in its own body renders it again with the function given
+        // to the closure rendering it.
+        let addr = def as *const Macro<'_> as *const ();
+        if let Some(index) = self.macro_stack.iter().position(|(m, _)| *m == addr) {
+            if caller.is_some() {
+                return Err(format!(
+                    "the recursive calls of macro {name:?} cannot have a `{{% call %}}` body"
+                )
+                .into());
+            }
+            self.flush_ws(ws);
+            self.write_buf_writable(buf)?;
+            let values = self.recursive_macro_arguments(&exprs)?;
+            let state = &self.macro_stack[index].1;
+            buf.writeln(&format!(
+                "{}_macro_recurse{index}(writer, {state}, ({values}))?;",
+                assign_state(state),
+            ))?;
+            self.prepare_ws(ws);
+            return Ok(0);
+        }
 
         self.flush_ws(ws); // Cannot handle_ws() here: whitespace from macro definition comes first
         self.locals.push();
         self.write_buf_writable(buf)?;
         buf.writeln("{")?;
+        let mut size_hint = 0;
+        let new_caller = match caller {
+            Some(caller) => {
+                let (caller_name, caller_size_hint) = self.write_caller(ctx, buf, ws, caller)?;
+                size_hint += caller_size_hint;
+                Some((caller_name, caller.args.len()))
+            }
+            None => None,
+        };
         self.prepare_ws(def.ws1);
 
-        let mut names = Buffer::new(0);
-        let mut values = Buffer::new(0);
-        let mut is_first_variable = true;
-        if args.len() != def.args.len() {
-            return Err(CompileError::from(format!(
-                "macro {name:?} expected {} argument{}, found {}",
-                def.args.len(),
-                if def.args.len() != 1 { "s" } else { "" },
-                args.len()
-            )));
-        }
-        let mut named_arguments = HashMap::new();
-        // Since named arguments can only be passed last, we only need to check if the last argument
-        // is a named one.
-        if let Some(Expr::NamedArgument(_, _)) = args.last() {
-            // First we check that all named arguments actually exist in the called item.
-            for arg in args.iter().rev() {
-                let Expr::NamedArgument(arg_name, _) = arg else {
-                    break;
-                };
-                if !def.args.iter().any(|arg| arg == arg_name) {
-                    return Err(CompileError::from(format!(
-                        "no argument named `{arg_name}` in macro {name:?}"
-                    )));
-                }
-                named_arguments.insert(Cow::Borrowed(arg_name), arg);
+        let recursive = self.is_recursive_macro(own_ctx, def);
+        if recursive {
+            // The macro is rendered by a closure, which renders it again for every call in its
+            // body. The arguments are passed to it as a tuple, so every call must pass them with
+            // the same types.
+            let values = self.recursive_macro_arguments(&exprs)?;
+            let (state, pattern) = self.recursive_state();
+            let mut params = String::new();
+            for (arg, _) in &def.args {
+                let arg = normalize_identifier(arg);
+                params.push_str(arg);
+                params.push_str(", ");
+                self.locals.insert_with_default(Cow::Borrowed(arg));
             }
+            buf.writeln(&format!(
+                "{}{CRATE}::helpers::recursive_macro(writer, {state}, ({values}), {name:?}, {}, \
+                 |writer, {pattern}, ({params}), _macro_recurse{}| {{",
+                assign_state(&state),
+                self.input.config.macro_recursion_limit,
+                self.macro_stack.len(),
+            ))?;
+            self.macro_stack.push((addr, state));
+        } else {
+            self.write_macro_arguments(buf, def, &exprs)?;
         }
 
-        // Handling both named and unnamed arguments requires to be careful of the named arguments
-        // order. To do so, we iterate through the macro defined arguments and then check if we have
-        // a named argument with this name:
-        //
-        // * If there is one, we add it and move to the next argument.
-        // * If there isn't one, then we pick the next argument (we can do it without checking
-        //   anything since named arguments are always last).
-        let mut allow_positional = true;
-        for (index, arg) in def.args.iter().enumerate() {
-            let expr = match named_arguments.get(&Cow::Borrowed(arg)) {
-                Some(expr) => {
-                    allow_positional = false;
-                    expr
-                }
-                None => {
-                    if !allow_positional {
-                        // If there is already at least one named argument, then it's not allowed
-                        // to use unnamed ones at this point anymore.
-                        return Err(CompileError::from(format!(
-                            "cannot have unnamed argument (`{arg}`) after named argument in macro \
-                             {name:?}"
-                        )));
-                    }
-                    &args[index]
-                }
-            };
+        // Macros are defined at the top level, so their body is dedented relative to the first column.
+        let prev_caller = mem::replace(&mut self.caller, new_caller);
+        size_hint += self.handle_body(own_ctx, &def.nodes, buf, Some(0))?;
+        self.caller = prev_caller;
+
+        self.flush_ws(def.ws2);
+        size_hint += self.write_buf_writable(buf)?;
+        if recursive {
+            let (_, state) = self.macro_stack.pop().unwrap();
+            buf.writeln(&format!("{CRATE}::Result::Ok({state})"))?;
+            buf.writeln("})?;")?;
+        }
+        buf.writeln("}")?;
+        self.locals.pop();
+        self.prepare_ws(caller.map_or(ws, |caller| caller.ws));
+        Ok(size_hint)
+    }
+
+    // Replaces the `*value` argument of a call of `def` with the items of `value`, one for each of
+    // the remaining parameters of the macro up to the last one without a default value. Since
+    // the number of items of a slice isn't known at compile time, the parameters with a default
+    // value after them are only set by name.
+    fn spread_macro_arguments<'e>(
+        &mut self,
+        name: &str,
+        def: &Macro<'_>,
+        args: &'e [Expr<'e>],
+    ) -> Result<Cow<'e, [Expr<'e>]>, CompileError> {
+        let Some(index) = args.iter().position(|arg| matches!(arg, Expr::Spread(_))) else {
+            return Ok(Cow::Borrowed(args));
+        };
+        let (positional, named) = args.split_at(index + 1);
+        if named
+            .iter()
+            .any(|arg| !matches!(arg, Expr::NamedArgument(_, _)))
+        {
+            return Err(format!(
+                "only named arguments can follow the spread argument in a call of macro {name:?}"
+            )
+            .into());
+        }
+        let Some(Expr::Spread(value)) = positional.last() else {
+            unreachable!();
+        };
+        let code = self.visit_expr_root(value)?;
+        let required = def
+            .args
+            .iter()
+            .rposition(|(_, default)| default.is_none())
+            .map_or(0, |last| last + 1);
+        let count = required.saturating_sub(index);
+        let mut spread = positional[..index].to_vec();
+        spread.extend((0..count).map(|item| {
+            Expr::Generated(format!(
+                "{CRATE}::helpers::spread_item::<{item}, _>(&({code})){}?",
+                self.error_context(&expr_source(&args[index]))
+            ))
+        }));
+        spread.extend_from_slice(named);
+        Ok(Cow::Owned(spread))
+    }
+
+    // Binds the arguments of a call of `def` to its parameters.
+    fn write_macro_arguments(
+        &mut self,
+        buf: &mut Buffer,
+        def: &Macro<'a>,
+        exprs: &[&Expr<'_>],
+    ) -> Result<(), CompileError> {
+        let mut names = Buffer::new(0);
+        let mut values = Buffer::new(0);
+        let mut is_first_variable = true;
+        for ((arg, _), &expr) in def.args.iter().zip(exprs) {
+            let arg = normalize_identifier(arg);
             match expr {
                 // If `expr` is already a form of variable then
                 // don't reintroduce a new variable. This is
                 // to avoid moving non-copyable values.
                 &Expr::Var(name) if name != "self" => {
-                    let var = self.locals.resolve_or_self(name);
+                    let var = self.resolve_var(name);
                     self.locals
                         .insert(Cow::Borrowed(arg), LocalMeta::with_ref(var));
                 }
@@ -693,15 +1673,87 @@ impl<'a> Generator<'a> {
         if !names.buf.is_empty() {
             buf.writeln(&format!("let ({}) = ({});", names.buf, values.buf))?;
         }
+        Ok(())
+    }
+
+    // Binds the named arguments of a `super()` call to the variables of the same names in `locals`,
+    // the variables of the rendered block. They are evaluated in the block calling `super()`.
+    fn write_super_arguments(
+        &mut self,
+        buf: &mut Buffer,
+        args: &[Expr<'_>],
+        locals: &mut MapChain<'_, Cow<'_, str>, LocalMeta>,
+    ) -> Result<(), CompileError> {
+        let mut names = Vec::new();
+        let mut values = Vec::new();
+        for arg in args {
+            let Expr::NamedArgument(name, expr) = arg else {
+                return Err("`super()` only takes named arguments".into());
+            };
+            let name = normalize_identifier(name).to_owned();
+            match **expr {
+                Expr::Var(var) if var != "self" => {
+                    let var = self.resolve_var(var);
+                    locals.insert(Cow::Owned(name), LocalMeta::with_ref(var));
+                }
+                Expr::Attr(ref obj, attr) => {
+                    let mut attr_buf = Buffer::new(0);
+                    self.visit_attr(&mut attr_buf, obj, attr)?;
+                    let var = self.locals.resolve(&attr_buf.buf).unwrap_or(attr_buf.buf);
+                    locals.insert(Cow::Owned(name), LocalMeta::with_ref(var));
+                }
+                _ => {
+                    values.push(format!("({})", self.visit_expr_root(expr)?));
+                    names.push(name.clone());
+                    locals.insert_with_default(Cow::Owned(name));
+                }
+            }
+        }
+        if !names.is_empty() {
+            buf.writeln(&format!(
+                "let ({}) = ({});",
+                names.join(", "),
+                values.join(", "),
+            ))?;
+        }
+        Ok(())
+    }
 
-        let mut size_hint = self.handle(own_ctx, &def.nodes, buf, AstLevel::Nested)?;
+    // Returns the variables declared with `let mut` in scope as a tuple, e.g. `(count, total, )`,
+    // and the pattern binding them in a closure rendering a recursive loop or macro, e.g.
+    // `(mut count, mut total, )`. The closure can be called again while it runs, so it can't
+    // capture them mutably: their values are moved in and out of it instead.
+    fn recursive_state(&self) -> (String, String) {
+        let mut state = String::from("(");
+        let mut pattern = String::from("(");
+        for name in self.locals.mutable_names() {
+            state.push_str(&format!("{name}, "));
+            pattern.push_str(&format!("mut {name}, "));
+        }
+        state.push(')');
+        pattern.push(')');
+        (state, pattern)
+    }
 
-        self.flush_ws(def.ws2);
-        size_hint += self.write_buf_writable(buf)?;
-        buf.writeln("}")?;
-        self.locals.pop();
-        self.prepare_ws(ws);
-        Ok(size_hint)
+    // Returns the arguments of a call of a recursive macro, as the content of a tuple. Like when
+    // they are bound to the parameters of other macros, variables are not moved: they are
+    // borrowed, and a reference to a reference passed by a recursive call, e.g. to the item of a
+    // loop, is dereferenced to the type of the first call.
+    fn recursive_macro_arguments(&mut self, exprs: &[&Expr<'_>]) -> Result<String, CompileError> {
+        let mut values = String::new();
+        for &expr in exprs {
+            let expr = match expr {
+                Expr::NamedArgument(_, expr) => expr,
+                expr => expr,
+            };
+            let code = self.visit_expr_root(expr)?;
+            match expr {
+                Expr::Var(name) if *name != "self" => values.push_str(&format!("&({code}), ")),
+                Expr::Attr(_, _) => values.push_str(&format!("&({code}), ")),
+                _ => values.push_str(&format!("({code}), ")),
+            }
+        }
+        Ok(values)
     }
 
     fn write_filter_block(
@@ -709,6 +1761,7 @@ impl<'a> Generator<'a> {
         ctx: &Context<'a>,
         buf: &mut Buffer,
         filter: &'a FilterBlock<'_>,
+        tag_indent: Option<usize>,
     ) -> Result<usize, CompileError> {
         self.flush_ws(filter.ws1);
         let mut var_name = String::new();
@@ -722,7 +1775,7 @@ impl<'a> Generator<'a> {
         let current_buf = mem::take(&mut self.buf_writable.buf);
 
         self.prepare_ws(filter.ws1);
-        let mut size_hint = self.handle(ctx, &filter.nodes, buf, AstLevel::Nested)?;
+        let mut size_hint = self.handle_body(ctx, &filter.nodes, buf, tag_indent)?;
         self.flush_ws(filter.ws2);
 
         let WriteParts {
@@ -772,6 +1825,94 @@ impl<'a> Generator<'a> {
         Ok(size_hint)
     }
 
+    fn write_component(
+        &mut self,
+        ctx: &Context<'a>,
+        buf: &mut Buffer,
+        component: &'a Component<'_>,
+        tag_indent: Option<usize>,
+    ) -> Result<usize, CompileError> {
+        self.flush_ws(component.ws1);
+        self.write_buf_writable(buf)?;
+
+        let mut fields = Buffer::new(0);
+        for (name, value) in &component.args {
+            if *name == "body" {
+                return Err("`body` cannot be passed as a component argument, \
+                    it contains the content of the `component` block"
+                    .into());
+            }
+            fields.write(name);
+            fields.write(": ");
+            self.visit_expr(&mut fields, value)?;
+            fields.write(", ");
+        }
+
+        // The body is rendered into a `String` first, so it can be passed to the component
+        // as a `Slot`. It isn't written to the output, so it doesn't count in the size hint.
+        buf.writeln("{")?;
+        buf.writeln("let mut __askama_component_body = ::std::string::String::new();")?;
+        buf.writeln("{")?;
+        buf.writeln("#[allow(unused_imports)] use ::std::fmt::Write as _;")?;
+        buf.writeln("let writer = &mut __askama_component_body;")?;
+        self.locals.push();
+        self.prepare_ws(component.ws1);
+        self.handle_body(ctx, &component.nodes, buf, tag_indent)?;
+        self.flush_ws(component.ws2);
+        self.write_buf_writable(buf)?;
+        self.locals.pop();
+        buf.writeln("}")?;
+
+        let mime_type = &self.input.mime_type;
+        buf.writeln(&format!(
+            "(&&{CRATE}::helpers::Render(&{} {{ {}body: {CRATE}::Slot::new(__askama_component_body, {mime_type:?}) }}))\
+                .{}(writer, {}, {mime_type:?})?;",
+            component.path.join("::"),
+            fields.buf,
+            self.render_method(),
+            self.input.escaper,
+        ))?;
+        buf.writeln("}")?;
+        self.prepare_ws(component.ws2);
+
+        Ok(3)
+    }
+
+    // Every copy of a `once` block, e.g. in a macro called from several places, shares the flag
+    // telling if it was rendered.
+    fn write_once(
+        &mut self,
+        ctx: &Context<'a>,
+        buf: &mut Buffer,
+        once: &'a Once<'_>,
+        tag_indent: Option<usize>,
+    ) -> Result<usize, CompileError> {
+        self.flush_ws(once.ws1);
+        self.write_buf_writable(buf)?;
+
+        let addr = once as *const Once<'_> as *const ();
+        let id = match self.once_blocks.iter().position(|&block| block == addr) {
+            Some(id) => id,
+            None => {
+                self.once_blocks.push(addr);
+                self.once_blocks.len() - 1
+            }
+        };
+        // The flag is a `Cell`, so it can be set from the closure rendering a recursive loop or
+        // macro.
+        buf.writeln(&format!("if !__askama_once_{id}.replace(true) {{"))?;
+        self.locals.push();
+        self.prepare_ws(once.ws1);
+        let mut size_hint = self.handle_body(ctx, &once.nodes, buf, tag_indent)?;
+        self.flush_ws(once.ws2);
+        size_hint += self.write_buf_writable(buf)?;
+        self.locals.pop();
+        buf.writeln("}")?;
+        self.prepare_ws(once.ws2);
+
+        Ok(size_hint)
+    }
+
     fn handle_include(
         &mut self,
         ctx: &Context<'a>,
@@ -780,14 +1921,68 @@ impl<'a> Generator<'a> {
     ) -> Result<usize, CompileError> {
         self.flush_ws(i.ws);
         self.write_buf_writable(buf)?;
+        let root = match &i.context {
+            Some(context) => {
+                let context = self.visit_expr_root(context)?;
+                buf.writeln("{")?;
+                buf.writeln(&format!("let __askama_include_context = &({context});"))?;
+                Some("__askama_include_context".to_owned())
+            }
+            None => self.root.clone(),
+        };
+        let size_hint = match (&i.expr, &i.paths[..]) {
+            (None, [path]) => self.write_include(ctx, buf, path, &root, i.only)?,
+            (Some(expr), paths) => {
+                // The template is chosen when rendering, among the ones the tag lists.
+                let expr_code = self.visit_expr_root(expr)?;
+                buf.writeln(&format!(
+                    "match ::std::convert::AsRef::<str>::as_ref(&({expr_code})) {{"
+                ))?;
+                let mut arm_sizes = Vec::with_capacity(paths.len());
+                for path in paths {
+                    buf.writeln(&format!("{path:?} => {{"))?;
+                    arm_sizes.push(self.write_include(ctx, buf, path, &root, i.only)?);
+                    buf.writeln("}")?;
+                }
+                let context = self.error_context(&format!("include {}", expr_source(expr)));
+                buf.writeln(&format!(
+                    "__askama_path => {{
+                        return ::core::result::Result::Err({CRATE}::shared::Error::Custom(\
+                        ::core::convert::Into::into(::std::format!(\
+                        \"template {{:?}} cannot be included here\", __askama_path)))){context};
+                    }}"
+                ))?;
+                buf.writeln("}")?;
+                median(&mut arm_sizes)
+            }
+            (None, _) => unreachable!("an include tag without expression has a single path"),
+        };
+        if i.context.is_some() {
+            buf.writeln("}")?;
+        }
+        self.prepare_ws(i.ws);
+        Ok(size_hint)
+    }
+
+    // Renders the template at `path`, relative to the current template, where it is included.
+    // The variables of the including template are only visible if not `only`, and the other
+    // variables are the fields of `root`.
+    fn write_include(
+        &mut self,
+        ctx: &Context<'a>,
+        buf: &mut Buffer,
+        path: &str,
+        root: &Option<String>,
+        only: bool,
+    ) -> Result<usize, CompileError> {
         let path = self
             .input
             .config
-            .find_template(i.path, Some(&self.input.path))?;
+            .find_template(path, Some(&self.input.path))?;
 
         // Make sure the compiler understands that the generated code depends on the template file.
         {
-            let canonical_path = path.canonicalize().unwrap();
+            let canonical_path = self.input.config.files.canonicalize(&path).unwrap();
             let include_path = canonical_path.to_str().unwrap();
             buf.writeln(
                 &quote! {
@@ -810,11 +2005,23 @@ impl<'a> Generator<'a> {
                 .entry(name)
                 .or_insert_with(|| import.clone());
         }
+        for (name, import) in &ctx.imported_macros {
+            child_ctx
+                .imported_macros
+                .entry(name)
+                .or_insert_with(|| import.clone());
+        }
 
         // Create a new generator for the child, and call it like in `impl_template` as if it were
         // a full template, while preserving the context.
+        // The blocks of the included template can be overridden by the templates inheriting from
+        // this one, so its heritage continues this template's one.
         let heritage = if !child_ctx.blocks.is_empty() || child_ctx.extends.is_some() {
-            Some(Heritage::new(child_ctx, self.contexts))
+            let heritage = Heritage::new(child_ctx, self.contexts);
+            Some(match self.heritage {
+                Some(outer) => heritage.with_outer(outer),
+                None => heritage,
+            })
         } else {
             None
         };
@@ -823,13 +2030,105 @@ impl<'a> Generator<'a> {
             Some(heritage) => heritage.root,
             None => child_ctx,
         };
-        let locals = MapChain::with_parent(&self.locals);
-        let mut child = Self::new(self.input, self.contexts, heritage.as_ref(), locals);
+        self.write_included(buf, child_ctx, handle_ctx, heritage.as_ref(), root, only)
+    }
+
+    // Renders the included template `ctx`, whose root context is `handle_ctx`.
+    fn write_included(
+        &mut self,
+        buf: &mut Buffer,
+        ctx: &Context<'_>,
+        handle_ctx: &Context<'_>,
+        heritage: Option<&Heritage<'_>>,
+        root: &Option<String>,
+        only: bool,
+    ) -> Result<usize, CompileError> {
+        let locals = match only {
+            true => MapChain::default(),
+            false => MapChain::with_parent(&self.locals),
+        };
+        let mut child = Self::new(self.input, self.contexts, heritage, locals);
+        if !only {
+            child.consts = self.consts.clone();
+        }
+        child.collect_consts(ctx)?;
+        child.root = root.clone();
         child.buf_writable.discard = self.buf_writable.discard;
+        child.debug_markers = self.debug_markers;
+        child.location = self.location.clone();
+        child.once_blocks = mem::take(&mut self.once_blocks);
+        child.macro_stack = mem::take(&mut self.macro_stack);
+        child.dyn_writer = self.dyn_writer;
+        child.literals = mem::take(&mut self.literals);
+        child.ws_reports = mem::take(&mut self.ws_reports);
         let mut size_hint = child.handle(handle_ctx, handle_ctx.nodes, buf, AstLevel::Top)?;
         size_hint += child.write_buf_writable(buf)?;
-        self.prepare_ws(i.ws);
+        self.once_blocks = mem::take(&mut child.once_blocks);
+        self.macro_stack = mem::take(&mut child.macro_stack);
+        self.literals = mem::take(&mut child.literals);
+        self.ws_reports = mem::take(&mut child.ws_reports);
+        let local_filters = mem::take(&mut child.local_filters);
+        let mut local_tests = mem::take(&mut child.local_tests);
+        self.add_local_filters(local_filters)?;
+        self.local_tests.append(&mut local_tests);
+
+        Ok(size_hint)
+    }
+
+    // Renders the template at the path of `embed` like an included one, as if a template
+    // extending it defined the blocks of `embed`.
+    fn write_embed(
+        &mut self,
+        ctx: &Context<'a>,
+        buf: &mut Buffer,
+        embed: &'a Embed<'_>,
+    ) -> Result<usize, CompileError> {
+        for node in &embed.nodes {
+            match node {
+                Node::BlockDef(_) | Node::Comment(_) => {}
+                Node::Lit(lit) if lit.val.is_empty() => {}
+                _ => return Err("only blocks can be defined in an embed tag".into()),
+            }
+        }
+
+        let ws = Ws(embed.ws1.0, embed.ws2.1);
+        self.flush_ws(ws);
+        self.write_buf_writable(buf)?;
+
+        let path = self
+            .input
+            .config
+            .find_template(embed.path, Some(&self.input.path))?;
+        {
+            let canonical_path = self.input.config.files.canonicalize(&path).unwrap();
+            let include_path = canonical_path.to_str().unwrap();
+            buf.writeln(
+                &quote! {
+                    include_bytes!(#include_path);
+                }
+                .to_string(),
+            )?;
+        }
 
+        // The blocks of the tag are the ones of an anonymous template extending the embedded one,
+        // in the context of this template, so they can call its macros.
+        let mut embed_ctx = Context::new(self.input.config, &self.input.path, &embed.nodes)?;
+        embed_ctx.name = Rc::clone(&ctx.name);
+        embed_ctx.extends = Some(path);
+        embed_ctx.macros = ctx.macros.clone();
+        embed_ctx.imports = ctx.imports.clone();
+        embed_ctx.imported_macros = ctx.imported_macros.clone();
+
+        let heritage = Heritage::new(&embed_ctx, self.contexts);
+        let size_hint = self.write_included(
+            buf,
+            &embed_ctx,
+            heritage.root,
+            Some(&heritage),
+            &None,
+            false,
+        )?;
+        self.prepare_ws(ws);
         Ok(size_hint)
     }
 
@@ -874,20 +2173,42 @@ impl<'a> Generator<'a> {
         let Some(val) = &l.val else {
             self.write_buf_writable(buf)?;
             buf.write("let ");
+            if l.mutable {
+                buf.write("mut ");
+            }
             self.visit_target(buf, false, true, &l.var);
+            self.mark_mutable(l);
             return buf.writeln(";");
         };
 
         let mut expr_buf = Buffer::new(0);
         self.visit_expr(&mut expr_buf, val)?;
 
+        // A variable declared with `let mut` is updated, e.g. from the body of a loop.
+        if let Target::Name(name) = l.var {
+            let name = normalize_identifier(name);
+            let mutable =
+                matches!(self.locals.get(&Cow::Borrowed(name)), Some(meta) if meta.mutable);
+            if mutable && !l.mutable {
+                self.write_buf_writable(buf)?;
+                // Going through a temporary keeps clippy from suggesting `total += item` in the
+                // generated code.
+                return buf.writeln(&format!(
+                    "{{ let __askama_value = {}; {name} = __askama_value; }}",
+                    &expr_buf.buf
+                ));
+            }
+        }
+
         let shadowed = self.is_shadowing_variable(&l.var)?;
         if shadowed {
             // Need to flush the buffer if the variable is being shadowed,
             // to ensure the old variable is used.
             self.write_buf_writable(buf)?;
         }
-        if shadowed
+        if l.mutable {
+            buf.write("let mut ");
+        } else if shadowed
             || !matches!(l.var, Target::Name(_))
             || matches!(&l.var, Target::Name(name) if self.locals.get(&Cow::Borrowed(name)).is_none())
         {
@@ -895,35 +2216,141 @@ impl<'a> Generator<'a> {
         }
 
         self.visit_target(buf, true, true, &l.var);
+        self.mark_mutable(l);
         buf.writeln(&format!(" = {};", &expr_buf.buf))
     }
 
-    // If `name` is `Some`, this is a call to a block definition, and we have to find
-    // the first block for that name from the ancestry chain. If name is `None`, this
-    // is from a `super()` call, and we can get the name from `self.super_block`.
-    fn write_block(
+    fn mark_mutable(&mut self, l: &Let<'_>) {
+        if let (true, Target::Name(name)) = (l.mutable, &l.var) {
+            let initialized = l.val.is_some();
+            self.locals.insert(
+                Cow::Owned(normalize_identifier(name).to_owned()),
+                LocalMeta::mutable(initialized),
+            );
+        }
+    }
+
+    // The body is rendered into a `String`, kept in a `Slot` so it is not escaped a second time
+    // when the variable is used. It isn't written to the output, so it doesn't count in the size
+    // hint.
+    fn write_set_block(
         &mut self,
         ctx: &Context<'a>,
         buf: &mut Buffer,
-        name: Option<&'a str>,
-        outer: Ws,
+        set: &'a SetBlock<'_>,
+        tag_indent: Option<usize>,
+    ) -> Result<(), CompileError> {
+        self.flush_ws(set.ws1);
+        self.write_buf_writable(buf)?;
+
+        let name = normalize_identifier(set.var);
+        let mutable = matches!(self.locals.get(&Cow::Borrowed(name)), Some(meta) if meta.mutable);
+        let declared = mutable
+            || matches!(
+                self.locals.get(&Cow::Borrowed(name)),
+                Some(meta) if !meta.initialized
+            );
+        if !declared {
+            buf.write("let ");
+        }
+        buf.writeln(&format!("{name} = {{"))?;
+        buf.writeln("let mut __askama_set_body = ::std::string::String::new();")?;
+        buf.writeln("{")?;
+        buf.writeln("#[allow(unused_imports)] use ::std::fmt::Write as _;")?;
+        buf.writeln("let writer = &mut __askama_set_body;")?;
+        self.locals.push();
+        self.prepare_ws(set.ws1);
+        self.handle_body(ctx, &set.nodes, buf, tag_indent)?;
+        self.flush_ws(set.ws2);
+        self.write_buf_writable(buf)?;
+        self.locals.pop();
+        buf.writeln("}")?;
+        buf.writeln(&format!(
+            "{CRATE}::Slot::new(__askama_set_body, {:?})",
+            self.input.mime_type
+        ))?;
+        buf.writeln("};")?;
+        if !mutable {
+            self.locals
+                .insert(Cow::Borrowed(name), LocalMeta::initialized());
+        }
+        self.prepare_ws(set.ws2);
+        Ok(())
+    }
+
+    // All the values are evaluated before the variables are introduced, so they only see the
+    // variables of the enclosing scope.
+    fn write_with(
+        &mut self,
+        ctx: &Context<'a>,
+        buf: &mut Buffer,
+        with: &'a With<'_>,
+        tag_indent: Option<usize>,
+    ) -> Result<usize, CompileError> {
+        self.flush_ws(with.ws1);
+        self.write_buf_writable(buf)?;
+
+        let mut values = Buffer::new(0);
+        for (_, value) in &with.vars {
+            self.visit_expr(&mut values, value)?;
+            values.write(", ");
+        }
+
+        buf.writeln("{")?;
+        self.locals.push();
+        buf.write("let (");
+        for (name, _) in &with.vars {
+            let name = normalize_identifier(name);
+            self.locals
+                .insert(Cow::Borrowed(name), LocalMeta::initialized());
+            buf.write(name);
+            buf.write(", ");
+        }
+        buf.writeln(&format!(") = ({});", values.buf))?;
+
+        self.prepare_ws(with.ws1);
+        let mut size_hint = self.handle_body(ctx, &with.nodes, buf, tag_indent)?;
+        self.flush_ws(with.ws2);
+        size_hint += self.write_buf_writable(buf)?;
+        self.locals.pop();
+        buf.writeln("}")?;
+        self.prepare_ws(with.ws2);
+
+        Ok(size_hint)
+    }
+
+    // For a block definition, we have to find the first block for that name from the ancestry
+    // chain. For a `super()` call, we can get the name from `self.super_block`, and the
+    // definition is the given number of levels further in the chain.
+    fn write_block(
+        &mut self,
+        ctx: &Context<'a>,
+        buf: &mut Buffer,
+        block: BlockCall<'a>,
+        outer: Ws,
     ) -> Result<usize, CompileError> {
         // Flush preceding whitespace according to the outer WS spec
         self.flush_ws(outer);
 
-        let cur = match (name, self.super_block) {
+        let (name, args) = match block {
+            BlockCall::Def(name) => (Some(name), &[][..]),
+            BlockCall::Super(_, args) => (None, args),
+        };
+        let cur = match (block, self.super_block) {
             // The top-level context contains a block definition
-            (Some(cur_name), None) => (cur_name, 0),
+            (BlockCall::Def(cur_name), None) => (cur_name, 0),
             // A block definition contains a block definition of the same name
-            (Some(cur_name), Some((prev_name, _))) if cur_name == prev_name => {
+            (BlockCall::Def(cur_name), Some((prev_name, _))) if cur_name == prev_name => {
                 return Err(format!("cannot define recursive blocks ({cur_name})").into());
             }
             // A block definition contains a definition of another block
-            (Some(cur_name), Some((_, _))) => (cur_name, 0),
+            (BlockCall::Def(cur_name), Some((_, _))) => (cur_name, 0),
             // `super()` was called inside a block
-            (None, Some((prev_name, gen))) => (prev_name, gen + 1),
+            (BlockCall::Super(levels, _), Some((prev_name, gen))) => (prev_name, gen + levels),
             // `super()` is called from outside a block
-            (None, None) => return Err("cannot call 'super()' outside block".into()),
+            (BlockCall::Super(_, _), None) => {
+                return Err("cannot call 'super()' outside block".into());
+            }
         };
 
         self.write_buf_writable(buf)?;
@@ -939,10 +2366,15 @@ impl<'a> Generator<'a> {
         let heritage = self
             .heritage
             .ok_or_else(|| CompileError::from("no block ancestors available"))?;
-        let (child_ctx, def) = *heritage.blocks[cur.0].get(cur.1).ok_or_else(|| {
-            CompileError::from(match name {
-                None => format!("no super() block found for block '{}'", cur.0),
-                Some(name) => format!("no block found for name '{name}'"),
+        let defs = heritage.blocks.get(cur.0).map_or(&[][..], |defs| defs);
+        let (child_ctx, def) = *defs.get(cur.1).ok_or_else(|| {
+            CompileError::from(match block {
+                BlockCall::Super(levels, _) => format!(
+                    "no {}super() block found for block '{}'",
+                    "super.".repeat(levels - 1),
+                    cur.0,
+                ),
+                BlockCall::Def(name) => format!("no block found for name '{name}'"),
             })
         })?;
 
@@ -959,15 +2391,39 @@ impl<'a> Generator<'a> {
                 .entry(name)
                 .or_insert_with(|| import.clone());
         }
+        for (name, import) in &ctx.imported_macros {
+            child_ctx
+                .imported_macros
+                .entry(name)
+                .or_insert_with(|| import.clone());
+        }
 
-        let mut child = Self::new(
-            self.input,
-            self.contexts,
-            Some(heritage),
-            // Variables are NOT inherited from the parent scope.
-            MapChain::default(),
-        );
+        // Only the variables of the top-level scope are inherited from the parent scope, unless
+        // the block is rendered in a loop, or is `scoped` in one of its definitions.
+        let mut locals = match self.in_loop || defs.iter().any(|(_, def)| def.scoped) {
+            true => self.locals.clone(),
+            false => self.locals.top_level(),
+        };
+        locals.push();
+        let mut child = Self::new(self.input, self.contexts, Some(heritage), locals);
+        child.consts = self.consts.clone();
         child.buf_writable = mem::take(&mut self.buf_writable);
+        child.debug_markers = self.debug_markers;
+        child.location.block = Some(cur.0);
+        child.recursive_loop = self.recursive_loop.clone();
+        child.in_loop = self.in_loop;
+        child.once_blocks = mem::take(&mut self.once_blocks);
+        child.macro_stack = mem::take(&mut self.macro_stack);
+        child.dyn_writer = self.dyn_writer;
+        child.literals = mem::take(&mut self.literals);
+        child.ws_reports = mem::take(&mut self.ws_reports);
+
+        // The arguments of `super()` are the variables of the block it renders, so they must not
+        // outlive it.
+        if !args.is_empty() {
+            buf.writeln("{")?;
+            self.write_super_arguments(buf, args, &mut child.locals)?;
+        }
 
         // Handle inner whitespace suppression spec and process block nodes
         child.prepare_ws(def.ws1);
@@ -981,7 +2437,17 @@ impl<'a> Generator<'a> {
         }
 
         child.flush_ws(def.ws2);
+        if !args.is_empty() {
+            child.write_buf_writable(buf)?;
+            buf.writeln("}")?;
+        }
         self.buf_writable = child.buf_writable;
+        self.once_blocks = child.once_blocks;
+        self.macro_stack = child.macro_stack;
+        self.literals = child.literals;
+        self.ws_reports = child.ws_reports;
+        self.add_local_filters(child.local_filters)?;
+        self.local_tests.append(&mut child.local_tests);
 
         // Restore original block context and set whitespace suppression for
         // succeeding whitespace according to the outer WS spec
@@ -997,33 +2463,189 @@ impl<'a> Generator<'a> {
     }
 
     fn write_expr(&mut self, ws: Ws, s: &'a Expr<'a>) {
-        self.handle_ws(ws);
-        self.buf_writable.push(Writable::Expr(s));
+        self.handle_expr_ws(ws);
+        self.buf_writable
+            .push(Writable::Expr(s, self.location.clone()));
     }
 
     // Write expression buffer and empty
+    //
+    // Consecutive literals are merged into a single `write_str()` call. Every expression is
+    // written on its own, so that nested `Template` values are rendered directly into the writer,
+    // and other values are escaped into it, instead of going through a `Formatter`.
     fn write_buf_writable(&mut self, buf: &mut Buffer) -> Result<usize, CompileError> {
-        let WriteParts { size_hint, buffers } = self.prepare_format(buf.indent)?;
-        match buffers {
-            None => Ok(size_hint),
-            Some(WritePartsBuffers { format, expr: None }) => {
-                buf.writeln(&format!("writer.write_str({:#?})?;", &format.buf))?;
-                Ok(size_hint)
+        let mut size_hint = 0;
+        let mut buf_lit = String::new();
+        for s in mem::take(&mut self.buf_writable.buf) {
+            let (expr, wrapped, context) = match s {
+                Writable::Lit(s) => {
+                    buf_lit.push_str(&s);
+                    continue;
+                }
+                Writable::Expr(s, location) if self.debug_markers => {
+                    // The source of the expression is escaped like any other string.
+                    buf_lit.push_str("[[");
+                    let source = format!("{:?}", expr_source(s));
+                    size_hint += self.write_buf_expr(
+                        buf,
+                        &mut buf_lit,
+                        source,
+                        DisplayWrap::Unwrapped,
+                        String::new(),
+                    )?;
+                    buf_lit.push_str(" = ");
+                    let mut expr_buf = Buffer::new(0);
+                    let (wrapped, context) = self.visit_located_expr(&mut expr_buf, s, location)?;
+                    size_hint +=
+                        self.write_buf_expr(buf, &mut buf_lit, expr_buf.buf, wrapped, context)?;
+                    buf_lit.push_str("]]");
+                    continue;
+                }
+                Writable::Expr(s, location) => {
+                    let mut expr_buf = Buffer::new(0);
+                    let (wrapped, context) = self.visit_located_expr(&mut expr_buf, s, location)?;
+                    (expr_buf.buf, wrapped, context)
+                }
+                Writable::Generated(s, wrapped) => (s, wrapped, String::new()),
+            };
+            size_hint += self.write_buf_expr(buf, &mut buf_lit, expr, wrapped, context)?;
+        }
+        size_hint += self.write_lit_buf(buf, &mut buf_lit)?;
+        Ok(size_hint)
+    }
+
+    fn write_buf_expr(
+        &mut self,
+        buf: &mut Buffer,
+        buf_lit: &mut String,
+        expr: String,
+        wrapped: DisplayWrap,
+        map_err: String,
+    ) -> Result<usize, CompileError> {
+        let mut size_hint = self.write_lit_buf(buf, buf_lit)?;
+        match wrapped {
+            DisplayWrap::Wrapped => {
+                buf.writeln(&format!(
+                    "::std::write!(writer, \"{{}}\", &({expr})){map_err}?;"
+                ))?;
             }
-            Some(WritePartsBuffers {
-                format,
-                expr: Some(expr),
+            DisplayWrap::Unwrapped => {
+                buf.writeln(&format!(
+                    "(&&{CRATE}::helpers::Render(&({expr}))).{}(writer, {}, {:?}){map_err}?;",
+                    self.render_method(),
+                    self.input.escaper,
+                    self.input.mime_type,
+                ))?;
+            }
+        }
+        size_hint += 3;
+        Ok(size_hint)
+    }
+
+    // Visits an expression that was buffered at `location`, and returns the `.map_err()` call
+    // annotating its rendering errors with that location, if any.
+    fn visit_located_expr(
+        &mut self,
+        buf: &mut Buffer,
+        expr: &Expr<'_>,
+        location: Location<'a>,
+    ) -> Result<(DisplayWrap, String), CompileError> {
+        let prev_location = mem::replace(&mut self.location, location);
+        let wrapped = match expr {
+            // An optional chain renders nothing if it has no value.
+            Expr::OptionalChain(_) => {
+                buf.write(&format!("{CRATE}::helpers::OptionalChain("));
+                self.visit_expr(buf, expr)?;
+                buf.write(")");
+                DisplayWrap::Unwrapped
+            }
+            // The elements of a `join` are only escaped on their own where it's written.
+            Expr::Filter(Filter {
+                name: "join",
+                arguments,
             }) => {
-                buf.writeln("::std::write!(")?;
-                buf.indent();
-                buf.writeln("writer,")?;
-                buf.writeln(&format!("{:#?},", &format.buf))?;
-                buf.writeln(expr.buf.trim())?;
-                buf.dedent()?;
-                buf.writeln(")?;")?;
-                Ok(size_hint)
+                if self.input.no_panic {
+                    check_no_panic(expr)?;
+                }
+                self._visit_join_filter(buf, arguments, true)?
             }
+            _ => self.visit_expr(buf, expr)?,
+        };
+        let context = self.error_context(&expr_source(expr));
+        self.location = prev_location;
+        Ok((wrapped, context))
+    }
+
+    // Returns a closure marking the error as returned by the filter `name`, and annotating it
+    // like `error_context()`.
+    fn filter_error_context(&self, name: &str, args: &[Expr<'_>]) -> String {
+        let err = format!(
+            "{CRATE}::shared::Error::in_filter(::core::convert::Into::into(err), {name:?})"
+        );
+        if !self.input.error_context {
+            return format!("|err| {err}");
         }
+        self.wrap_error(&err, &filter_source(name, args))
+    }
+
+    // With `error_context = true`, returns a `.map_err()` call annotating a rendering error with
+    // the current location and `source`. Otherwise errors are returned as is.
+    fn error_context(&self, source: &str) -> String {
+        if !self.input.error_context {
+            return String::new();
+        }
+        format!(
+            ".map_err({})",
+            self.wrap_error("::core::convert::Into::into(err)", source)
+        )
+    }
+
+    // Returns a closure annotating the error `err`, built from the closure argument `err`.
+    fn wrap_error(&self, err: &str, source: &str) -> String {
+        let block = match self.location.block {
+            Some(block) => format!("::core::option::Option::Some({block:?})"),
+            None => "::core::option::Option::None".to_owned(),
+        };
+        format!(
+            "|err| {CRATE}::shared::Error::with_context({err}, {:?}, {block}, {source:?})",
+            &*self.location.template,
+        )
+    }
+
+    // The method of the `Render*` helper traits rendering an expression into `writer`.
+    fn render_method(&self) -> &'static str {
+        match self.dyn_writer {
+            true => "askama_render_dyn",
+            false => "askama_render",
+        }
+    }
+
+    fn write_lit_buf(
+        &mut self,
+        buf: &mut Buffer,
+        buf_lit: &mut String,
+    ) -> Result<usize, CompileError> {
+        if buf_lit.is_empty() {
+            return Ok(0);
+        }
+        if self.dyn_writer {
+            // Identical literals share their entry of the table.
+            let index = match self.literals.iter().position(|lit| lit == buf_lit) {
+                Some(index) => index,
+                None => {
+                    self.literals.push(buf_lit.clone());
+                    self.literals.len() - 1
+                }
+            };
+            buf.writeln(&format!(
+                "{CRATE}::helpers::write_str(writer, __ASKAMA_LITERALS[{index}])?;"
+            ))?;
+        } else {
+            buf.writeln(&format!("writer.write_str({:#?})?;", buf_lit))?;
+        }
+        let size_hint = buf_lit.len();
+        buf_lit.clear();
+        Ok(size_hint)
     }
 
     /// This is the common code to generate an expression. It is used for filter blocks and for
@@ -1045,7 +2667,7 @@ impl<'a> Generator<'a> {
             let mut buf_lit = Buffer::new(0);
             for s in mem::take(&mut self.buf_writable.buf) {
                 if let Writable::Lit(s) = s {
-                    buf_lit.write(s);
+                    buf_lit.write(&s);
                 };
             }
             return Ok(WriteParts {
@@ -1069,9 +2691,23 @@ impl<'a> Generator<'a> {
                     buf_format.write(&s.replace('{', "{{").replace('}', "}}"));
                     size_hint += s.len();
                 }
-                Writable::Expr(s) => {
+                Writable::Expr(s, location) => {
+                    if self.debug_markers {
+                        // The source of the expression is escaped like any other string.
+                        buf_format.write("[[");
+                        size_hint += 2 + self.named_expression(
+                            &mut buf_expr,
+                            &mut buf_format,
+                            format!("{:?}", expr_source(s)),
+                            DisplayWrap::Unwrapped,
+                            false,
+                            &mut expr_cache,
+                        )?;
+                        buf_format.write(" = ");
+                        size_hint += 3;
+                    }
                     let mut expr_buf = Buffer::new(0);
-                    let wrapped = self.visit_expr(&mut expr_buf, s)?;
+                    let (wrapped, _) = self.visit_located_expr(&mut expr_buf, s, location)?;
                     let cacheable = is_cacheable(s);
                     size_hint += self.named_expression(
                         &mut buf_expr,
@@ -1081,6 +2717,10 @@ impl<'a> Generator<'a> {
                         cacheable,
                         &mut expr_cache,
                     )?;
+                    if self.debug_markers {
+                        buf_format.write("]]");
+                        size_hint += 2;
+                    }
                 }
                 Writable::Generated(s, wrapped) => {
                     size_hint += self.named_expression(
@@ -1144,33 +2784,80 @@ impl<'a> Generator<'a> {
 
     fn visit_lit(&mut self, lit: &'a Lit<'_>) {
         assert!(self.next_ws.is_none());
-        let Lit { lws, val, rws } = *lit;
+        let Lit { mut lws, val, rws } = *lit;
+        // With `trim_blocks`, the line break ending the line of a block tag is removed.
+        let mut trimmed = false;
+        if self.skip_ws == WsSource::Default && self.input.trim_blocks {
+            if let Some(rest) = lws.strip_prefix("\r\n").or_else(|| lws.strip_prefix('\n')) {
+                lws = rest;
+                trimmed = true;
+            }
+        }
         if !lws.is_empty() {
-            match self.skip_ws {
-                WhitespaceHandling::Suppress => {}
+            let handling = self.ws_handling(self.skip_ws);
+            match handling {
+                // Whitespace between two tags is handled once the next tag is known.
                 _ if val.is_empty() => {
                     assert!(rws.is_empty());
-                    self.next_ws = Some(lws);
+                    self.next_ws = Some(self.dedent_lit(lws));
+                    self.next_ws_after = "";
+                    self.next_ws_line_start = trimmed || self.skip_ws == WsSource::NoTag;
+                    self.next_ws_source = self.skip_ws;
                 }
-                WhitespaceHandling::Preserve => self.buf_writable.push(Writable::Lit(lws)),
-                WhitespaceHandling::Minimize => {
-                    self.buf_writable
-                        .push(Writable::Lit(match lws.contains('\n') {
-                            true => "\n",
-                            false => " ",
-                        }));
+                WhitespaceHandling::Suppress => {}
+                WhitespaceHandling::Preserve => {
+                    let lws = self.dedent_lit(lws);
+                    self.buf_writable.push(Writable::Lit(lws));
                 }
+                WhitespaceHandling::Minimize | WhitespaceHandling::Linebreak => {
+                    if let Some(ws) = collapse_ws(lws, handling) {
+                        self.buf_writable.push(Writable::Lit(Cow::Borrowed(ws)));
+                    }
+                }
+            }
+            if !val.is_empty() {
+                self.report_ws(
+                    lws,
+                    &format!("before {:?}", excerpt(val, false)),
+                    self.skip_ws,
+                );
             }
         }
 
         if !val.is_empty() {
-            self.skip_ws = WhitespaceHandling::Preserve;
+            self.skip_ws = WsSource::NoTag;
+            let val = self.dedent_lit(val);
             self.buf_writable.push(Writable::Lit(val));
         }
 
         if !rws.is_empty() {
-            self.next_ws = Some(rws);
+            self.next_ws = Some(self.dedent_lit(rws));
+            self.next_ws_after = val;
+            self.next_ws_line_start = false;
+            self.next_ws_source = WsSource::NoTag;
+        }
+    }
+
+    // Removes the indentation of the current block body from every line of `s` but the first
+    // one, which continues the line of the previous node.
+    fn dedent_lit(&self, s: &'a str) -> Cow<'a, str> {
+        if self.dedent == 0 || !s.contains('\n') {
+            return Cow::Borrowed(s);
         }
+
+        let mut lines = s.split('\n');
+        let mut dedented = String::with_capacity(s.len());
+        dedented.push_str(lines.next().unwrap_or_default());
+        for line in lines {
+            dedented.push('\n');
+            let indent = line
+                .chars()
+                .take(self.dedent)
+                .take_while(|&c| c == ' ' || c == '\t')
+                .count();
+            dedented.push_str(&line[indent..]);
+        }
+        Cow::Owned(dedented)
     }
 
     fn write_comment(&mut self, comment: &'a Comment<'_>) {
@@ -1190,20 +2877,28 @@ impl<'a> Generator<'a> {
         buf: &mut Buffer,
         expr: &Expr<'_>,
     ) -> Result<DisplayWrap, CompileError> {
+        if self.input.no_panic {
+            check_no_panic(expr)?;
+        }
         Ok(match *expr {
             Expr::BoolLit(s) => self.visit_bool_lit(buf, s),
             Expr::NumLit(s) => self.visit_num_lit(buf, s),
-            Expr::StrLit(s) => self.visit_str_lit(buf, s),
+            Expr::StrLit(ref s) => self.visit_str_lit(buf, s),
+            Expr::FormatStr(ref parts) => self.visit_format_str(buf, parts)?,
             Expr::CharLit(s) => self.visit_char_lit(buf, s),
             Expr::Var(s) => self.visit_var(buf, s),
             Expr::Path(ref path) => self.visit_path(buf, path),
             Expr::Array(ref elements) => self.visit_array(buf, elements)?,
+            Expr::Map(ref entries) => self.visit_map(buf, entries)?,
             Expr::Attr(ref obj, name) => self.visit_attr(buf, obj, name)?,
+            Expr::OptionalAttr(ref obj, name) => self.visit_optional_attr(buf, obj, name)?,
+            Expr::OptionalChain(ref inner) => self.visit_optional_chain(buf, inner)?,
             Expr::Index(ref obj, ref key) => self.visit_index(buf, obj, key)?,
             Expr::Filter(Filter {
                 name,
                 ref arguments,
             }) => self.visit_filter(buf, name, arguments)?,
+            Expr::Test(ref test) => self.visit_test(buf, test)?,
             Expr::Unary(op, ref inner) => self.visit_unary(buf, op, inner)?,
             Expr::BinOp(op, ref left, ref right) => self.visit_binop(buf, op, left, right)?,
             Expr::Range(op, ref left, ref right) => {
@@ -1211,10 +2906,24 @@ impl<'a> Generator<'a> {
             }
             Expr::Group(ref inner) => self.visit_group(buf, inner)?,
             Expr::Call(ref obj, ref args) => self.visit_call(buf, obj, args)?,
+            Expr::Turbofish(ref obj, ref types) => self.visit_turbofish(buf, obj, types)?,
             Expr::RustMacro(ref path, args) => self.visit_rust_macro(buf, path, args),
             Expr::Try(ref expr) => self.visit_try(buf, expr)?,
+            Expr::Conditional(ref cond, ref then, ref otherwise) => {
+                self.visit_conditional(buf, cond, then, otherwise)?
+            }
+            Expr::Closure(ref params, ref body) => self.visit_closure(buf, params, body)?,
+            Expr::TypeHint(ref expr, ty) => self.visit_type_hint(buf, expr, ty)?,
+            Expr::Matches(ref expr, ref pattern) => self.visit_matches(buf, expr, pattern)?,
             Expr::Tuple(ref exprs) => self.visit_tuple(buf, exprs)?,
             Expr::NamedArgument(_, ref expr) => self.visit_named_argument(buf, expr)?,
+            Expr::Spread(_) => {
+                return Err(format!(
+                    "`{}` can only be used in the arguments of a macro call",
+                    expr_source(expr)
+                )
+                .into());
+            }
             Expr::Generated(ref s) => self.visit_generated(buf, s),
         })
     }
@@ -1228,10 +2937,213 @@ impl<'a> Generator<'a> {
         self.visit_expr(buf, expr)?;
         buf.write(", |err| ");
         buf.write(CRATE);
-        buf.write("::shared::Error::Custom(::core::convert::Into::into(err)))?");
+        buf.write("::shared::Error::Custom(::core::convert::Into::into(err)))");
+        buf.write(&self.error_context(&format!("{}?", expr_source(expr))));
+        buf.write("?");
+        Ok(DisplayWrap::Unwrapped)
+    }
+
+    // The parameters are local variables in the body, which isn't resolved against `self`
+    fn visit_closure(
+        &mut self,
+        buf: &mut Buffer,
+        params: &[&str],
+        body: &Expr<'_>,
+    ) -> Result<DisplayWrap, CompileError> {
+        self.locals.push();
+        buf.write("|");
+        for (i, param) in params.iter().enumerate() {
+            if i > 0 {
+                buf.write(", ");
+            }
+            let param = normalize_identifier(param);
+            if param != "_" {
+                self.locals
+                    .insert(Cow::Owned(param.to_owned()), LocalMeta::initialized());
+            }
+            buf.write(param);
+        }
+        buf.write("| ");
+        let result = self.visit_expr(buf, body);
+        self.locals.pop();
+        result?;
+        Ok(DisplayWrap::Unwrapped)
+    }
+
+    fn visit_conditional(
+        &mut self,
+        buf: &mut Buffer,
+        cond: &Expr<'_>,
+        then: &Expr<'_>,
+        otherwise: &Expr<'_>,
+    ) -> Result<DisplayWrap, CompileError> {
+        buf.write("(if *(&(");
+        self.visit_expr(buf, cond)?;
+        buf.write(") as &bool) { ");
+        let then = self.visit_expr(buf, then)?;
+        buf.write(" } else { ");
+        let otherwise = self.visit_expr(buf, otherwise)?;
+        buf.write(" })");
+        Ok(match (then, otherwise) {
+            (DisplayWrap::Wrapped, DisplayWrap::Wrapped) => DisplayWrap::Wrapped,
+            _ => DisplayWrap::Unwrapped,
+        })
+    }
+
+    // The compiler checks the type of the value against the hint. Values of primitive types are
+    // written without going through the escaper, since they never contain characters to escape.
+    fn visit_type_hint(
+        &mut self,
+        buf: &mut Buffer,
+        expr: &Expr<'_>,
+        ty: &str,
+    ) -> Result<DisplayWrap, CompileError> {
+        buf.write(&format!("{CRATE}::helpers::type_hint::<{ty}>(&("));
+        let wrapped = self.visit_expr(buf, expr)?;
+        buf.write("))");
+
+        let primitive = matches!(
+            ty.trim_start_matches(|c: char| c == '&' || c.is_whitespace()),
+            "bool"
+                | "f32"
+                | "f64"
+                | "i8"
+                | "i16"
+                | "i32"
+                | "i64"
+                | "i128"
+                | "isize"
+                | "u8"
+                | "u16"
+                | "u32"
+                | "u64"
+                | "u128"
+                | "usize"
+        );
+        Ok(match primitive {
+            true => DisplayWrap::Wrapped,
+            false => wrapped,
+        })
+    }
+
+    fn visit_test(
+        &mut self,
+        buf: &mut Buffer,
+        test: &Test<'_>,
+    ) -> Result<DisplayWrap, CompileError> {
+        let Test {
+            name,
+            negated,
+            ref arguments,
+        } = *test;
+        if negated {
+            buf.write("!");
+        }
+        buf.write("(");
+        match (name, arguments.as_slice()) {
+            ("defined", [Expr::Var(var)]) => match self.defined(var) {
+                Defined::Optional => buf.write(&format!("{}.is_some()", self.resolve_var(var))),
+                defined => buf.write(&(defined == Defined::Yes).to_string()),
+            },
+            ("defined", [_]) => return Err("only a variable can be tested with `defined`".into()),
+            ("odd" | "even" | "divisibleby" | "empty" | "none" | "some", [value, args @ ..]) => {
+                let expected = usize::from(name == "divisibleby");
+                if args.len() != expected {
+                    return Err(format!(
+                        "test `{name}` expects {expected} argument(s), found {}",
+                        args.len()
+                    )
+                    .into());
+                }
+                buf.write("(");
+                self.visit_expr(buf, value)?;
+                buf.write(")");
+                match name {
+                    "odd" => buf.write(" % 2 != 0"),
+                    "even" => buf.write(" % 2 == 0"),
+                    "divisibleby" => {
+                        buf.write(" % (");
+                        self.visit_expr(buf, &args[0])?;
+                        buf.write(") == 0");
+                    }
+                    "empty" => buf.write(".is_empty()"),
+                    "none" => buf.write(".is_none()"),
+                    _ => buf.write(".is_some()"),
+                }
+            }
+            (_, [value, args @ ..]) => {
+                self.local_tests.insert(name.to_owned());
+                buf.write(&format!("__askama_test_{name}(&("));
+                self.visit_expr(buf, value)?;
+                buf.write(")");
+                for arg in args {
+                    buf.write(", &(");
+                    self.visit_expr(buf, arg)?;
+                    buf.write(")");
+                }
+                buf.write(")");
+            }
+            (_, []) => unreachable!("the tested value is the first argument of a test"),
+        }
+        buf.write(")");
         Ok(DisplayWrap::Unwrapped)
     }
 
+    // Whether the variable `var` exists, for the `defined` test. A template field whose type is an
+    // `Option`, or which is marked with `#[template_opt]`, is only defined if it's `Some`.
+    fn defined(&self, var: &str) -> Defined {
+        if var == "self" || self.locals.get(&var.into()).is_some() {
+            return Defined::Yes;
+        }
+        let syn::Data::Struct(data) = &self.input.ast.data else {
+            return Defined::No;
+        };
+        let Some(field) = data
+            .fields
+            .iter()
+            .find(|field| field.ident.as_ref().map_or(false, |ident| ident == var))
+        else {
+            return Defined::No;
+        };
+        let is_option = match &field.ty {
+            syn::Type::Path(ty) => ty
+                .path
+                .segments
+                .last()
+                .map_or(false, |segment| segment.ident == "Option"),
+            _ => false,
+        };
+        match is_option
+            || field
+                .attrs
+                .iter()
+                .any(|a| a.path().is_ident("template_opt"))
+        {
+            true => Defined::Optional,
+            false => Defined::Yes,
+        }
+    }
+
+    // Returns the variable of a condition like `var is defined` or `var is not defined`, if the
+    // condition is only this test, with whether it's negated.
+    fn defined_test<'e>(&self, cond: &'e CondTest<'_>) -> Option<(&'e str, bool, Defined)> {
+        match cond {
+            CondTest {
+                target: None,
+                expr:
+                    Expr::Test(Test {
+                        name: "defined",
+                        negated,
+                        arguments,
+                    }),
+            } => match arguments.as_slice() {
+                [Expr::Var(var)] => Some((var, *negated, self.defined(var))),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
     fn visit_rust_macro(&mut self, buf: &mut Buffer, path: &[&str], args: &str) -> DisplayWrap {
         self.visit_path(buf, path);
         buf.write("!(");
@@ -1249,26 +3161,70 @@ impl<'a> Generator<'a> {
     ) -> Result<DisplayWrap, CompileError> {
         match name {
             "as_ref" => return self._visit_as_ref_filter(buf, args),
+            "b64encode" | "b64decode" => return self._visit_base64_filter(buf, name, args),
+            "batch" => return self._visit_chunks_filter(buf, "batch", args),
+            "capitalize_first" => return self._visit_capitalize_first_filter(buf, args),
             "deref" => return self._visit_deref_filter(buf, args),
+            "dictsort" | "items" => return self._visit_dictsort_filter(buf, args),
             "escape" | "e" => return self._visit_escape_filter(buf, args),
+            "first" | "last" => return self._visit_first_last_filter(buf, name, args),
             "fmt" => return self._visit_fmt_filter(buf, args),
             "format" => return self._visit_format_filter(buf, args),
-            "join" => return self._visit_join_filter(buf, args),
+            "intcomma" => return self._visit_intcomma_filter(buf, args),
+            "join" => return self._visit_join_filter(buf, args, false),
             "json" | "tojson" => return self._visit_json_filter(buf, args),
+            "ok" | "unwrap_or" | "map_or" => return self._visit_option_filter(buf, name, args),
+            "round" => return self._visit_round_filter(buf, args),
             "safe" => return self._visit_safe_filter(buf, args),
+            "selectattr" | "rejectattr" => return self._visit_attr_select_filter(buf, name, args),
+            "slice" | "columns" => return self._visit_chunks_filter(buf, "slice", args),
+            "striptags" => return self._visit_striptags_filter(buf, args),
+            "unique" => return self._visit_unique_filter(buf, args),
+            "wordwrap" => return self._visit_wordwrap_filter(buf, args),
             _ => {}
         }
 
         if crate::BUILT_IN_FILTERS.contains(&name) {
             buf.write(&format!("{CRATE}::filters::{name}("));
         } else {
-            buf.write(&format!("filters::{name}("));
+            self.add_local_filter(name, args.len())?;
+            buf.write(&format!(
+                "__askama_filter_args_{name}(__askama_filter_{name})("
+            ));
         }
         self._visit_args(buf, args)?;
-        buf.write(")?");
+        buf.write(&format!(
+            ").map_err({})?",
+            self.filter_error_context(name, args)
+        ));
         Ok(DisplayWrap::Unwrapped)
     }
 
+    fn add_local_filter(&mut self, name: &str, args: usize) -> Result<(), CompileError> {
+        match self.local_filters.get(name) {
+            Some(&prev) if prev != args => Err(format!(
+                "filter `{name}` is called with {args} argument(s) (including the filtered \
+                 value), but it is called with {prev} argument(s) elsewhere in the template",
+            )
+            .into()),
+            Some(_) => Ok(()),
+            None => {
+                self.local_filters.insert(name.to_owned(), args);
+                Ok(())
+            }
+        }
+    }
+
+    fn add_local_filters(
+        &mut self,
+        local_filters: BTreeMap<String, usize>,
+    ) -> Result<(), CompileError> {
+        for (name, args) in local_filters {
+            self.add_local_filter(&name, args)?;
+        }
+        Ok(())
+    }
+
     fn _visit_as_ref_filter(
         &mut self,
         buf: &mut Buffer,
@@ -1283,6 +3239,355 @@ impl<'a> Generator<'a> {
         Ok(DisplayWrap::Unwrapped)
     }
 
+    // `batch` and `slice`, which split an iterable into chunks
+    fn _visit_chunks_filter(
+        &mut self,
+        buf: &mut Buffer,
+        name: &str,
+        args: &[Expr<'_>],
+    ) -> Result<DisplayWrap, CompileError> {
+        let (iterable, size, fill) = match args {
+            [iterable, size] => (iterable, size, None),
+            [iterable, size, fill] => (iterable, size, Some(fill)),
+            _ => {
+                let size = if name == "batch" { "size" } else { "count" };
+                return Err(format!(
+                    "the `{name}` filter takes one or two arguments, the {size} and the fill value"
+                )
+                .into());
+            }
+        };
+        buf.write(&format!("{CRATE}::filters::{name}("));
+        // The fill value must have the type of the items.
+        let borrow = match self._visit_iterable_arg(buf, iterable)? {
+            true => "&",
+            false => "",
+        };
+        buf.write(", ");
+        self.visit_expr(buf, size)?;
+        match fill {
+            Some(fill) => {
+                buf.write(&format!(", ::core::option::Option::Some({borrow}("));
+                self.visit_expr(buf, fill)?;
+                buf.write("))");
+            }
+            None => buf.write(", ::core::option::Option::None"),
+        }
+        buf.write(&format!(
+            ").map_err({})?",
+            self.filter_error_context(name, args)
+        ));
+        Ok(DisplayWrap::Unwrapped)
+    }
+
+    fn _visit_dictsort_filter(
+        &mut self,
+        buf: &mut Buffer,
+        args: &[Expr<'_>],
+    ) -> Result<DisplayWrap, CompileError> {
+        let [map] = args else {
+            return Err("unexpected argument(s) in `dictsort` filter".into());
+        };
+        buf.write(&format!("{CRATE}::filters::dictsort("));
+        self._visit_iterable_arg(buf, map)?;
+        buf.write(&format!(
+            ").map_err({})?",
+            self.filter_error_context("dictsort", args)
+        ));
+        Ok(DisplayWrap::Unwrapped)
+    }
+
+    fn _visit_first_last_filter(
+        &mut self,
+        buf: &mut Buffer,
+        name: &str,
+        args: &[Expr<'_>],
+    ) -> Result<DisplayWrap, CompileError> {
+        let [iterable] = args else {
+            return Err(format!("unexpected argument(s) in `{name}` filter").into());
+        };
+        buf.write(&format!("{CRATE}::filters::{name}("));
+        self._visit_iterable_arg(buf, iterable)?;
+        buf.write(&format!(
+            ").map_err({})?",
+            self.filter_error_context(name, args)
+        ));
+        Ok(DisplayWrap::Unwrapped)
+    }
+
+    // `selectattr` and `rejectattr`, which keep or remove the items whose attribute is `true`, or
+    // passes a comparison. The test is compiled into a closure.
+    fn _visit_attr_select_filter(
+        &mut self,
+        buf: &mut Buffer,
+        name: &str,
+        args: &[Expr<'_>],
+    ) -> Result<DisplayWrap, CompileError> {
+        let (iterable, attr, comparison) = match args {
+            [iterable, attr] => (iterable, attr, None),
+            [iterable, attr, op, value] => (iterable, attr, Some((op, value))),
+            _ => {
+                return Err(format!(
+                    "the `{name}` filter takes the name of an attribute, optionally followed by \
+                     an operator and a value"
+                )
+                .into());
+            }
+        };
+        let attr = match attr {
+            Expr::StrLit(StrLit { content, .. })
+                if content.split('.').all(|part| {
+                    part.chars().next().map_or(false, |c| !c.is_ascii_digit())
+                        && part.chars().all(|c| c.is_alphanumeric() || c == '_')
+                        || !part.is_empty() && part.chars().all(|c| c.is_ascii_digit())
+                }) =>
+            {
+                content
+            }
+            _ => {
+                return Err(format!(
+                    "the attribute of the `{name}` filter must be a string literal containing a \
+                     field name or a path like `\"address.city\"`"
+                )
+                .into());
+            }
+        };
+        let op = match comparison {
+            None => None,
+            Some((Expr::StrLit(StrLit { content: op, .. }), _)) => Some(match *op {
+                "==" | "eq" => "==",
+                "!=" | "ne" => "!=",
+                "<" | "lt" => "<",
+                "<=" | "le" => "<=",
+                ">" | "gt" => ">",
+                ">=" | "ge" => ">=",
+                op => {
+                    return Err(format!(
+                        "unknown operator `{op}` in `{name}` filter, expected one of `==`, `!=`, \
+                         `<`, `<=`, `>` or `>=`"
+                    )
+                    .into());
+                }
+            }),
+            Some(_) => {
+                return Err(format!(
+                    "the operator of the `{name}` filter must be a string literal"
+                )
+                .into());
+            }
+        };
+
+        buf.write(&format!("{CRATE}::filters::{name}("));
+        self._visit_iterable_arg(buf, iterable)?;
+        buf.write(", |__askama_item| __askama_item");
+        for part in attr.split('.') {
+            buf.write(&format!(".{}", normalize_identifier(part)));
+        }
+        if let (Some(op), Some((_, value))) = (op, comparison) {
+            buf.write(&format!(" {op} ("));
+            self.visit_expr(buf, value)?;
+            buf.write(")");
+        }
+        buf.write(&format!(
+            ").map_err({})?",
+            self.filter_error_context(name, args)
+        ));
+        Ok(DisplayWrap::Unwrapped)
+    }
+
+    fn _visit_unique_filter(
+        &mut self,
+        buf: &mut Buffer,
+        args: &[Expr<'_>],
+    ) -> Result<DisplayWrap, CompileError> {
+        let (iterable, key) = match args {
+            [iterable] => (iterable, None),
+            [iterable, key] => (iterable, Some(key)),
+            _ => return Err("unexpected argument(s) in `unique` filter".into()),
+        };
+        buf.write(&format!("{CRATE}::filters::unique("));
+        self._visit_iterable_arg(buf, iterable)?;
+        buf.write(", ");
+        match key {
+            Some(key) => {
+                self.visit_expr(buf, key)?;
+            }
+            None => buf.write("|__askama_item| ::core::clone::Clone::clone(__askama_item)"),
+        }
+        buf.write(&format!(
+            ").map_err({})?",
+            self.filter_error_context("unique", args)
+        ));
+        Ok(DisplayWrap::Unwrapped)
+    }
+
+    // Writes the iterable filtered by a filter which iterates over it, returning whether its items
+    // are borrowed. Like in `{% for %}`, ranges and the results of calls are iterated by value, and
+    // other values are borrowed, going through smart pointers.
+    fn _visit_iterable_arg(
+        &mut self,
+        buf: &mut Buffer,
+        iterable: &Expr<'_>,
+    ) -> Result<bool, CompileError> {
+        let mut inner = iterable;
+        while let Expr::Group(expr) = inner {
+            inner = expr;
+        }
+        if matches!(inner, Expr::Range(..) | Expr::Call(..) | Expr::Filter(..)) {
+            buf.write("(");
+            self.visit_expr(buf, iterable)?;
+            buf.write(")");
+            return Ok(false);
+        }
+        buf.write(&format!("(&&{CRATE}::helpers::Iterable(&("));
+        self.visit_expr(buf, iterable)?;
+        buf.write("))).askama_iter()");
+        Ok(true)
+    }
+
+    fn _visit_capitalize_first_filter(
+        &mut self,
+        buf: &mut Buffer,
+        args: &[Expr<'_>],
+    ) -> Result<DisplayWrap, CompileError> {
+        if !matches!(args.len(), 1 | 2) {
+            return Err("unexpected argument(s) in `capitalize_first` filter".into());
+        }
+        buf.write(&format!("{CRATE}::filters::capitalize_first("));
+        self._visit_args(buf, args)?;
+        if args.len() == 1 {
+            // no locale given
+            buf.write(", \"\"");
+        }
+        buf.write(&format!(
+            ").map_err({})?",
+            self.filter_error_context("capitalize_first", args)
+        ));
+        Ok(DisplayWrap::Unwrapped)
+    }
+
+    fn _visit_striptags_filter(
+        &mut self,
+        buf: &mut Buffer,
+        args: &[Expr<'_>],
+    ) -> Result<DisplayWrap, CompileError> {
+        if !matches!(args.len(), 1 | 2) {
+            return Err("unexpected argument(s) in `striptags` filter".into());
+        }
+        buf.write(&format!("{CRATE}::filters::striptags("));
+        self._visit_args(buf, args)?;
+        if args.len() == 1 {
+            // entities are not decoded by default
+            buf.write(", false");
+        }
+        buf.write(&format!(
+            ").map_err({})?",
+            self.filter_error_context("striptags", args)
+        ));
+        Ok(DisplayWrap::Unwrapped)
+    }
+
+    fn _visit_round_filter(
+        &mut self,
+        buf: &mut Buffer,
+        args: &[Expr<'_>],
+    ) -> Result<DisplayWrap, CompileError> {
+        if !matches!(args.len(), 1..=3) {
+            return Err("unexpected argument(s) in `round` filter".into());
+        }
+        buf.write(&format!("{CRATE}::filters::round("));
+        self._visit_args(buf, args)?;
+        // the precision and the mode are optional
+        if args.len() == 1 {
+            buf.write(", 0");
+        }
+        if args.len() < 3 {
+            buf.write(", \"common\"");
+        }
+        buf.write(&format!(
+            ").map_err({})?",
+            self.filter_error_context("round", args)
+        ));
+        Ok(DisplayWrap::Unwrapped)
+    }
+
+    fn _visit_wordwrap_filter(
+        &mut self,
+        buf: &mut Buffer,
+        args: &[Expr<'_>],
+    ) -> Result<DisplayWrap, CompileError> {
+        if !matches!(args.len(), 1..=3) {
+            return Err("unexpected argument(s) in `wordwrap` filter".into());
+        }
+        buf.write(&format!("{CRATE}::filters::wordwrap("));
+        self._visit_args(buf, &args[..1])?;
+        // the width and whether long words are broken are passed by value, and are optional
+        for arg in &args[1..] {
+            buf.write(", ");
+            self.visit_expr(buf, arg)?;
+        }
+        if args.len() == 1 {
+            buf.write(", 79");
+        }
+        if args.len() < 3 {
+            buf.write(", true");
+        }
+        buf.write(&format!(
+            ").map_err({})?",
+            self.filter_error_context("wordwrap", args)
+        ));
+        Ok(DisplayWrap::Unwrapped)
+    }
+
+    fn _visit_intcomma_filter(
+        &mut self,
+        buf: &mut Buffer,
+        args: &[Expr<'_>],
+    ) -> Result<DisplayWrap, CompileError> {
+        if !matches!(args.len(), 1 | 2) {
+            return Err("unexpected argument(s) in `intcomma` filter".into());
+        }
+        buf.write(&format!("{CRATE}::filters::intcomma("));
+        self._visit_args(buf, args)?;
+        if args.len() == 1 {
+            // the default separator
+            buf.write(", \",\"");
+        }
+        buf.write(&format!(
+            ").map_err({})?",
+            self.filter_error_context("intcomma", args)
+        ));
+        Ok(DisplayWrap::Unwrapped)
+    }
+
+    fn _visit_base64_filter(
+        &mut self,
+        buf: &mut Buffer,
+        name: &str,
+        args: &[Expr<'_>],
+    ) -> Result<DisplayWrap, CompileError> {
+        if cfg!(not(feature = "base64")) {
+            return Err(
+                format!("the `{name}` filter requires the `base64` feature to be enabled").into(),
+            );
+        }
+
+        if !matches!(args.len(), 1 | 2) {
+            return Err(format!("unexpected argument(s) in `{name}` filter").into());
+        }
+        buf.write(&format!("{CRATE}::filters::{name}("));
+        self._visit_args(buf, args)?;
+        if args.len() == 1 {
+            // the default alphabet
+            buf.write(", \"standard\"");
+        }
+        buf.write(&format!(
+            ").map_err({})?",
+            self.filter_error_context(name, args)
+        ));
+        Ok(DisplayWrap::Unwrapped)
+    }
+
     fn _visit_deref_filter(
         &mut self,
         buf: &mut Buffer,
@@ -1316,6 +3621,47 @@ impl<'a> Generator<'a> {
         Ok(DisplayWrap::Unwrapped)
     }
 
+    fn _visit_option_filter(
+        &mut self,
+        buf: &mut Buffer,
+        name: &str,
+        args: &[Expr<'_>],
+    ) -> Result<DisplayWrap, CompileError> {
+        let expected = match name {
+            "ok" => 1,
+            "unwrap_or" => 2,
+            _ => 3,
+        };
+        if args.len() != expected {
+            return Err(format!(
+                "`{name}` filter expects {} argument(s) besides the filtered value",
+                expected - 1
+            )
+            .into());
+        }
+
+        // The `Option` or `Result`, and the default of `unwrap_or`, are always borrowed, as
+        // `unwrap_or` returns a reference to one of them.
+        buf.write(&format!("{CRATE}::filters::{name}(&("));
+        self.visit_expr(buf, &args[0])?;
+        buf.write(")");
+        for arg in &args[1..] {
+            buf.write(", ");
+            if name == "unwrap_or" {
+                buf.write("&(");
+                self.visit_expr(buf, arg)?;
+                buf.write(")");
+            } else {
+                self.visit_expr(buf, arg)?;
+            }
+        }
+        buf.write(&format!(
+            ").map_err({})?",
+            self.filter_error_context(name, args)
+        ));
+        Ok(DisplayWrap::Unwrapped)
+    }
+
     fn _visit_safe_filter(
         &mut self,
         buf: &mut Buffer,
@@ -1342,7 +3688,7 @@ impl<'a> Generator<'a> {
             return Err("only two arguments allowed to escape filter".into());
         }
         let opt_escaper = match args.get(1) {
-            Some(Expr::StrLit(name)) => Some(*name),
+            Some(Expr::StrLit(name)) => Some(name.value()),
             Some(_) => return Err("invalid escaper type for escape filter".into()),
             None => None,
         };
@@ -1352,7 +3698,7 @@ impl<'a> Generator<'a> {
                 .config
                 .escapers
                 .iter()
-                .find_map(|(escapers, escaper)| escapers.contains(name).then_some(escaper))
+                .find_map(|(escapers, escaper)| escapers.contains(&*name).then_some(escaper))
                 .ok_or_else(|| CompileError::from("invalid escaper for escape filter"))?,
             None => self.input.escaper,
         };
@@ -1402,24 +3748,43 @@ impl<'a> Generator<'a> {
     }
 
     // Force type coercion on first argument to `join` filter (see #39).
+    //
+    // If the joined value is written as is, every element and the separator are escaped on their
+    // own. If another filter or a `let` consumes it, it is escaped once where it's written.
     fn _visit_join_filter(
         &mut self,
         buf: &mut Buffer,
         args: &[Expr<'_>],
+        escape: bool,
     ) -> Result<DisplayWrap, CompileError> {
+        let [iterable, separator] = args else {
+            return Err("the `join` filter takes exactly one argument, the separator".into());
+        };
+        let escaper = self.input.escaper;
+
         buf.write(CRATE);
-        buf.write("::filters::join((&");
-        for (i, arg) in args.iter().enumerate() {
-            if i > 0 {
-                buf.write(", &");
-            }
-            self.visit_expr(buf, arg)?;
-            if i == 0 {
-                buf.write(").into_iter()");
-            }
+        buf.write("::filters::join(");
+        self._visit_iterable_arg(buf, iterable)?;
+        if !escape {
+            buf.write(", &(");
+            self.visit_expr(buf, separator)?;
+            buf.write("))?");
+            return Ok(DisplayWrap::Unwrapped);
         }
-        buf.write(")?");
-        Ok(DisplayWrap::Unwrapped)
+        // A string literal separator is part of the template, like the markup around the
+        // expression, so only the elements are escaped.
+        let separator_display = match separator {
+            Expr::StrLit(_) => "new_safe",
+            _ => "new_unsafe",
+        };
+        buf.write(&format!(
+            ".map(|__askama_item| \
+             {CRATE}::MarkupDisplay::new_unsafe(__askama_item, {escaper})), \
+             {CRATE}::MarkupDisplay::{separator_display}(&("
+        ));
+        self.visit_expr(buf, separator)?;
+        buf.write(&format!("), {escaper}))?"));
+        Ok(DisplayWrap::Wrapped)
     }
 
     fn _visit_args(&mut self, buf: &mut Buffer, args: &[Expr<'_>]) -> Result<(), CompileError> {
@@ -1475,13 +3840,99 @@ impl<'a> Generator<'a> {
                 } else if attr == "last" {
                     buf.write("_loop_item.last");
                     return Ok(DisplayWrap::Unwrapped);
+                } else if attr == "length" {
+                    buf.write("_loop_length");
+                    return Ok(DisplayWrap::Unwrapped);
                 } else {
                     return Err("unknown loop variable".into());
                 }
             }
+            if name == "globals" && self.has_globals() {
+                return self.visit_global(buf, attr);
+            }
+        }
+        self.visit_expr(buf, obj)?;
+        buf.write(&format!(".{}", normalize_identifier(attr)));
+        Ok(DisplayWrap::Unwrapped)
+    }
+
+    // The variables bound by the pattern only exist in the pattern.
+    fn visit_matches(
+        &mut self,
+        buf: &mut Buffer,
+        expr: &Expr<'_>,
+        pattern: &Target<'_>,
+    ) -> Result<DisplayWrap, CompileError> {
+        buf.write("::core::matches!(&(");
+        self.visit_expr(buf, expr)?;
+        buf.write("), ");
+        self.locals.push();
+        self.visit_target(buf, true, true, pattern);
+        self.locals.pop();
+        buf.write(")");
+        Ok(DisplayWrap::Unwrapped)
+    }
+
+    fn visit_turbofish(
+        &mut self,
+        buf: &mut Buffer,
+        obj: &Expr<'_>,
+        types: &[&str],
+    ) -> Result<DisplayWrap, CompileError> {
+        self.visit_expr(buf, obj)?;
+        buf.write(&format!("::<{}>", types.join(", ")));
+        Ok(DisplayWrap::Unwrapped)
+    }
+
+    // The `?` returns `None` from the closure written by `visit_optional_chain()`.
+    fn visit_optional_attr(
+        &mut self,
+        buf: &mut Buffer,
+        obj: &Expr<'_>,
+        attr: &str,
+    ) -> Result<DisplayWrap, CompileError> {
+        buf.write(&format!("{CRATE}::filters::FilterOption::filter_option(&("));
+        self.visit_expr(buf, obj)?;
+        buf.write(&format!("))?.{}", normalize_identifier(attr)));
+        Ok(DisplayWrap::Unwrapped)
+    }
+
+    fn visit_optional_chain(
+        &mut self,
+        buf: &mut Buffer,
+        inner: &Expr<'_>,
+    ) -> Result<DisplayWrap, CompileError> {
+        // A chain ending with an attribute or an index is a place, which is borrowed instead of
+        // being moved out of the value it belongs to.
+        let borrow = match inner {
+            Expr::Attr(..) | Expr::OptionalAttr(..) | Expr::Index(..) => "&",
+            _ => "",
+        };
+        buf.write(&format!("(|| ::core::option::Option::Some({borrow}("));
+        self.visit_expr(buf, inner)?;
+        buf.write(")))()");
+        Ok(DisplayWrap::Unwrapped)
+    }
+
+    // `globals` refers to the configured globals, unless it is shadowed by a local variable.
+    fn has_globals(&self) -> bool {
+        let config = &self.input.config;
+        (!config.globals.is_empty() || config.globals_type.is_some())
+            && self.locals.resolve("globals").is_none()
+    }
+
+    fn visit_global(&mut self, buf: &mut Buffer, name: &str) -> Result<DisplayWrap, CompileError> {
+        let config = &self.input.config;
+        if let Some(value) = config.globals.get(name) {
+            buf.write(value);
+        } else if let Some(ty) = config.globals_type {
+            buf.write(&format!(
+                "<{ty} as {CRATE}::Globals>::globals().{}",
+                normalize_identifier(name)
+            ));
+        } else {
+            return Err(format!("unknown global `{name}`").into());
         }
-        self.visit_expr(buf, obj)?;
-        buf.write(&format!(".{}", normalize_identifier(attr)));
         Ok(DisplayWrap::Unwrapped)
     }
 
@@ -1491,6 +3942,25 @@ impl<'a> Generator<'a> {
         obj: &Expr<'_>,
         key: &Expr<'_>,
     ) -> Result<DisplayWrap, CompileError> {
+        if let Expr::Range(..) = key {
+            buf.write("(&(");
+            self.visit_expr(buf, obj)?;
+            buf.write(")).askama_slice(");
+            self.visit_expr(buf, key)?;
+            buf.write(")");
+            return Ok(DisplayWrap::Unwrapped);
+        }
+        // A negative literal index counts from the end, which Rust's indexing doesn't support.
+        // Other keys are left to `Index`, so user types indexed by e.g. `i32` keep working.
+        if matches!(key, Expr::Unary("-", inner) if matches!(**inner, Expr::NumLit(_))) {
+            buf.write("(&(");
+            self.visit_expr(buf, obj)?;
+            buf.write(")).askama_get_item(");
+            self.visit_expr(buf, key)?;
+            buf.write(")");
+            return Ok(DisplayWrap::Unwrapped);
+        }
+
         buf.write("&");
         self.visit_expr(buf, obj)?;
         buf.write("[");
@@ -1525,15 +3995,37 @@ impl<'a> Generator<'a> {
                         buf.writeln("_cycle[_loop_item.index % _len]")?;
                         buf.writeln("})")?;
                     }
-                    _ => return Err("loop.cycle(…) expects exactly one argument".into()),
+                    // Only the selected value is evaluated
+                    [args @ .., last] if !args.is_empty() => {
+                        buf.writeln(&format!("(match _loop_item.index % {} {{", args.len() + 1))?;
+                        for (index, arg) in args.iter().enumerate() {
+                            buf.write(&format!("{index} => "));
+                            self.visit_expr(buf, arg)?;
+                            buf.writeln(",")?;
+                        }
+                        buf.write("_ => ");
+                        self.visit_expr(buf, last)?;
+                        buf.writeln(",")?;
+                        buf.writeln("})")?;
+                    }
+                    _ => return Err("loop.cycle(…) expects at least one argument".into()),
                 },
                 s => return Err(format!("unknown loop method: {s:?}").into()),
             },
+            Expr::Var("loop") => {
+                return Err(
+                    "`loop(…)` must be used on its own, like `{{ loop(item.children) }}`".into(),
+                );
+            }
+            #[cfg(feature = "image-dims")]
+            Expr::Var("image_dims") if self.locals.resolve("image_dims").is_none() => {
+                return self.visit_image_dims(buf, args);
+            }
             left => {
                 match left {
                     Expr::Var(name) => match self.locals.resolve(name) {
                         Some(resolved) => buf.write(&resolved),
-                        None => buf.write(&format!("(&self.{})", normalize_identifier(name))),
+                        None => buf.write(&format!("(&{})", self.resolve_var(name))),
                     },
                     left => {
                         self.visit_expr(buf, left)?;
@@ -1548,6 +4040,43 @@ impl<'a> Generator<'a> {
         Ok(DisplayWrap::Unwrapped)
     }
 
+    // Reads the size of the image at compile time, and makes the generated code depend on it.
+    #[cfg(feature = "image-dims")]
+    fn visit_image_dims(
+        &mut self,
+        buf: &mut Buffer,
+        args: &[Expr<'_>],
+    ) -> Result<DisplayWrap, CompileError> {
+        let [Expr::StrLit(path)] = args else {
+            return Err("`image_dims()` expects the path of an image as a string literal".into());
+        };
+        let root = std::path::PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
+        let path = root.join(&*path.value());
+        let size = self
+            .input
+            .config
+            .files
+            .read(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|data| imagesize::blob_size(&data).map_err(|err| err.to_string()))
+            .map_err(|err| {
+                format!(
+                    "unable to read the size of image '{}': {err}",
+                    path.display()
+                )
+            })?;
+
+        let canonical_path = self.input.config.files.canonicalize(&path).unwrap();
+        let include_path = canonical_path.to_str().unwrap();
+        buf.write(&format!(
+            "{{ {} {CRATE}::ImageDims {{ width: {}, height: {} }} }}",
+            quote!(include_bytes!(#include_path);),
+            size.width,
+            size.height,
+        ));
+        Ok(DisplayWrap::Wrapped)
+    }
+
     fn visit_unary(
         &mut self,
         buf: &mut Buffer,
@@ -1583,12 +4112,41 @@ impl<'a> Generator<'a> {
         left: &Expr<'_>,
         right: &Expr<'_>,
     ) -> Result<DisplayWrap, CompileError> {
+        if let "in" | "not in" = op {
+            return self.visit_membership(buf, op, left, right);
+        }
+
         self.visit_expr(buf, left)?;
         buf.write(&format!(" {op} "));
         self.visit_expr(buf, right)?;
         Ok(DisplayWrap::Unwrapped)
     }
 
+    // A string literal is passed as is, so it can be looked up as a `str` in e.g. a `Vec<String>`.
+    fn visit_membership(
+        &mut self,
+        buf: &mut Buffer,
+        op: &str,
+        item: &Expr<'_>,
+        container: &Expr<'_>,
+    ) -> Result<DisplayWrap, CompileError> {
+        if op == "not in" {
+            buf.write("!");
+        }
+        buf.write("(&(");
+        self.visit_expr(buf, container)?;
+        buf.write(")).askama_contains(");
+        if let Expr::StrLit(_) = item {
+            self.visit_expr(buf, item)?;
+        } else {
+            buf.write("&(");
+            self.visit_expr(buf, item)?;
+            buf.write(")");
+        }
+        buf.write(")");
+        Ok(DisplayWrap::Unwrapped)
+    }
+
     fn visit_group(
         &mut self,
         buf: &mut Buffer,
@@ -1642,7 +4200,35 @@ impl<'a> Generator<'a> {
         Ok(DisplayWrap::Unwrapped)
     }
 
+    fn visit_map(
+        &mut self,
+        buf: &mut Buffer,
+        entries: &[(Expr<'_>, Expr<'_>)],
+    ) -> Result<DisplayWrap, CompileError> {
+        buf.write("::std::collections::BTreeMap::from([");
+        for (i, (key, value)) in entries.iter().enumerate() {
+            if i > 0 {
+                buf.write(", ");
+            }
+            buf.write("(");
+            self.visit_expr(buf, key)?;
+            buf.write(", ");
+            self.visit_expr(buf, value)?;
+            buf.write(")");
+        }
+        buf.write("])");
+        Ok(DisplayWrap::Unwrapped)
+    }
+
     fn visit_path(&mut self, buf: &mut Buffer, path: &[&str]) -> DisplayWrap {
+        // Capitalized names like `PAGE_SIZE` are parsed as paths, and can be constants, unless a
+        // local variable shadows them.
+        if let [name] = path {
+            if let (None, Some(value)) = (self.locals.resolve(name), self.consts.get(name)) {
+                buf.write(value);
+                return DisplayWrap::Unwrapped;
+            }
+        }
         for (i, part) in path.iter().enumerate() {
             if i > 0 {
                 buf.write("::");
@@ -1658,10 +4244,53 @@ impl<'a> Generator<'a> {
             return DisplayWrap::Unwrapped;
         }
 
-        buf.write(normalize_identifier(&self.locals.resolve_or_self(s)));
+        buf.write(normalize_identifier(&self.resolve_var(s)));
         DisplayWrap::Unwrapped
     }
 
+    // Returns the code of the variable `name`: a local variable, a constant, or else a field of
+    // the template, or of the context the template was included with.
+    fn resolve_var(&self, name: &str) -> String {
+        if let Some(var) = self.locals.resolve(name) {
+            return var;
+        }
+        if let Some(value) = self.consts.get(name) {
+            return value.clone();
+        }
+        match &self.root {
+            Some(root) => format!("{root}.{}", normalize_identifier(name)),
+            None => format!("self.{}", normalize_identifier(name)),
+        }
+    }
+
+    // Generates the code of the constants of `ctx` and of the templates it extends, starting with
+    // the one at the root of the chain, so a template can use the constants of its parents and
+    // override them.
+    fn collect_consts(&mut self, ctx: &Context<'a>) -> Result<(), CompileError> {
+        let mut chain = vec![ctx];
+        let mut ctx = ctx;
+        while let Some(path) = &ctx.extends {
+            ctx = &self.contexts[path];
+            chain.push(ctx);
+        }
+
+        for ctx in chain.into_iter().rev() {
+            for c in &ctx.consts {
+                check_const_value(&c.value, &self.consts).map_err(|var| {
+                    CompileError::from(format!(
+                        "the value of the constant `{}` can only use literals, paths and the \
+                         constants defined before it, not `{var}`",
+                        c.name,
+                    ))
+                })?;
+                let mut value = Buffer::new(0);
+                self.visit_expr(&mut value, &c.value)?;
+                self.consts.insert(c.name, format!("({})", value.buf));
+            }
+        }
+        Ok(())
+    }
+
     fn visit_generated(&mut self, buf: &mut Buffer, s: &str) -> DisplayWrap {
         buf.write(s);
         DisplayWrap::Unwrapped
@@ -1672,11 +4301,36 @@ impl<'a> Generator<'a> {
         DisplayWrap::Unwrapped
     }
 
-    fn visit_str_lit(&mut self, buf: &mut Buffer, s: &str) -> DisplayWrap {
-        buf.write(&format!("\"{s}\""));
+    fn visit_str_lit(&mut self, buf: &mut Buffer, s: &StrLit<'_>) -> DisplayWrap {
+        buf.write(&str_lit_source(s));
         DisplayWrap::Unwrapped
     }
 
+    fn visit_format_str(
+        &mut self,
+        buf: &mut Buffer,
+        parts: &[FormatPart<'_>],
+    ) -> Result<DisplayWrap, CompileError> {
+        buf.write("::std::format!(\"");
+        for part in parts {
+            match part {
+                FormatPart::Lit(s) => buf.write(s),
+                FormatPart::Expr(_, None) => buf.write("{}"),
+                FormatPart::Expr(_, Some(spec)) => buf.write(&format!("{{:{spec}}}")),
+            }
+        }
+        buf.write("\"");
+        for part in parts {
+            if let FormatPart::Expr(expr, _) = part {
+                buf.write(", &(");
+                self.visit_expr(buf, expr)?;
+                buf.write(")");
+            }
+        }
+        buf.write(")");
+        Ok(DisplayWrap::Unwrapped)
+    }
+
     fn visit_char_lit(&mut self, buf: &mut Buffer, s: &str) -> DisplayWrap {
         buf.write(&format!("'{s}'"));
         DisplayWrap::Unwrapped
@@ -1692,7 +4346,7 @@ impl<'a> Generator<'a> {
         buf: &mut Buffer,
         initialized: bool,
         first_level: bool,
-        target: &Target<'a>,
+        target: &Target<'_>,
     ) {
         match target {
             Target::Name("_") => {
@@ -1700,11 +4354,10 @@ impl<'a> Generator<'a> {
             }
             Target::Name(name) => {
                 let name = normalize_identifier(name);
+                let var = Cow::Owned(name.to_owned());
                 match initialized {
-                    true => self
-                        .locals
-                        .insert(Cow::Borrowed(name), LocalMeta::initialized()),
-                    false => self.locals.insert_with_default(Cow::Borrowed(name)),
+                    true => self.locals.insert(var, LocalMeta::initialized()),
+                    false => self.locals.insert_with_default(var),
                 }
                 buf.write(name);
             }
@@ -1739,7 +4392,19 @@ impl<'a> Generator<'a> {
                 buf.write(" }");
             }
             Target::Path(path) => {
-                self.visit_path(buf, path);
+                buf.write(&path.join("::"));
+            }
+            Target::Binding(name, target) => {
+                self.visit_target(buf, initialized, first_level, &Target::Name(name));
+                buf.write(" @ ");
+                let is_or_chain = matches!(**target, Target::OrChain(_));
+                if is_or_chain {
+                    buf.write("(");
+                }
+                self.visit_target(buf, initialized, first_level, target);
+                if is_or_chain {
+                    buf.write(")");
+                }
             }
             Target::StrLit(s) => {
                 if first_level {
@@ -1765,6 +4430,21 @@ impl<'a> Generator<'a> {
                 }
                 buf.write(s);
             }
+            Target::Range(op, start, end) => {
+                if first_level {
+                    buf.write("&(");
+                }
+                if let Some(start) = start {
+                    self.visit_target(buf, initialized, false, start);
+                }
+                buf.write(op);
+                if let Some(end) = end {
+                    self.visit_target(buf, initialized, false, end);
+                }
+                if first_level {
+                    buf.write(")");
+                }
+            }
         }
     }
 
@@ -1777,12 +4457,21 @@ impl<'a> Generator<'a> {
         self.prepare_ws(ws);
     }
 
-    fn should_trim_ws(&self, ws: Option<Whitespace>) -> WhitespaceHandling {
-        match ws {
-            Some(Whitespace::Suppress) => WhitespaceHandling::Suppress,
-            Some(Whitespace::Preserve) => WhitespaceHandling::Preserve,
-            Some(Whitespace::Minimize) => WhitespaceHandling::Minimize,
-            None => self.input.config.whitespace,
+    // Like `handle_ws()`, for an expression tag, which `trim_blocks` and `lstrip_blocks` don't
+    // apply to.
+    fn handle_expr_ws(&mut self, ws: Ws) {
+        self.flush_ws_from(WsSource::expr(ws.0));
+        self.skip_ws = WsSource::expr(ws.1);
+    }
+
+    fn ws_handling(&self, source: WsSource) -> WhitespaceHandling {
+        match source {
+            WsSource::Marker(Whitespace::Suppress) => WhitespaceHandling::Suppress,
+            WsSource::Marker(Whitespace::Preserve) => WhitespaceHandling::Preserve,
+            WsSource::Marker(Whitespace::Minimize) => WhitespaceHandling::Minimize,
+            WsSource::Marker(Whitespace::Linebreak) => WhitespaceHandling::Linebreak,
+            WsSource::Default | WsSource::Expr => self.input.config.whitespace,
+            WsSource::NoTag => WhitespaceHandling::Preserve,
         }
     }
 
@@ -1790,42 +4479,176 @@ impl<'a> Generator<'a> {
     // prefix whitespace suppressor from the given argument, flush that whitespace.
     // In either case, `next_ws` is reset to `None` (no trailing whitespace).
     fn flush_ws(&mut self, ws: Ws) {
-        if self.next_ws.is_none() {
+        let source = WsSource::new(ws.0);
+        if source == WsSource::Default && self.input.lstrip_blocks {
+            self.lstrip_next_ws();
+        }
+        self.flush_ws_from(source);
+    }
+
+    // With `lstrip_blocks`, removes the indentation of the line of the next block tag, if the tag
+    // starts the line.
+    fn lstrip_next_ws(&mut self) {
+        let Some(val) = &self.next_ws else {
+            return;
+        };
+        let start = match val.rfind('\n') {
+            Some(index) => index + 1,
+            None if self.next_ws_line_start => 0,
+            None => return,
+        };
+        self.next_ws = Some(match self.next_ws.take().unwrap() {
+            Cow::Borrowed(val) => Cow::Borrowed(&val[..start]),
+            Cow::Owned(mut val) => {
+                val.truncate(start);
+                Cow::Owned(val)
+            }
+        });
+    }
+
+    fn flush_ws_from(&mut self, source: WsSource) {
+        let Some(val) = self.next_ws.take() else {
+            return;
+        };
+        if val.is_empty() {
             return;
         }
 
+        // Whitespace between two tags is handled by their markers, or by the `whitespace`
+        // setting if neither has one.
+        let source = self.next_ws_source.combine(source);
         // If `whitespace` is set to `suppress`, we keep the whitespace characters only if there is
         // a `+` character.
-        match self.should_trim_ws(ws.0) {
+        match self.ws_handling(source) {
             WhitespaceHandling::Preserve => {
-                let val = self.next_ws.unwrap();
-                if !val.is_empty() {
-                    self.buf_writable.push(Writable::Lit(val));
-                }
+                self.buf_writable.push(Writable::Lit(val.clone()));
             }
-            WhitespaceHandling::Minimize => {
-                let val = self.next_ws.unwrap();
-                if !val.is_empty() {
-                    self.buf_writable
-                        .push(Writable::Lit(match val.contains('\n') {
-                            true => "\n",
-                            false => " ",
-                        }));
+            handling @ (WhitespaceHandling::Minimize | WhitespaceHandling::Linebreak) => {
+                if let Some(ws) = collapse_ws(&val, handling) {
+                    self.buf_writable.push(Writable::Lit(Cow::Borrowed(ws)));
                 }
             }
             WhitespaceHandling::Suppress => {}
         }
-        self.next_ws = None;
+        let position = match self.next_ws_after {
+            "" => "between tags".to_owned(),
+            after => format!("after {:?}", excerpt(after, true)),
+        };
+        self.report_ws(&val, &position, source);
     }
 
     // Sets `skip_ws` to match the suffix whitespace suppressor from the given
     // argument, to determine whether to suppress leading whitespace from the
     // next literal.
     fn prepare_ws(&mut self, ws: Ws) {
-        self.skip_ws = self.should_trim_ws(ws.1);
+        self.skip_ws = WsSource::new(ws.1);
+    }
+
+    // With `print = "whitespace"`, reports how the whitespace `ws` of a literal was handled and why.
+    fn report_ws(&mut self, ws: &str, position: &str, source: WsSource) {
+        if !self.print_ws {
+            return;
+        }
+
+        let action = match self.ws_handling(source) {
+            WhitespaceHandling::Preserve => "preserved",
+            WhitespaceHandling::Suppress => "suppressed",
+            WhitespaceHandling::Minimize => "minimized",
+            WhitespaceHandling::Linebreak => "collapsed to a line break",
+        };
+        let reason = match source {
+            WsSource::Marker(Whitespace::Suppress) => "`-` marker",
+            WsSource::Marker(Whitespace::Preserve) => "`+` marker",
+            WsSource::Marker(Whitespace::Minimize) => "`~` marker",
+            WsSource::Marker(Whitespace::Linebreak) => "`^` marker",
+            WsSource::Default | WsSource::Expr => match self.input.config.whitespace {
+                WhitespaceHandling::Preserve => "`whitespace = \"preserve\"`",
+                WhitespaceHandling::Suppress => "`whitespace = \"suppress\"`",
+                WhitespaceHandling::Minimize => "`whitespace = \"minimize\"`",
+                WhitespaceHandling::Linebreak => "`whitespace = \"linebreak\"`",
+            },
+            WsSource::NoTag => "no adjacent tag",
+        };
+        let block = match self.location.block {
+            Some(block) => format!(", block `{block}`"),
+            None => String::new(),
+        };
+        self.ws_reports.push(format!(
+            "{}{block}: {ws:?} {position}: {action} ({reason})",
+            self.location.template,
+        ));
+    }
+}
+
+// Where the handling of the whitespace next to a tag comes from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum WsSource {
+    // A `-`, `+` or `~` marker on the tag
+    Marker(Whitespace),
+    // The `whitespace` setting, for block tags without marker
+    Default,
+    // The `whitespace` setting, for expression tags without marker
+    Expr,
+    // No adjacent tag, e.g. at the start of the template: the whitespace is kept
+    NoTag,
+}
+
+impl WsSource {
+    fn new(marker: Option<Whitespace>) -> Self {
+        marker.map_or(Self::Default, Self::Marker)
+    }
+
+    fn expr(marker: Option<Whitespace>) -> Self {
+        marker.map_or(Self::Expr, Self::Marker)
+    }
+
+    // Combines the handling of the whitespace after a tag with the one of the next tag, for the
+    // whitespace between them: a marker takes precedence over the `whitespace` setting, and of
+    // two markers, the one removing the most whitespace wins.
+    fn combine(self, next: Self) -> Self {
+        fn strength(ws: Whitespace) -> u8 {
+            match ws {
+                Whitespace::Preserve => 0,
+                Whitespace::Minimize => 1,
+                Whitespace::Linebreak => 2,
+                Whitespace::Suppress => 3,
+            }
+        }
+
+        match (self, next) {
+            (Self::Marker(prev), Self::Marker(next)) if strength(prev) > strength(next) => self,
+            (Self::Marker(_), Self::Marker(_)) => next,
+            (Self::Marker(_), _) => self,
+            _ => next,
+        }
     }
 }
 
+// Collapses the whitespace `ws` with `~` (`Minimize`) or `^` (`Linebreak`), returning what's left
+// of it.
+fn collapse_ws(ws: &str, handling: WhitespaceHandling) -> Option<&'static str> {
+    match (ws.contains('\n'), handling) {
+        (true, _) => Some("\n"),
+        (false, WhitespaceHandling::Minimize) => Some(" "),
+        (false, _) => None,
+    }
+}
+
+// Shortens a literal to its first (or last) characters, to show where some whitespace is.
+fn excerpt(s: &str, end: bool) -> Cow<'_, str> {
+    const LEN: usize = 20;
+    if s.chars().count() <= LEN {
+        return Cow::Borrowed(s);
+    }
+    Cow::Owned(match end {
+        false => format!("{}…", s.chars().take(LEN).collect::<String>()),
+        true => {
+            let start = s.char_indices().rev().nth(LEN - 1).map_or(0, |(i, _)| i);
+            format!("…{}", &s[start..])
+        }
+    })
+}
+
 struct Buffer {
     // The buffer to generate the code into
     buf: String,
@@ -1895,6 +4718,8 @@ impl Buffer {
 pub(crate) struct LocalMeta {
     refs: Option<String>,
     initialized: bool,
+    // Declared with `let mut`, so assigning it updates it instead of shadowing it
+    mutable: bool,
 }
 
 impl LocalMeta {
@@ -1902,6 +4727,7 @@ impl LocalMeta {
         Self {
             refs: None,
             initialized: true,
+            mutable: false,
         }
     }
 
@@ -1909,6 +4735,15 @@ impl LocalMeta {
         Self {
             refs: Some(refs),
             initialized: true,
+            mutable: false,
+        }
+    }
+
+    fn mutable(initialized: bool) -> Self {
+        Self {
+            refs: None,
+            initialized,
+            mutable: true,
         }
     }
 }
@@ -1969,6 +4804,18 @@ where
         self.scopes.push(HashMap::new());
     }
 
+    /// Returns the chain without the scopes pushed after the first one.
+    fn top_level(&self) -> Self
+    where
+        K: Clone,
+        V: Clone,
+    {
+        MapChain {
+            parent: self.parent,
+            scopes: vec![self.scopes[0].clone()],
+        }
+    }
+
     fn pop(&mut self) {
         self.scopes.pop().unwrap();
         assert!(!self.scopes.is_empty());
@@ -1984,9 +4831,34 @@ impl MapChain<'_, Cow<'_, str>, LocalMeta> {
         })
     }
 
-    fn resolve_or_self(&self, name: &str) -> String {
-        let name = normalize_identifier(name);
-        self.resolve(name).unwrap_or_else(|| format!("self.{name}"))
+    /// Returns the sorted names of the initialized variables declared with `let mut`, which
+    /// aren't shadowed by other variables.
+    fn mutable_names(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        let mut chain = Some(self);
+        while let Some(map) = chain {
+            names.extend(map.scopes.iter().flat_map(|scope| scope.keys()));
+            chain = map.parent;
+        }
+        let mut names = names
+            .into_iter()
+            .filter(
+                |&name| matches!(self.get(name), Some(meta) if meta.mutable && meta.initialized),
+            )
+            .map(|name| name.to_string())
+            .collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+        names
+    }
+}
+
+/// Returns the start of the statement calling a recursive closure, which assigns the mutable
+/// variables in `state` the values it returns, if there are any.
+fn assign_state(state: &str) -> String {
+    match state {
+        "()" => String::new(),
+        _ => format!("{state} = "),
     }
 }
 
@@ -2013,6 +4885,9 @@ fn is_copyable_within_op(expr: &Expr<'_>, within_op: bool) -> bool {
             is_copyable_within_op(lhs, true) && is_copyable_within_op(rhs, true)
         }
         Expr::Range(..) => true,
+        // A closure is passed as is, so the types of its parameters are inferred from the
+        // function it's passed to.
+        Expr::Closure(..) => true,
         // The result of a call likely doesn't need to be borrowed,
         // as in that case the call is more likely to return a
         // reference in the first place then.
@@ -2036,6 +4911,193 @@ pub(crate) fn is_attr_self(expr: &Expr<'_>) -> bool {
     }
 }
 
+// Rejects the expressions that can panic at runtime in templates with `no_panic = true`.
+// The operands of `expr` are checked when they are visited themselves.
+fn check_no_panic(expr: &Expr<'_>) -> Result<(), CompileError> {
+    let reason = match expr {
+        // Slicing with a range clamps the range instead of panicking
+        Expr::Index(_, key) if !matches!(**key, Expr::Range(..)) => {
+            "indexing can panic, use `.get()` instead"
+        }
+        Expr::Call(obj, _)
+            if matches!(
+                **obj,
+                Expr::Attr(_, "unwrap" | "expect" | "unwrap_err" | "expect_err")
+            ) =>
+        {
+            "it can panic, use `match`, `if let` or the `unwrap_or` filter instead"
+        }
+        Expr::RustMacro(path, _)
+            if matches!(
+                path.last(),
+                Some(
+                    &("panic"
+                        | "unreachable"
+                        | "todo"
+                        | "unimplemented"
+                        | "assert"
+                        | "assert_eq"
+                        | "assert_ne")
+                )
+            ) =>
+        {
+            "this macro can panic"
+        }
+        Expr::Filter(Filter { name: "abs", .. }) => "the `abs` filter can overflow",
+        // Dividing by a literal can only panic if it's zero, which doesn't compile
+        Expr::BinOp("/" | "%", _, divisor) if !matches!(**divisor, Expr::NumLit(_)) => {
+            "it panics if the divisor is zero or the division overflows, use `.checked_div()` or \
+             `.checked_rem()` instead"
+        }
+        _ => return Ok(()),
+    };
+    Err(format!(
+        "`{}` is not allowed with `no_panic = true`: {reason}",
+        expr_source(expr)
+    )
+    .into())
+}
+
+/// Returns the template source of an expression, as shown by `render_debug()`.
+fn expr_source(expr: &Expr<'_>) -> String {
+    fn list(exprs: &[Expr<'_>]) -> String {
+        exprs.iter().map(expr_source).collect::<Vec<_>>().join(", ")
+    }
+
+    match expr {
+        Expr::BoolLit(s) | Expr::NumLit(s) | Expr::Var(s) => (*s).to_owned(),
+        Expr::StrLit(s) => str_lit_source(s),
+        Expr::FormatStr(parts) => {
+            let parts = parts
+                .iter()
+                .map(|part| match part {
+                    FormatPart::Lit(s) => (*s).to_owned(),
+                    FormatPart::Expr(expr, None) => format!("{{{}}}", expr_source(expr)),
+                    FormatPart::Expr(expr, Some(spec)) => {
+                        format!("{{{}:{spec}}}", expr_source(expr))
+                    }
+                })
+                .collect::<String>();
+            format!("f\"{parts}\"")
+        }
+        Expr::CharLit(s) => format!("'{s}'"),
+        Expr::Path(path) => path.join("::"),
+        Expr::Array(elements) => format!("[{}]", list(elements)),
+        Expr::Map(entries) => {
+            let entries = entries
+                .iter()
+                .map(|(key, value)| format!("{}: {}", expr_source(key), expr_source(value)))
+                .collect::<Vec<_>>();
+            format!("{{{}}}", entries.join(", "))
+        }
+        Expr::Attr(obj, attr) => format!("{}.{attr}", expr_source(obj)),
+        Expr::OptionalAttr(obj, attr) => format!("{}?.{attr}", expr_source(obj)),
+        Expr::OptionalChain(inner) => expr_source(inner),
+        Expr::Index(obj, key) => format!("{}[{}]", expr_source(obj), expr_source(key)),
+        Expr::Filter(Filter { name, arguments }) => filter_source(name, arguments),
+        Expr::Test(Test {
+            name,
+            negated,
+            arguments,
+        }) => {
+            let not = if *negated { "not " } else { "" };
+            match arguments.split_first() {
+                Some((value, [])) => format!("{} is {not}{name}", expr_source(value)),
+                Some((value, args)) => {
+                    format!("{} is {not}{name}({})", expr_source(value), list(args))
+                }
+                None => format!("is {not}{name}"),
+            }
+        }
+        Expr::NamedArgument(name, value) => format!("{name} = {}", expr_source(value)),
+        Expr::Spread(value) => format!("*{}", expr_source(value)),
+        Expr::Unary(op, value) => format!("{op}{}", expr_source(value)),
+        Expr::BinOp(op, left, right) => {
+            format!("{} {op} {}", expr_source(left), expr_source(right))
+        }
+        Expr::Range(op, start, end) => format!(
+            "{}{op}{}",
+            start.as_deref().map(expr_source).unwrap_or_default(),
+            end.as_deref().map(expr_source).unwrap_or_default(),
+        ),
+        Expr::Group(inner) => format!("({})", expr_source(inner)),
+        Expr::Tuple(elements) if elements.len() == 1 => format!("({},)", list(elements)),
+        Expr::Tuple(elements) => format!("({})", list(elements)),
+        Expr::Call(callee, args) => format!("{}({})", expr_source(callee), list(args)),
+        Expr::Turbofish(callee, types) => {
+            format!("{}::<{}>", expr_source(callee), types.join(", "))
+        }
+        Expr::RustMacro(path, args) => format!("{}!({args})", path.join("::")),
+        Expr::Try(inner) => format!("{}?", expr_source(inner)),
+        Expr::Closure(params, body) => format!("|{}| {}", params.join(", "), expr_source(body)),
+        Expr::Conditional(cond, then, otherwise) => format!(
+            "{} if {} else {}",
+            expr_source(then),
+            expr_source(cond),
+            expr_source(otherwise)
+        ),
+        Expr::TypeHint(inner, ty) => format!("{} : {ty}", expr_source(inner)),
+        Expr::Matches(inner, pattern) => {
+            format!("{} matches {}", expr_source(inner), target_source(pattern))
+        }
+        Expr::Generated(s) => s.clone(),
+    }
+}
+
+fn target_source(target: &Target<'_>) -> String {
+    fn list(targets: &[Target<'_>]) -> String {
+        targets
+            .iter()
+            .map(target_source)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    match target {
+        Target::Name(s) | Target::NumLit(s) | Target::BoolLit(s) => (*s).to_owned(),
+        Target::StrLit(s) => str_lit_source(s),
+        Target::CharLit(s) => format!("'{s}'"),
+        Target::Path(path) => path.join("::"),
+        Target::Tuple(path, targets) => format!("{}({})", path.join("::"), list(targets)),
+        Target::Struct(path, targets) => {
+            let fields = targets
+                .iter()
+                .map(|(name, target)| format!("{name}: {}", target_source(target)))
+                .collect::<Vec<_>>();
+            format!("{} {{ {} }}", path.join("::"), fields.join(", "))
+        }
+        Target::OrChain(targets) => targets
+            .iter()
+            .map(target_source)
+            .collect::<Vec<_>>()
+            .join(" | "),
+        Target::Binding(name, target) => format!("{name} @ {}", target_source(target)),
+        Target::Range(op, start, end) => format!(
+            "{}{op}{}",
+            start.as_deref().map(target_source).unwrap_or_default(),
+            end.as_deref().map(target_source).unwrap_or_default(),
+        ),
+    }
+}
+
+fn str_lit_source(s: &StrLit<'_>) -> String {
+    match s.raw {
+        Some(hashes) => format!("r{0}\"{1}\"{0}", "#".repeat(hashes), s.content),
+        None => format!("\"{}\"", s.content),
+    }
+}
+
+fn filter_source(name: &str, arguments: &[Expr<'_>]) -> String {
+    match arguments.split_first() {
+        Some((value, [])) => format!("{}|{name}", expr_source(value)),
+        Some((value, args)) => {
+            let args = args.iter().map(expr_source).collect::<Vec<_>>();
+            format!("{}|{name}({})", expr_source(value), args.join(", "))
+        }
+        None => name.to_string(),
+    }
+}
+
 /// Returns `true` if the outcome of this expression may be used multiple times in the same
 /// `write!()` call, without evaluating the expression again, i.e. the expression should be
 /// side-effect free.
@@ -2051,9 +5113,18 @@ pub(crate) fn is_cacheable(expr: &Expr<'_>) -> bool {
         Expr::Path(_) => true,
         // Check recursively:
         Expr::Array(args) => args.iter().all(is_cacheable),
-        Expr::Attr(lhs, _) => is_cacheable(lhs),
+        Expr::Map(entries) => entries
+            .iter()
+            .all(|(key, value)| is_cacheable(key) && is_cacheable(value)),
+        Expr::FormatStr(parts) => parts.iter().all(|part| match part {
+            FormatPart::Lit(_) => true,
+            FormatPart::Expr(expr, _) => is_cacheable(expr),
+        }),
+        Expr::Attr(lhs, _) | Expr::OptionalAttr(lhs, _) => is_cacheable(lhs),
+        Expr::OptionalChain(inner) => is_cacheable(inner),
         Expr::Index(lhs, rhs) => is_cacheable(lhs) && is_cacheable(rhs),
         Expr::Filter(Filter { arguments, .. }) => arguments.iter().all(is_cacheable),
+        Expr::Test(Test { arguments, .. }) => arguments.iter().all(is_cacheable),
         Expr::Unary(_, arg) => is_cacheable(arg),
         Expr::BinOp(_, lhs, rhs) => is_cacheable(lhs) && is_cacheable(rhs),
         Expr::Range(_, lhs, rhs) => {
@@ -2062,15 +5133,151 @@ pub(crate) fn is_cacheable(expr: &Expr<'_>) -> bool {
         }
         Expr::Group(arg) => is_cacheable(arg),
         Expr::Tuple(args) => args.iter().all(is_cacheable),
-        Expr::NamedArgument(_, expr) => is_cacheable(expr),
+        Expr::NamedArgument(_, expr) | Expr::Spread(expr) => is_cacheable(expr),
         // We have too little information to tell if the expression is pure:
         Expr::Call(_, _) => false,
         Expr::RustMacro(_, _) => false,
         Expr::Try(_) => false,
+        Expr::Conditional(cond, then, otherwise) => {
+            is_cacheable(cond) && is_cacheable(then) && is_cacheable(otherwise)
+        }
+        Expr::Closure(_, body) => is_cacheable(body),
+        Expr::TypeHint(expr, _) => is_cacheable(expr),
+        Expr::Turbofish(expr, _) => is_cacheable(expr),
+        Expr::Matches(expr, _) => is_cacheable(expr),
         Expr::Generated(_) => true,
     }
 }
 
+// Checks that the value of a `{% const %}` is a constant expression, which only uses `consts`.
+// Otherwise returns the source of the expression that is not allowed.
+fn check_const_value(expr: &Expr<'_>, consts: &HashMap<&str, String>) -> Result<(), String> {
+    match expr {
+        Expr::BoolLit(_) | Expr::NumLit(_) | Expr::StrLit(_) | Expr::CharLit(_) | Expr::Path(_) => {
+            Ok(())
+        }
+        Expr::Var(name) if consts.contains_key(name) => Ok(()),
+        Expr::Array(args) | Expr::Tuple(args) => args
+            .iter()
+            .try_for_each(|arg| check_const_value(arg, consts)),
+        Expr::Attr(expr, _)
+        | Expr::Unary(_, expr)
+        | Expr::Group(expr)
+        | Expr::TypeHint(expr, _) => check_const_value(expr, consts),
+        Expr::Index(lhs, rhs) | Expr::BinOp(_, lhs, rhs) => {
+            check_const_value(lhs, consts)?;
+            check_const_value(rhs, consts)
+        }
+        Expr::Range(_, lhs, rhs) => [lhs, rhs]
+            .into_iter()
+            .flatten()
+            .try_for_each(|expr| check_const_value(expr, consts)),
+        Expr::Conditional(cond, then, otherwise) => [cond, then, otherwise]
+            .into_iter()
+            .try_for_each(|expr| check_const_value(expr, consts)),
+        _ => Err(expr_source(expr)),
+    }
+}
+
+fn expr_uses_loop_length(expr: &Expr<'_>) -> bool {
+    match expr {
+        Expr::Attr(obj, "length") if **obj == Expr::Var("loop") => true,
+        Expr::BoolLit(_)
+        | Expr::NumLit(_)
+        | Expr::StrLit(_)
+        | Expr::CharLit(_)
+        | Expr::Var(_)
+        | Expr::Path(_)
+        | Expr::RustMacro(_, _)
+        | Expr::Generated(_) => false,
+        Expr::Array(args) | Expr::Tuple(args) => args.iter().any(expr_uses_loop_length),
+        Expr::Map(entries) => entries
+            .iter()
+            .any(|(key, value)| expr_uses_loop_length(key) || expr_uses_loop_length(value)),
+        Expr::FormatStr(parts) => parts
+            .iter()
+            .any(|part| matches!(part, FormatPart::Expr(expr, _) if expr_uses_loop_length(expr))),
+        Expr::Attr(obj, _) | Expr::OptionalAttr(obj, _) | Expr::OptionalChain(obj) => {
+            expr_uses_loop_length(obj)
+        }
+        Expr::Index(lhs, rhs) | Expr::BinOp(_, lhs, rhs) => {
+            expr_uses_loop_length(lhs) || expr_uses_loop_length(rhs)
+        }
+        Expr::Filter(Filter { arguments, .. }) | Expr::Test(Test { arguments, .. }) => {
+            arguments.iter().any(expr_uses_loop_length)
+        }
+        Expr::NamedArgument(_, expr)
+        | Expr::Spread(expr)
+        | Expr::Unary(_, expr)
+        | Expr::Group(expr)
+        | Expr::Try(expr)
+        | Expr::TypeHint(expr, _)
+        | Expr::Turbofish(expr, _)
+        | Expr::Matches(expr, _)
+        | Expr::Closure(_, expr) => expr_uses_loop_length(expr),
+        Expr::Range(_, lhs, rhs) => [lhs, rhs]
+            .into_iter()
+            .flatten()
+            .any(|expr| expr_uses_loop_length(expr)),
+        Expr::Call(path, args) => {
+            expr_uses_loop_length(path) || args.iter().any(expr_uses_loop_length)
+        }
+        Expr::Conditional(cond, then, otherwise) => [cond, then, otherwise]
+            .into_iter()
+            .any(|expr| expr_uses_loop_length(expr)),
+    }
+}
+
+// Returns the indentation of the line containing the tag `nodes[index]`, if the literals before
+// it in `nodes` contain the start of the line.
+fn tag_indent(nodes: &[Node<'_>], index: usize) -> Option<usize> {
+    let mut line = String::new();
+    for node in nodes[..index].iter().rev() {
+        if let Node::Lit(Lit { lws, val, rws }) = node {
+            let text = format!("{lws}{val}{rws}");
+            if let Some(pos) = text.rfind('\n') {
+                line.insert_str(0, &text[pos + 1..]);
+                return Some(indentation(&line));
+            }
+            line.insert_str(0, &text);
+        }
+    }
+    None
+}
+
+// Returns the smallest indentation of the lines of a block body, ignoring blank lines and the
+// line of the tag closing the body.
+fn body_indent(nodes: &[Node<'_>]) -> Option<usize> {
+    let mut min = None;
+    for (index, node) in nodes.iter().enumerate() {
+        let Node::Lit(Lit { lws, val, rws }) = node else {
+            continue;
+        };
+        let text = format!("{lws}{val}{rws}");
+        let lines = text.split('\n').collect::<Vec<_>>();
+        // The first line continues the line of the previous node.
+        for (i, line) in lines.iter().enumerate().skip(1) {
+            let last = i == lines.len() - 1;
+            let is_indent = if last {
+                // The last line is followed by the next node, which is the closing tag if this
+                // literal ends the body.
+                index + 1 < nodes.len()
+            } else {
+                !line.trim().is_empty()
+            };
+            if is_indent {
+                let indent = indentation(line);
+                min = Some(min.map_or(indent, |min: usize| min.min(indent)));
+            }
+        }
+    }
+    min
+}
+
+fn indentation(line: &str) -> usize {
+    line.chars().take_while(|&c| c == ' ' || c == '\t').count()
+}
+
 fn median(sizes: &mut [usize]) -> usize {
     sizes.sort_unstable();
     if sizes.len() % 2 == 1 {
@@ -2120,6 +5327,33 @@ fn insert_first_filter_argument(args: &mut Vec<Expr<'_>>, var_name: String) {
     }
 }
 
+// A block rendered by `write_block()`.
+#[derive(Clone, Copy)]
+enum BlockCall<'a> {
+    // The definition of the block with this name
+    Def(&'a str),
+    // A `super()` call, going up the given number of levels, with its arguments
+    Super(usize, &'a [Expr<'a>]),
+}
+
+// Returns the number of levels a call of `name` goes up in the ancestry of the current block, if
+// it is `super` or a dotted chain of them like `super.super`.
+fn super_levels(name: &str) -> Option<usize> {
+    match name.split('.').all(|part| part == "super") {
+        true => Some(name.split('.').count()),
+        false => None,
+    }
+}
+
+// Whether a variable exists, for the `defined` test.
+#[derive(Clone, Copy, PartialEq)]
+enum Defined {
+    No,
+    Yes,
+    // A template field which may be `None`
+    Optional,
+}
+
 #[derive(Clone, Copy, PartialEq)]
 enum AstLevel {
     Top,
@@ -2157,11 +5391,18 @@ impl<'a> Deref for WritableBuffer<'a> {
 
 #[derive(Debug)]
 enum Writable<'a> {
-    Lit(&'a str),
-    Expr(&'a Expr<'a>),
+    Lit(Cow<'a, str>),
+    Expr(&'a Expr<'a>, Location<'a>),
     Generated(String, DisplayWrap),
 }
 
+// The template and block in which an expression was written
+#[derive(Clone, Debug)]
+struct Location<'a> {
+    template: Rc<str>,
+    block: Option<&'a str>,
+}
+
 struct WriteParts {
     size_hint: usize,
     buffers: Option<WritePartsBuffers>,
@@ -2183,6 +5424,80 @@ struct WritePartsBuffers {
     expr: Option<Buffer>,
 }
 
+// Returns the expressions passed to each parameter of `def` by a call with `args`.
+fn macro_arguments<'e>(
+    name: &str,
+    def: &'e Macro<'e>,
+    args: &'e [Expr<'e>],
+) -> Result<Vec<&'e Expr<'e>>, CompileError> {
+    let required = def
+        .args
+        .iter()
+        .filter(|(_, default)| default.is_none())
+        .count();
+    if args.len() < required || args.len() > def.args.len() {
+        let expected = if required == def.args.len() {
+            format!(
+                "{required} argument{}",
+                if required != 1 { "s" } else { "" }
+            )
+        } else {
+            format!("{required} to {} arguments", def.args.len())
+        };
+        return Err(CompileError::from(format!(
+            "macro {name:?} expected {expected}, found {}",
+            args.len()
+        )));
+    }
+    let mut named_arguments = HashMap::new();
+    // Since named arguments can only be passed last, we only need to check if the last argument
+    // is a named one.
+    if let Some(Expr::NamedArgument(_, _)) = args.last() {
+        // First we check that all named arguments actually exist in the called item.
+        for arg in args.iter().rev() {
+            let Expr::NamedArgument(arg_name, _) = arg else {
+                break;
+            };
+            if !def.args.iter().any(|(arg, _)| arg == arg_name) {
+                return Err(CompileError::from(format!(
+                    "no argument named `{arg_name}` in macro {name:?}"
+                )));
+            }
+            named_arguments.insert(Cow::Borrowed(arg_name), arg);
+        }
+    }
+    let positional = args.len() - named_arguments.len();
+
+    // Handling both named and unnamed arguments requires to be careful of the named arguments
+    // order. To do so, we iterate through the macro defined arguments and then check if we have
+    // a named argument with this name:
+    //
+    // * If there is one, we add it and move to the next argument. It must not also be used by
+    //   one of the unnamed arguments, which are always first.
+    // * If there isn't one, then we pick the next unnamed argument.
+    // * If there are no unnamed arguments left, then we use the default value of the argument.
+    let mut exprs = Vec::with_capacity(def.args.len());
+    for (index, (arg, default)) in def.args.iter().enumerate() {
+        let expr = match named_arguments.get(&Cow::Borrowed(arg)) {
+            Some(expr) => {
+                if index < positional {
+                    return Err(CompileError::from(format!(
+                        "argument `{arg}` is passed both by position and by name in macro \
+                         {name:?}"
+                    )));
+                }
+                *expr
+            }
+            None if index < positional => &args[index],
+            None => default.as_ref().ok_or_else(|| {
+                CompileError::from(format!("missing argument `{arg}` in macro {name:?}"))
+            })?,
+        };
+        exprs.push(expr);
+    }
+    Ok(exprs)
+}
+
 // Identifiers to be replaced with raw identifiers, so as to avoid
 // collisions between template syntax and Rust's syntax. In particular
 // [Rust keywords](https://doc.rust-lang.org/reference/keywords.html)
@@ -4,7 +4,7 @@ use std::rc::Rc;
 
 use crate::config::Config;
 use crate::CompileError;
-use parser::node::{BlockDef, Macro, Match};
+use parser::node::{BlockDef, Const, Macro, Match};
 use parser::Node;
 
 pub(crate) struct Heritage<'a> {
@@ -34,15 +34,36 @@ impl Heritage<'_> {
     }
 }
 
+impl<'a> Heritage<'a> {
+    /// Makes the blocks of an included template overridable by the templates inheriting from the
+    /// including one, whose heritage is `outer`: their definitions come before the included ones.
+    pub(crate) fn with_outer(mut self, outer: &Heritage<'a>) -> Self {
+        for (name, defs) in &outer.blocks {
+            self.blocks
+                .entry(name)
+                .or_default()
+                .splice(0..0, defs.iter().copied());
+        }
+        self
+    }
+}
+
 type BlockAncestry<'a> = HashMap<&'a str, Vec<(&'a Context<'a>, &'a BlockDef<'a>)>>;
 
-#[derive(Default, Clone)]
+#[derive(Clone)]
 pub(crate) struct Context<'a> {
     pub(crate) nodes: &'a [Node<'a>],
+    // The template path relative to its templates directory, used in rendering errors
+    pub(crate) name: Rc<str>,
     pub(crate) extends: Option<Rc<Path>>,
     pub(crate) blocks: HashMap<&'a str, &'a BlockDef<'a>>,
     pub(crate) macros: HashMap<&'a str, &'a Macro<'a>>,
     pub(crate) imports: HashMap<&'a str, Rc<Path>>,
+    // The macros imported with `{% from … import … %}`, with the template defining them and their
+    // name in it
+    pub(crate) imported_macros: HashMap<&'a str, (Rc<Path>, &'a str)>,
+    // The constants defined with `{% const %}`, in their order in the template
+    pub(crate) consts: Vec<&'a Const<'a>>,
 }
 
 impl Context<'_> {
@@ -55,6 +76,8 @@ impl Context<'_> {
         let mut blocks = HashMap::new();
         let mut macros = HashMap::new();
         let mut imports = HashMap::new();
+        let mut imported_macros = HashMap::new();
+        let mut consts = Vec::new();
         let mut nested = vec![nodes];
         let mut top = true;
 
@@ -74,7 +97,21 @@ impl Context<'_> {
                         let path = config.find_template(import.path, Some(path))?;
                         imports.insert(import.scope, path);
                     }
-                    Node::Extends(_) | Node::Macro(_) | Node::Import(_) if !top => {
+                    Node::FromImport(import) if top => {
+                        let path = config.find_template(import.path, Some(path))?;
+                        for &(name, alias) in &import.names {
+                            imported_macros.insert(alias.unwrap_or(name), (path.clone(), name));
+                        }
+                    }
+                    Node::Const(c) if top => {
+                        consts.push(c);
+                    }
+                    Node::Const(_) => {
+                        return Err("const blocks only allowed at the top level".into());
+                    }
+                    Node::Extends(_) | Node::Macro(_) | Node::Import(_) | Node::FromImport(_)
+                        if !top =>
+                    {
                         return Err(
                             "extends, macro or import blocks not allowed below top level".into(),
                         );
@@ -92,23 +129,47 @@ impl Context<'_> {
                         nested.push(&l.body);
                         nested.push(&l.else_nodes);
                     }
+                    Node::While(w) => {
+                        nested.push(&w.nodes);
+                    }
                     Node::Match(Match { arms, .. }) => {
                         for arm in arms {
                             nested.push(&arm.nodes);
                         }
                     }
+                    Node::Component(c) => {
+                        nested.push(&c.nodes);
+                    }
+                    Node::Once(o) => {
+                        nested.push(&o.nodes);
+                    }
+                    Node::SetBlock(b) => {
+                        nested.push(&b.nodes);
+                    }
+                    Node::With(w) => {
+                        nested.push(&w.nodes);
+                    }
                     _ => {}
                 }
             }
             top = false;
         }
 
+        let name = config
+            .dirs
+            .iter()
+            .find_map(|dir| path.strip_prefix(dir).ok())
+            .unwrap_or(path);
+
         Ok(Context {
             nodes,
+            name: name.to_string_lossy().into(),
             extends,
             blocks,
             macros,
             imports,
+            imported_macros,
+            consts,
         })
     }
 }
@@ -7,9 +7,9 @@ use mime::Mime;
 use quote::ToTokens;
 use syn::punctuated::Punctuated;
 
-use crate::config::{get_template_source, read_config_file, Config};
+use crate::config::{get_template_source, read_config_file, Codegen, Config};
 use crate::CompileError;
-use parser::{Node, Parsed, Syntax};
+use parser::{FileSource, Node, Parsed, Syntax};
 
 pub(crate) struct TemplateInput<'a> {
     pub(crate) ast: &'a syn::DeriveInput,
@@ -22,6 +22,15 @@ pub(crate) struct TemplateInput<'a> {
     pub(crate) ext: Option<&'a str>,
     pub(crate) mime_type: String,
     pub(crate) path: Rc<Path>,
+    pub(crate) codegen: Codegen,
+    pub(crate) render_debug: bool,
+    pub(crate) no_panic: bool,
+    pub(crate) variants: &'a [TemplateVariant],
+    pub(crate) dedent: bool,
+    pub(crate) trim_blocks: bool,
+    pub(crate) lstrip_blocks: bool,
+    pub(crate) serialize: Serialize,
+    pub(crate) error_context: bool,
 }
 
 impl TemplateInput<'_> {
@@ -40,6 +49,15 @@ impl TemplateInput<'_> {
             escaping,
             ext,
             syntax,
+            codegen,
+            render_debug,
+            no_panic,
+            variants,
+            dedent,
+            trim_blocks,
+            lstrip_blocks,
+            serialize,
+            error_context,
             ..
         } = args;
 
@@ -61,7 +79,11 @@ impl TemplateInput<'_> {
 
         // Validate syntax
         let syntax = syntax.as_deref().map_or_else(
-            || Ok(config.syntaxes.get(config.default_syntax).unwrap()),
+            || {
+                Ok(config
+                    .find_syntax(&path)
+                    .unwrap_or_else(|| config.syntaxes.get(config.default_syntax).unwrap()))
+            },
             |s| {
                 config
                     .syntaxes
@@ -92,6 +114,11 @@ impl TemplateInput<'_> {
             extension_to_mime_type(ext_default_to_path(ext.as_deref(), &path).unwrap_or("txt"))
                 .to_string();
 
+        let codegen = match codegen {
+            Some(codegen) => codegen.parse()?,
+            None => config.codegen,
+        };
+
         Ok(TemplateInput {
             ast,
             config,
@@ -103,6 +130,15 @@ impl TemplateInput<'_> {
             ext: ext.as_deref(),
             mime_type,
             path,
+            codegen,
+            render_debug: *render_debug,
+            no_panic: *no_panic,
+            variants,
+            dedent: dedent.unwrap_or(config.dedent),
+            trim_blocks: trim_blocks.unwrap_or(config.trim_blocks),
+            lstrip_blocks: lstrip_blocks.unwrap_or(config.lstrip_blocks),
+            serialize: *serialize,
+            error_context: error_context.unwrap_or(config.error_context),
         })
     }
 
@@ -113,7 +149,7 @@ impl TemplateInput<'_> {
         let (source, source_path) = match &self.source {
             Source::Source(s) => (s.into(), None),
             Source::Path(_) => (
-                get_template_source(&self.path)?,
+                get_template_source(&*self.config.files, &self.path, self.config.newlines)?,
                 Some(Rc::clone(&self.path)),
             ),
         };
@@ -121,7 +157,13 @@ impl TemplateInput<'_> {
         let mut dependency_graph = Vec::new();
         let mut check = vec![(Rc::clone(&self.path), source, source_path)];
         while let Some((path, source, source_path)) = check.pop() {
-            let parsed = Parsed::new(source, source_path, self.syntax)?;
+            // The templates used by this one are parsed with the syntax configured for their
+            // directory, if any, so they can use another syntax than this template.
+            let syntax = match *path == *self.path {
+                true => self.syntax,
+                false => self.config.find_syntax(&path).unwrap_or(self.syntax),
+            };
+            let parsed = Parsed::new(source, source_path, syntax)?;
 
             let mut top = true;
             let mut nested = vec![parsed.nodes()];
@@ -132,7 +174,11 @@ impl TemplateInput<'_> {
                             // Add a dummy entry to `map` in order to prevent adding `path`
                             // multiple times to `check`.
                             map.insert(Rc::clone(&path), Parsed::default());
-                            let source = get_template_source(&path)?;
+                            let source = get_template_source(
+                                &*self.config.files,
+                                &path,
+                                self.config.newlines,
+                            )?;
                             check.push((path.clone(), source, Some(path)));
                         }
                         Ok(())
@@ -159,12 +205,40 @@ impl TemplateInput<'_> {
                             let import = self.config.find_template(import.path, Some(&path))?;
                             add_to_check(import)?;
                         }
+                        Node::FromImport(import) if top => {
+                            let import = self.config.find_template(import.path, Some(&path))?;
+                            add_to_check(import)?;
+                        }
+                        Node::Call(c) => {
+                            if let Some(caller) = &c.caller {
+                                nested.push(&caller.nodes);
+                            }
+                        }
                         Node::FilterBlock(f) => {
                             nested.push(&f.nodes);
                         }
+                        Node::Component(c) => {
+                            nested.push(&c.nodes);
+                        }
+                        Node::Once(o) => {
+                            nested.push(&o.nodes);
+                        }
+                        Node::SetBlock(b) => {
+                            nested.push(&b.nodes);
+                        }
+                        Node::With(w) => {
+                            nested.push(&w.nodes);
+                        }
                         Node::Include(include) => {
-                            let include = self.config.find_template(include.path, Some(&path))?;
-                            add_to_check(include)?;
+                            for include in &include.paths {
+                                let include = self.config.find_template(include, Some(&path))?;
+                                add_to_check(include)?;
+                            }
+                        }
+                        Node::Embed(embed) => {
+                            let path = self.config.find_template(embed.path, Some(&path))?;
+                            add_to_check(path)?;
+                            nested.push(&embed.nodes);
                         }
                         Node::BlockDef(b) => {
                             nested.push(&b.nodes);
@@ -178,6 +252,9 @@ impl TemplateInput<'_> {
                             nested.push(&l.body);
                             nested.push(&l.else_nodes);
                         }
+                        Node::While(w) => {
+                            nested.push(&w.nodes);
+                        }
                         Node::Match(m) => {
                             for arm in &m.arms {
                                 nested.push(&arm.nodes);
@@ -186,13 +263,15 @@ impl TemplateInput<'_> {
                         Node::Lit(_)
                         | Node::Comment(_)
                         | Node::Expr(_, _)
-                        | Node::Call(_)
                         | Node::Extends(_)
                         | Node::Let(_)
+                        | Node::Const(_)
                         | Node::Import(_)
+                        | Node::FromImport(_)
                         | Node::Macro(_)
                         | Node::Raw(_)
                         | Node::Continue(_)
+                        | Node::Do(_, _)
                         | Node::Break(_) => {}
                     }
                 }
@@ -209,7 +288,7 @@ impl TemplateInput<'_> {
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default)]
 pub(crate) struct TemplateArgs {
     source: Option<Source>,
     block: Option<String>,
@@ -219,6 +298,107 @@ pub(crate) struct TemplateArgs {
     syntax: Option<String>,
     config: Option<String>,
     pub(crate) whitespace: Option<String>,
+    codegen: Option<String>,
+    render_debug: bool,
+    no_panic: bool,
+    pub(crate) variants: Vec<TemplateVariant>,
+    dedent: Option<bool>,
+    trim_blocks: Option<bool>,
+    lstrip_blocks: Option<bool>,
+    serialize: Serialize,
+    error_context: Option<bool>,
+}
+
+/// A `#[template_variant(key = "…", path = "…")]` attribute: the template at `path` is rendered
+/// instead of the main one when `TemplateVariants::render_variant()` is called with `key`.
+#[derive(Clone, Debug)]
+pub(crate) struct TemplateVariant {
+    // The pattern matching the key, e.g. `Theme::Dark` or `Theme::Dark | Theme::Dim`
+    pub(crate) key: String,
+    // The keys matched by the pattern
+    keys: Vec<String>,
+    // The type of the key, e.g. `Theme`
+    pub(crate) key_type: String,
+    path: String,
+}
+
+impl TemplateVariant {
+    fn new(attr: &syn::Attribute) -> Result<Self, CompileError> {
+        let items = attr
+            .parse_args_with(Punctuated::<syn::Meta, syn::Token![,]>::parse_terminated)
+            .map_err(|e| format!("unable to parse template_variant arguments: {e}"))?;
+
+        let mut key = None;
+        let mut path = None;
+        for item in items {
+            let (ident, value) = match item {
+                syn::Meta::NameValue(syn::MetaNameValue {
+                    path,
+                    value:
+                        syn::Expr::Lit(syn::ExprLit {
+                            lit: syn::Lit::Str(value),
+                            ..
+                        }),
+                    ..
+                }) if path.get_ident().is_some() => (path.get_ident().unwrap().clone(), value),
+                _ => {
+                    return Err(format!(
+                        "unsupported template_variant argument {:?}, expected `key = \"…\"` or \
+                         `path = \"…\"`",
+                        item.to_token_stream().to_string()
+                    )
+                    .into())
+                }
+            };
+            if ident == "key" {
+                key = Some(value);
+            } else if ident == "path" {
+                path = Some(value.value());
+            } else {
+                return Err(format!("unsupported template_variant key {ident:?} found").into());
+            }
+        }
+
+        let key = key.ok_or("template_variant is missing a `key`, e.g. `key = \"Theme::Dark\"`")?;
+        let path = path.ok_or("template_variant is missing a `path`")?;
+
+        // Several keys can share a variant, e.g. `key = "Theme::Dark | Theme::Dim"`.
+        let mut keys = Vec::new();
+        let mut key_type: Option<String> = None;
+        for key in key.value().split('|') {
+            let invalid_key = || {
+                CompileError::from(format!(
+                    "template_variant key must be a path like `Theme::Dark`, found {:?}",
+                    key.trim(),
+                ))
+            };
+            let mut key_path = syn::parse_str::<syn::Path>(key).map_err(|_| invalid_key())?;
+            if key_path.segments.len() < 2 {
+                return Err(invalid_key());
+            }
+            keys.push(key.trim().to_owned());
+
+            key_path.segments.pop();
+            key_path.segments.pop_punct();
+            let ty = key_path.to_token_stream().to_string();
+            match &key_type {
+                Some(prev) if *prev != ty => return Err(Self::different_types(prev, &ty)),
+                Some(_) => {}
+                None => key_type = Some(ty),
+            }
+        }
+
+        Ok(Self {
+            key: keys.join(" | "),
+            keys,
+            key_type: key_type.unwrap(),
+            path,
+        })
+    }
+
+    fn different_types(a: &str, b: &str) -> CompileError {
+        format!("all template variants must use the same key type, found `{a}` and `{b}`").into()
+    }
 }
 
 impl TemplateArgs {
@@ -242,6 +422,25 @@ impl TemplateArgs {
             template_args.ok_or_else(|| CompileError::from("no attribute 'template' found"))?;
 
         let mut args = Self::default();
+        for attr in &ast.attrs {
+            if attr.path().is_ident("template_variant") {
+                let variant = TemplateVariant::new(attr)?;
+                if let Some(first) = args.variants.first() {
+                    if first.key_type != variant.key_type {
+                        return Err(TemplateVariant::different_types(
+                            &first.key_type,
+                            &variant.key_type,
+                        ));
+                    }
+                }
+                for key in &variant.keys {
+                    if args.variants.iter().any(|v| v.keys.contains(key)) {
+                        return Err(format!("duplicated template_variant key `{key}`").into());
+                    }
+                }
+                args.variants.push(variant);
+            }
+        }
         // Loop over the meta attributes and find everything that we
         // understand. Return a CompileError if something is not right.
         // `source` contains an enum that can represent `path` or `source`.
@@ -333,6 +532,54 @@ impl TemplateArgs {
                 } else {
                     return Err("whitespace value must be string literal".into());
                 }
+            } else if ident == "codegen" {
+                if let syn::Lit::Str(s) = value.lit {
+                    args.codegen = Some(s.value())
+                } else {
+                    return Err("codegen value must be string literal".into());
+                }
+            } else if ident == "dedent" {
+                if let syn::Lit::Bool(b) = value.lit {
+                    args.dedent = Some(b.value);
+                } else {
+                    return Err("dedent value must be boolean literal".into());
+                }
+            } else if ident == "trim_blocks" {
+                if let syn::Lit::Bool(b) = value.lit {
+                    args.trim_blocks = Some(b.value);
+                } else {
+                    return Err("trim_blocks value must be boolean literal".into());
+                }
+            } else if ident == "lstrip_blocks" {
+                if let syn::Lit::Bool(b) = value.lit {
+                    args.lstrip_blocks = Some(b.value);
+                } else {
+                    return Err("lstrip_blocks value must be boolean literal".into());
+                }
+            } else if ident == "error_context" {
+                if let syn::Lit::Bool(b) = value.lit {
+                    args.error_context = Some(b.value);
+                } else {
+                    return Err("error_context value must be boolean literal".into());
+                }
+            } else if ident == "no_panic" {
+                if let syn::Lit::Bool(b) = value.lit {
+                    args.no_panic = b.value;
+                } else {
+                    return Err("no_panic value must be boolean literal".into());
+                }
+            } else if ident == "serialize" {
+                if let syn::Lit::Str(s) = value.lit {
+                    args.serialize = s.value().parse()?;
+                } else {
+                    return Err("serialize value must be string literal".into());
+                }
+            } else if ident == "render_debug" {
+                if let syn::Lit::Bool(b) = value.lit {
+                    args.render_debug = b.value;
+                } else {
+                    return Err("render_debug value must be boolean literal".into());
+                }
             } else {
                 return Err(format!("unsupported attribute key {ident:?} found").into());
             }
@@ -349,8 +596,20 @@ impl TemplateArgs {
         }
     }
 
-    pub(crate) fn config(&self) -> Result<String, CompileError> {
-        read_config_file(self.config.as_deref())
+    /// Returns the arguments used to generate the variant at `variant`'s path.
+    pub(crate) fn for_variant(&self, variant: &TemplateVariant) -> Self {
+        Self {
+            source: Some(Source::Path(variant.path.clone())),
+            // The extension of the variant comes from its own path
+            ext: None,
+            render_debug: false,
+            variants: Vec::new(),
+            ..self.clone()
+        }
+    }
+
+    pub(crate) fn config(&self, files: &dyn FileSource) -> Result<String, CompileError> {
+        read_config_file(self.config.as_deref(), files)
     }
 }
 
@@ -373,18 +632,20 @@ fn extension(path: &Path) -> Option<&str> {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub(crate) enum Source {
     Path(String),
     Source(String),
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
 pub(crate) enum Print {
     All,
     Ast,
     Code,
+    #[default]
     None,
+    Whitespace,
 }
 
 impl FromStr for Print {
@@ -396,14 +657,33 @@ impl FromStr for Print {
             "ast" => Print::Ast,
             "code" => Print::Code,
             "none" => Print::None,
+            "whitespace" => Print::Whitespace,
             v => return Err(format!("invalid value for print option: {v}",).into()),
         })
     }
 }
 
-impl Default for Print {
-    fn default() -> Self {
-        Self::None
+/// How the `serde::Serialize` implementation requested with `serialize = "…"` represents
+/// the template.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub(crate) enum Serialize {
+    #[default]
+    None,
+    /// The rendered output as a string
+    String,
+    /// An object with the rendered output in `html` and the template's `mime_type`
+    Object,
+}
+
+impl FromStr for Serialize {
+    type Err = CompileError;
+
+    fn from_str(s: &str) -> Result<Serialize, Self::Err> {
+        Ok(match s {
+            "string" => Serialize::String,
+            "object" => Serialize::Object,
+            v => return Err(format!("invalid value for serialize option: {v}").into()),
+        })
     }
 }
 
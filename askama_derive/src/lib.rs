@@ -7,8 +7,10 @@ use std::{borrow::Cow, collections::HashMap};
 use proc_macro::TokenStream;
 use proc_macro2::Span;
 
-use parser::ParseError;
+use parser::{FileSource, FileSystem, ParseError};
 
+mod cache;
+use cache::CacheEntry;
 mod config;
 use config::Config;
 mod generator;
@@ -20,7 +22,7 @@ use input::{Print, TemplateArgs, TemplateInput};
 #[cfg(test)]
 mod tests;
 
-#[proc_macro_derive(Template, attributes(template))]
+#[proc_macro_derive(Template, attributes(template, template_variant, template_opt))]
 pub fn derive_template(input: TokenStream) -> TokenStream {
     let ast = syn::parse::<syn::DeriveInput>(input).unwrap();
     match build_template(&ast) {
@@ -41,7 +43,7 @@ fn build_skeleton(ast: &syn::DeriveInput) -> Result<String, CompileError> {
     let config = Config::new("", None)?;
     let input = TemplateInput::new(ast, &config, &template_args)?;
     let mut contexts = HashMap::new();
-    contexts.insert(&input.path, Context::default());
+    contexts.insert(&input.path, Context::new(&config, &input.path, &[])?);
     Generator::new(&input, &contexts, None, MapChain::default()).build(&contexts[&input.path])
 }
 
@@ -53,11 +55,58 @@ fn build_skeleton(ast: &syn::DeriveInput) -> Result<String, CompileError> {
 /// the parse tree and/or generated source according to the `print` key's
 /// value as passed to the `template()` attribute.
 pub(crate) fn build_template(ast: &syn::DeriveInput) -> Result<String, CompileError> {
+    build_template_with_files(ast, Box::new(FileSystem))
+}
+
+/// Like [`build_template()`], but reading the configuration file and the templates from `files`.
+pub(crate) fn build_template_with_files(
+    ast: &syn::DeriveInput,
+    files: Box<dyn FileSource>,
+) -> Result<String, CompileError> {
+    build_template_reporting_ws(ast, files, &mut |report| eprintln!("{report}"))
+}
+
+/// Like [`build_template_with_files()`], but giving each whitespace decision reported with
+/// `print = "whitespace"` or `print = "all"` to `report_ws`, instead of printing it.
+pub(crate) fn build_template_reporting_ws(
+    ast: &syn::DeriveInput,
+    files: Box<dyn FileSource>,
+    report_ws: &mut dyn FnMut(String),
+) -> Result<String, CompileError> {
     let template_args = TemplateArgs::new(ast)?;
-    let toml = template_args.config()?;
-    let config = Config::new(&toml, template_args.whitespace.as_deref())?;
+    let toml = template_args.config(&*files)?;
+    let config = Config::with_files(&toml, template_args.whitespace.as_deref(), files)?;
     let input = TemplateInput::new(ast, &config, &template_args)?;
 
+    // Templates printing what they generate are not cached, to always print it.
+    let cache = match (&config.cache_dir, input.print) {
+        (Some(dir), Print::None) => Some(CacheEntry::new(dir, ast, &toml)),
+        _ => None,
+    };
+    if let Some(code) = cache.as_ref().and_then(|cache| cache.get(&config)) {
+        return Ok(code);
+    }
+
+    let mut code = build_template_input(&input, None, report_ws)?;
+
+    // Every `#[template_variant]` is generated like the main template, as a separate method.
+    for (index, variant) in template_args.variants.iter().enumerate() {
+        let variant_args = template_args.for_variant(variant);
+        let input = TemplateInput::new(ast, &config, &variant_args)?;
+        code.push_str(&build_template_input(&input, Some(index), report_ws)?);
+    }
+
+    if let Some(cache) = cache {
+        cache.store(&code, &config);
+    }
+    Ok(code)
+}
+
+fn build_template_input(
+    input: &TemplateInput<'_>,
+    variant: Option<usize>,
+    report_ws: &mut dyn FnMut(String),
+) -> Result<String, CompileError> {
     let mut templates = HashMap::new();
     input.find_used_templates(&mut templates)?;
 
@@ -85,8 +134,14 @@ pub(crate) fn build_template(ast: &syn::DeriveInput) -> Result<String, CompileEr
         eprintln!("{:?}", templates[&input.path].nodes());
     }
 
-    let code = Generator::new(&input, &contexts, heritage.as_ref(), MapChain::default())
-        .build(&contexts[&input.path])?;
+    let mut generator = Generator::new(input, &contexts, heritage.as_ref(), MapChain::default());
+    let code = match variant {
+        Some(index) => generator.build_variant(&contexts[&input.path], index)?,
+        None => generator.build(&contexts[&input.path])?,
+    };
+    for report in generator.ws_reports {
+        report_ws(report);
+    }
     if input.print == Print::Code || input.print == Print::All {
         eprintln!("{code}");
     }
@@ -150,32 +205,56 @@ impl From<String> for CompileError {
 // in the const vector based on features seems impossible right now).
 const BUILT_IN_FILTERS: &[&str] = &[
     "abs",
+    "batch",
     "capitalize",
+    "capitalize_first",
     "center",
+    "char_count",
+    "columns",
+    "dictsort",
     "e",
     "escape",
     "filesizeformat",
+    "first",
     "fmt",
     "format",
+    "humanize",
     "indent",
+    "intcomma",
     "into_f64",
     "into_isize",
+    "items",
     "join",
+    "last",
     "linebreaks",
     "linebreaksbr",
     "paragraphbreaks",
     "lower",
     "lowercase",
+    "map_or",
+    "ok",
+    "reading_time",
+    "rejectattr",
+    "round",
     "safe",
+    "selectattr",
+    "slice",
+    "split",
+    "striptags",
     "title",
     "trim",
     "truncate",
+    "unique",
+    "unwrap_or",
     "upper",
     "uppercase",
     "urlencode",
     "urlencode_strict",
     "wordcount",
+    "wordwrap",
     // optional features, reserve the names anyway:
+    "b64decode",
+    "b64encode",
     "json",
 ];
 
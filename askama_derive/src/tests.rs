@@ -1,7 +1,29 @@
 // Files containing tests for generated code.
 
-use crate::build_template;
+use crate::{build_template, build_template_reporting_ws, build_template_with_files, CompileError};
+use parser::{FileSource, InMemoryFiles};
 use std::fmt::Write;
+use std::path::Path;
+
+/// Returns in-memory files with the given paths, relative to `CARGO_MANIFEST_DIR`, e.g.
+/// `("templates/base.html", "...")` or `("askama.toml", "...")`.
+pub(crate) fn in_memory_files(files: &[(&str, &str)]) -> Box<dyn FileSource> {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    Box::new(
+        files
+            .iter()
+            .map(|(path, source)| (root.join(path), *source))
+            .collect::<InMemoryFiles>(),
+    )
+}
+
+// Builds the template of `input` with only the given files available.
+fn build_with_files(input: &str, files: &[(&str, &str)]) -> Result<String, CompileError> {
+    build_template_with_files(
+        &syn::parse_str::<syn::DeriveInput>(input).unwrap(),
+        in_memory_files(files),
+    )
+}
 
 #[test]
 fn check_if_let() {
@@ -25,6 +47,12 @@ struct Foo;"##
         let expected = format!(
             r#"impl ::askama::Template for Foo {{
     fn render_into(&self, writer: &mut (impl ::std::fmt::Write + ?Sized)) -> ::askama::Result<()> {{
+        #![allow(clippy::manual_range_contains)]
+        #[allow(unused_imports)]
+        use ::askama::helpers::{{
+            Contains as _, GetItem as _, IterableDeref as _, IterableRef as _,
+            RenderDisplay as _, RenderTemplate as _, Slice as _
+        }};
         {new_expected}
         ::askama::Result::Ok(())
     }}
@@ -53,10 +81,10 @@ impl ::std::fmt::Display for Foo {{
     compare(
         "{% if let Some(query) = s && !query.is_empty() %}{{query}}{% endif %}",
         r#"if let Some(query,) = &self.s && !query.is_empty() {
-    ::std::write!(
+    (&&::askama::helpers::Render(&(query))).askama_render(
         writer,
-        "{expr0}",
-        expr0 = &::askama::MarkupDisplay::new_unsafe(&(query), ::askama::Text),
+        ::askama::Text,
+        "text/plain; charset=utf-8"
     )?;
 }"#,
     );
@@ -66,10 +94,10 @@ impl ::std::fmt::Display for Foo {{
     compare(
         "{% if let Some(s) = s %}{{ s }}{% endif %}",
         r#"if let Some(s,) = &self.s {
-    ::std::write!(
+    (&&::askama::helpers::Render(&(s))).askama_render(
         writer,
-        "{expr0}",
-        expr0 = &::askama::MarkupDisplay::new_unsafe(&(s), ::askama::Text),
+        ::askama::Text,
+        "text/plain; charset=utf-8"
     )?;
 }"#,
     );
@@ -79,11 +107,266 @@ impl ::std::fmt::Display for Foo {{
     compare(
         "{% if let Some(s) = s && !s.is_empty() %}{{s}}{% endif %}",
         r#"if let Some(s,) = &self.s && !s.is_empty() {
-    ::std::write!(
+    (&&::askama::helpers::Render(&(s))).askama_render(
         writer,
-        "{expr0}",
-        expr0 = &::askama::MarkupDisplay::new_unsafe(&(s), ::askama::Text),
+        ::askama::Text,
+        "text/plain; charset=utf-8"
     )?;
 }"#,
     );
 }
+
+#[test]
+fn in_memory_templates() {
+    let generated = build_with_files(
+        r#"#[template(path = "page.html")] struct Page;"#,
+        &[
+            (
+                "templates/base.html",
+                "<main>{% block content %}{% endblock %}</main>",
+            ),
+            (
+                "templates/page.html",
+                r#"{% extends "base.html" %}{% block content %}{% include "part.html" %}{% endblock %}"#,
+            ),
+            ("templates/part.html", "in memory"),
+        ],
+    )
+    .unwrap();
+    assert!(generated.contains(r#""<main>""#), "{generated}");
+    assert!(generated.contains(r#""in memory""#), "{generated}");
+    assert!(generated.contains(r#""</main>""#), "{generated}");
+
+    let missing = build_with_files(r#"#[template(path = "a.html")] struct A;"#, &[]);
+    assert!(missing.is_err());
+}
+
+#[test]
+fn crlf_templates() {
+    let generated = build_with_files(
+        r#"#[template(path = "crlf.html")] struct Crlf;"#,
+        &[(
+            "templates/crlf.html",
+            "\u{feff}<ul>\r\n  {%- for i in 0..2 %}\r\n  <li>{{ i }}</li>\r\n  {%- endfor %}\r\n</ul>\r\n",
+        )],
+    )
+    .unwrap();
+    // The byte order mark and the final line break are removed, `\r\n` is kept.
+    assert!(!generated.contains("feff"), "{generated}");
+    assert!(
+        generated.contains(r#"writer.write_str("<ul>")?;"#),
+        "{generated}"
+    );
+    assert!(
+        generated.contains(r#"writer.write_str("\r\n  <li>")?;"#),
+        "{generated}"
+    );
+    assert!(
+        generated.contains(r#"writer.write_str("\r\n</ul>")?;"#),
+        "{generated}"
+    );
+}
+
+#[cfg(feature = "config")]
+#[test]
+fn crlf_templates_normalized() {
+    let generated = build_with_files(
+        r#"#[template(path = "crlf.html")] struct Crlf;"#,
+        &[
+            ("askama.toml", "[general]\nnewlines = \"lf\""),
+            ("templates/crlf.html", "<p>\r\n  {{ 1 }}\r\n</p>\r\n"),
+        ],
+    )
+    .unwrap();
+    assert!(!generated.contains(r"\r"), "{generated}");
+}
+
+#[cfg(not(feature = "with-serde"))]
+#[test]
+fn serialize_requires_feature() {
+    let input = r#"#[template(source = "", ext = "txt", serialize = "string")] struct S;"#;
+    let err = build_template(&syn::parse_str::<syn::DeriveInput>(input).unwrap()).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "the `serialize` attribute requires the `serde` feature to be enabled"
+    );
+}
+
+#[test]
+fn no_panic_allows_fallible_alternatives() {
+    let input = r#"#[template(
+        source = "{{ items.get(0)|unwrap_or(0) }}{% if let Some(name) = name %}{{ name }}{% endif %}{{ a / 2 }}{{ a % 2.5 }}{{ a.checked_div(b)|unwrap_or(0) }}",
+        ext = "txt",
+        no_panic = true,
+    )] struct S;"#;
+    build_template(&syn::parse_str::<syn::DeriveInput>(input).unwrap()).unwrap();
+}
+
+#[test]
+fn print_whitespace() {
+    let reports = |attrs: &str| {
+        let input = format!(
+            r#"#[template(source = " a\n{{%- if b +%}}\n{{{{ c ~}}}}\n{{% endif %}}\n", ext = "txt", {attrs})]
+struct Ws;"#
+        );
+        let mut reports = Vec::new();
+        build_template_reporting_ws(
+            &syn::parse_str::<syn::DeriveInput>(&input).unwrap(),
+            in_memory_files(&[]),
+            &mut |report| reports.push(report),
+        )
+        .unwrap();
+        reports
+    };
+
+    let expected = |default: &str| {
+        vec![
+            r#"Ws.txt: " " before "a": preserved (no adjacent tag)"#.to_owned(),
+            r#"Ws.txt: "\n" after "a": suppressed (`-` marker)"#.to_owned(),
+            r#"Ws.txt: "\n" between tags: preserved (`+` marker)"#.to_owned(),
+            r#"Ws.txt: "\n" between tags: minimized (`~` marker)"#.to_owned(),
+            format!(r#"Ws.txt: "\n" between tags: {default}"#),
+        ]
+    };
+    assert_eq!(
+        reports(r#"print = "whitespace""#),
+        expected(r#"preserved (`whitespace = "preserve"`)"#),
+    );
+    // `all` also reports the whitespace decisions, which follow the `whitespace` setting.
+    assert_eq!(
+        reports(r#"print = "all", whitespace = "suppress""#),
+        expected(r#"suppressed (`whitespace = "suppress"`)"#),
+    );
+    assert!(reports(r#"print = "code""#).is_empty());
+    assert!(reports(r#"print = "none""#).is_empty());
+}
+
+#[test]
+fn type_hints() {
+    let input = r#"#[template(source = "{{ a : u32 }}{{ b : &str }}", ext = "html")] struct S;"#;
+    let generated = build_template(&syn::parse_str::<syn::DeriveInput>(input).unwrap()).unwrap();
+    // Only the value of a primitive type skips the escaper.
+    assert!(generated.contains(
+        r#"::std::write!(writer, "{}", &(::askama::helpers::type_hint::<u32>(&(self.a))))"#
+    ));
+    assert!(generated
+        .contains("::askama::helpers::Render(&(::askama::helpers::type_hint::<&str>(&(self.b))))"));
+}
+
+#[test]
+fn codegen_size() {
+    let input = r#"#[template(
+        source = "{% for x in xs %}<br>{{ x }}{% if b %}<br>{% endif %}{% endfor %}.",
+        ext = "html",
+        codegen = "size",
+    )] struct S;"#;
+    let generated = build_template(&syn::parse_str::<syn::DeriveInput>(input).unwrap()).unwrap();
+    let generated = generated.split_whitespace().collect::<String>();
+    // The literals are written from a table by the shared helpers, and identical ones share their
+    // entry.
+    assert!(
+        generated.contains(r#"const__ASKAMA_LITERALS:&[&::std::primitive::str]=&["<br>",".",];"#)
+    );
+    assert!(generated.contains("::askama::helpers::write_str(writer,__ASKAMA_LITERALS[0])?;"));
+    assert!(generated
+        .contains(r#".askama_render_dyn(writer,::askama::Html,"text/html;charset=utf-8")?;"#));
+    assert!(!generated.contains("writer.write_str("));
+}
+
+#[cfg(feature = "config")]
+#[test]
+fn cached_templates() {
+    let dir = std::env::temp_dir().join(format!("askama-cache-{}", std::process::id()));
+    let config = format!("[general]\ncache_dir = {:?}", dir.to_str().unwrap());
+    let input = r#"#[template(path = "cached.html")] struct Cached;"#;
+    let build = |source: &str| {
+        build_with_files(
+            input,
+            &[("askama.toml", &config), ("templates/cached.html", source)],
+        )
+        .unwrap()
+    };
+
+    let generated = build("a");
+    let entries = std::fs::read_dir(&dir).unwrap().collect::<Vec<_>>();
+    assert_eq!(entries.len(), 1);
+    let entry = entries[0].as_ref().unwrap().path();
+
+    // The stored code is returned as long as the template is unchanged.
+    let stored = std::fs::read_to_string(&entry).unwrap();
+    std::fs::write(&entry, stored.replace("Cached", "Renamed")).unwrap();
+    assert_eq!(build("a"), generated.replace("Cached", "Renamed"));
+
+    // The code is generated again once the template changed.
+    let changed = build("b");
+    assert!(changed.contains(r#"writer.write_str("b")?;"#), "{changed}");
+    assert!(changed.contains("Cached"), "{changed}");
+    assert_eq!(build("b"), changed);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "config")]
+#[test]
+fn cached_templates_lookups() {
+    let dir = std::env::temp_dir().join(format!("askama-cache-lookups-{}", std::process::id()));
+    let config = format!(
+        "[general]\ndirs = [\"templates\", \"other\"]\ncache_dir = {:?}",
+        dir.to_str().unwrap()
+    );
+    let input = r#"#[template(path = "cached.html")] struct Cached;"#;
+    let build = |files: &[(&str, &str)]| {
+        let mut files = files.to_vec();
+        files.push(("askama.toml", &config));
+        files.push(("templates/cached.html", r#"{% include "x.html" %}"#));
+        build_with_files(input, &files).unwrap()
+    };
+
+    let generated = build(&[("other/x.html", "other")]);
+    assert!(
+        generated.contains(r#"writer.write_str("other")?;"#),
+        "{generated}"
+    );
+    assert_eq!(build(&[("other/x.html", "other")]), generated);
+
+    // A template found first in another directory replaces the cached code, although none of the
+    // files it was generated from changed.
+    let shadowed = build(&[("other/x.html", "other"), ("templates/x.html", "templates")]);
+    assert!(
+        shadowed.contains(r#"writer.write_str("templates")?;"#),
+        "{shadowed}"
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "image-dims")]
+#[test]
+fn image_dims() {
+    let build = |source: &str| {
+        let input = format!("#[template(source = {source:?}, ext = \"html\")] struct Img;");
+        build_with_files(
+            &input,
+            &[("logo.gif", "GIF89a\u{7}\0\u{5}\0\0\0\0\0\0\0\0\0")],
+        )
+    };
+
+    let generated = build(r#"{{ image_dims("logo.gif") }}"#).unwrap();
+    assert!(
+        generated.contains("ImageDims { width: 7, height: 5 }"),
+        "{generated}"
+    );
+    assert!(generated.contains("include_bytes !"), "{generated}");
+
+    let err = build(r#"{{ image_dims("missing.gif") }}"#).unwrap_err();
+    assert!(
+        err.to_string()
+            .starts_with("unable to read the size of image"),
+        "{err}"
+    );
+    let err = build(r#"{{ image_dims(path) }}"#).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "`image_dims()` expects the path of an image as a string literal"
+    );
+}
@@ -2,6 +2,9 @@
 #![deny(elided_lifetimes_in_paths)]
 #![deny(unreachable_pub)]
 
+#[cfg(feature = "std")]
+extern crate std;
+
 use core::fmt::{self, Display, Formatter, Write};
 use core::str;
 
@@ -163,6 +166,55 @@ pub trait Escaper {
     fn write_escaped<W>(&self, fmt: W, string: &str) -> fmt::Result
     where
         W: Write;
+
+    /// Escapes `string` into `fmt` like [`write_escaped()`][Escaper::write_escaped], but
+    /// reports why the input could not be escaped.
+    ///
+    /// Askama renders expressions through this method, so the error is returned by
+    /// `Template::render()`. The default implementation calls `write_escaped()`. Escapers that
+    /// can reject their input should override it, and make `write_escaped()` return
+    /// `fmt::Error` for the same input.
+    fn try_write_escaped<W>(&self, fmt: W, string: &str) -> Result<(), EscapeError>
+    where
+        W: Write,
+    {
+        Ok(self.write_escaped(fmt, string)?)
+    }
+}
+
+/// An error returned by [`Escaper::try_write_escaped()`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EscapeError {
+    /// the underlying writer failed
+    Fmt(fmt::Error),
+    /// the input cannot be escaped, e.g. because it contains characters that are not allowed in
+    /// the output format
+    Invalid(&'static str),
+}
+
+impl Display for EscapeError {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            EscapeError::Fmt(err) => write!(fmt, "formatting error: {err}"),
+            EscapeError::Invalid(msg) => fmt.write_str(msg),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for EscapeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EscapeError::Fmt(err) => Some(err),
+            EscapeError::Invalid(_) => None,
+        }
+    }
+}
+
+impl From<fmt::Error> for EscapeError {
+    fn from(err: fmt::Error) -> Self {
+        EscapeError::Fmt(err)
+    }
 }
 
 #[cfg(test)]
@@ -180,4 +232,36 @@ mod tests {
         assert_eq!(escape("<foo", Html).to_string(), "&lt;foo");
         assert_eq!(escape("bla&h", Html).to_string(), "bla&amp;h");
     }
+
+    #[test]
+    fn test_try_write_escaped() {
+        struct NoNul;
+
+        impl Escaper for NoNul {
+            fn write_escaped<W>(&self, fmt: W, string: &str) -> fmt::Result
+            where
+                W: Write,
+            {
+                self.try_write_escaped(fmt, string).map_err(|_| fmt::Error)
+            }
+
+            fn try_write_escaped<W>(&self, mut fmt: W, string: &str) -> Result<(), EscapeError>
+            where
+                W: Write,
+            {
+                if string.contains('\0') {
+                    return Err(EscapeError::Invalid("NUL characters are not allowed"));
+                }
+                Ok(fmt.write_str(string)?)
+            }
+        }
+
+        let mut buf = std::string::String::new();
+        assert_eq!(Html.try_write_escaped(&mut buf, "<a>"), Ok(()));
+        assert_eq!(buf, "&lt;a&gt;");
+        assert_eq!(
+            NoNul.try_write_escaped(&mut buf, "a\0b"),
+            Err(EscapeError::Invalid("NUL characters are not allowed"))
+        );
+    }
 }
@@ -1,3 +1,4 @@
+use std::cell::Cell;
 use std::collections::HashSet;
 use std::str;
 
@@ -7,22 +8,40 @@ use nom::character::complete::char;
 use nom::combinator::{cut, map, not, opt, peek, recognize};
 use nom::error::ErrorKind;
 use nom::error_position;
-use nom::multi::{fold_many0, many0, separated_list0};
-use nom::sequence::{pair, preceded, terminated, tuple};
+use nom::multi::{fold_many0, many0, separated_list0, separated_list1};
+use nom::sequence::{delimited, pair, preceded, separated_pair, terminated, tuple};
 
 use super::{
-    char_lit, filter, identifier, not_ws, num_lit, path_or_identifier, str_lit, ws, Level,
-    PathOrIdentifier,
+    char_lit, escaped_str_lit, filter, identifier, keyword, not_ws, num_lit, path_or_identifier,
+    str_lit, ws, ws_comments, Level, PathOrIdentifier, StrLit,
 };
+use crate::node::Target;
 use crate::{ErrorContext, ParseResult};
 
+// A Rust type, possibly behind a reference and with generic arguments, e.g. `&str` or
+// `Vec<u32>`.
+fn type_hint(i: &str, level: Level) -> ParseResult<'_> {
+    let (_, level) = level.nest(i)?;
+    let generics = tuple((
+        ws(char('<')),
+        separated_list1(ws(char(',')), move |i| type_hint(i, level)),
+        char('>'),
+    ));
+    recognize(tuple((
+        opt(ws(char('&'))),
+        opt(tag("::")),
+        separated_list1(ws(tag("::")), identifier),
+        opt(generics),
+    )))(i)
+}
+
 macro_rules! expr_prec_layer {
     ( $name:ident, $inner:ident, $op:expr ) => {
         fn $name(i: &'a str, level: Level) -> ParseResult<'a, Self> {
             let (_, level) = level.nest(i)?;
             let (i, left) = Self::$inner(i, level)?;
             let (i, right) = many0(pair(
-                ws(tag($op)),
+                ws(binary_op($op)),
                 |i| Self::$inner(i, level),
             ))(i)?;
             Ok((
@@ -38,7 +57,7 @@ macro_rules! expr_prec_layer {
             let (_, level) = level.nest(i)?;
             let (i, left) = Self::$inner(i, level)?;
             let (i, right) = many0(pair(
-                ws(alt(($( tag($op) ),+,))),
+                ws(alt(($( binary_op($op) ),+,))),
                 |i| Self::$inner(i, level),
             ))(i)?;
             Ok((
@@ -51,27 +70,62 @@ macro_rules! expr_prec_layer {
     }
 }
 
+/// Parses a binary operator, unless it is the start of a `/* comment */`.
+fn binary_op<'a>(op: &'static str) -> impl FnMut(&'a str) -> ParseResult<'a> {
+    move |i| match tag(op)(i)? {
+        (j, "/") if j.starts_with('*') => Err(nom::Err::Error(error_position!(i, ErrorKind::Tag))),
+        // Don't split `||`: what follows the second `|` would be parsed as a closure
+        (j, "|") if j.starts_with('|') => Err(nom::Err::Error(error_position!(i, ErrorKind::Tag))),
+        result => Ok(result),
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum Expr<'a> {
     BoolLit(&'a str),
     NumLit(&'a str),
-    StrLit(&'a str),
+    StrLit(StrLit<'a>),
+    /// A string literal with interpolated expressions, like `f"Hello, {name}!"`.
+    FormatStr(Vec<FormatPart<'a>>),
     CharLit(&'a str),
     Var(&'a str),
     Path(Vec<&'a str>),
     Array(Vec<Expr<'a>>),
+    /// A map literal like `{"a": 1, "b": 2}`, with its keys and values in order.
+    Map(Vec<(Expr<'a>, Expr<'a>)>),
     Attr(Box<Expr<'a>>, &'a str),
+    /// An attribute accessed with `?.`, on an `Option` or a `Result`. It only appears inside an
+    /// [`Expr::OptionalChain`].
+    OptionalAttr(Box<Expr<'a>>, &'a str),
+    /// A chain of attributes, indexes and calls containing at least one `?.`, like
+    /// `user?.address?.city`. Its value is `None` if any link of the chain is `None` or `Err`.
+    OptionalChain(Box<Expr<'a>>),
     Index(Box<Expr<'a>>, Box<Expr<'a>>),
     Filter(Filter<'a>),
+    Test(Test<'a>),
     NamedArgument(&'a str, Box<Expr<'a>>),
+    /// `*value` in the arguments of a macro call, spreading the items of a tuple or a sequence
+    /// into the arguments.
+    Spread(Box<Expr<'a>>),
     Unary(&'a str, Box<Expr<'a>>),
     BinOp(&'a str, Box<Expr<'a>>, Box<Expr<'a>>),
     Range(&'a str, Option<Box<Expr<'a>>>, Option<Box<Expr<'a>>>),
     Group(Box<Expr<'a>>),
     Tuple(Vec<Expr<'a>>),
     Call(Box<Expr<'a>>, Vec<Expr<'a>>),
+    /// A method or a function given generic arguments with the turbofish syntax, like
+    /// `value.parse::<u32>`, with the source of each argument.
+    Turbofish(Box<Expr<'a>>, Vec<&'a str>),
     RustMacro(Vec<&'a str>, &'a str),
     Try(Box<Expr<'a>>),
+    /// A closure like `|a, b| a.cmp(b)`, with the names of its parameters and its body.
+    Closure(Vec<&'a str>, Box<Expr<'a>>),
+    /// An inline conditional: `then if cond else otherwise`, stored as `(cond, then, otherwise)`.
+    Conditional(Box<Expr<'a>>, Box<Expr<'a>>, Box<Expr<'a>>),
+    /// `value matches pattern`: whether the value matches the pattern, like `matches!()`.
+    Matches(Box<Expr<'a>>, Target<'a>),
+    /// An expression annotated with the Rust type of its value, e.g. `{{ total : f64 }}`.
+    TypeHint(Box<Expr<'a>>, &'a str),
     /// This variant should never be used directly. It is created when generating filter blocks.
     Generated(String),
 }
@@ -91,13 +145,14 @@ impl<'a> Expr<'a> {
             cut(terminated(
                 separated_list0(
                     char(','),
-                    ws(move |i| {
+                    ws_comments(move |i| {
                         // Needed to prevent borrowing it twice between this closure and the one
                         // calling `Self::named_arguments`.
                         let named_arguments = &mut named_arguments;
                         let has_named_arguments = !named_arguments.is_empty();
 
                         let (i, expr) = alt((
+                            move |i| Self::spread(i, level, is_template_macro),
                             move |i| {
                                 Self::named_argument(
                                     i,
@@ -119,11 +174,24 @@ impl<'a> Expr<'a> {
                         }
                     }),
                 ),
-                tuple((opt(ws(char(','))), char(')'))),
+                tuple((opt(ws_comments(char(','))), char(')'))),
             )),
         )(i)
     }
 
+    // `*value` in the arguments of a macro call, whose items are spread into the arguments.
+    fn spread(i: &'a str, level: Level, is_template_macro: bool) -> ParseResult<'a, Self> {
+        if !is_template_macro {
+            return Err(nom::Err::Error(error_position!(i, ErrorKind::Alt)));
+        }
+
+        let (_, level) = level.nest(i)?;
+        map(
+            preceded(char('*'), cut(move |i| Self::parse(i, level))),
+            |value| Self::Spread(Box::new(value)),
+        )(i)
+    }
+
     fn named_argument(
         i: &'a str,
         level: Level,
@@ -152,6 +220,23 @@ impl<'a> Expr<'a> {
 
     pub(super) fn parse(i: &'a str, level: Level) -> ParseResult<'a, Self> {
         let (_, level) = level.nest(i)?;
+        let (i, expr) = Self::range(i, level)?;
+        // Without an `else`, the `if` may belong to the enclosing tag, like in `{% for x in y if
+        // x %}`, so only the expression after `else` is required.
+        let (i, cond) = opt(pair(
+            preceded(ws(keyword("if")), move |i| Self::or(i, level)),
+            preceded(ws(keyword("else")), cut(move |i| Self::parse(i, level))),
+        ))(i)?;
+        Ok(match cond {
+            Some((cond, otherwise)) => (
+                i,
+                Self::Conditional(Box::new(cond), Box::new(expr), Box::new(otherwise)),
+            ),
+            None => (i, expr),
+        })
+    }
+
+    fn range(i: &'a str, level: Level) -> ParseResult<'a, Self> {
         let range_right = move |i| {
             pair(
                 ws(alt((tag("..="), tag("..")))),
@@ -172,10 +257,107 @@ impl<'a> Expr<'a> {
         ))(i)
     }
 
+    /// Parses an expression, optionally followed by a type hint: `: Type`.
+    pub(super) fn with_type_hint(i: &'a str, level: Level) -> ParseResult<'a, Self> {
+        let (_, level) = level.nest(i)?;
+        let (i, (expr, ty)) = pair(
+            move |i| Self::parse(i, level),
+            opt(preceded(
+                ws(char(':')),
+                cut(ws(move |i| type_hint(i, level))),
+            )),
+        )(i)?;
+        match ty {
+            Some(ty) => Ok((i, Self::TypeHint(Box::new(expr), ty))),
+            None => Ok((i, expr)),
+        }
+    }
+
     // Keep in sync with `TWO_PLUS_CHAR_OPS`, below
     expr_prec_layer!(or, and, "||");
     expr_prec_layer!(and, compare, "&&");
-    expr_prec_layer!(compare, bor, "==", "!=", ">=", ">", "<=", "<");
+
+    // A comparison, or a chain of comparisons like `0 <= x < 10`, which is desugared to
+    // `0 <= x && x < 10`.
+    fn compare(i: &'a str, level: Level) -> ParseResult<'a, Self> {
+        let (_, level) = level.nest(i)?;
+        let (i, left) = Self::membership(i, level)?;
+        let (i, right) = many0(pair(
+            ws(alt((
+                binary_op("=="),
+                binary_op("!="),
+                binary_op(">="),
+                binary_op(">"),
+                binary_op("<="),
+                binary_op("<"),
+            ))),
+            |i| Self::membership(i, level),
+        ))(i)?;
+
+        let mut comparisons = Vec::with_capacity(right.len());
+        let mut left = left;
+        for (op, right) in right {
+            comparisons.push(Self::BinOp(op, Box::new(left), Box::new(right.clone())));
+            left = right;
+        }
+        let expr = comparisons
+            .into_iter()
+            .reduce(|chain, cmp| Self::BinOp("&&", Box::new(chain), Box::new(cmp)));
+        Ok((i, expr.unwrap_or(left)))
+    }
+
+    // `item in container` or `item not in container`
+    fn membership(i: &'a str, level: Level) -> ParseResult<'a, Self> {
+        let (i, (item, container)) = pair(
+            move |i| Self::tested(i, level),
+            opt(pair(
+                alt((
+                    map(ws(keyword("in")), |_| "in"),
+                    map(pair(ws(keyword("not")), ws(keyword("in"))), |_| "not in"),
+                )),
+                cut(move |i| Self::tested(i, level)),
+            )),
+        )(i)?;
+        Ok(match container {
+            Some((op, container)) => (i, Self::BinOp(op, Box::new(item), Box::new(container))),
+            None => (i, item),
+        })
+    }
+
+    // `value is [not] name[(arguments)]`, or `value matches pattern`
+    fn tested(i: &'a str, level: Level) -> ParseResult<'a, Self> {
+        let (i, value) = Self::bor(i, level)?;
+        let (i, pattern) = opt(preceded(
+            ws(keyword("matches")),
+            cut(ws(|i| Target::parse_nested(i, &Cell::new(level)))),
+        ))(i)?;
+        if let Some(pattern) = pattern {
+            return Ok((i, Self::Matches(Box::new(value), pattern)));
+        }
+
+        let (i, test) = opt(preceded(
+            ws(keyword("is")),
+            cut(tuple((
+                opt(ws(keyword("not"))),
+                ws(identifier),
+                opt(move |i| Self::arguments(i, level, false)),
+            ))),
+        ))(i)?;
+        Ok(match test {
+            Some((not, name, args)) => {
+                let mut arguments = args.unwrap_or_default();
+                arguments.insert(0, value);
+                let test = Test {
+                    name,
+                    negated: not.is_some(),
+                    arguments,
+                };
+                (i, Self::Test(test))
+            }
+            None => (i, value),
+        })
+    }
+
     expr_prec_layer!(bor, bxor, "|");
     expr_prec_layer!(bxor, band, "^");
     expr_prec_layer!(band, shifts, "&");
@@ -225,13 +407,33 @@ impl<'a> Expr<'a> {
         alt((
             Self::num,
             Self::str,
+            move |i| Self::format_str(i, level),
             Self::char,
             Self::path_var_bool,
             move |i| Self::array(i, level),
+            move |i| Self::map(i, level),
             move |i| Self::group(i, level),
+            move |i| Self::closure(i, level),
         ))(i)
     }
 
+    fn closure(i: &'a str, level: Level) -> ParseResult<'a, Self> {
+        let (_, level) = level.nest(i)?;
+        let params = alt((
+            map(tag("||"), |_| Vec::new()),
+            delimited(
+                char('|'),
+                terminated(
+                    separated_list0(char(','), ws(identifier)),
+                    opt(ws(char(','))),
+                ),
+                char('|'),
+            ),
+        ));
+        let (i, (params, body)) = pair(ws(params), cut(move |i| Self::parse(i, level)))(i)?;
+        Ok((i, Self::Closure(params, Box::new(body))))
+    }
+
     fn group(i: &'a str, level: Level) -> ParseResult<'a, Self> {
         let (_, level) = level.nest(i)?;
         let (i, expr) = preceded(ws(char('(')), opt(|i| Self::parse(i, level)))(i)?;
@@ -275,6 +477,24 @@ impl<'a> Expr<'a> {
         )(i)
     }
 
+    fn map(i: &'a str, level: Level) -> ParseResult<'a, Self> {
+        let (_, level) = level.nest(i)?;
+        let entry = move |i| {
+            separated_pair(
+                ws(move |i| Self::parse(i, level)),
+                char(':'),
+                ws(move |i| Self::parse(i, level)),
+            )(i)
+        };
+        preceded(
+            ws(char('{')),
+            cut(terminated(
+                map(separated_list0(char(','), entry), Self::Map),
+                pair(opt(ws(char(','))), char('}')),
+            )),
+        )(i)
+    }
+
     fn path_var_bool(i: &'a str) -> ParseResult<'a, Self> {
         map(path_or_identifier, |v| match v {
             PathOrIdentifier::Path(v) => Self::Path(v),
@@ -288,6 +508,52 @@ impl<'a> Expr<'a> {
         map(str_lit, Self::StrLit)(i)
     }
 
+    fn format_str(i: &'a str, level: Level) -> ParseResult<'a, Self> {
+        let (_, level) = level.nest(i)?;
+        let start = i;
+        let (i, lit) = preceded(char('f'), escaped_str_lit)(i)?;
+        let unmatched = |msg| nom::Err::Failure(ErrorContext::new(msg, start));
+
+        let mut parts = Vec::new();
+        let mut lit_start = lit.content;
+        let mut rest = lit.content;
+        while let Some(pos) = rest.find(['\\', '{', '}']) {
+            let tail = &rest[pos..];
+            if let Some(escape) = tail.strip_prefix('\\') {
+                // `\u{..}` escapes contain braces, which don't start an interpolation.
+                rest = match escape.strip_prefix("u{") {
+                    Some(escape) => escape.split_once('}').map_or("", |(_, tail)| tail),
+                    None => escape.get(1..).unwrap_or_default(),
+                };
+            } else if tail.starts_with("{{") || tail.starts_with("}}") {
+                rest = &tail[2..];
+            } else if tail.starts_with('}') {
+                return Err(unmatched(
+                    "unmatched `}` in format string, use `}}` to escape it",
+                ));
+            } else {
+                let lit = &lit_start[..lit_start.len() - tail.len()];
+                if !lit.is_empty() {
+                    parts.push(FormatPart::Lit(lit));
+                }
+                let spec = preceded(char(':'), take_till(|c| c == '{' || c == '}'));
+                let (tail, (expr, spec)) = delimited(
+                    char('{'),
+                    pair(ws(move |i| Self::parse(i, level)), opt(spec)),
+                    char('}'),
+                )(tail)
+                .map_err(|_| unmatched("invalid interpolation in format string"))?;
+                parts.push(FormatPart::Expr(expr, spec));
+                lit_start = tail;
+                rest = tail;
+            }
+        }
+        if !lit_start.is_empty() {
+            parts.push(FormatPart::Lit(lit_start));
+        }
+        Ok((i, Self::FormatStr(parts)))
+    }
+
     fn num(i: &'a str) -> ParseResult<'a, Self> {
         map(num_lit, Self::NumLit)(i)
     }
@@ -297,16 +563,35 @@ impl<'a> Expr<'a> {
     }
 }
 
+/// A part of an [`Expr::FormatStr`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum FormatPart<'a> {
+    /// Literal text, with its escape sequences and its braces doubled like in `format!()`.
+    Lit(&'a str),
+    /// An interpolated expression, with its optional format spec, like `{price:.2}`.
+    Expr(Expr<'a>, Option<&'a str>),
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct Filter<'a> {
     pub name: &'a str,
     pub arguments: Vec<Expr<'a>>,
 }
 
+/// A test like `value is odd`: its first argument is the tested value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Test<'a> {
+    pub name: &'a str,
+    pub negated: bool,
+    pub arguments: Vec<Expr<'a>>,
+}
+
 enum Suffix<'a> {
     Attr(&'a str),
+    OptionalAttr(&'a str),
     Index(Expr<'a>),
     Call(Vec<Expr<'a>>),
+    Turbofish(Vec<&'a str>),
     // The value is the arguments of the macro call.
     MacroCall(&'a str),
     Try,
@@ -316,19 +601,27 @@ impl<'a> Suffix<'a> {
     fn parse(i: &'a str, level: Level) -> ParseResult<'a, Expr<'a>> {
         let (_, level) = level.nest(i)?;
         let (mut i, mut expr) = Expr::single(i, level)?;
+        let mut optional = false;
         loop {
             let (j, suffix) = opt(alt((
                 Self::attr,
                 |i| Self::index(i, level),
                 |i| Self::call(i, level),
+                |i| Self::turbofish(i, level),
+                Self::optional_attr,
                 Self::r#try,
                 Self::r#macro,
             )))(i)?;
 
             match suffix {
                 Some(Self::Attr(attr)) => expr = Expr::Attr(expr.into(), attr),
+                Some(Self::OptionalAttr(attr)) => {
+                    expr = Expr::OptionalAttr(expr.into(), attr);
+                    optional = true;
+                }
                 Some(Self::Index(index)) => expr = Expr::Index(expr.into(), index.into()),
                 Some(Self::Call(args)) => expr = Expr::Call(expr.into(), args),
+                Some(Self::Turbofish(types)) => expr = Expr::Turbofish(expr.into(), types),
                 Some(Self::Try) => expr = Expr::Try(expr.into()),
                 Some(Self::MacroCall(args)) => match expr {
                     Expr::Path(path) => expr = Expr::RustMacro(path, args),
@@ -340,6 +633,9 @@ impl<'a> Suffix<'a> {
 
             i = j;
         }
+        if optional {
+            expr = Expr::OptionalChain(expr.into());
+        }
         Ok((i, expr))
     }
 
@@ -411,6 +707,30 @@ impl<'a> Suffix<'a> {
         )(i)
     }
 
+    fn turbofish(i: &'a str, level: Level) -> ParseResult<'a, Self> {
+        let (_, level) = level.nest(i)?;
+        map(
+            preceded(
+                pair(ws(tag("::")), ws(char('<'))),
+                cut(terminated(
+                    separated_list1(char(','), ws(move |i| type_hint(i, level))),
+                    char('>'),
+                )),
+            ),
+            Self::Turbofish,
+        )(i)
+    }
+
+    fn optional_attr(i: &'a str) -> ParseResult<'a, Self> {
+        map(
+            preceded(
+                ws(pair(tag("?."), not(char('.')))),
+                cut(alt((num_lit, identifier))),
+            ),
+            Self::OptionalAttr,
+        )(i)
+    }
+
     fn index(i: &'a str, level: Level) -> ParseResult<'a, Self> {
         let (_, level) = level.nest(i)?;
         map(
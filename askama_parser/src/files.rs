@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::{fmt, fs, io};
+
+/// The files templates and configuration files are read from.
+///
+/// Askama reads them from the [`FileSystem`]. Tools and tests can read them from somewhere else,
+/// e.g. from [`InMemoryFiles`], so they don't depend on the files of the crate being built.
+pub trait FileSource: fmt::Debug {
+    /// Returns `true` if there's a file at `path`.
+    fn exists(&self, path: &Path) -> bool;
+
+    /// Reads the contents of the file at `path`.
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+
+    /// Reads the contents of the file at `path`, which must be valid UTF-8.
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        String::from_utf8(self.read(path)?)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    /// Returns the canonical path of the file at `path`, which must exist.
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf>;
+}
+
+/// Reads files from the file system.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FileSystem;
+
+impl FileSource for FileSystem {
+    fn exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        fs::read(path)
+    }
+
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        fs::read_to_string(path)
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        path.canonicalize()
+    }
+}
+
+/// Files kept in memory, keyed by their path. No other file exists.
+///
+/// Paths are used as they are given, so they are usually absolute, like the paths Askama looks
+/// templates up at, e.g. `$CARGO_MANIFEST_DIR/templates/index.html`. Their canonical path is the
+/// path itself.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct InMemoryFiles {
+    files: HashMap<PathBuf, Vec<u8>>,
+}
+
+impl InMemoryFiles {
+    /// Creates an empty set of files.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the file at `path`, replacing the file previously added at that path, if any.
+    pub fn insert(&mut self, path: impl Into<PathBuf>, contents: impl Into<Vec<u8>>) {
+        self.files.insert(path.into(), contents.into());
+    }
+}
+
+impl<P: Into<PathBuf>, C: Into<Vec<u8>>> FromIterator<(P, C)> for InMemoryFiles {
+    fn from_iter<I: IntoIterator<Item = (P, C)>>(iter: I) -> Self {
+        let mut files = Self::new();
+        for (path, contents) in iter {
+            files.insert(path, contents);
+        }
+        files
+    }
+}
+
+impl FileSource for InMemoryFiles {
+    fn exists(&self, path: &Path) -> bool {
+        self.files.contains_key(path)
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        match self.files.get(path) {
+            Some(contents) => Ok(contents.clone()),
+            None => Err(io::ErrorKind::NotFound.into()),
+        }
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        match self.exists(path) {
+            true => Ok(path.to_owned()),
+            false => Err(io::ErrorKind::NotFound.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn in_memory_files() {
+        let files = [("/t/a.html", "a"), ("/t/b.html", "\u{ff}")]
+            .into_iter()
+            .collect::<InMemoryFiles>();
+        assert!(files.exists(Path::new("/t/a.html")));
+        assert!(!files.exists(Path::new("/t/c.html")));
+        assert_eq!(files.read_to_string(Path::new("/t/a.html")).unwrap(), "a");
+        assert_eq!(
+            files.read(Path::new("/t/b.html")).unwrap(),
+            "\u{ff}".as_bytes()
+        );
+        assert_eq!(
+            files.canonicalize(Path::new("/t/a.html")).unwrap(),
+            Path::new("/t/a.html")
+        );
+        let err = files.read(Path::new("/t/c.html")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(files.canonicalize(Path::new("/t/c.html")).is_err());
+
+        let mut files = InMemoryFiles::new();
+        files.insert("/t/a.html", vec![0xff]);
+        let err = files.read_to_string(Path::new("/t/a.html")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
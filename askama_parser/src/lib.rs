@@ -18,7 +18,9 @@ use nom::sequence::{delimited, pair, preceded, terminated, tuple};
 use nom::{error_position, AsChar, InputTakeAtPosition};
 
 pub mod expr;
-pub use expr::{Expr, Filter};
+pub use expr::{Expr, Filter, FormatPart, Test};
+pub mod files;
+pub use files::{FileSource, FileSystem, InMemoryFiles};
 pub mod node;
 pub use node::Node;
 #[cfg(test)]
@@ -30,7 +32,7 @@ mod _parsed {
     use std::{fmt, mem};
 
     use super::node::Node;
-    use super::{Ast, ParseError, Syntax};
+    use super::{expand_line_statements, Ast, ParseError, Syntax};
 
     #[derive(Default)]
     pub struct Parsed {
@@ -51,6 +53,10 @@ mod _parsed {
             // Self-referential borrowing: `self` will keep the source alive as `String`,
             // internally we will transmute it to `&'static str` to satisfy the compiler.
             // However, we only expose the nodes with a lifetime limited to `self`.
+            let source = match syntax.line_statement_prefix {
+                Some(prefix) => expand_line_statements(&source, prefix, syntax),
+                None => source,
+            };
             let src = unsafe { mem::transmute::<&str, &'static str>(source.as_str()) };
             let ast = Ast::from_str(src, file_path, syntax)?;
             Ok(Self { ast, source })
@@ -257,6 +263,28 @@ fn ws<'a, O>(
     delimited(take_till(not_ws), inner, take_till(not_ws))
 }
 
+/// Like [`ws`], but also skips `/* ... */` comments around `inner`.
+fn ws_comments<'a, O>(
+    inner: impl FnMut(&'a str) -> ParseResult<'a, O>,
+) -> impl FnMut(&'a str) -> ParseResult<'a, O> {
+    delimited(skip_comments, inner, skip_comments)
+}
+
+fn skip_comments(mut i: &str) -> ParseResult<'_, ()> {
+    i = i.trim_start_matches(is_ws);
+    while let Some(comment) = i.strip_prefix("/*") {
+        match comment.find("*/") {
+            Some(end) => i = comment[end + 2..].trim_start_matches(is_ws),
+            None => {
+                return Err(nom::Err::Failure(ErrorContext::unclosed(
+                    "comment", "*/", i,
+                )));
+            }
+        }
+    }
+    Ok((i, ()))
+}
+
 /// Skips input until `end` was found, but does not consume it.
 /// Returns tuple that would be returned when parsing `end`.
 fn skip_till<'a, O>(
@@ -381,13 +409,116 @@ fn separated_digits(radix: u32, start: bool) -> impl Fn(&str) -> ParseResult<'_>
     }
 }
 
-fn str_lit(i: &str) -> ParseResult<'_> {
-    let (i, s) = delimited(
+/// A string literal, like `"foo\n"` or `r#"say "foo""#`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StrLit<'a> {
+    /// The content of the literal between its quotes, with its escape sequences.
+    pub content: &'a str,
+    /// The number of `#` around a raw string literal, or `None` if it's not a raw string.
+    pub raw: Option<usize>,
+}
+
+impl<'a> StrLit<'a> {
+    /// Returns the value of the literal, with its escape sequences interpreted.
+    pub fn value(&self) -> Cow<'a, str> {
+        if self.raw.is_some() || !self.content.contains('\\') {
+            return Cow::Borrowed(self.content);
+        }
+
+        // The escape sequences were checked when the literal was parsed.
+        let mut value = String::with_capacity(self.content.len());
+        let mut rest = self.content;
+        while let Some(pos) = rest.find('\\') {
+            value.push_str(&rest[..pos]);
+            let mut chars = rest[pos + 1..].chars();
+            let c = match chars.next() {
+                Some('n') => '\n',
+                Some('r') => '\r',
+                Some('t') => '\t',
+                Some('0') => '\0',
+                Some('x') => {
+                    let code = chars.as_str().get(..2).unwrap_or_default();
+                    chars = chars.as_str()[code.len()..].chars();
+                    u8::from_str_radix(code, 16).map_or(char::REPLACEMENT_CHARACTER, char::from)
+                }
+                Some('u') => {
+                    let (code, tail) = chars.as_str().split_once('}').unwrap_or_default();
+                    chars = tail.chars();
+                    u32::from_str_radix(code.trim_start_matches('{'), 16)
+                        .ok()
+                        .and_then(char::from_u32)
+                        .unwrap_or(char::REPLACEMENT_CHARACTER)
+                }
+                // A line continuation: the line break and the whitespace after it are skipped
+                Some('\r' | '\n') => {
+                    rest = chars.as_str().trim_start();
+                    continue;
+                }
+                Some(c) => c,
+                None => break,
+            };
+            value.push(c);
+            rest = chars.as_str();
+        }
+        value.push_str(rest);
+        Cow::Owned(value)
+    }
+}
+
+// Information about string literals is available at:
+// <https://doc.rust-lang.org/reference/tokens.html#string-literals>.
+fn str_lit(i: &str) -> ParseResult<'_, StrLit<'_>> {
+    alt((raw_str_lit, escaped_str_lit))(i)
+}
+
+fn escaped_str_lit(i: &str) -> ParseResult<'_, StrLit<'_>> {
+    let start = i;
+    let (i, content) = delimited(
         char('"'),
         opt(escaped(is_not("\\\""), '\\', anychar)),
         char('"'),
     )(i)?;
-    Ok((i, s.unwrap_or_default()))
+    let content = content.unwrap_or_default();
+
+    let mut rest = content;
+    while let Some(pos) = rest.find('\\') {
+        rest = &rest[pos..];
+        if let Some(tail) = rest.strip_prefix("\\\n").or(rest.strip_prefix("\\\r\n")) {
+            rest = tail;
+            continue;
+        }
+        let Ok((tail, c)) = Char::parse(rest) else {
+            return Err(nom::Err::Failure(ErrorContext::new(
+                "invalid escape sequence in string literal",
+                start,
+            )));
+        };
+        check_escape(c, start)?;
+        rest = tail;
+    }
+    Ok((i, StrLit { content, raw: None }))
+}
+
+fn raw_str_lit(i: &str) -> ParseResult<'_, StrLit<'_>> {
+    let start = i;
+    let (i, hashes) = delimited(char('r'), many0_count(char('#')), char('"'))(i)?;
+    let end = format!("\"{}", "#".repeat(hashes));
+    match i.find(&end) {
+        Some(len) => {
+            let content = &i[..len];
+            Ok((
+                &i[len + end.len()..],
+                StrLit {
+                    content,
+                    raw: Some(hashes),
+                },
+            ))
+        }
+        None => Err(nom::Err::Failure(ErrorContext::new(
+            "unterminated raw string literal",
+            start,
+        ))),
+    }
 }
 
 // Information about allowed character escapes is available at:
@@ -412,9 +543,14 @@ fn char_lit(i: &str) -> ParseResult<'_> {
             start,
         )));
     };
+    check_escape(c, start)?;
+    Ok((i, s))
+}
 
+// Checks the value of a `\x` or `\u{…}` escape sequence.
+fn check_escape<'a>(c: Char<'_>, start: &'a str) -> Result<(), nom::Err<ErrorContext<'a>>> {
     let (nb, max_value, err1, err2) = match c {
-        Char::Literal | Char::Escaped => return Ok((i, s)),
+        Char::Literal | Char::Escaped => return Ok(()),
         Char::AsciiEscape(nb) => (
             nb,
             // `0x7F` is the maximum value for a `\x` escaped character.
@@ -438,7 +574,7 @@ fn char_lit(i: &str) -> ParseResult<'_> {
         return Err(nom::Err::Failure(ErrorContext::new(err2, start)));
     }
 
-    Ok((i, s))
+    Ok(())
 }
 
 /// Represents the different kinds of char declarations:
@@ -598,6 +734,9 @@ pub struct Syntax<'a> {
     pub expr_end: &'a str,
     pub comment_start: &'a str,
     pub comment_end: &'a str,
+    /// The prefix making a line a statement, as if it were written between `block_start` and
+    /// `block_end`.
+    pub line_statement_prefix: Option<&'a str>,
 }
 
 impl Default for Syntax<'static> {
@@ -609,10 +748,86 @@ impl Default for Syntax<'static> {
             expr_end: "}}",
             comment_start: "{#",
             comment_end: "#}",
+            line_statement_prefix: None,
         }
     }
 }
 
+// Rewrites the lines starting with `prefix`, after some indentation, into statements between the
+// block delimiters of `syntax`. The indentation and the line break are moved inside the
+// delimiters, so the line doesn't render anything and the rows of the source are kept for the
+// error messages. Only the lines starting in literal text are rewritten: the source is scanned with
+// the parsers of the lexer, so comments, `raw` blocks and other tags are copied as they are.
+fn expand_line_statements(source: &str, prefix: &str, syntax: &Syntax<'_>) -> String {
+    let s = State::new(syntax);
+    let mut expanded = String::with_capacity(source.len());
+    let mut i = source;
+    let mut line_start = true;
+    while !i.is_empty() {
+        if line_start {
+            let line = match i.find('\n') {
+                Some(end) => &i[..=end],
+                None => i,
+            };
+            let statement = line.trim_start_matches([' ', '\t']);
+            if let Some(statement) = statement.strip_prefix(prefix) {
+                let indent = &line[..line.len() - statement.len() - prefix.len()];
+                let end = statement.trim_end_matches(['\r', '\n']);
+                let line_break = match &statement[end.len()..] {
+                    "" => " ",
+                    line_break => line_break,
+                };
+                expanded.push_str(syntax.block_start);
+                expanded.push_str(indent);
+                expanded.push_str(end);
+                expanded.push_str(line_break);
+                expanded.push_str(syntax.block_end);
+                i = &i[line.len()..];
+                continue;
+            }
+        }
+
+        let rest = match skip_tag(i, &s) {
+            Ok((rest, ())) => rest,
+            Err(nom::Err::Error(_)) => {
+                let c = i.chars().next().unwrap_or_default();
+                line_start = c == '\n';
+                expanded.push(c);
+                i = &i[c.len_utf8()..];
+                continue;
+            }
+            // An unclosed comment or `raw` block, which the parser reports.
+            Err(_) => "",
+        };
+        expanded.push_str(&i[..i.len() - rest.len()]);
+        line_start = false;
+        i = rest;
+    }
+    expanded
+}
+
+// Skips a comment, a `raw` block or any other tag.
+fn skip_tag<'a>(i: &'a str, s: &State<'_>) -> ParseResult<'a, ()> {
+    let raw = tuple((
+        |i| s.tag_block_start(i),
+        |i| node::Raw::parse(i, s),
+        |i| s.tag_block_end(i),
+    ));
+    let (i, skipped) = opt(alt((
+        map(|i| node::Comment::parse(i, s), |_| ()),
+        map(raw, |_| ()),
+    )))(i)?;
+    if skipped.is_some() {
+        return Ok((i, ()));
+    }
+
+    let (_, (i, _)) = alt((
+        preceded(|i| s.tag_block_start(i), skip_till(|i| s.tag_block_end(i))),
+        preceded(|i| s.tag_expr_start(i), skip_till(|i| s.tag_expr_end(i))),
+    ))(i)?;
+    Ok((i, ()))
+}
+
 #[derive(Clone, Copy, Default)]
 pub(crate) struct Level(u8);
 
@@ -1,10 +1,11 @@
+use std::cell::Cell;
 use std::str;
 
 use nom::branch::alt;
 use nom::bytes::complete::{tag, take_till};
 use nom::character::complete::char;
 use nom::combinator::{
-    complete, consumed, cut, eof, map, map_res, not, opt, peek, recognize, value,
+    complete, consumed, cut, eof, map, map_res, not, opt, peek, recognize, value, verify,
 };
 use nom::error::ErrorKind;
 use nom::error_position;
@@ -15,7 +16,7 @@ use crate::{not_ws, ErrorContext, ParseResult};
 
 use super::{
     bool_lit, char_lit, filter, identifier, is_ws, keyword, num_lit, path_or_identifier, skip_till,
-    str_lit, ws, Expr, Filter, PathOrIdentifier, State,
+    str_lit, ws, Expr, Filter, Level, PathOrIdentifier, State, StrLit,
 };
 
 #[derive(Debug, PartialEq)]
@@ -25,18 +26,28 @@ pub enum Node<'a> {
     Expr(Ws, Expr<'a>),
     Call(Call<'a>),
     Let(Let<'a>),
+    Const(Const<'a>),
+    SetBlock(SetBlock<'a>),
+    With(With<'a>),
     If(If<'a>),
     Match(Match<'a>),
     Loop(Box<Loop<'a>>),
+    While(While<'a>),
     Extends(Extends<'a>),
     BlockDef(BlockDef<'a>),
     Include(Include<'a>),
+    Embed(Embed<'a>),
     Import(Import<'a>),
+    FromImport(FromImport<'a>),
     Macro(Macro<'a>),
     Raw(Raw<'a>),
     Break(Ws),
     Continue(Ws),
+    /// `{% do expr %}`: evaluates the expression, without rendering its value.
+    Do(Ws, Expr<'a>),
     FilterBlock(FilterBlock<'a>),
+    Component(Component<'a>),
+    Once(Once<'a>),
 }
 
 impl<'a> Node<'a> {
@@ -68,19 +79,33 @@ impl<'a> Node<'a> {
 
         let func = match tag {
             "call" => |i, s| wrap(Self::Call, Call::parse(i, s)),
-            "let" | "set" => |i, s| wrap(Self::Let, Let::parse(i, s)),
+            "let" => |i, s| wrap(Self::Let, Let::parse(i, s)),
+            "const" => |i, s| wrap(Self::Const, Const::parse(i, s)),
+            "set" => |i, s| {
+                alt((
+                    |i| wrap(Self::SetBlock, SetBlock::parse(i, s)),
+                    |i| wrap(Self::Let, Let::parse(i, s)),
+                ))(i)
+            },
             "if" => |i, s| wrap(Self::If, If::parse(i, s)),
             "for" => |i, s| wrap(|n| Self::Loop(Box::new(n)), Loop::parse(i, s)),
+            "while" => |i, s| wrap(Self::While, While::parse(i, s)),
             "match" => |i, s| wrap(Self::Match, Match::parse(i, s)),
             "extends" => |i, _s| wrap(Self::Extends, Extends::parse(i)),
-            "include" => |i, _s| wrap(Self::Include, Include::parse(i)),
+            "include" => |i, s| wrap(Self::Include, Include::parse(i, s)),
+            "embed" => |i, s| wrap(Self::Embed, Embed::parse(i, s)),
             "import" => |i, _s| wrap(Self::Import, Import::parse(i)),
+            "from" => |i, _s| wrap(Self::FromImport, FromImport::parse(i)),
             "block" => |i, s| wrap(Self::BlockDef, BlockDef::parse(i, s)),
             "macro" => |i, s| wrap(Self::Macro, Macro::parse(i, s)),
             "raw" => |i, s| wrap(Self::Raw, Raw::parse(i, s)),
             "break" => |i, s| Self::r#break(i, s),
             "continue" => |i, s| Self::r#continue(i, s),
+            "do" => |i, s| Self::r#do(i, s),
             "filter" => |i, s| wrap(Self::FilterBlock, FilterBlock::parse(i, s)),
+            "component" => |i, s| wrap(Self::Component, Component::parse(i, s)),
+            "once" => |i, s| wrap(Self::Once, Once::parse(i, s)),
+            "with" => |i, s| wrap(Self::With, With::parse(i, s)),
             _ => {
                 return Err(ErrorContext::from_err(nom::Err::Error(error_position!(
                     i,
@@ -120,6 +145,19 @@ impl<'a> Node<'a> {
         Ok((j, Self::Break(Ws(pws, nws))))
     }
 
+    fn r#do(i: &'a str, s: &State<'_>) -> ParseResult<'a, Self> {
+        let mut p = tuple((
+            opt(Whitespace::parse),
+            ws(keyword("do")),
+            cut(pair(
+                ws(|i| Expr::parse(i, s.level.get())),
+                opt(Whitespace::parse),
+            )),
+        ));
+        let (i, (pws, _, (expr, nws))) = p(i)?;
+        Ok((i, Self::Do(Ws(pws, nws), expr)))
+    }
+
     fn r#continue(i: &'a str, s: &State<'_>) -> ParseResult<'a, Self> {
         let mut p = tuple((
             opt(Whitespace::parse),
@@ -141,7 +179,7 @@ impl<'a> Node<'a> {
             |i| s.tag_expr_start(i),
             cut(pair(
                 opt(Whitespace::parse),
-                ws(|i| Expr::parse(i, s.level.get())),
+                ws(|i| Expr::with_type_hint(i, s.level.get())),
             )),
         )(i)?;
 
@@ -162,21 +200,31 @@ pub enum Target<'a> {
     Tuple(Vec<&'a str>, Vec<Target<'a>>),
     Struct(Vec<&'a str>, Vec<(&'a str, Target<'a>)>),
     NumLit(&'a str),
-    StrLit(&'a str),
+    StrLit(StrLit<'a>),
     CharLit(&'a str),
     BoolLit(&'a str),
     Path(Vec<&'a str>),
     OrChain(Vec<Target<'a>>),
+    Binding(&'a str, Box<Target<'a>>),
+    /// A range of literals, like `1..=9`, `'a'..` or `..=9`
+    Range(&'a str, Option<Box<Target<'a>>>, Option<Box<Target<'a>>>),
 }
 
 impl<'a> Target<'a> {
-    /// Parses multiple targets with `or` separating them
+    /// Parses multiple targets with `or` or `|` separating them
     pub(super) fn parse(i: &'a str, s: &State<'_>) -> ParseResult<'a, Self> {
+        Self::parse_nested(i, &s.level)
+    }
+
+    /// Like [`Target::parse()`], for a pattern in an expression, which only knows the nesting
+    /// `level` of the parser.
+    pub(super) fn parse_nested(i: &'a str, level: &Cell<Level>) -> ParseResult<'a, Self> {
         map(
-            separated_list1(ws(tag("or")), |i| {
-                s.nest(i)?;
-                let ret = Self::parse_one(i, s)?;
-                s.leave();
+            separated_list1(ws(alt((tag("or"), pattern_or))), |i| {
+                let (_, nested) = level.get().nest(i)?;
+                level.set(nested);
+                let ret = Self::parse_one(i, level)?;
+                level.set(level.get().leave());
                 Ok(ret)
             }),
             |mut opts| match opts.len() {
@@ -187,14 +235,23 @@ impl<'a> Target<'a> {
     }
 
     /// Parses a single target without an `or`, unless it is wrapped in parentheses.
-    fn parse_one(i: &'a str, s: &State<'_>) -> ParseResult<'a, Self> {
+    fn parse_one(i: &'a str, level: &Cell<Level>) -> ParseResult<'a, Self> {
         let mut opt_opening_paren = map(opt(ws(char('('))), |o| o.is_some());
         let mut opt_closing_paren = map(opt(ws(char(')'))), |o| o.is_some());
         let mut opt_opening_brace = map(opt(ws(char('{'))), |o| o.is_some());
 
         let (i, lit) = opt(Self::lit)(i)?;
         if let Some(lit) = lit {
-            return Ok((i, lit));
+            let (i, range) = opt(pair(ws(alt((tag("..="), tag("..")))), opt(Self::lit)))(i)?;
+            return Ok(match range {
+                Some((op, end)) => (i, Self::Range(op, Some(Box::new(lit)), end.map(Box::new))),
+                None => (i, lit),
+            });
+        }
+
+        let (i, range_to) = opt(preceded(ws(tag("..=")), cut(Self::lit)))(i)?;
+        if let Some(end) = range_to {
+            return Ok((i, Self::Range("..=", None, Some(Box::new(end)))));
         }
 
         // match tuples and unused parentheses
@@ -205,7 +262,7 @@ impl<'a> Target<'a> {
                 return Ok((i, Self::Tuple(Vec::new(), Vec::new())));
             }
 
-            let (i, first_target) = Self::parse(i, s)?;
+            let (i, first_target) = Self::parse_nested(i, level)?;
             let (i, is_unused_paren) = opt_closing_paren(i)?;
             if is_unused_paren {
                 return Ok((i, first_target));
@@ -214,7 +271,7 @@ impl<'a> Target<'a> {
             let mut targets = vec![first_target];
             let (i, _) = cut(tuple((
                 fold_many0(
-                    preceded(ws(char(',')), |i| Self::parse(i, s)),
+                    preceded(ws(char(',')), |i| Self::parse_nested(i, level)),
                     || (),
                     |_, target| {
                         targets.push(target);
@@ -244,7 +301,9 @@ impl<'a> Target<'a> {
                 let (i, targets) = alt((
                     map(char(')'), |_| Vec::new()),
                     terminated(
-                        cut(separated_list1(ws(char(',')), |i| Self::parse(i, s))),
+                        cut(separated_list1(ws(char(',')), |i| {
+                            Self::parse_nested(i, level)
+                        })),
                         pair(opt(ws(char(','))), ws(cut(char(')')))),
                     ),
                 ))(i)?;
@@ -256,7 +315,7 @@ impl<'a> Target<'a> {
                 let (i, targets) = alt((
                     map(char('}'), |_| Vec::new()),
                     terminated(
-                        cut(separated_list1(ws(char(',')), |i| Self::named(i, s))),
+                        cut(separated_list1(ws(char(',')), |i| Self::named(i, level))),
                         pair(opt(ws(char(','))), ws(cut(char('}')))),
                     ),
                 ))(i)?;
@@ -268,7 +327,18 @@ impl<'a> Target<'a> {
 
         // neither literal nor struct nor path
         let (new_i, name) = identifier(i)?;
-        Ok((new_i, Self::verify_name(i, name)?))
+        let target = Self::verify_name(i, name)?;
+
+        // `name @ pattern` binds the whole matched value to `name`
+        let (new_i, is_binding) = map(opt(ws(char('@'))), |o| o.is_some())(new_i)?;
+        if !is_binding {
+            return Ok((new_i, target));
+        }
+        let (_, nested) = level.get().nest(new_i)?;
+        level.set(nested);
+        let (new_i, pattern) = cut(|i| Self::parse_one(i, level))(new_i)?;
+        level.set(level.get().leave());
+        Ok((new_i, Self::Binding(name, Box::new(pattern))))
     }
 
     fn lit(i: &'a str) -> ParseResult<'a, Self> {
@@ -280,10 +350,10 @@ impl<'a> Target<'a> {
         ))(i)
     }
 
-    fn named(init_i: &'a str, s: &State<'_>) -> ParseResult<'a, (&'a str, Self)> {
+    fn named(init_i: &'a str, level: &Cell<Level>) -> ParseResult<'a, (&'a str, Self)> {
         let (i, (src, target)) = pair(
             identifier,
-            opt(preceded(ws(char(':')), |i| Self::parse(i, s))),
+            opt(preceded(ws(char(':')), |i| Self::parse_nested(i, level))),
         )(init_i)?;
 
         let target = match target {
@@ -305,10 +375,17 @@ impl<'a> Target<'a> {
     }
 }
 
+// A `|` separating the alternatives of a pattern, unless it is the start of `||`
+fn pattern_or(i: &str) -> ParseResult<'_> {
+    terminated(tag("|"), not(char('|')))(i)
+}
+
 #[derive(Debug, PartialEq)]
 pub struct When<'a> {
     pub ws: Ws,
     pub target: Target<'a>,
+    /// The `if` condition of the arm, e.g. `{% when Some(x) if x.len() > 3 %}`
+    pub guard: Option<Expr<'a>>,
     pub nodes: Vec<Node<'a>>,
 }
 
@@ -330,6 +407,7 @@ impl<'a> When<'a> {
             Self {
                 ws: Ws(pws, nws),
                 target: Target::Name("_"),
+                guard: None,
                 nodes,
             },
         ))
@@ -343,17 +421,22 @@ impl<'a> When<'a> {
             ws(keyword("when")),
             cut(tuple((
                 ws(|i| Target::parse(i, s)),
+                opt(preceded(
+                    ws(keyword("if")),
+                    cut(ws(|i| Expr::parse(i, s.level.get()))),
+                )),
                 opt(Whitespace::parse),
                 |i| s.tag_block_end(i),
                 cut(|i| Node::many(i, s)),
             ))),
         ));
-        let (i, (_, pws, _, (target, nws, _, nodes))) = p(i)?;
+        let (i, (_, pws, _, (target, guard, nws, _, nodes))) = p(i)?;
         Ok((
             i,
             Self {
                 ws: Ws(pws, nws),
                 target,
+                guard,
                 nodes,
             },
         ))
@@ -423,6 +506,8 @@ pub enum Whitespace {
     Preserve,
     Suppress,
     Minimize,
+    /// Collapses the whitespace to a single line break if it contains one, or removes it.
+    Linebreak,
 }
 
 impl Whitespace {
@@ -431,6 +516,7 @@ impl Whitespace {
             value(Self::Preserve, char('+')),
             value(Self::Suppress, char('-')),
             value(Self::Minimize, char('~')),
+            value(Self::Linebreak, char('^')),
         ))(i)
     }
 }
@@ -441,6 +527,7 @@ pub struct Loop<'a> {
     pub var: Target<'a>,
     pub iter: Expr<'a>,
     pub cond: Option<Expr<'a>>,
+    pub recursive: bool,
     pub body: Vec<Node<'a>>,
     pub ws2: Ws,
     pub else_nodes: Vec<Node<'a>>,
@@ -487,6 +574,7 @@ impl<'a> Loop<'a> {
                 cut(tuple((
                     ws(|i| Expr::parse(i, s.level.get())),
                     opt(if_cond),
+                    opt(ws(keyword("recursive"))),
                     opt(Whitespace::parse),
                     |i| s.tag_block_end(i),
                     cut(tuple((
@@ -502,8 +590,14 @@ impl<'a> Loop<'a> {
                 ))),
             ))),
         ));
-        let (i, (pws1, _, (var, _, (iter, cond, nws1, _, (body, (_, pws2, else_block, _, nws2)))))) =
-            p(i)?;
+        let (
+            i,
+            (
+                pws1,
+                _,
+                (var, _, (iter, cond, recursive, nws1, _, (body, (_, pws2, else_block, _, nws2)))),
+            ),
+        ) = p(i)?;
         let (nws3, else_block, pws3) = else_block.unwrap_or_default();
         Ok((
             i,
@@ -512,6 +606,7 @@ impl<'a> Loop<'a> {
                 var,
                 iter,
                 cond,
+                recursive: recursive.is_some(),
                 body,
                 ws2: Ws(pws2, nws3),
                 else_nodes: else_block,
@@ -525,20 +620,42 @@ impl<'a> Loop<'a> {
 pub struct Macro<'a> {
     pub ws1: Ws,
     pub name: &'a str,
-    pub args: Vec<&'a str>,
+    /// The parameters, with their default value if they have one.
+    pub args: Vec<(&'a str, Option<Expr<'a>>)>,
     pub nodes: Vec<Node<'a>>,
     pub ws2: Ws,
 }
 
 impl<'a> Macro<'a> {
     fn parse(i: &'a str, s: &State<'_>) -> ParseResult<'a, Self> {
-        fn parameters(i: &str) -> ParseResult<'_, Vec<&str>> {
-            delimited(
+        let parameters = |i| -> ParseResult<'_, _> {
+            let (j, args) = delimited(
                 ws(char('(')),
-                separated_list0(char(','), ws(identifier)),
+                separated_list0(
+                    char(','),
+                    pair(
+                        ws(identifier),
+                        opt(preceded(
+                            ws(char('=')),
+                            cut(ws(|i| Expr::parse(i, s.level.get()))),
+                        )),
+                    ),
+                ),
                 tuple((opt(ws(char(','))), char(')'))),
-            )(i)
-        }
+            )(i)?;
+            let mut has_default = false;
+            for (_, default) in &args {
+                if default.is_some() {
+                    has_default = true;
+                } else if has_default {
+                    return Err(nom::Err::Failure(ErrorContext::new(
+                        "macro arguments without a default value cannot follow arguments with one",
+                        i,
+                    )));
+                }
+            }
+            Ok((j, args))
+        };
 
         let mut start = tuple((
             opt(Whitespace::parse),
@@ -650,6 +767,159 @@ impl<'a> FilterBlock<'a> {
     }
 }
 
+#[derive(Debug, PartialEq)]
+pub struct Component<'a> {
+    pub ws1: Ws,
+    pub path: Vec<&'a str>,
+    pub args: Vec<(&'a str, Expr<'a>)>,
+    pub nodes: Vec<Node<'a>>,
+    pub ws2: Ws,
+}
+
+impl<'a> Component<'a> {
+    fn parse(i: &'a str, s: &State<'_>) -> ParseResult<'a, Self> {
+        let mut start = tuple((
+            opt(Whitespace::parse),
+            ws(keyword("component")),
+            cut(ws(path_or_identifier)),
+        ));
+        let (args_start, (pws1, _, path)) = start(i)?;
+
+        let mut args = cut(tuple((
+            ws(opt(|i| Expr::arguments(i, s.level.get(), true))),
+            opt(Whitespace::parse),
+            |i| s.tag_block_end(i),
+        )));
+        let (i, (args, nws1, _)) = args(args_start)?;
+
+        let path = match path {
+            PathOrIdentifier::Path(path) => path,
+            PathOrIdentifier::Identifier(name) => vec![name],
+        };
+        let args = args
+            .unwrap_or_default()
+            .into_iter()
+            .map(|arg| match arg {
+                Expr::NamedArgument(name, value) => Ok((name, *value)),
+                _ => Err(nom::Err::Failure(ErrorContext::new(
+                    "component arguments must be named, e.g. `name = value`",
+                    args_start,
+                ))),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut end = cut(tuple((
+            |i| Node::many(i, s),
+            cut(tuple((
+                |i| s.tag_block_start(i),
+                opt(Whitespace::parse),
+                ws(keyword("endcomponent")),
+                opt(Whitespace::parse),
+            ))),
+        )));
+        let (i, (nodes, (_, pws2, _, nws2))) = end(i)?;
+
+        Ok((
+            i,
+            Self {
+                ws1: Ws(pws1, nws1),
+                path,
+                args,
+                nodes,
+                ws2: Ws(pws2, nws2),
+            },
+        ))
+    }
+}
+
+/// `{% while cond %}…{% endwhile %}`, where the condition can be a `let` pattern like in `if`.
+#[derive(Debug, PartialEq)]
+pub struct While<'a> {
+    pub ws1: Ws,
+    pub cond: CondTest<'a>,
+    pub nodes: Vec<Node<'a>>,
+    pub ws2: Ws,
+}
+
+impl<'a> While<'a> {
+    fn parse(i: &'a str, s: &State<'_>) -> ParseResult<'a, Self> {
+        let mut start = tuple((
+            opt(Whitespace::parse),
+            ws(keyword("while")),
+            cut(tuple((
+                |i| CondTest::parse_cond(i, s),
+                opt(Whitespace::parse),
+                |i| s.tag_block_end(i),
+            ))),
+        ));
+        let (i, (pws1, _, (cond, nws1, _))) = start(i)?;
+
+        let mut end = cut(tuple((
+            |i| {
+                s.enter_loop();
+                let result = Node::many(i, s);
+                s.leave_loop();
+                result
+            },
+            cut(tuple((
+                |i| s.tag_block_start(i),
+                opt(Whitespace::parse),
+                ws(keyword("endwhile")),
+                opt(Whitespace::parse),
+            ))),
+        )));
+        let (i, (nodes, (_, pws2, _, nws2))) = end(i)?;
+
+        Ok((
+            i,
+            Self {
+                ws1: Ws(pws1, nws1),
+                cond,
+                nodes,
+                ws2: Ws(pws2, nws2),
+            },
+        ))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Once<'a> {
+    pub ws1: Ws,
+    pub nodes: Vec<Node<'a>>,
+    pub ws2: Ws,
+}
+
+impl<'a> Once<'a> {
+    fn parse(i: &'a str, s: &State<'_>) -> ParseResult<'a, Self> {
+        let mut start = tuple((
+            opt(Whitespace::parse),
+            ws(keyword("once")),
+            cut(tuple((opt(Whitespace::parse), |i| s.tag_block_end(i)))),
+        ));
+        let (i, (pws1, _, (nws1, _))) = start(i)?;
+
+        let mut end = cut(tuple((
+            |i| Node::many(i, s),
+            cut(tuple((
+                |i| s.tag_block_start(i),
+                opt(Whitespace::parse),
+                ws(keyword("endonce")),
+                opt(Whitespace::parse),
+            ))),
+        )));
+        let (i, (nodes, (_, pws2, _, nws2))) = end(i)?;
+
+        Ok((
+            i,
+            Self {
+                ws1: Ws(pws1, nws1),
+                nodes,
+                ws2: Ws(pws2, nws2),
+            },
+        ))
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub struct Import<'a> {
     pub ws: Ws,
@@ -663,7 +933,7 @@ impl<'a> Import<'a> {
             opt(Whitespace::parse),
             ws(keyword("import")),
             cut(tuple((
-                ws(str_lit),
+                ws(map(str_lit, |s| s.content)),
                 ws(keyword("as")),
                 cut(pair(ws(identifier), opt(Whitespace::parse))),
             ))),
@@ -680,12 +950,62 @@ impl<'a> Import<'a> {
     }
 }
 
+#[derive(Debug, PartialEq)]
+pub struct FromImport<'a> {
+    pub ws: Ws,
+    pub path: &'a str,
+    /// The imported macros, with the name they are imported as if it's not their own.
+    pub names: Vec<(&'a str, Option<&'a str>)>,
+}
+
+impl<'a> FromImport<'a> {
+    fn parse(i: &'a str) -> ParseResult<'a, Self> {
+        let mut p = tuple((
+            opt(Whitespace::parse),
+            ws(keyword("from")),
+            cut(tuple((
+                ws(map(str_lit, |s| s.content)),
+                ws(keyword("import")),
+                separated_list1(
+                    ws(char(',')),
+                    pair(
+                        ws(identifier),
+                        opt(preceded(ws(keyword("as")), cut(ws(identifier)))),
+                    ),
+                ),
+                opt(ws(char(','))),
+                opt(Whitespace::parse),
+            ))),
+        ));
+        let (i, (pws, _, (path, _, names, _, nws))) = p(i)?;
+        Ok((
+            i,
+            Self {
+                ws: Ws(pws, nws),
+                path,
+                names,
+            },
+        ))
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub struct Call<'a> {
     pub ws: Ws,
     pub scope: Option<&'a str>,
+    /// The name of the macro, or `super` to render the definition of the current block that was
+    /// overridden, repeated with dots (e.g. `super.super`) to go up more than one level.
     pub name: &'a str,
     pub args: Vec<Expr<'a>>,
+    /// The body of a `{% call(args) macro() %}…{% endcall %}` block, rendered by `caller()`.
+    pub caller: Option<Caller<'a>>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Caller<'a> {
+    pub args: Vec<&'a str>,
+    pub nodes: Vec<Node<'a>>,
+    pub ws: Ws,
 }
 
 impl<'a> Call<'a> {
@@ -694,15 +1014,48 @@ impl<'a> Call<'a> {
             opt(Whitespace::parse),
             ws(keyword("call")),
             cut(tuple((
+                opt(ws(delimited(
+                    char('('),
+                    separated_list0(char(','), ws(identifier)),
+                    tuple((opt(ws(char(','))), char(')'))),
+                ))),
                 opt(tuple((ws(identifier), ws(tag("::"))))),
-                ws(identifier),
+                ws(alt((
+                    recognize(pair(
+                        keyword("super"),
+                        many1(pair(char('.'), keyword("super"))),
+                    )),
+                    identifier,
+                ))),
                 opt(ws(|nested| Expr::arguments(nested, s.level.get(), true))),
                 opt(Whitespace::parse),
             ))),
         ));
-        let (i, (pws, _, (scope, name, args, nws))) = p(i)?;
+        let (i, (pws, _, (caller_args, scope, name, args, nws))) = p(i)?;
         let scope = scope.map(|(scope, _)| scope);
         let args = args.unwrap_or_default();
+        let (i, caller) = match caller_args {
+            Some(caller_args) => {
+                let mut body = cut(tuple((
+                    |i| s.tag_block_end(i),
+                    |i| Node::many(i, s),
+                    cut(tuple((
+                        |i| s.tag_block_start(i),
+                        opt(Whitespace::parse),
+                        ws(keyword("endcall")),
+                        opt(Whitespace::parse),
+                    ))),
+                )));
+                let (i, (_, nodes, (_, pws2, _, nws2))) = body(i)?;
+                let caller = Caller {
+                    args: caller_args,
+                    nodes,
+                    ws: Ws(pws2, nws2),
+                };
+                (i, Some(caller))
+            }
+            None => (i, None),
+        };
         Ok((
             i,
             Self {
@@ -710,6 +1063,7 @@ impl<'a> Call<'a> {
                 scope,
                 name,
                 args,
+                caller,
             },
         ))
     }
@@ -770,6 +1124,9 @@ impl<'a> Match<'a> {
 pub struct BlockDef<'a> {
     pub ws1: Ws,
     pub name: &'a str,
+    /// Whether the definitions of the block see the local variables where it is defined, e.g.
+    /// the variable of an enclosing loop.
+    pub scoped: bool,
     pub nodes: Vec<Node<'a>>,
     pub ws2: Ws,
 }
@@ -779,11 +1136,14 @@ impl<'a> BlockDef<'a> {
         let mut start = tuple((
             opt(Whitespace::parse),
             ws(keyword("block")),
-            cut(tuple((ws(identifier), opt(Whitespace::parse), |i| {
-                s.tag_block_end(i)
-            }))),
+            cut(tuple((
+                ws(identifier),
+                opt(ws(keyword("scoped"))),
+                opt(Whitespace::parse),
+                |i| s.tag_block_end(i),
+            ))),
         ));
-        let (i, (pws1, _, (name, nws1, _))) = start(i)?;
+        let (i, (pws1, _, (name, scoped, nws1, _))) = start(i)?;
 
         let mut end = cut(tuple((
             |i| Node::many(i, s),
@@ -807,6 +1167,7 @@ impl<'a> BlockDef<'a> {
             BlockDef {
                 ws1: Ws(pws1, nws1),
                 name,
+                scoped: scoped.is_some(),
                 nodes,
                 ws2: Ws(pws2, nws2),
             },
@@ -882,26 +1243,35 @@ pub struct Raw<'a> {
 }
 
 impl<'a> Raw<'a> {
-    fn parse(i: &'a str, s: &State<'_>) -> ParseResult<'a, Self> {
+    pub(super) fn parse(i: &'a str, s: &State<'_>) -> ParseResult<'a, Self> {
+        // With a marker, like `{% raw "EOF" %}`, the block only ends at `{% endraw "EOF" %}`.
+        let (i, (pws1, _, marker)) =
+            tuple((opt(Whitespace::parse), ws(keyword("raw")), opt(ws(str_lit))))(i)?;
+        let end_marker = move |i| match marker {
+            Some(marker) => map(
+                verify(ws(str_lit), |end: &StrLit<'_>| {
+                    end.content == marker.content
+                }),
+                Some,
+            )(i),
+            None => Ok((i, None)),
+        };
         let endraw = tuple((
             |i| s.tag_block_start(i),
             opt(Whitespace::parse),
             ws(keyword("endraw")),
+            end_marker,
             opt(Whitespace::parse),
             peek(|i| s.tag_block_end(i)),
         ));
 
-        let mut p = tuple((
+        let mut p = cut(tuple((
             opt(Whitespace::parse),
-            ws(keyword("raw")),
-            cut(tuple((
-                opt(Whitespace::parse),
-                |i| s.tag_block_end(i),
-                consumed(skip_till(endraw)),
-            ))),
-        ));
+            |i| s.tag_block_end(i),
+            consumed(skip_till(endraw)),
+        )));
 
-        let (_, (pws1, _, (nws1, _, (contents, (i, (_, pws2, _, nws2, _)))))) = p(i)?;
+        let (_, (nws1, _, (contents, (i, (_, pws2, _, _, nws2, _))))) = p(i)?;
         let lit = Lit::split_ws_parts(contents);
         let ws1 = Ws(pws1, nws1);
         let ws2 = Ws(pws2, nws2);
@@ -912,6 +1282,7 @@ impl<'a> Raw<'a> {
 #[derive(Debug, PartialEq)]
 pub struct Let<'a> {
     pub ws: Ws,
+    pub mutable: bool,
     pub var: Target<'a>,
     pub val: Option<Expr<'a>>,
 }
@@ -922,6 +1293,7 @@ impl<'a> Let<'a> {
             opt(Whitespace::parse),
             ws(alt((keyword("let"), keyword("set")))),
             cut(tuple((
+                opt(ws(keyword("mut"))),
                 ws(|i| Target::parse(i, s)),
                 opt(preceded(
                     ws(char('=')),
@@ -930,12 +1302,19 @@ impl<'a> Let<'a> {
                 opt(Whitespace::parse),
             ))),
         ));
-        let (i, (pws, _, (var, val, nws))) = p(i)?;
+        let (j, (pws, _, (mutable, var, val, nws))) = p(i)?;
+        if mutable.is_some() && !matches!(var, Target::Name(_)) {
+            return Err(nom::Err::Failure(ErrorContext::new(
+                "only a variable name can be declared `mut`",
+                i,
+            )));
+        }
 
         Ok((
-            i,
+            j,
             Let {
                 ws: Ws(pws, nws),
+                mutable: mutable.is_some(),
                 var,
                 val,
             },
@@ -943,6 +1322,132 @@ impl<'a> Let<'a> {
     }
 }
 
+/// `{% const NAME = value %}`: a constant of the template, usable in all its blocks and in the
+/// blocks of the templates extending it.
+#[derive(Debug, PartialEq)]
+pub struct Const<'a> {
+    pub ws: Ws,
+    pub name: &'a str,
+    pub value: Expr<'a>,
+}
+
+impl<'a> Const<'a> {
+    fn parse(i: &'a str, s: &State<'_>) -> ParseResult<'a, Self> {
+        let mut p = tuple((
+            opt(Whitespace::parse),
+            ws(keyword("const")),
+            cut(tuple((
+                ws(identifier),
+                ws(char('=')),
+                ws(|i| Expr::parse(i, s.level.get())),
+                opt(Whitespace::parse),
+            ))),
+        ));
+        let (i, (pws, _, (name, _, value, nws))) = p(i)?;
+        Ok((
+            i,
+            Self {
+                ws: Ws(pws, nws),
+                name,
+                value,
+            },
+        ))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct SetBlock<'a> {
+    pub ws1: Ws,
+    pub var: &'a str,
+    pub nodes: Vec<Node<'a>>,
+    pub ws2: Ws,
+}
+
+impl<'a> SetBlock<'a> {
+    fn parse(i: &'a str, s: &State<'_>) -> ParseResult<'a, Self> {
+        let mut start = tuple((
+            opt(Whitespace::parse),
+            ws(keyword("set")),
+            ws(identifier),
+            opt(Whitespace::parse),
+            |i| s.tag_block_end(i),
+        ));
+        let (i, (pws1, _, var, nws1, _)) = start(i)?;
+
+        let mut end = cut(tuple((
+            |i| Node::many(i, s),
+            cut(tuple((
+                |i| s.tag_block_start(i),
+                opt(Whitespace::parse),
+                ws(keyword("endset")),
+                opt(Whitespace::parse),
+            ))),
+        )));
+        let (i, (nodes, (_, pws2, _, nws2))) = end(i)?;
+
+        Ok((
+            i,
+            Self {
+                ws1: Ws(pws1, nws1),
+                var,
+                nodes,
+                ws2: Ws(pws2, nws2),
+            },
+        ))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct With<'a> {
+    pub ws1: Ws,
+    pub vars: Vec<(&'a str, Expr<'a>)>,
+    pub nodes: Vec<Node<'a>>,
+    pub ws2: Ws,
+}
+
+impl<'a> With<'a> {
+    fn parse(i: &'a str, s: &State<'_>) -> ParseResult<'a, Self> {
+        let var = pair(
+            ws(identifier),
+            cut(preceded(
+                ws(char('=')),
+                ws(|i| Expr::parse(i, s.level.get())),
+            )),
+        );
+        let mut start = tuple((
+            opt(Whitespace::parse),
+            ws(keyword("with")),
+            cut(tuple((
+                separated_list1(ws(char(',')), var),
+                opt(Whitespace::parse),
+                |i| s.tag_block_end(i),
+            ))),
+        ));
+        let (i, (pws1, _, (vars, nws1, _))) = start(i)?;
+
+        let mut end = cut(tuple((
+            |i| Node::many(i, s),
+            cut(tuple((
+                |i| s.tag_block_start(i),
+                opt(Whitespace::parse),
+                ws(keyword("endwith")),
+                opt(Whitespace::parse),
+            ))),
+        )));
+        let (i, (nodes, (_, pws2, _, nws2))) = end(i)?;
+
+        Ok((
+            i,
+            Self {
+                ws1: Ws(pws1, nws1),
+                vars,
+                nodes,
+                ws2: Ws(pws2, nws2),
+            },
+        ))
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub struct If<'a> {
     pub ws: Ws,
@@ -991,22 +1496,102 @@ impl<'a> If<'a> {
 #[derive(Debug, PartialEq)]
 pub struct Include<'a> {
     pub ws: Ws,
-    pub path: &'a str,
+    /// The expression choosing the included template when rendering, among `paths`.
+    pub expr: Option<Expr<'a>>,
+    /// The path of the included template, or of the templates `expr` can choose.
+    pub paths: Vec<&'a str>,
+    /// The value whose fields the included template sees instead of the ones of the template.
+    pub context: Option<Expr<'a>>,
+    /// Whether the included template doesn't see the variables of the including one.
+    pub only: bool,
 }
 
 impl<'a> Include<'a> {
-    fn parse(i: &'a str) -> ParseResult<'a, Self> {
+    fn parse(i: &'a str, s: &State<'_>) -> ParseResult<'a, Self> {
+        let paths = |i| {
+            delimited(
+                ws(char('[')),
+                separated_list1(char(','), ws(map(str_lit, |s| s.content))),
+                tuple((opt(ws(char(','))), char(']'))),
+            )(i)
+        };
         let mut p = tuple((
             opt(Whitespace::parse),
             ws(keyword("include")),
-            cut(pair(ws(str_lit), opt(Whitespace::parse))),
+            cut(tuple((
+                alt((
+                    map(
+                        pair(
+                            ws(|i| Expr::parse(i, s.level.get())),
+                            preceded(ws(keyword("from")), cut(ws(paths))),
+                        ),
+                        |(expr, paths)| (Some(expr), paths),
+                    ),
+                    map(ws(map(str_lit, |s| s.content)), |path| (None, vec![path])),
+                )),
+                opt(preceded(
+                    ws(keyword("with")),
+                    cut(ws(|i| Expr::parse(i, s.level.get()))),
+                )),
+                opt(ws(keyword("only"))),
+                opt(Whitespace::parse),
+            ))),
         ));
-        let (i, (pws, _, (path, nws))) = p(i)?;
+        let (i, (pws, _, ((expr, paths), context, only, nws))) = p(i)?;
         Ok((
             i,
             Self {
                 ws: Ws(pws, nws),
+                expr,
+                paths,
+                context,
+                only: only.is_some(),
+            },
+        ))
+    }
+}
+
+/// `{% embed "path" %}…{% endembed %}`: includes a template, overriding its blocks with the
+/// ones defined in the tag.
+#[derive(Debug, PartialEq)]
+pub struct Embed<'a> {
+    pub ws1: Ws,
+    pub path: &'a str,
+    pub nodes: Vec<Node<'a>>,
+    pub ws2: Ws,
+}
+
+impl<'a> Embed<'a> {
+    fn parse(i: &'a str, s: &State<'_>) -> ParseResult<'a, Self> {
+        let mut start = tuple((
+            opt(Whitespace::parse),
+            ws(keyword("embed")),
+            cut(tuple((
+                ws(map(str_lit, |s| s.content)),
+                opt(Whitespace::parse),
+                |i| s.tag_block_end(i),
+            ))),
+        ));
+        let (i, (pws1, _, (path, nws1, _))) = start(i)?;
+
+        let mut end = cut(tuple((
+            |i| Node::many(i, s),
+            cut(tuple((
+                |i| s.tag_block_start(i),
+                opt(Whitespace::parse),
+                ws(keyword("endembed")),
+                opt(Whitespace::parse),
+            ))),
+        )));
+        let (i, (nodes, (_, pws2, _, nws2))) = end(i)?;
+
+        Ok((
+            i,
+            Self {
+                ws1: Ws(pws1, nws1),
                 path,
+                nodes,
+                ws2: Ws(pws2, nws2),
             },
         ))
     }
@@ -1024,7 +1609,10 @@ impl<'a> Extends<'a> {
         let (i, (pws, _, (path, nws))) = tuple((
             opt(Whitespace::parse),
             ws(keyword("extends")),
-            cut(pair(ws(str_lit), opt(Whitespace::parse))),
+            cut(pair(
+                ws(map(str_lit, |s| s.content)),
+                opt(Whitespace::parse),
+            )),
         ))(i)?;
         match (pws, nws) {
             (None, None) => Ok((i, Self { path })),
@@ -1043,7 +1631,7 @@ pub struct Comment<'a> {
 }
 
 impl<'a> Comment<'a> {
-    fn parse(i: &'a str, s: &State<'_>) -> ParseResult<'a, Self> {
+    pub(super) fn parse(i: &'a str, s: &State<'_>) -> ParseResult<'a, Self> {
         #[derive(Debug, Clone, Copy)]
         enum Tag {
             Open,
@@ -1094,6 +1682,7 @@ impl<'a> Comment<'a> {
                 Some('-') => Some(Whitespace::Suppress),
                 Some('+') => Some(Whitespace::Preserve),
                 Some('~') => Some(Whitespace::Minimize),
+                Some('^') => Some(Whitespace::Linebreak),
                 _ => None,
             }
         };
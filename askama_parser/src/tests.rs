@@ -1,5 +1,8 @@
-use super::node::{Lit, Whitespace, Ws};
-use super::{Ast, Expr, Filter, Node, Syntax};
+use super::node::{
+    BlockDef, Call, Caller, Component, CondTest, Const, Embed, FromImport, Include, Let, Lit,
+    Macro, Match, Once, Raw, SetBlock, Target, When, While, Whitespace, With, Ws,
+};
+use super::{Ast, Expr, Filter, FormatPart, Node, Parsed, StrLit, Syntax, Test};
 
 fn check_ws_split(s: &str, res: &(&str, &str, &str)) {
     let Lit { lws, val, rws } = Lit::split_ws_parts(s);
@@ -170,7 +173,13 @@ fn test_parse_var_call() {
             Ws(None, None),
             Expr::Call(
                 Box::new(Expr::Var("function")),
-                vec![Expr::StrLit("123"), Expr::NumLit("3")]
+                vec![
+                    Expr::StrLit(StrLit {
+                        content: "123",
+                        raw: None,
+                    }),
+                    Expr::NumLit("3")
+                ]
             ),
         )],
     );
@@ -208,12 +217,61 @@ fn test_parse_path_call() {
             Ws(None, None),
             Expr::Call(
                 Box::new(Expr::Path(vec!["self", "function"])),
-                vec![Expr::StrLit("123"), Expr::NumLit("3")],
+                vec![
+                    Expr::StrLit(StrLit {
+                        content: "123",
+                        raw: None,
+                    }),
+                    Expr::NumLit("3")
+                ],
             ),
         )],
     );
 }
 
+#[test]
+fn test_parse_turbofish() {
+    let s = Syntax::default();
+
+    assert_eq!(
+        Ast::from_str("{{ value.parse::<u32>().unwrap_or(0) }}", None, &s)
+            .unwrap()
+            .nodes,
+        vec![Node::Expr(
+            Ws(None, None),
+            Expr::Call(
+                Box::new(Expr::Attr(
+                    Box::new(Expr::Call(
+                        Box::new(Expr::Turbofish(
+                            Box::new(Expr::Attr(Box::new(Expr::Var("value")), "parse")),
+                            vec!["u32"],
+                        )),
+                        vec![],
+                    )),
+                    "unwrap_or",
+                )),
+                vec![Expr::NumLit("0")],
+            ),
+        )],
+    );
+    assert_eq!(
+        Ast::from_str("{{ std::mem::size_of :: < Vec<u8> , &str >() }}", None, &s)
+            .unwrap()
+            .nodes,
+        vec![Node::Expr(
+            Ws(None, None),
+            Expr::Call(
+                Box::new(Expr::Turbofish(
+                    Box::new(Expr::Path(vec!["std", "mem", "size_of"])),
+                    vec!["Vec<u8>", "&str"],
+                )),
+                vec![],
+            ),
+        )],
+    );
+    assert!(Ast::from_str("{{ value.parse::<>() }}", None, &s).is_err());
+}
+
 #[test]
 fn test_parse_root_path() {
     let syntax = Syntax::default();
@@ -445,24 +503,24 @@ fn test_associativity() {
         vec![Node::Expr(
             Ws(None, None),
             Expr::BinOp(
-                "==",
+                "&&",
                 Expr::BinOp(
-                    ">",
+                    "&&",
                     Expr::BinOp(
-                        ">",
+                        "&&",
                         Expr::BinOp(
-                            "!=",
+                            "&&",
                             Expr::BinOp("==", Expr::Var("a").into(), Expr::Var("b").into()).into(),
-                            Expr::Var("c").into()
+                            Expr::BinOp("!=", Expr::Var("b").into(), Expr::Var("c").into()).into()
                         )
                         .into(),
-                        Expr::Var("d").into()
+                        Expr::BinOp(">", Expr::Var("c").into(), Expr::Var("d").into()).into()
                     )
                     .into(),
-                    Expr::Var("e").into()
+                    Expr::BinOp(">", Expr::Var("d").into(), Expr::Var("e").into()).into()
                 )
                 .into(),
-                Expr::Var("f").into()
+                Expr::BinOp("==", Expr::Var("e").into(), Expr::Var("f").into()).into()
             )
         )],
     );
@@ -624,10 +682,43 @@ fn test_parse_comments() {
         "{#~ foo\n {#~ bar\n ~#} baz -~#}",
         Ws(Some(Whitespace::Minimize), Some(Whitespace::Minimize)),
     );
+    one_comment_ws("{#^ #}", Ws(Some(Whitespace::Linebreak), None));
+    one_comment_ws("{# ^#}", Ws(None, Some(Whitespace::Linebreak)));
+    one_comment_ws(
+        "{#^ foo\n bar -^#}",
+        Ws(Some(Whitespace::Linebreak), Some(Whitespace::Linebreak)),
+    );
 
     one_comment_ws("{# foo {# bar #} {# {# baz #} qux #} #}", Ws(None, None));
 }
 
+#[test]
+fn test_linebreak_marker() {
+    let syntax = Syntax::default();
+    assert_eq!(
+        Ast::from_str("{%^ let a = 1 ^%}{{^ a }}", None, &syntax)
+            .unwrap()
+            .nodes,
+        vec![
+            Node::Let(Let {
+                ws: Ws(Some(Whitespace::Linebreak), Some(Whitespace::Linebreak)),
+                mutable: false,
+                var: Target::Name("a"),
+                val: Some(Expr::NumLit("1")),
+            }),
+            Node::Expr(Ws(Some(Whitespace::Linebreak), None), Expr::Var("a")),
+        ],
+    );
+    // `^` is still a binary operator when it isn't a marker.
+    assert_eq!(
+        Ast::from_str("{{ a ^ b ^}}", None, &syntax).unwrap().nodes,
+        vec![Node::Expr(
+            Ws(None, Some(Whitespace::Linebreak)),
+            Expr::BinOp("^", Box::new(Expr::Var("a")), Box::new(Expr::Var("b"))),
+        )],
+    );
+}
+
 #[test]
 fn test_parse_tuple() {
     let syntax = Syntax::default();
@@ -805,6 +896,52 @@ fn test_missing_space_after_kw() {
     ));
 }
 
+#[test]
+fn test_parse_map() {
+    let syntax = Syntax::default();
+    assert_eq!(
+        Ast::from_str("{{ {} }}", None, &syntax).unwrap().nodes,
+        vec![Node::Expr(Ws(None, None), Expr::Map(vec![]))],
+    );
+    assert_eq!(
+        Ast::from_str("{{ { \"a\": 1, b : [2], } }}", None, &syntax)
+            .unwrap()
+            .nodes,
+        vec![Node::Expr(
+            Ws(None, None),
+            Expr::Map(vec![
+                (
+                    Expr::StrLit(StrLit {
+                        content: "a",
+                        raw: None
+                    }),
+                    Expr::NumLit("1")
+                ),
+                (Expr::Var("b"), Expr::Array(vec![Expr::NumLit("2")])),
+            ])
+        )],
+    );
+    assert_eq!(
+        Ast::from_str("{{{\"a\":1}|json}}", None, &syntax)
+            .unwrap()
+            .nodes,
+        vec![Node::Expr(
+            Ws(None, None),
+            Expr::Filter(Filter {
+                name: "json",
+                arguments: vec![Expr::Map(vec![(
+                    Expr::StrLit(StrLit {
+                        content: "a",
+                        raw: None
+                    }),
+                    Expr::NumLit("1")
+                )])]
+            })
+        )],
+    );
+    assert!(Ast::from_str("{{ {\"a\"} }}", None, &syntax).is_err());
+}
+
 #[test]
 fn test_parse_array() {
     let syntax = Syntax::default();
@@ -936,11 +1073,1096 @@ fn fuzzed_comment_depth() {
 #[test]
 fn let_set() {
     assert_eq!(
-        Ast::from_str("{% let a %}", None, &Syntax::default())
+        Ast::from_str("{% let a = 1 %}", None, &Syntax::default())
+            .unwrap()
+            .nodes(),
+        Ast::from_str("{% set a = 1 %}", None, &Syntax::default())
             .unwrap()
             .nodes(),
-        Ast::from_str("{% set a %}", None, &Syntax::default())
+    );
+}
+
+#[test]
+fn test_set_block() {
+    let syntax = Syntax::default();
+    assert_eq!(
+        Ast::from_str("{% set a -%} b {%- endset %}", None, &syntax)
+            .unwrap()
+            .nodes,
+        vec![Node::SetBlock(SetBlock {
+            ws1: Ws(None, Some(Whitespace::Suppress)),
+            var: "a",
+            nodes: vec![Node::Lit(Lit {
+                lws: " ",
+                val: "b",
+                rws: " ",
+            })],
+            ws2: Ws(Some(Whitespace::Suppress), None),
+        })],
+    );
+    // Without a value, `set` starts a block.
+    assert!(Ast::from_str("{% set a %}", None, &syntax).is_err());
+}
+
+#[test]
+fn test_component() {
+    let syntax = Syntax::default();
+    assert_eq!(
+        Ast::from_str(
+            "{% component ui::Card(title = x) %}a{% endcomponent %}",
+            None,
+            &syntax
+        )
+        .unwrap()
+        .nodes,
+        vec![Node::Component(Component {
+            ws1: Ws(None, None),
+            path: vec!["ui", "Card"],
+            args: vec![("title", Expr::Var("x"))],
+            nodes: vec![Node::Lit(Lit {
+                lws: "",
+                val: "a",
+                rws: "",
+            })],
+            ws2: Ws(None, None),
+        })],
+    );
+    assert_eq!(
+        Ast::from_str("{% component Card -%}{%- endcomponent %}", None, &syntax)
+            .unwrap()
+            .nodes,
+        vec![Node::Component(Component {
+            ws1: Ws(None, Some(Whitespace::Suppress)),
+            path: vec!["Card"],
+            args: vec![],
+            nodes: vec![],
+            ws2: Ws(Some(Whitespace::Suppress), None),
+        })],
+    );
+    assert!(Ast::from_str("{% component Card(x) %}{% endcomponent %}", None, &syntax).is_err());
+    assert!(Ast::from_str("{% component Card %}", None, &syntax).is_err());
+}
+
+#[test]
+fn test_argument_comments_and_trailing_commas() {
+    let syntax = Syntax::default();
+    for (commented, plain) in [
+        (
+            "{% call m(\n  a, /* first */\n  b = 1 /* second */,\n) %}",
+            "{% call m(a, b = 1) %}",
+        ),
+        ("{{ x|truncate(/* chars */ 10,) }}", "{{ x|truncate(10) }}"),
+        ("{{ f(a /* b */) }}", "{{ f(a) }}"),
+        (
+            "{{ f(a /* b */ /* c */, /**/ d, /* e */) }}",
+            "{{ f(a, d) }}",
+        ),
+        ("{{ f(a / b, c * d) }}", "{{ f(a / b, c * d) }}"),
+    ] {
+        assert_eq!(
+            Ast::from_str(commented, None, &syntax).unwrap().nodes,
+            Ast::from_str(plain, None, &syntax).unwrap().nodes,
+            "{commented}",
+        );
+    }
+    assert!(Ast::from_str("{{ f(a /* b) }}", None, &syntax).is_err());
+    assert!(Ast::from_str("{{ f(a,,) }}", None, &syntax).is_err());
+}
+
+#[test]
+fn test_match_bindings() {
+    let syntax = Syntax::default();
+    let nodes = Ast::from_str(
+        "{% match self.lookup(id) %}{% when all @ Some with (n @ (1 or 2)) %}{% endmatch %}",
+        None,
+        &syntax,
+    )
+    .unwrap()
+    .nodes;
+    let [Node::Match(Match { expr, arms, .. })] = &nodes[..] else {
+        panic!("expected a single match node: {nodes:?}");
+    };
+    assert_eq!(
+        *expr,
+        Expr::Call(
+            Box::new(Expr::Attr(Box::new(Expr::Var("self")), "lookup")),
+            vec![Expr::Var("id")],
+        ),
+    );
+    let [When { target, .. }] = &arms[..] else {
+        panic!("expected a single arm: {arms:?}");
+    };
+    assert_eq!(
+        *target,
+        Target::Binding(
+            "all",
+            Box::new(Target::Tuple(
+                vec!["Some"],
+                vec![Target::Binding(
+                    "n",
+                    Box::new(Target::OrChain(vec![
+                        Target::NumLit("1"),
+                        Target::NumLit("2"),
+                    ])),
+                )],
+            )),
+        ),
+    );
+    assert!(Ast::from_str("{% match x %}{% when n @ %}{% endmatch %}", None, &syntax).is_err());
+}
+
+#[test]
+fn test_type_hints() {
+    let syntax = Syntax::default();
+    assert_eq!(
+        Ast::from_str("{{ total : f64 }}", None, &syntax)
+            .unwrap()
+            .nodes,
+        vec![Node::Expr(
+            Ws(None, None),
+            Expr::TypeHint(Box::new(Expr::Var("total")), "f64"),
+        )],
+    );
+    assert_eq!(
+        Ast::from_str(
+            "{{ a|join(\", \") : HashMap<&str, Vec<u8>> }}",
+            None,
+            &syntax
+        )
+        .unwrap()
+        .nodes,
+        vec![Node::Expr(
+            Ws(None, None),
+            Expr::TypeHint(
+                Box::new(Expr::Filter(Filter {
+                    name: "join",
+                    arguments: vec![
+                        Expr::Var("a"),
+                        Expr::StrLit(StrLit {
+                            content: ", ",
+                            raw: None,
+                        })
+                    ],
+                })),
+                "HashMap<&str, Vec<u8>>",
+            ),
+        )],
+    );
+    assert!(Ast::from_str("{{ a : }}", None, &syntax).is_err());
+    assert!(Ast::from_str("{% if a : bool %}{% endif %}", None, &syntax).is_err());
+}
+
+#[test]
+fn test_once() {
+    let syntax = Syntax::default();
+    assert_eq!(
+        Ast::from_str("{%- once %}a{% endonce -%}", None, &syntax)
+            .unwrap()
+            .nodes,
+        vec![Node::Once(Once {
+            ws1: Ws(Some(Whitespace::Suppress), None),
+            nodes: vec![Node::Lit(Lit {
+                lws: "",
+                val: "a",
+                rws: "",
+            })],
+            ws2: Ws(None, Some(Whitespace::Suppress)),
+        })],
+    );
+    assert!(Ast::from_str("{% once %}a", None, &syntax).is_err());
+}
+
+#[test]
+fn test_with() {
+    let syntax = Syntax::default();
+    assert_eq!(
+        Ast::from_str("{% with a = 1, b = c|d %}{% endwith %}", None, &syntax)
+            .unwrap()
+            .nodes,
+        vec![Node::With(With {
+            ws1: Ws(None, None),
+            vars: vec![
+                ("a", Expr::NumLit("1")),
+                (
+                    "b",
+                    Expr::Filter(Filter {
+                        name: "d",
+                        arguments: vec![Expr::Var("c")],
+                    }),
+                ),
+            ],
+            nodes: vec![],
+            ws2: Ws(None, None),
+        })],
+    );
+    assert!(Ast::from_str("{% with %}{% endwith %}", None, &syntax).is_err());
+    assert!(Ast::from_str("{% with a %}{% endwith %}", None, &syntax).is_err());
+    assert!(Ast::from_str("{% with a = 1 %}", None, &syntax).is_err());
+}
+
+#[test]
+fn test_recursive_loop() {
+    let syntax = Syntax::default();
+    let nodes = Ast::from_str(
+        "{% for i in items if i.visible recursive %}{{ loop(i.children) }}{% endfor %}",
+        None,
+        &syntax,
+    )
+    .unwrap()
+    .nodes;
+    let [Node::Loop(l)] = &nodes[..] else {
+        panic!("expected a single loop: {nodes:?}");
+    };
+    assert!(l.recursive);
+    assert_eq!(
+        l.cond,
+        Some(Expr::Attr(Box::new(Expr::Var("i")), "visible"))
+    );
+
+    let nodes = Ast::from_str(
+        "{% for recursive in recursive %}{% endfor %}",
+        None,
+        &syntax,
+    )
+    .unwrap()
+    .nodes;
+    let [Node::Loop(l)] = &nodes[..] else {
+        panic!("expected a single loop: {nodes:?}");
+    };
+    assert!(!l.recursive);
+}
+
+#[test]
+fn test_loop_over_stepped_range() {
+    let syntax = Syntax::default();
+    let nodes = Ast::from_str(
+        "{% for i in (0..=100).step_by(5).rev() %}{% endfor %}",
+        None,
+        &syntax,
+    )
+    .unwrap()
+    .nodes;
+    let [Node::Loop(l)] = &nodes[..] else {
+        panic!("expected a single loop: {nodes:?}");
+    };
+    let range = Expr::Group(Box::new(Expr::Range(
+        "..=",
+        Some(Box::new(Expr::NumLit("0"))),
+        Some(Box::new(Expr::NumLit("100"))),
+    )));
+    let step_by = Expr::Call(
+        Box::new(Expr::Attr(Box::new(range), "step_by")),
+        vec![Expr::NumLit("5")],
+    );
+    assert_eq!(
+        l.iter,
+        Expr::Call(Box::new(Expr::Attr(Box::new(step_by), "rev")), vec![])
+    );
+}
+
+#[test]
+fn test_let_mut() {
+    let syntax = Syntax::default();
+    assert_eq!(
+        Ast::from_str("{% let mut a = 1 %}", None, &syntax)
+            .unwrap()
+            .nodes,
+        vec![Node::Let(Let {
+            ws: Ws(None, None),
+            mutable: true,
+            var: Target::Name("a"),
+            val: Some(Expr::NumLit("1")),
+        })],
+    );
+    assert!(Ast::from_str("{% let mut (a, b) = c %}", None, &syntax).is_err());
+}
+
+#[test]
+fn test_is_tests() {
+    let syntax = Syntax::default();
+    assert_eq!(
+        Ast::from_str("{{ a is odd }}", None, &syntax)
+            .unwrap()
+            .nodes,
+        vec![Node::Expr(
+            Ws(None, None),
+            Expr::Test(Test {
+                name: "odd",
+                negated: false,
+                arguments: vec![Expr::Var("a")],
+            }),
+        )],
+    );
+    assert_eq!(
+        Ast::from_str("{{ a + 1 is not divisibleby(3) && b }}", None, &syntax)
+            .unwrap()
+            .nodes,
+        vec![Node::Expr(
+            Ws(None, None),
+            Expr::BinOp(
+                "&&",
+                Box::new(Expr::Test(Test {
+                    name: "divisibleby",
+                    negated: true,
+                    arguments: vec![
+                        Expr::BinOp("+", Box::new(Expr::Var("a")), Box::new(Expr::NumLit("1"))),
+                        Expr::NumLit("3"),
+                    ],
+                })),
+                Box::new(Expr::Var("b")),
+            ),
+        )],
+    );
+    assert!(Ast::from_str("{{ a is }}", None, &syntax).is_err());
+}
+
+#[test]
+fn test_inline_if() {
+    let syntax = Syntax::default();
+    assert_eq!(
+        Ast::from_str("{{ a if b || c else d if e else f }}", None, &syntax)
+            .unwrap()
+            .nodes,
+        vec![Node::Expr(
+            Ws(None, None),
+            Expr::Conditional(
+                Box::new(Expr::BinOp(
+                    "||",
+                    Box::new(Expr::Var("b")),
+                    Box::new(Expr::Var("c")),
+                )),
+                Box::new(Expr::Var("a")),
+                Box::new(Expr::Conditional(
+                    Box::new(Expr::Var("e")),
+                    Box::new(Expr::Var("d")),
+                    Box::new(Expr::Var("f")),
+                )),
+            ),
+        )],
+    );
+    assert!(Ast::from_str("{{ a if b }}", None, &syntax).is_err());
+    assert!(Ast::from_str("{{ a if b else }}", None, &syntax).is_err());
+    assert!(Ast::from_str("{% for x in y if x %}{% endfor %}", None, &syntax).is_ok());
+}
+
+#[test]
+fn test_membership() {
+    let syntax = Syntax::default();
+    assert_eq!(
+        Ast::from_str("{{ a in b && c + 1 not in d }}", None, &syntax)
+            .unwrap()
+            .nodes,
+        vec![Node::Expr(
+            Ws(None, None),
+            Expr::BinOp(
+                "&&",
+                Box::new(Expr::BinOp(
+                    "in",
+                    Box::new(Expr::Var("a")),
+                    Box::new(Expr::Var("b")),
+                )),
+                Box::new(Expr::BinOp(
+                    "not in",
+                    Box::new(Expr::BinOp(
+                        "+",
+                        Box::new(Expr::Var("c")),
+                        Box::new(Expr::NumLit("1")),
+                    )),
+                    Box::new(Expr::Var("d")),
+                )),
+            ),
+        )],
+    );
+    assert!(Ast::from_str("{{ a in }}", None, &syntax).is_err());
+    assert!(Ast::from_str("{{ a not b }}", None, &syntax).is_err());
+}
+
+#[test]
+fn test_slicing() {
+    let syntax = Syntax::default();
+    assert_eq!(
+        Ast::from_str("{{ a[1..b] }}{{ a[..5] }}", None, &syntax)
+            .unwrap()
+            .nodes,
+        vec![
+            Node::Expr(
+                Ws(None, None),
+                Expr::Index(
+                    Box::new(Expr::Var("a")),
+                    Box::new(Expr::Range(
+                        "..",
+                        Some(Box::new(Expr::NumLit("1"))),
+                        Some(Box::new(Expr::Var("b"))),
+                    )),
+                ),
+            ),
+            Node::Expr(
+                Ws(None, None),
+                Expr::Index(
+                    Box::new(Expr::Var("a")),
+                    Box::new(Expr::Range("..", None, Some(Box::new(Expr::NumLit("5"))))),
+                ),
+            ),
+        ],
+    );
+}
+
+#[test]
+fn test_match_guard() {
+    let syntax = Syntax::default();
+    let nodes = Ast::from_str(
+        "{% match a %}{% when Some(x) if x > 1 %}{% when None %}{% endmatch %}",
+        None,
+        &syntax,
+    )
+    .unwrap()
+    .nodes;
+    let [Node::Match(Match { arms, .. })] = &nodes[..] else {
+        panic!("expected a match, found {nodes:?}");
+    };
+    assert_eq!(
+        arms[0].guard,
+        Some(Expr::BinOp(
+            ">",
+            Box::new(Expr::Var("x")),
+            Box::new(Expr::NumLit("1")),
+        )),
+    );
+    assert_eq!(arms[1].guard, None);
+    assert!(Ast::from_str(
+        "{% match a %}{% when Some(x) if %}{% endmatch %}",
+        None,
+        &syntax
+    )
+    .is_err());
+}
+
+#[test]
+fn test_match_range() {
+    let syntax = Syntax::default();
+    let nodes = Ast::from_str(
+        "{% match x %}{% when n @ 1..=9 %}{% when 'a'.. or ..=0 %}{% endmatch %}",
+        None,
+        &syntax,
+    )
+    .unwrap()
+    .nodes;
+    let [Node::Match(Match { arms, .. })] = &nodes[..] else {
+        panic!("expected a single match node: {nodes:?}");
+    };
+    assert_eq!(
+        arms[0].target,
+        Target::Binding(
+            "n",
+            Box::new(Target::Range(
+                "..=",
+                Some(Box::new(Target::NumLit("1"))),
+                Some(Box::new(Target::NumLit("9"))),
+            )),
+        ),
+    );
+    assert_eq!(
+        arms[1].target,
+        Target::OrChain(vec![
+            Target::Range("..", Some(Box::new(Target::CharLit("a"))), None),
+            Target::Range("..=", None, Some(Box::new(Target::NumLit("0")))),
+        ]),
+    );
+}
+
+#[test]
+fn test_closures() {
+    let syntax = Syntax::default();
+    assert_eq!(
+        Ast::from_str("{{ a|sort_by(|u, v| u.age) }}{{ f(|| 1) }}", None, &syntax)
+            .unwrap()
+            .nodes,
+        vec![
+            Node::Expr(
+                Ws(None, None),
+                Expr::Filter(Filter {
+                    name: "sort_by",
+                    arguments: vec![
+                        Expr::Var("a"),
+                        Expr::Closure(
+                            vec!["u", "v"],
+                            Box::new(Expr::Attr(Box::new(Expr::Var("u")), "age")),
+                        ),
+                    ],
+                }),
+            ),
+            Node::Expr(
+                Ws(None, None),
+                Expr::Call(
+                    Box::new(Expr::Var("f")),
+                    vec![Expr::Closure(vec![], Box::new(Expr::NumLit("1")))],
+                ),
+            ),
+        ],
+    );
+    assert!(Ast::from_str("{{ f(|a| ) }}", None, &syntax).is_err());
+    assert!(Ast::from_str("{{ f(|a, 1| a) }}", None, &syntax).is_err());
+}
+
+#[test]
+fn test_str_lit() {
+    let syntax = Syntax::default();
+    assert_eq!(
+        Ast::from_str(r##"{{ "a\"b" }}{{ r"c\d" }}{{ r#"e"f"# }}"##, None, &syntax)
+            .unwrap()
+            .nodes,
+        vec![
+            Node::Expr(
+                Ws(None, None),
+                Expr::StrLit(StrLit {
+                    content: r#"a\"b"#,
+                    raw: None,
+                }),
+            ),
+            Node::Expr(
+                Ws(None, None),
+                Expr::StrLit(StrLit {
+                    content: r"c\d",
+                    raw: Some(0),
+                }),
+            ),
+            Node::Expr(
+                Ws(None, None),
+                Expr::StrLit(StrLit {
+                    content: r#"e"f"#,
+                    raw: Some(1),
+                }),
+            ),
+        ],
+    );
+    assert!(Ast::from_str(r#"{{ "\q" }}"#, None, &syntax).is_err());
+    assert!(Ast::from_str(r#"{{ "\x80" }}"#, None, &syntax).is_err());
+    assert!(Ast::from_str(r#"{{ "\u{110000}" }}"#, None, &syntax).is_err());
+    assert!(Ast::from_str(r##"{{ r#"a" }}"##, None, &syntax).is_err());
+
+    let value = |content| StrLit { content, raw: None }.value();
+    assert_eq!(value(r#"a\tb\n\\\"\'\0"#), "a\tb\n\\\"'\0");
+    assert_eq!(value(r"\x41\u{e9}\u{1F600}"), "A\u{e9}\u{1F600}");
+    assert_eq!(value("a\\\n   b"), "ab");
+    assert_eq!(
+        StrLit {
+            content: r"\n",
+            raw: Some(0)
+        }
+        .value(),
+        r"\n"
+    );
+}
+
+#[test]
+fn test_macro_defaults() {
+    let syntax = Syntax::default();
+    assert_eq!(
+        Ast::from_str(
+            r#"{% macro input(name, type="text") %}{% endmacro %}"#,
+            None,
+            &syntax
+        )
+        .unwrap()
+        .nodes,
+        vec![Node::Macro(Macro {
+            ws1: Ws(None, None),
+            name: "input",
+            args: vec![
+                ("name", None),
+                (
+                    "type",
+                    Some(Expr::StrLit(StrLit {
+                        content: "text",
+                        raw: None,
+                    })),
+                ),
+            ],
+            nodes: vec![],
+            ws2: Ws(None, None),
+        })],
+    );
+    assert!(Ast::from_str(
+        "{% macro input(type=1, name) %}{% endmacro %}",
+        None,
+        &syntax
+    )
+    .is_err());
+    assert!(Ast::from_str("{% macro input(type=) %}{% endmacro %}", None, &syntax).is_err());
+}
+
+#[test]
+fn test_from_import() {
+    let syntax = Syntax::default();
+    assert_eq!(
+        Ast::from_str(
+            r#"{% from "forms.html" import input, label as lbl, %}"#,
+            None,
+            &syntax
+        )
+        .unwrap()
+        .nodes,
+        vec![Node::FromImport(FromImport {
+            ws: Ws(None, None),
+            path: "forms.html",
+            names: vec![("input", None), ("label", Some("lbl"))],
+        })],
+    );
+    assert!(Ast::from_str(r#"{% from "forms.html" import %}"#, None, &syntax).is_err());
+    assert!(Ast::from_str(r#"{% from "forms.html" %}"#, None, &syntax).is_err());
+}
+
+#[test]
+fn test_call_with_caller() {
+    let syntax = Syntax::default();
+    assert_eq!(
+        Ast::from_str("{% call(x) m(1) %}{{ x }}{%- endcall %}", None, &syntax)
+            .unwrap()
+            .nodes,
+        vec![Node::Call(Call {
+            ws: Ws(None, None),
+            scope: None,
+            name: "m",
+            args: vec![Expr::NumLit("1")],
+            caller: Some(Caller {
+                args: vec!["x"],
+                nodes: vec![Node::Expr(Ws(None, None), Expr::Var("x"))],
+                ws: Ws(Some(Whitespace::Suppress), None),
+            }),
+        })],
+    );
+    assert!(Ast::from_str("{% call() m() %}", None, &syntax).is_err());
+}
+
+#[test]
+fn test_dynamic_include() {
+    let syntax = Syntax::default();
+    assert_eq!(
+        Ast::from_str(r#"{% include "a.html" %}"#, None, &syntax)
+            .unwrap()
+            .nodes,
+        vec![Node::Include(Include {
+            ws: Ws(None, None),
+            expr: None,
+            paths: vec!["a.html"],
+            context: None,
+            only: false,
+        })],
+    );
+    assert_eq!(
+        Ast::from_str(
+            r#"{% include theme from ["a.html", "b.html",] %}"#,
+            None,
+            &syntax
+        )
+        .unwrap()
+        .nodes,
+        vec![Node::Include(Include {
+            ws: Ws(None, None),
+            expr: Some(Expr::Var("theme")),
+            paths: vec!["a.html", "b.html"],
+            context: None,
+            only: false,
+        })],
+    );
+    assert!(Ast::from_str("{% include theme %}", None, &syntax).is_err());
+    assert!(Ast::from_str("{% include theme from [] %}", None, &syntax).is_err());
+}
+
+#[test]
+fn test_include_with_context() {
+    let syntax = Syntax::default();
+    assert_eq!(
+        Ast::from_str(r#"{% include "row.html" with item only %}"#, None, &syntax)
+            .unwrap()
+            .nodes,
+        vec![Node::Include(Include {
+            ws: Ws(None, None),
+            expr: None,
+            paths: vec!["row.html"],
+            context: Some(Expr::Var("item")),
+            only: true,
+        })],
+    );
+    assert_eq!(
+        Ast::from_str(r#"{% include "row.html" only -%}"#, None, &syntax)
+            .unwrap()
+            .nodes,
+        vec![Node::Include(Include {
+            ws: Ws(None, Some(Whitespace::Suppress)),
+            expr: None,
+            paths: vec!["row.html"],
+            context: None,
+            only: true,
+        })],
+    );
+    assert!(Ast::from_str(r#"{% include "row.html" with %}"#, None, &syntax).is_err());
+}
+
+#[test]
+fn test_super_call() {
+    let syntax = Syntax::default();
+    assert_eq!(
+        Ast::from_str("{% call super.super(title = t) %}", None, &syntax)
+            .unwrap()
+            .nodes,
+        vec![Node::Call(Call {
+            ws: Ws(None, None),
+            scope: None,
+            name: "super.super",
+            args: vec![Expr::NamedArgument("title", Box::new(Expr::Var("t")))],
+            caller: None,
+        })],
+    );
+    assert!(Ast::from_str("{% call super.foo() %}", None, &syntax).is_err());
+}
+
+#[test]
+fn test_scoped_block() {
+    let syntax = Syntax::default();
+    assert_eq!(
+        Ast::from_str(
+            "{% block item scoped -%}{{ item }}{% endblock %}",
+            None,
+            &syntax
+        )
+        .unwrap()
+        .nodes,
+        vec![Node::BlockDef(BlockDef {
+            ws1: Ws(None, Some(Whitespace::Suppress)),
+            name: "item",
+            scoped: true,
+            nodes: vec![Node::Expr(Ws(None, None), Expr::Var("item"))],
+            ws2: Ws(None, None),
+        })],
+    );
+}
+
+#[test]
+fn test_embed() {
+    let syntax = Syntax::default();
+    assert_eq!(
+        Ast::from_str(
+            r#"{% embed "card.html" -%}{% block body %}{% endblock %}{%- endembed %}"#,
+            None,
+            &syntax
+        )
+        .unwrap()
+        .nodes,
+        vec![Node::Embed(Embed {
+            ws1: Ws(None, Some(Whitespace::Suppress)),
+            path: "card.html",
+            nodes: vec![Node::BlockDef(BlockDef {
+                ws1: Ws(None, None),
+                name: "body",
+                scoped: false,
+                nodes: vec![],
+                ws2: Ws(None, None),
+            })],
+            ws2: Ws(Some(Whitespace::Suppress), None),
+        })],
+    );
+    assert!(Ast::from_str(r#"{% embed "card.html" %}"#, None, &syntax).is_err());
+}
+
+#[test]
+fn test_line_statements() {
+    let syntax = Syntax {
+        line_statement_prefix: Some("#%"),
+        ..Syntax::default()
+    };
+    let parsed = Parsed::new(
+        "a\n  #% if x\nb {{ c }}\n#% endif".to_owned(),
+        None,
+        &syntax,
+    )
+    .unwrap();
+    assert_eq!(
+        parsed.nodes(),
+        Ast::from_str("a\n{%   if x\n%}b {{ c }}\n{% endif %}", None, &syntax)
             .unwrap()
             .nodes(),
     );
 }
+
+#[test]
+fn test_line_statements_in_raw_and_comments() {
+    let syntax = Syntax {
+        line_statement_prefix: Some("#%"),
+        ..Syntax::default()
+    };
+    let parsed = Parsed::new(
+        "{% raw %}\n#% if x\n{% endraw %}\n{#\n#% endif\n#}\n#% if y\n{{ \"a\n#% b\" }}\n#% endif"
+            .to_owned(),
+        None,
+        &syntax,
+    )
+    .unwrap();
+    assert_eq!(
+        parsed.nodes(),
+        Ast::from_str(
+            "{% raw %}\n#% if x\n{% endraw %}\n{#\n#% endif\n#}\n{% if y\n%}{{ \"a\n#% b\" }}\n{% endif %}",
+            None,
+            &syntax,
+        )
+        .unwrap()
+        .nodes(),
+    );
+}
+
+#[test]
+fn test_do() {
+    let syntax = Syntax::default();
+    assert_eq!(
+        Ast::from_str("{%- do x.incr() %}", None, &syntax)
+            .unwrap()
+            .nodes,
+        vec![Node::Do(
+            Ws(Some(Whitespace::Suppress), None),
+            Expr::Call(
+                Box::new(Expr::Attr(Box::new(Expr::Var("x")), "incr")),
+                vec![]
+            ),
+        )],
+    );
+    assert!(Ast::from_str("{% do %}", None, &syntax).is_err());
+}
+
+#[test]
+fn test_while() {
+    let syntax = Syntax::default();
+    assert_eq!(
+        Ast::from_str(
+            "{% while let Some(x) = it.next() %}{% break %}{%- endwhile %}",
+            None,
+            &syntax
+        )
+        .unwrap()
+        .nodes,
+        vec![Node::While(While {
+            ws1: Ws(None, None),
+            cond: CondTest {
+                target: Some(Target::Tuple(vec!["Some"], vec![Target::Name("x")])),
+                expr: Expr::Call(
+                    Box::new(Expr::Attr(Box::new(Expr::Var("it")), "next")),
+                    vec![]
+                ),
+            },
+            nodes: vec![Node::Break(Ws(None, None))],
+            ws2: Ws(Some(Whitespace::Suppress), None),
+        })],
+    );
+    assert!(Ast::from_str("{% while %}{% endwhile %}", None, &syntax).is_err());
+}
+
+#[test]
+fn test_optional_chain() {
+    let syntax = Syntax::default();
+    assert_eq!(
+        Ast::from_str("{{ user?.address?.city.len() }}", None, &syntax)
+            .unwrap()
+            .nodes,
+        vec![Node::Expr(
+            Ws(None, None),
+            Expr::OptionalChain(Box::new(Expr::Call(
+                Box::new(Expr::Attr(
+                    Box::new(Expr::OptionalAttr(
+                        Box::new(Expr::OptionalAttr(Box::new(Expr::Var("user")), "address")),
+                        "city"
+                    )),
+                    "len"
+                )),
+                vec![]
+            )))
+        )],
+    );
+    // `?` followed by `.` in a group is still the `?` operator.
+    assert_eq!(
+        Ast::from_str("{{ (a?).b }}", None, &syntax).unwrap().nodes,
+        vec![Node::Expr(
+            Ws(None, None),
+            Expr::Attr(
+                Box::new(Expr::Group(Box::new(Expr::Try(Box::new(Expr::Var("a")))))),
+                "b"
+            )
+        )],
+    );
+    assert_eq!(
+        Ast::from_str("{{ a?.b|upper }}", None, &syntax)
+            .unwrap()
+            .nodes,
+        vec![Node::Expr(
+            Ws(None, None),
+            Expr::Filter(Filter {
+                name: "upper",
+                arguments: vec![Expr::OptionalChain(Box::new(Expr::OptionalAttr(
+                    Box::new(Expr::Var("a")),
+                    "b"
+                )))]
+            })
+        )],
+    );
+}
+
+#[test]
+fn test_const() {
+    let syntax = Syntax::default();
+    assert_eq!(
+        Ast::from_str("{% const PAGE_SIZE = 10 * 2 -%}", None, &syntax)
+            .unwrap()
+            .nodes,
+        vec![Node::Const(Const {
+            ws: Ws(None, Some(Whitespace::Suppress)),
+            name: "PAGE_SIZE",
+            value: Expr::BinOp(
+                "*",
+                Box::new(Expr::NumLit("10")),
+                Box::new(Expr::NumLit("2"))
+            ),
+        })],
+    );
+    assert!(Ast::from_str("{% const PAGE_SIZE %}", None, &syntax).is_err());
+}
+
+#[test]
+fn test_chained_comparison() {
+    let syntax = Syntax::default();
+    assert_eq!(
+        Ast::from_str("{{ 0 <= x < 10 }}", None, &syntax)
+            .unwrap()
+            .nodes,
+        vec![Node::Expr(
+            Ws(None, None),
+            Expr::BinOp(
+                "&&",
+                Box::new(Expr::BinOp(
+                    "<=",
+                    Box::new(Expr::NumLit("0")),
+                    Box::new(Expr::Var("x"))
+                )),
+                Box::new(Expr::BinOp(
+                    "<",
+                    Box::new(Expr::Var("x")),
+                    Box::new(Expr::NumLit("10"))
+                )),
+            )
+        )],
+    );
+}
+
+#[test]
+fn test_matches() {
+    let syntax = Syntax::default();
+    assert_eq!(
+        Ast::from_str(
+            "{{ s matches Status::Active | Some(1..) or _ }}",
+            None,
+            &syntax
+        )
+        .unwrap()
+        .nodes,
+        vec![Node::Expr(
+            Ws(None, None),
+            Expr::Matches(
+                Box::new(Expr::Var("s")),
+                Target::OrChain(vec![
+                    Target::Path(vec!["Status", "Active"]),
+                    Target::Tuple(
+                        vec!["Some"],
+                        vec![Target::Range(
+                            "..",
+                            Some(Box::new(Target::NumLit("1"))),
+                            None
+                        )]
+                    ),
+                    Target::Path(vec!["_"]),
+                ])
+            )
+        )],
+    );
+    assert_eq!(
+        Ast::from_str("{{ a matches None || b }}", None, &syntax)
+            .unwrap()
+            .nodes,
+        vec![Node::Expr(
+            Ws(None, None),
+            Expr::BinOp(
+                "||",
+                Box::new(Expr::Matches(
+                    Box::new(Expr::Var("a")),
+                    Target::Path(vec!["None"])
+                )),
+                Box::new(Expr::Var("b"))
+            )
+        )],
+    );
+}
+
+#[test]
+fn test_format_str() {
+    let syntax = Syntax::default();
+    assert_eq!(
+        Ast::from_str(r#"{{ f"a {{b}} {c}{ d.e :>4}\u{7b}" }}"#, None, &syntax)
+            .unwrap()
+            .nodes,
+        vec![Node::Expr(
+            Ws(None, None),
+            Expr::FormatStr(vec![
+                FormatPart::Lit("a {{b}} "),
+                FormatPart::Expr(Expr::Var("c"), None),
+                FormatPart::Expr(Expr::Attr(Box::new(Expr::Var("d")), "e"), Some(">4")),
+                FormatPart::Lit(r"\u{7b}"),
+            ])
+        )],
+    );
+    assert_eq!(
+        Ast::from_str(r#"{{ f"" }}"#, None, &syntax).unwrap().nodes,
+        vec![Node::Expr(Ws(None, None), Expr::FormatStr(vec![]))],
+    );
+
+    assert!(Ast::from_str(r#"{{ f"a } b" }}"#, None, &syntax).is_err());
+    assert!(Ast::from_str(r#"{{ f"a {b" }}"#, None, &syntax).is_err());
+    assert!(Ast::from_str(r#"{{ f"a {} b" }}"#, None, &syntax).is_err());
+}
+
+#[test]
+fn test_raw_marker() {
+    let syntax = Syntax::default();
+    assert_eq!(
+        Ast::from_str(
+            r#"{% raw "A" %}{% endraw %}{% endraw "B" %}{% endraw "A" %}"#,
+            None,
+            &syntax
+        )
+        .unwrap()
+        .nodes,
+        vec![Node::Raw(Raw {
+            ws1: Ws(None, None),
+            lit: Lit {
+                lws: "",
+                val: r#"{% endraw %}{% endraw "B" %}"#,
+                rws: "",
+            },
+            ws2: Ws(None, None),
+        })],
+    );
+    assert!(Ast::from_str(r#"{% raw "A" %}x{% endraw %}"#, None, &syntax).is_err());
+}
+
+#[test]
+fn test_spread_argument() {
+    let syntax = Syntax::default();
+    let ast = Ast::from_str("{% call m(a, *b, c = 1) %}", None, &syntax).unwrap();
+    let Node::Call(call) = &ast.nodes[0] else {
+        panic!("expected a call, found {:?}", ast.nodes);
+    };
+    assert_eq!(
+        call.args,
+        vec![
+            Expr::Var("a"),
+            Expr::Spread(Box::new(Expr::Var("b"))),
+            Expr::NamedArgument("c", Box::new(Expr::NumLit("1"))),
+        ],
+    );
+    assert!(Ast::from_str("{{ m(*b) }}", None, &syntax).is_err());
+}
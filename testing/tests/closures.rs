@@ -0,0 +1,47 @@
+use askama::Template;
+
+struct User {
+    name: &'static str,
+    age: u32,
+}
+
+mod filters {
+    pub fn sort_by<T, K: Ord>(items: &[T], key: impl Fn(&T) -> K) -> askama::Result<Vec<&T>> {
+        let mut items: Vec<_> = items.iter().collect();
+        items.sort_by_key(|item| key(item));
+        Ok(items)
+    }
+}
+
+#[derive(Template)]
+#[template(
+    source = "{% for user in users|sort_by(|u| u.age) %}{{ user.name }} {% endfor %}\
+              {{ users.iter().any(|u| u.age > min_age) }} \
+              {{ users.iter().filter(|user| user.name.len() == len).count() }} \
+              {{ users.iter().map(|_| 1).count() }}",
+    ext = "txt"
+)]
+struct ClosureTemplate {
+    users: Vec<User>,
+    min_age: u32,
+    len: usize,
+}
+
+#[test]
+fn test_closures() {
+    let t = ClosureTemplate {
+        users: vec![
+            User {
+                name: "Bob",
+                age: 40,
+            },
+            User {
+                name: "Alice",
+                age: 30,
+            },
+        ],
+        min_age: 35,
+        len: 5,
+    };
+    assert_eq!(t.render().unwrap(), "Alice Bob true 1 2");
+}
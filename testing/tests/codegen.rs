@@ -0,0 +1,64 @@
+use std::fmt::Display;
+
+use askama::Template;
+
+#[derive(Template)]
+#[template(
+    source = "{% for item in items %}{{ item|upper }}{% if !loop.last %}, {% endif %}{% endfor %}",
+    ext = "html",
+    codegen = "size"
+)]
+struct List<'a, T: Display> {
+    items: &'a [T],
+}
+
+#[test]
+fn test_codegen_size() {
+    let t = List {
+        items: &["a", "<b>"],
+    };
+    assert_eq!(t.render().unwrap(), "A, &lt;B&gt;");
+    assert_eq!(t.to_string(), "A, &lt;B&gt;");
+
+    let mut s = String::new();
+    t.render_into(&mut s).unwrap();
+    assert_eq!(s, "A, &lt;B&gt;");
+}
+
+#[derive(Template)]
+#[template(source = "<ul>{{ list }}</ul>", ext = "html", codegen = "size")]
+struct Parent<'a> {
+    list: List<'a, i32>,
+}
+
+#[test]
+fn test_codegen_size_nested() {
+    let t = Parent {
+        list: List { items: &[1, 2] },
+    };
+    assert_eq!(t.render().unwrap(), "<ul>1, 2</ul>");
+}
+
+#[derive(Template)]
+#[template(source = "<{{ tag }}>", ext = "txt", codegen = "size")]
+struct Tag<'a> {
+    tag: &'a str,
+}
+
+#[derive(Template)]
+#[template(
+    source = "{{ tag }}{% set body %}<i>{{ tag }}</i>{% endset %}{{ body }}",
+    ext = "html",
+    codegen = "size"
+)]
+struct Escaped<'a> {
+    tag: Tag<'a>,
+}
+
+#[test]
+fn test_codegen_size_escaped() {
+    let t = Escaped {
+        tag: Tag { tag: "b" },
+    };
+    assert_eq!(t.render().unwrap(), "&lt;b&gt;<i>&lt;b&gt;</i>");
+}
@@ -0,0 +1,100 @@
+use askama::{Slot, Template};
+
+#[derive(Template)]
+#[template(
+    source = r#"<div class="card"><h1>{{ title }}</h1>{{ body }}</div>"#,
+    ext = "html"
+)]
+struct Card<'a> {
+    title: &'a str,
+    body: Slot,
+}
+
+#[derive(Template)]
+#[template(
+    source = r#"{% component Card(title = title) %}<p>{{ text }}</p>{% endcomponent %}"#,
+    ext = "html"
+)]
+struct Page<'a> {
+    title: &'a str,
+    text: &'a str,
+}
+
+#[test]
+fn test_component() {
+    let t = Page {
+        title: "a&b",
+        text: "<c>",
+    };
+    assert_eq!(
+        t.render().unwrap(),
+        r#"<div class="card"><h1>a&amp;b</h1><p>&lt;c&gt;</p></div>"#
+    );
+}
+
+#[derive(Template)]
+#[template(
+    source = r#"{% component Card(title = title) %}{{ text }}{% endcomponent %}"#,
+    ext = "txt"
+)]
+struct TextPage<'a> {
+    title: &'a str,
+    text: &'a str,
+}
+
+// The body isn't escaped by a text template, so the HTML component escapes it.
+#[test]
+fn test_component_other_mime_type() {
+    let t = TextPage {
+        title: "a&b",
+        text: "<c>",
+    };
+    assert_eq!(
+        t.render().unwrap(),
+        r#"<div class="card"><h1>a&amp;b</h1>&lt;c&gt;</div>"#
+    );
+}
+
+#[derive(Template)]
+#[template(source = "[{{ body }}]", ext = "txt")]
+struct Wrapper {
+    body: Slot,
+}
+
+#[derive(Template)]
+#[template(
+    source = "{% for item in items -%}
+{% component Wrapper %}{% component Wrapper -%} {{ item }} {%- endcomponent %}{% endcomponent %}
+{% endfor %}",
+    ext = "txt"
+)]
+struct NestedComponents<'a> {
+    items: &'a [&'a str],
+}
+
+#[test]
+fn test_nested_components() {
+    let t = NestedComponents { items: &["a", "b"] };
+    assert_eq!(t.render().unwrap(), "[[a]]\n[[b]]\n");
+}
+
+#[derive(Template)]
+#[template(
+    source = "{% if body.is_empty() %}nothing{% else %}{{ body }}{% endif %}",
+    ext = "txt"
+)]
+struct Optional {
+    body: Slot,
+}
+
+#[derive(Template)]
+#[template(
+    source = "{% component Optional %}{% endcomponent %}/{% component Optional %}something{% endcomponent %}",
+    ext = "txt"
+)]
+struct EmptyBody;
+
+#[test]
+fn test_component_empty_body() {
+    assert_eq!(EmptyBody.render().unwrap(), "nothing/something");
+}
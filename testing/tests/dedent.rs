@@ -0,0 +1,121 @@
+use askama::Template;
+
+#[derive(Template)]
+#[template(
+    source = "
+server:
+  port: {{ port }}
+  {%- if tls %}
+    tls:
+      cert: {{ cert }}
+      {%- for name in names %}
+        - {{ name }}
+      {%- endfor %}
+  {%- endif %}
+",
+    ext = "txt",
+    dedent = true
+)]
+struct Yaml<'a> {
+    port: u16,
+    tls: bool,
+    cert: &'a str,
+    names: &'a [&'a str],
+}
+
+#[test]
+fn test_dedent() {
+    let t = Yaml {
+        port: 443,
+        tls: true,
+        cert: "a.pem",
+        names: &["a.example", "b.example"],
+    };
+    assert_eq!(
+        t.render().unwrap(),
+        "
+server:
+  port: 443
+  tls:
+    cert: a.pem
+    - a.example
+    - b.example
+"
+    );
+
+    let t = Yaml {
+        port: 80,
+        tls: false,
+        cert: "",
+        names: &[],
+    };
+    assert_eq!(t.render().unwrap(), "\nserver:\n  port: 80\n");
+}
+
+#[derive(Template)]
+#[template(
+    source = "
+def f():
+    {%- if a %}
+        return 1
+    {%- else %}
+        return 2
+    {%- endif %}
+",
+    ext = "txt",
+    dedent = true
+)]
+struct Python {
+    a: bool,
+}
+
+#[test]
+fn test_dedent_branches() {
+    assert_eq!(
+        Python { a: true }.render().unwrap(),
+        "\ndef f():\n    return 1\n"
+    );
+    assert_eq!(
+        Python { a: false }.render().unwrap(),
+        "\ndef f():\n    return 2\n"
+    );
+}
+
+#[derive(Template)]
+#[template(
+    source = "
+{%- match value %}
+  {%- when Some with (v) %}
+    some: {{ v }}
+  {%- when None %}
+    none
+{%- endmatch %}
+",
+    ext = "txt",
+    dedent = true
+)]
+struct Match {
+    value: Option<u8>,
+}
+
+#[test]
+fn test_dedent_match() {
+    assert_eq!(Match { value: Some(1) }.render().unwrap(), "\nsome: 1\n");
+    assert_eq!(Match { value: None }.render().unwrap(), "\nnone\n");
+}
+
+#[derive(Template)]
+#[template(
+    source = "
+{%- if true %}
+    kept
+{%- endif %}
+",
+    ext = "txt"
+)]
+struct NoDedent;
+
+#[test]
+fn test_no_dedent() {
+    assert_eq!(NoDedent.render().unwrap(), "\n    kept\n");
+}
@@ -0,0 +1,69 @@
+use askama::Template;
+
+#[derive(Template)]
+#[template(path = "error-context.html", error_context = true)]
+struct ErrorContextTemplate<'a> {
+    title: &'a str,
+    show: bool,
+    value: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "error-context.html")]
+struct NoErrorContextTemplate<'a> {
+    title: &'a str,
+    show: bool,
+    value: &'a str,
+}
+
+mod filters {
+    pub fn fail<T>(_: T, msg: &str) -> ::askama::Result<&'static str> {
+        Err(::askama::Error::Custom(msg.to_owned().into()))
+    }
+}
+
+#[test]
+fn test_error_context() {
+    let t = ErrorContextTemplate {
+        title: "Title",
+        show: true,
+        value: "x",
+    };
+    let err = t.render().unwrap_err();
+    match err.without_context() {
+        askama::Error::Filter { name, error } => {
+            assert_eq!(*name, "fail");
+            assert!(matches!(**error, askama::Error::Custom(_)));
+        }
+        err => panic!("unexpected error: {err:?}"),
+    }
+
+    let ctx = err.context().unwrap();
+    assert_eq!(ctx.template, "error-context.html");
+    assert_eq!(ctx.block, Some("content"));
+    assert_eq!(ctx.expr, r#"value|fail("bad input")"#);
+    assert_eq!(
+        err.to_string(),
+        r#"filter `fail`: bad input (in template `error-context.html`, block `content`, expression `value|fail("bad input")`)"#,
+    );
+
+    let t = ErrorContextTemplate {
+        title: "Title",
+        show: false,
+        value: "x",
+    };
+    assert_eq!(t.render().unwrap(), "<h1>Title</h1>\n");
+}
+
+#[test]
+fn test_no_error_context() {
+    let t = NoErrorContextTemplate {
+        title: "Title",
+        show: true,
+        value: "x",
+    };
+    let err = t.render().unwrap_err();
+    assert!(err.context().is_none());
+    assert!(matches!(err, askama::Error::Filter { name: "fail", .. }));
+    assert_eq!(err.to_string(), "filter `fail`: bad input");
+}
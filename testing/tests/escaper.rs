@@ -0,0 +1,72 @@
+use std::fmt::{self, Write};
+
+use askama::{EscapeError, Escaper, Template};
+
+/// Rejects values containing NUL characters, which the output format cannot represent.
+struct NoNul;
+
+impl Escaper for NoNul {
+    fn write_escaped<W: Write>(&self, fmt: W, string: &str) -> fmt::Result {
+        self.try_write_escaped(fmt, string).map_err(|_| fmt::Error)
+    }
+
+    fn try_write_escaped<W: Write>(&self, mut fmt: W, string: &str) -> Result<(), EscapeError> {
+        if string.contains('\0') {
+            return Err(EscapeError::Invalid("NUL characters are not allowed"));
+        }
+        Ok(fmt.write_str(string)?)
+    }
+}
+
+#[derive(Template)]
+#[template(source = "[{{ value }}]", ext = "nonul", config = "test_escaper.toml")]
+struct FallibleEscaper<'a> {
+    value: &'a str,
+}
+
+#[test]
+fn test_fallible_escaper() {
+    let t = FallibleEscaper { value: "abc" };
+    assert_eq!(t.render().unwrap(), "[abc]");
+
+    let t = FallibleEscaper { value: "a\0c" };
+    let err = t.render().unwrap_err();
+    assert!(matches!(
+        err,
+        askama::Error::Escape(EscapeError::Invalid("NUL characters are not allowed"))
+    ));
+    assert_eq!(
+        err.to_string(),
+        "escaping error: NUL characters are not allowed"
+    );
+}
+
+#[derive(Template)]
+#[template(source = "{{ value }}", ext = "txt", error_context = true)]
+struct TextChild<'a> {
+    value: &'a str,
+}
+
+#[derive(Template)]
+#[template(source = "<{{ child }}>", ext = "nonul", config = "test_escaper.toml")]
+struct FallibleEscaperParent<'a> {
+    child: TextChild<'a>,
+}
+
+#[test]
+fn test_fallible_escaper_nested_template() {
+    let t = FallibleEscaperParent {
+        child: TextChild { value: "abc" },
+    };
+    assert_eq!(t.render().unwrap(), "<abc>");
+
+    let t = FallibleEscaperParent {
+        child: TextChild { value: "a\0c" },
+    };
+    let err = t.render().unwrap_err();
+    assert!(matches!(
+        err.without_context(),
+        askama::Error::Escape(EscapeError::Invalid(_))
+    ));
+    assert_eq!(err.context().unwrap().template, "TextChild.txt");
+}
@@ -123,7 +123,7 @@ mod filters {
         Ok(s.replace("oo", "aa"))
     }
     // for test_nested_filter_ref
-    pub fn mytrim(s: &dyn (::std::fmt::Display)) -> ::askama::Result<String> {
+    pub fn mytrim(s: &dyn ::std::fmt::Display) -> ::askama::Result<String> {
         Ok(s.to_string().trim().to_owned())
     }
 }
@@ -162,6 +162,51 @@ fn test_vec_join() {
     assert_eq!(t.render().unwrap(), "foo, bar, bazz");
 }
 
+#[derive(Template)]
+#[template(
+    source = "{{ items|join(\"<br>\") }}|{{ numbers|join(sep) }}|{{ items|join(\", \")|safe }}",
+    ext = "html"
+)]
+struct JoinEscapedTemplate<'a> {
+    items: &'a [&'a str],
+    numbers: Vec<u32>,
+    sep: &'a str,
+}
+
+#[test]
+fn test_join_escaped() {
+    let t = JoinEscapedTemplate {
+        items: &["a & b", "<c>"],
+        numbers: vec![1, 2, 3],
+        sep: " < ",
+    };
+    assert_eq!(
+        t.render().unwrap(),
+        "a &amp; b<br>&lt;c&gt;|1 &lt; 2 &lt; 3|a & b, <c>"
+    );
+}
+
+#[derive(Template)]
+#[template(
+    source = "{{ items|join(\" & \")|upper }}|{% let joined = items|join(\", \") %}{{ joined }}",
+    ext = "html"
+)]
+struct JoinConsumedTemplate<'a> {
+    items: &'a [&'a str],
+}
+
+// The joined value is only escaped once, where it's written.
+#[test]
+fn test_join_consumed() {
+    let t = JoinConsumedTemplate {
+        items: &["a&b", "<c>"],
+    };
+    assert_eq!(
+        t.render().unwrap(),
+        "A&amp;B &amp; &lt;C&gt;|a&amp;b, &lt;c&gt;"
+    );
+}
+
 #[cfg(feature = "serde-json")]
 #[derive(Template)]
 #[template(path = "json.html")]
@@ -195,6 +240,20 @@ fn test_json() {
     );
 }
 
+#[cfg(feature = "serde-json")]
+#[derive(Template)]
+#[template(source = r#"{{ {"a": 1, "b": 2}|json }}"#, ext = "txt")]
+struct JsonMapLiteralTemplate;
+
+#[cfg(feature = "serde-json")]
+#[test]
+fn test_json_map_literal() {
+    assert_eq!(
+        JsonMapLiteralTemplate.render().unwrap(),
+        "{\n  \"a\": 1,\n  \"b\": 2\n}"
+    );
+}
+
 #[derive(Template)]
 #[template(source = "{{ x|mytrim|safe }}", ext = "html")]
 struct NestedFilterTemplate {
@@ -328,3 +387,428 @@ fn test_let_borrow() {
     };
     assert_eq!(template.render().unwrap(), "hello1")
 }
+
+#[derive(askama::Template)]
+#[template(
+    source = r#"{{ text|reading_time(2) }} min read
+{%- let rt = text|reading_time(2) %} ({{ rt.words }} words, {{ text|char_count }} chars)"#,
+    ext = "txt"
+)]
+struct TextStatistics<'a> {
+    text: &'a str,
+}
+
+#[test]
+fn test_text_statistics() {
+    let template = TextStatistics {
+        text: "askama is sort of cool",
+    };
+    assert_eq!(template.render().unwrap(), "3 min read (5 words, 22 chars)");
+}
+
+#[derive(askama::Template)]
+#[template(
+    source = "{% block content %}{% if true %}{{ s|myfilter }}{% endif %}{% endblock %}",
+    ext = "txt"
+)]
+struct FilterInBlock<'a> {
+    s: &'a str,
+}
+
+#[test]
+fn test_filter_in_block() {
+    let t = FilterInBlock { s: "foo" };
+    assert_eq!(t.render().unwrap(), "faa");
+}
+
+#[derive(askama::Template)]
+#[template(
+    source = r#"{{ title|capitalize_first }} / {{ city|capitalize_first(lang) }}"#,
+    ext = "txt"
+)]
+struct CapitalizeFirst<'a> {
+    title: &'a str,
+    city: &'a str,
+    lang: String,
+}
+
+#[test]
+fn test_capitalize_first() {
+    let t = CapitalizeFirst {
+        title: "iPhone SE",
+        city: "istanbul",
+        lang: "tr-TR".to_owned(),
+    };
+    assert_eq!(t.render().unwrap(), "IPhone SE / İstanbul");
+}
+
+#[derive(askama::Template)]
+#[template(
+    source = "{% for row in items|batch(3, \"-\") %}\
+              {{ loop.index }}/{{ loop.length }}: {% for item in row %}{{ item }}{% endfor %} \
+              {% endfor %}\
+              {% for row in (1..6)|batch(size) %}{{ row|join(\",\") }};{% endfor %}",
+    ext = "txt"
+)]
+struct Batch<'a> {
+    items: Vec<&'a str>,
+    size: usize,
+}
+
+#[test]
+fn test_batch() {
+    let t = Batch {
+        items: vec!["a", "b", "c", "d"],
+        size: 2,
+    };
+    assert_eq!(t.render().unwrap(), "1/2: abc 2/2: d-- 1,2;3,4;5;");
+
+    let t = Batch {
+        items: vec![],
+        size: 0,
+    };
+    assert!(t.render().is_err());
+}
+
+#[derive(askama::Template)]
+#[template(
+    source = "{% for column in items|slice(3) %}[{{ column|join(\",\") }}]{% endfor %} \
+              {% for column in items|columns(2, \"-\") %}[{{ column|join(\",\") }}]{% endfor %}",
+    ext = "txt"
+)]
+struct Slice<'a> {
+    items: &'a [&'a str],
+}
+
+#[test]
+fn test_slice() {
+    let t = Slice {
+        items: &["a", "b", "c", "d", "e"],
+    };
+    assert_eq!(t.render().unwrap(), "[a,b][c,d][e] [a,b,c][d,e,-]");
+}
+
+#[derive(askama::Template)]
+#[template(
+    source = "{% for (name, score) in scores|dictsort %}{{ name }}={{ score }} {% endfor %}\
+              {% for (name, score) in ranks|items %}{{ loop.index }}.{{ name }}={{ score }} {% endfor %}",
+    ext = "txt"
+)]
+struct DictSort {
+    scores: std::collections::HashMap<String, u32>,
+    ranks: std::rc::Rc<std::collections::BTreeMap<&'static str, u32>>,
+}
+
+#[test]
+fn test_dictsort() {
+    let t = DictSort {
+        scores: [("zoe", 3), ("adam", 1), ("mia", 2)]
+            .into_iter()
+            .map(|(name, score)| (name.to_owned(), score))
+            .collect(),
+        ranks: std::rc::Rc::new([("b", 2), ("a", 1)].into_iter().collect()),
+    };
+    assert_eq!(t.render().unwrap(), "adam=1 mia=2 zoe=3 1.a=1 2.b=2 ");
+}
+
+#[derive(askama::Template)]
+#[template(
+    source = "{{ tags|unique|join(\", \") }} / \
+              {{ tags|unique(|tag| tag.to_lowercase())|join(\", \") }} / \
+              {% for n in (1..10)|unique(|n| n % 4) %}{{ n }}{% endfor %} / \
+              {{ (1..4)|join(\"-\") }}",
+    ext = "txt"
+)]
+struct Unique<'a> {
+    tags: Vec<&'a str>,
+}
+
+#[test]
+fn test_unique() {
+    let t = Unique {
+        tags: vec!["rust", "Web", "rust", "web", "cli"],
+    };
+    assert_eq!(
+        t.render().unwrap(),
+        "rust, Web, web, cli / rust, Web, cli / 1234 / 1-2-3"
+    );
+}
+
+#[derive(askama::Template)]
+#[template(
+    source = "{{ tags|first|unwrap_or(\"untagged\") }} \
+              {% if let Some(post) = posts|last %}{{ post }}{% else %}no posts{% endif %} \
+              {{ (1..n)|last|unwrap_or(0) }}",
+    ext = "txt"
+)]
+struct FirstLast<'a> {
+    tags: &'a [&'a str],
+    posts: Vec<String>,
+    n: u32,
+}
+
+#[test]
+fn test_first_last() {
+    let t = FirstLast {
+        tags: &["rust", "web"],
+        posts: vec!["Hello".to_owned(), "World".to_owned()],
+        n: 5,
+    };
+    assert_eq!(t.render().unwrap(), "rust World 4");
+
+    let t = FirstLast {
+        tags: &[],
+        posts: vec![],
+        n: 0,
+    };
+    assert_eq!(t.render().unwrap(), "untagged no posts 0");
+}
+
+#[derive(askama::Template)]
+#[template(
+    source = "{{ price|round(2) }} {{ price|round }} {{ price|round(1, \"ceil\") }} \
+              {{ price|round(0, \"floor\") }} {{ 1250|round(-2) }} \
+              {% for p in prices %}{{ p|round(1) }} {% endfor %}\
+              {{ (price * 3.0)|round(1) }}",
+    ext = "txt"
+)]
+struct Round<'a> {
+    price: f64,
+    prices: &'a [f32],
+}
+
+#[test]
+fn test_round() {
+    let t = Round {
+        price: 12.345,
+        prices: &[1.25, 2.0],
+    };
+    assert_eq!(t.render().unwrap(), "12.35 12 12.4 12 1300 1.3 2 37");
+}
+
+#[derive(askama::Template)]
+#[template(
+    source = "{% for field in line|split(\",\") %}[{{ field }}]{% endfor %} \
+              {{ path|split(sep)|join(\" > \") }} \
+              {% for word in words %}{{ word|split(\"-\")|last|unwrap_or(\"\") }}{% endfor %}",
+    ext = "txt"
+)]
+struct Split<'a> {
+    line: String,
+    path: &'a str,
+    sep: String,
+    words: &'a [&'a str],
+}
+
+#[test]
+fn test_split() {
+    let t = Split {
+        line: "a,b,,c".to_owned(),
+        path: "usr/local/bin",
+        sep: "/".to_owned(),
+        words: &["x-1", "y-2", "z"],
+    };
+    assert_eq!(t.render().unwrap(), "[a][b][][c] usr > local > bin 12z");
+}
+
+#[derive(askama::Template)]
+#[template(
+    source = "{{ body|wordwrap(width) }}\n--\n{{ body|wordwrap(8, false) }}\n--\n{{ body|wordwrap }}",
+    ext = "txt"
+)]
+struct WordWrap<'a> {
+    body: &'a str,
+    width: usize,
+}
+
+#[test]
+fn test_wordwrap() {
+    let t = WordWrap {
+        body: "Hello Sandra,\n\nYour order #1234 has shipped.",
+        width: 12,
+    };
+    assert_eq!(
+        t.render().unwrap(),
+        "Hello\nSandra,\n\nYour order\n#1234 has\nshipped.\n--\n\
+         Hello\nSandra,\n\nYour\norder\n#1234\nhas\nshipped.\n--\n\
+         Hello Sandra,\n\nYour order #1234 has shipped."
+    );
+
+    let t = WordWrap {
+        body: "text",
+        width: 0,
+    };
+    assert!(t.render().is_err());
+}
+
+#[cfg(feature = "base64")]
+#[derive(Template)]
+#[template(
+    source = r#"<img src="data:image/png;base64,{{ icon|b64encode }}">
+<a href="?t={{ token|b64encode("url") }}">{{ "PGI-IQ"|b64decode("url") }}</a>"#,
+    ext = "html"
+)]
+struct Base64Template<'a> {
+    icon: &'a [u8],
+    token: String,
+}
+
+#[cfg(feature = "base64")]
+#[test]
+fn test_filter_base64() {
+    let t = Base64Template {
+        icon: &[0x89, b'P', b'N', b'G', 0xff],
+        token: "id=42?".into(),
+    };
+    assert_eq!(
+        t.render().unwrap(),
+        "<img src=\"data:image/png;base64,iVBOR/8=\">\n\
+         <a href=\"?t=aWQ9NDI_\">&lt;b&gt;!</a>"
+    );
+}
+
+#[cfg(feature = "base64")]
+#[derive(Template)]
+#[template(source = "{{ s|b64decode }}", ext = "txt")]
+struct Base64DecodeTemplate<'a> {
+    s: &'a str,
+}
+
+#[cfg(feature = "base64")]
+#[test]
+fn test_filter_base64_decode_error() {
+    assert_eq!(
+        Base64DecodeTemplate { s: "aGVsbG8=" }.render().unwrap(),
+        "hello"
+    );
+    assert!(Base64DecodeTemplate { s: "not base64!" }.render().is_err());
+}
+
+#[derive(askama::Template)]
+#[template(
+    source = "{{ downloads|humanize }} / {{ downloads|intcomma }} / {{ downloads|intcomma(\".\") }} / \
+              {% for n in counts %}{{ n|humanize }} {% endfor %}/ {{ ratio|intcomma }}",
+    ext = "html"
+)]
+struct Humanize<'a> {
+    downloads: u64,
+    counts: &'a [i32],
+    ratio: f64,
+}
+
+#[test]
+fn test_humanize_intcomma() {
+    let t = Humanize {
+        downloads: 1_234_567,
+        counts: &[42, 1_050, -25_000],
+        ratio: 12345.678,
+    };
+    assert_eq!(
+        t.render().unwrap(),
+        "1.2M / 1,234,567 / 1.234.567 / 42 1.1k -25k / 12,345.678"
+    );
+}
+
+struct Member {
+    name: &'static str,
+    age: u32,
+    is_admin: bool,
+    team: (&'static str, u8),
+}
+
+#[derive(askama::Template)]
+#[template(
+    source = "{% for m in members|selectattr(\"is_admin\") %}{{ m.name }} {% endfor %}/ \
+              {% for m in members|rejectattr(\"is_admin\") %}{{ m.name }} {% endfor %}/ \
+              {% for m in members|selectattr(\"age\", \">=\", min_age) %}{{ m.name }} {% endfor %}/ \
+              {% for m in members|rejectattr(\"team.0\", \"eq\", \"red\") %}{{ m.name }}{% endfor %}",
+    ext = "txt"
+)]
+struct SelectAttr<'a> {
+    members: &'a [Member],
+    min_age: u32,
+}
+
+#[test]
+fn test_selectattr_rejectattr() {
+    let members = [
+        Member {
+            name: "ann",
+            age: 42,
+            is_admin: true,
+            team: ("red", 1),
+        },
+        Member {
+            name: "bob",
+            age: 17,
+            is_admin: false,
+            team: ("blue", 2),
+        },
+        Member {
+            name: "cid",
+            age: 30,
+            is_admin: false,
+            team: ("red", 3),
+        },
+    ];
+    let t = SelectAttr {
+        members: &members,
+        min_age: 18,
+    };
+    assert_eq!(t.render().unwrap(), "ann / bob cid / ann cid / bob");
+}
+
+#[derive(askama::Template)]
+#[template(
+    source = r#"<meta name="description" content="{{ body|striptags(true)|truncate(20) }}">
+<p>{{ body|striptags }}</p>"#,
+    ext = "html"
+)]
+struct StripTags<'a> {
+    body: &'a str,
+}
+
+#[test]
+fn test_striptags() {
+    let t = StripTags {
+        body: "<h1>Fish &amp; chips</h1>\n<p>A <em>classic</em> dish.</p>",
+    };
+    assert_eq!(
+        t.render().unwrap(),
+        "<meta name=\"description\" content=\"Fish &amp; chips A class...\">\n\
+         <p>Fish &amp;amp; chips A classic dish.</p>"
+    );
+}
+
+#[derive(askama::Template)]
+#[template(
+    source = r#"{{ nickname|unwrap_or(name) }} ({{ nickname|map_or(0, String::len) }})
+{%- if let Some(age) = age|ok %}, {{ age }}{% endif %}
+{%- if score|unwrap_or(0)|deref > 10 %}, high score{% endif %}"#,
+    ext = "html"
+)]
+struct OptionFilters {
+    nickname: Option<String>,
+    name: String,
+    age: Result<u8, String>,
+    score: Option<u32>,
+}
+
+#[test]
+fn test_option_filters() {
+    let t = OptionFilters {
+        nickname: Some("<nick>".to_owned()),
+        name: "name".to_owned(),
+        age: Ok(42),
+        score: Some(20),
+    };
+    assert_eq!(t.render().unwrap(), "&lt;nick&gt; (6), 42, high score");
+
+    let t = OptionFilters {
+        nickname: None,
+        name: "name".to_owned(),
+        age: Err("unknown".to_owned()),
+        score: None,
+    };
+    assert_eq!(t.render().unwrap(), "name (0)");
+}
@@ -0,0 +1,76 @@
+use askama::{Globals, Template};
+
+struct Site {
+    build_hash: &'static str,
+}
+
+impl Site {
+    fn visitors(&self) -> usize {
+        42
+    }
+}
+
+impl Globals for Site {
+    fn globals() -> &'static Self {
+        static SITE: Site = Site {
+            build_hash: "abc123",
+        };
+        &SITE
+    }
+}
+
+#[derive(Template)]
+#[template(
+    source = "{{ title }} - {{ globals.site_name }} ({{ globals.year + 1 }}, \
+              {{ globals.build_hash }}, {{ globals.visitors() }})",
+    ext = "html",
+    config = "test_globals.toml"
+)]
+struct Page<'a> {
+    title: &'a str,
+}
+
+#[test]
+fn test_globals() {
+    let page = Page { title: "Home" };
+    assert_eq!(
+        page.render().unwrap(),
+        "Home - Askama &amp; co (2025, abc123, 42)"
+    );
+}
+
+#[derive(Template)]
+#[template(
+    source = "{% for globals in locals %}{{ globals.site_name }}{% endfor %}",
+    ext = "txt",
+    config = "test_globals.toml"
+)]
+struct Shadowed {
+    locals: Vec<Local>,
+}
+
+struct Local {
+    site_name: &'static str,
+}
+
+#[test]
+fn test_shadowed_globals() {
+    let t = Shadowed {
+        locals: vec![Local { site_name: "local" }],
+    };
+    assert_eq!(t.render().unwrap(), "local");
+}
+
+#[derive(Template)]
+#[template(source = "{{ globals.site_name }}", ext = "txt")]
+struct NoGlobals {
+    globals: Local,
+}
+
+#[test]
+fn test_field_named_globals() {
+    let t = NoGlobals {
+        globals: Local { site_name: "field" },
+    };
+    assert_eq!(t.render().unwrap(), "field");
+}
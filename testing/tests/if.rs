@@ -24,3 +24,33 @@ fn test_if() {
     assert_eq!(If { s: "c" }.render().unwrap(), "c");
     assert_eq!(If { s: "d" }.render().unwrap(), "else");
 }
+
+#[derive(Template)]
+#[template(
+    source = r#"{{ "yes" if cond else "no" }} {{ n if n > 0 else 0 - n }} {{ "a" if n == 1 else "b" if n == 2 else "c" }} {% for x in items if x.len() > 1 %}{{ x }}{% endfor %} {{ s if cond else "&" }}"#,
+    ext = "html"
+)]
+struct InlineIf<'a> {
+    cond: bool,
+    n: i32,
+    items: Vec<&'a str>,
+    s: &'a str,
+}
+
+#[test]
+fn test_inline_if() {
+    let t = InlineIf {
+        cond: true,
+        n: 2,
+        items: vec!["ab", "c"],
+        s: "<",
+    };
+    assert_eq!(t.render().unwrap(), "yes 2 b ab &lt;");
+    let t = InlineIf {
+        cond: false,
+        n: -1,
+        items: vec![],
+        s: "<",
+    };
+    assert_eq!(t.render().unwrap(), "no 1 c  &amp;");
+}
@@ -0,0 +1,27 @@
+#![cfg(feature = "image-dims")]
+
+use askama::{ImageDims, Template};
+
+#[derive(Template)]
+#[template(
+    source = r#"<img src="/logo.png" {{ image_dims("static/logo.png") }}>
+{%- let dims = image_dims("static/logo.png") %} {{ dims.width * 2 }}"#,
+    ext = "html"
+)]
+struct Logo;
+
+#[test]
+fn test_image_dims() {
+    assert_eq!(
+        Logo.render().unwrap(),
+        r#"<img src="/logo.png" width=3 height=2> 6"#
+    );
+    assert_eq!(
+        ImageDims {
+            width: 120,
+            height: 40
+        }
+        .to_string(),
+        "width=120 height=40"
+    );
+}
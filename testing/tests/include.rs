@@ -53,3 +53,143 @@ fn test_include_macro() {
 
     assert_eq!(template.render().unwrap(), "Hello, Alice!\nHowdy, Bob!");
 }
+
+#[derive(Template)]
+#[template(path = "include-blocks-base.html")]
+struct IncludeBlocksBaseTemplate;
+
+#[derive(Template)]
+#[template(
+    source = r#"{% extends "include-blocks-base.html" %}
+{%- block nav %}{{ page }} / {% call super() %}{% endblock %}"#,
+    ext = "html"
+)]
+struct IncludeBlocksChildTemplate<'a> {
+    page: &'a str,
+}
+
+#[test]
+fn test_include_blocks() {
+    assert_eq!(
+        IncludeBlocksBaseTemplate.render().unwrap(),
+        "<main><nav>home</nav></main>"
+    );
+    let template = IncludeBlocksChildTemplate { page: "about" };
+    assert_eq!(
+        template.render().unwrap(),
+        "<main><nav>about / home</nav></main>"
+    );
+}
+
+#[derive(Template)]
+#[template(
+    source = r#"{% include theme from ["include-theme-dark.html", "include-theme-light.html"] %}"#,
+    ext = "html"
+)]
+struct DynamicInclude<'a> {
+    theme: String,
+    name: &'a str,
+}
+
+#[test]
+fn test_dynamic_include() {
+    let t = DynamicInclude {
+        theme: "include-theme-dark.html".to_owned(),
+        name: "Alice",
+    };
+    assert_eq!(t.render().unwrap(), "<b>Alice</b>");
+
+    let t = DynamicInclude {
+        theme: "include-theme-light.html".to_owned(),
+        ..t
+    };
+    assert_eq!(t.render().unwrap(), "<i>Alice</i>");
+
+    let t = DynamicInclude {
+        theme: "include-theme-blue.html".to_owned(),
+        ..t
+    };
+    assert_eq!(
+        t.render().unwrap_err().without_context().to_string(),
+        r#"template "include-theme-blue.html" cannot be included here"#
+    );
+}
+
+struct Item {
+    name: &'static str,
+    price: u32,
+}
+
+#[derive(Template)]
+#[template(
+    source = r#"{% for item in items %}{% include "include-row.html" with item only %}{% endfor %}"#,
+    ext = "html"
+)]
+struct IncludeWithContext {
+    items: Vec<Item>,
+}
+
+#[test]
+fn test_include_with_context() {
+    let t = IncludeWithContext {
+        items: vec![
+            Item {
+                name: "tea",
+                price: 3,
+            },
+            Item {
+                name: "cake",
+                price: 5,
+            },
+        ],
+    };
+    assert_eq!(t.render().unwrap(), "<li>tea: 3</li><li>cake: 5</li>");
+}
+
+#[derive(Template)]
+#[template(
+    source = r#"{% let name = "local" %}{% include "include-row.html" with item %}"#,
+    ext = "html"
+)]
+struct IncludeWithContextLocals {
+    item: Item,
+}
+
+#[test]
+fn test_include_with_context_locals() {
+    let t = IncludeWithContextLocals {
+        item: Item {
+            name: "tea",
+            price: 3,
+        },
+    };
+    assert_eq!(t.render().unwrap(), "<li>local: 3</li>");
+}
+
+#[derive(Template)]
+#[template(
+    source = r#"{% embed "embed-card.html" %}
+  {% block body %}<p>{{ text }}</p>{% endblock %}
+{% endembed %}
+{%- embed "embed-card.html" %}
+  {% block title %}{{ title }} ({% call super() %}){% endblock %}
+{% endembed %}"#,
+    ext = "html"
+)]
+struct Embed<'a> {
+    title: &'a str,
+    text: &'a str,
+}
+
+#[test]
+fn test_embed() {
+    let t = Embed {
+        title: "News",
+        text: "Hello",
+    };
+    assert_eq!(
+        t.render().unwrap(),
+        "<div class=\"card\"><h2>Untitled</h2><p>Hello</p></div>\
+         <div class=\"card\"><h2>News (Untitled)</h2></div>"
+    );
+}
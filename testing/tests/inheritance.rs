@@ -353,3 +353,144 @@ fn test_named_end() {
     let n = NamedBlocks { title: "title" };
     assert_eq!(n.render().unwrap(), "title\n\ntadam\nCopyright 2017");
 }
+
+#[derive(Template)]
+#[template(path = "super-args-mid.html")]
+struct SuperArgsMid<'a> {
+    greeting: &'a str,
+}
+
+#[derive(Template)]
+#[template(
+    source = r#"{% extends "super-args-mid.html" %}
+{%- block greeting -%}
+{% let word = "hi" %}{% call super.super(greeting = word|upper) %}{% call super.super() %}{% call super() %}
+{%- endblock %}"#,
+    ext = "txt"
+)]
+struct SuperArgsChild<'a> {
+    greeting: &'a str,
+}
+
+#[test]
+fn test_super_args() {
+    let t = SuperArgsMid { greeting: "base" };
+    assert_eq!(t.render().unwrap(), "base([mid])");
+
+    let t = SuperArgsChild { greeting: "base" };
+    assert_eq!(t.render().unwrap(), "[HI][base]base([mid])");
+}
+
+#[derive(Template)]
+#[template(path = "scoped-base.html")]
+struct ScopedBase<'a> {
+    items: &'a [&'a str],
+}
+
+#[derive(Template)]
+#[template(
+    source = r#"{% extends "scoped-base.html" %}
+{%- block item %}[{{ loop.index }}: {{ item }} ({% call super() %})]{% endblock %}"#,
+    ext = "txt"
+)]
+struct ScopedChild<'a> {
+    items: &'a [&'a str],
+}
+
+#[test]
+fn test_scoped_block() {
+    let t = ScopedBase { items: &["a", "b"] };
+    assert_eq!(t.render().unwrap(), "ab");
+
+    let t = ScopedChild { items: &["a", "b"] };
+    assert_eq!(t.render().unwrap(), "[1: a (a)][2: b (b)]");
+}
+
+#[derive(Template)]
+#[template(path = "const-base.html")]
+struct ConstBase;
+
+#[derive(Template)]
+#[template(
+    source = r#"{% extends "const-base.html" %}
+{%- const TITLE = "Users" %}
+{%- const LAST = PAGE_SIZE * 2 - 1 %}
+{%- block body %}{% for i in 0..PAGE_SIZE %}{{ i }}{% endfor %}..{{ LAST }}{% endblock %}"#,
+    ext = "txt"
+)]
+struct ConstChild;
+
+#[test]
+fn test_const() {
+    assert_eq!(ConstBase.render().unwrap(), "Items: 2");
+    assert_eq!(ConstChild.render().unwrap(), "Users: 01..3");
+}
+
+#[derive(Template)]
+#[template(path = "top-let-base.html")]
+struct TopLetBase<'a> {
+    name: &'a str,
+}
+
+#[derive(Template)]
+#[template(
+    source = r#"{% extends "top-let-base.html" %}
+{%- block title %}{{ title }} ({{ name }}){% endblock %}"#,
+    ext = "html"
+)]
+struct TopLetChild<'a> {
+    name: &'a str,
+}
+
+#[test]
+fn test_top_level_let_in_block() {
+    assert_eq!(
+        TopLetBase { name: "home" }.render().unwrap(),
+        "<h1>HOME</h1>"
+    );
+    assert_eq!(
+        TopLetChild { name: "home" }.render().unwrap(),
+        "<h1>HOME (home)</h1>"
+    );
+}
+
+#[derive(Template)]
+#[template(path = "loop-block-base.html")]
+struct LoopBlockBase<'a> {
+    items: &'a [&'a str],
+}
+
+#[derive(Template)]
+#[template(
+    source = r#"{% extends "loop-block-base.html" %}
+{%- block row %}{{ item }} ({{ loop.index }}/{{ loop.length }}): {% call super() %}{% endblock %}"#,
+    ext = "html"
+)]
+struct LoopBlockChild<'a> {
+    items: &'a [&'a str],
+}
+
+#[derive(Template)]
+#[template(
+    source = r#"{% extends "loop-block-base.html" %}
+{%- block row %}{% if loop.first %}{{ label }}{% else %}-{% endif %}{% endblock %}"#,
+    ext = "html",
+    block = "row"
+)]
+struct LoopBlockFragment<'a> {
+    items: &'a [&'a str],
+}
+
+#[test]
+fn test_block_in_loop() {
+    let items = ["a", "b"];
+    assert_eq!(
+        LoopBlockBase { items: &items }.render().unwrap(),
+        "<ul>\n  <li>1. A</li>\n  <li>2. B</li>\n</ul>"
+    );
+    assert_eq!(
+        LoopBlockChild { items: &items }.render().unwrap(),
+        "<ul>\n  <li>a (1/2): 1. A</li>\n  <li>b (2/2): 2. B</li>\n</ul>"
+    );
+    assert_eq!(LoopBlockFragment { items: &items }.render().unwrap(), "A-");
+}
@@ -0,0 +1,140 @@
+use askama::Template;
+
+#[derive(Template)]
+#[template(
+    source = "{% for n in numbers %}{% if n is odd %}o{% endif %}{% if n is even %}e{% endif %}\
+              {% if n is divisibleby(3) %}3{% endif %} {% endfor %}\
+              {% if numbers is empty %}empty{% endif %}{% if words is empty %}no words{% endif %}",
+    ext = "txt"
+)]
+struct NumbersTemplate {
+    numbers: Vec<u32>,
+    words: Vec<String>,
+}
+
+#[test]
+fn test_builtin_tests() {
+    let t = NumbersTemplate {
+        numbers: vec![1, 2, 3, 6],
+        words: vec![],
+    };
+    assert_eq!(t.render().unwrap(), "o e o3 e3 no words");
+}
+
+#[derive(Template)]
+#[template(
+    source = "{% if title is some %}{{ title.as_ref().unwrap() }}{% endif %}\
+              {% if title is none %}untitled{% endif %}\
+              {% if title is not none %}!{% endif %}",
+    ext = "txt"
+)]
+struct OptionTemplate<'a> {
+    title: Option<&'a str>,
+}
+
+#[test]
+fn test_option_tests() {
+    assert_eq!(
+        OptionTemplate { title: Some("Hi") }.render().unwrap(),
+        "Hi!"
+    );
+    assert_eq!(OptionTemplate { title: None }.render().unwrap(), "untitled");
+}
+
+#[derive(Template)]
+#[template(
+    source = "{% if name is defined %}{{ name }}{% endif %}{% if other is defined %}other{% endif %}\
+              {% let other = 1 %}{% if other is defined %} {{ other }}{% endif %}\
+              {% if missing is not defined %} missing{% endif %}",
+    ext = "txt"
+)]
+struct DefinedTemplate<'a> {
+    name: &'a str,
+}
+
+#[test]
+fn test_defined() {
+    assert_eq!(
+        DefinedTemplate { name: "a" }.render().unwrap(),
+        "a 1 missing"
+    );
+}
+
+#[derive(Template)]
+#[template(path = "defined-partial.html")]
+struct DefinedFieldTemplate<'a> {
+    extra: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "defined-partial.html")]
+struct DefinedOptionTemplate<'a> {
+    extra: Option<&'a str>,
+}
+
+type Maybe<'a> = Option<&'a str>;
+
+#[derive(Template)]
+#[template(
+    source = r#"{% include "defined-partial.html" %}/{{ extra is defined }}/{{ extra is not defined }}"#,
+    ext = "txt"
+)]
+struct DefinedOptMarkerTemplate<'a> {
+    #[template_opt]
+    extra: Maybe<'a>,
+}
+
+#[derive(Template)]
+#[template(
+    source = r#"{% include "defined-partial.html" %}{% if extra is not defined %} none{% endif %}"#,
+    ext = "txt"
+)]
+struct UndefinedTemplate;
+
+#[test]
+fn test_defined_optional() {
+    assert_eq!(DefinedFieldTemplate { extra: "a" }.render().unwrap(), "[a]");
+    assert_eq!(
+        DefinedOptionTemplate { extra: Some("b") }.render().unwrap(),
+        "[b]"
+    );
+    assert_eq!(DefinedOptionTemplate { extra: None }.render().unwrap(), "-");
+    assert_eq!(
+        DefinedOptMarkerTemplate { extra: Some("c") }
+            .render()
+            .unwrap(),
+        "[c]/true/false"
+    );
+    assert_eq!(
+        DefinedOptMarkerTemplate { extra: None }.render().unwrap(),
+        "-/false/true"
+    );
+    assert_eq!(UndefinedTemplate.render().unwrap(), "- none");
+}
+
+mod tests {
+    pub fn starting_with(s: &str, prefix: &&str) -> bool {
+        s.starts_with(prefix)
+    }
+
+    pub fn long(s: &str) -> bool {
+        s.len() > 5
+    }
+}
+
+#[derive(Template)]
+#[template(
+    source = r#"{% for name in names %}{% if name is starting_with("Dr") %}doctor{% elif name is long %}long{% else %}{{ name }}{% endif %} {% endfor %}"#,
+    ext = "txt"
+)]
+struct CustomTestTemplate<'a> {
+    names: Vec<&'a str>,
+}
+
+#[test]
+fn test_custom_tests() {
+    let t = CustomTestTemplate {
+        names: vec!["Dr Who", "Amelia", "Rory"],
+    };
+    assert_eq!(t.render().unwrap(), "doctor long Rory ");
+}
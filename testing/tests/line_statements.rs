@@ -0,0 +1,51 @@
+use askama::Template;
+
+#[derive(Template)]
+#[template(
+    source = "Items:
+#% for item in items
+  #% if loop.first
+  {{ item }} (first)
+  #% else
+  {{ item }}
+  #% endif
+#% endfor
+Done {# #% not a statement #}",
+    ext = "txt",
+    syntax = "lines",
+    config = "test_line_statements.toml"
+)]
+struct LineStatements<'a> {
+    items: &'a [&'a str],
+}
+
+#[test]
+fn test_line_statements() {
+    let t = LineStatements { items: &["a", "b"] };
+    assert_eq!(t.render().unwrap(), "Items:\n  a (first)\n  b\nDone ");
+}
+
+#[derive(Template)]
+#[template(
+    source = "{% raw %}
+#% if shown
+{% endraw %}
+{#
+#% if commented
+#}
+#% if shown
+shown
+#% endif",
+    ext = "txt",
+    syntax = "lines",
+    config = "test_line_statements.toml"
+)]
+struct LineStatementsInRaw {
+    shown: bool,
+}
+
+#[test]
+fn test_line_statements_in_raw() {
+    let t = LineStatementsInRaw { shown: true };
+    assert_eq!(t.render().unwrap(), "\n#% if shown\n\n\nshown\n");
+}
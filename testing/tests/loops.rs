@@ -93,6 +93,18 @@ fn test_for_array_trailing_comma() {
     assert_eq!(t.render().unwrap(), "123");
 }
 
+#[derive(Template)]
+#[template(
+    source = "{% for (color, code) in {\"red\": 1, \"green\": 2} %}{{ color }}={{ code }} {% endfor %}",
+    ext = "txt"
+)]
+struct ForMapTemplate;
+
+#[test]
+fn test_for_map() {
+    assert_eq!(ForMapTemplate.render().unwrap(), "green=2 red=1 ");
+}
+
 #[derive(Template)]
 #[template(
     source = "{% for i in [1, 2, 3].iter() %}{{ i }}{% endfor %}",
@@ -147,6 +159,24 @@ fn test_for_zip_ranges() {
     );
 }
 
+#[derive(Template)]
+#[template(
+    source = "{% for i in (0..20).step_by(5) %}{{ i }} {% endfor %}/\
+              {% for i in (start..=end).rev() %}{{ i }}{% if !loop.last %},{% endif %}{% endfor %}/\
+              {% for i in (0..10).rev().step_by(4) %}{{ loop.index }}:{{ i }} {% endfor %}",
+    ext = "txt"
+)]
+struct ForSteppedRangeTemplate {
+    start: i32,
+    end: i32,
+}
+
+#[test]
+fn test_for_stepped_range() {
+    let t = ForSteppedRangeTemplate { start: -2, end: 2 };
+    assert_eq!(t.render().unwrap(), "0 5 10 15 /2,1,0,-1,-2/1:9 2:5 3:1 ");
+}
+
 struct ForVecAttrVec {
     iterable: Vec<i32>,
 }
@@ -419,6 +449,23 @@ fn test_for_cycle_dynamic() {
     assert_eq!(t.render().unwrap(), "a1,b2,c3,d4,a5,b6,c7,d8,a9,");
 }
 
+#[derive(Template)]
+#[template(
+    source = r#"{% for v in values %}{{ loop.cycle("odd", "even") }}{{ loop.cycle(v * 10, v + 1, 0) }},{% endfor %}"#,
+    ext = "txt"
+)]
+struct ForCycleArgs<'a> {
+    values: &'a [u8],
+}
+
+#[test]
+fn test_for_cycle_args() {
+    let t = ForCycleArgs {
+        values: &[1, 2, 3, 4],
+    };
+    assert_eq!(t.render().unwrap(), "odd10,even3,odd0,even40,");
+}
+
 #[test]
 fn test_for_cycle_empty() {
     let t = ForCycleDynamic {
@@ -445,3 +492,229 @@ fn test_for_in_if() {
     let t = ForInIf { limit: 1 };
     assert_eq!(t.render().unwrap(), ":(");
 }
+
+#[derive(Template)]
+#[template(
+    source = "{% for v in values %}{{ loop.index }}/{{ loop.length }} {% endfor %}",
+    ext = "txt"
+)]
+struct ForLength<'a> {
+    values: &'a [&'a str],
+}
+
+#[test]
+fn test_for_length() {
+    let t = ForLength {
+        values: &["a", "b", "c"],
+    };
+    assert_eq!(t.render().unwrap(), "1/3 2/3 3/3 ");
+}
+
+#[derive(Template)]
+#[template(
+    source = "{% for i in 0..limit if i % 3 == 0 %}{{ i }}/{{ loop.length }} {% endfor %}",
+    ext = "txt"
+)]
+struct ForLengthFiltered {
+    limit: usize,
+}
+
+#[test]
+fn test_for_length_filtered() {
+    let t = ForLengthFiltered { limit: 10 };
+    assert_eq!(t.render().unwrap(), "0/4 3/4 6/4 9/4 ");
+}
+
+#[derive(Template)]
+#[template(
+    source = "{% for i in 0..limit if i % 3 == 0 %}{% include \"loop-length-include.html\" %}{% endfor %}",
+    ext = "txt"
+)]
+struct ForLengthInclude {
+    limit: usize,
+}
+
+#[test]
+fn test_for_length_include() {
+    let t = ForLengthInclude { limit: 10 };
+    assert_eq!(t.render().unwrap(), "0/4 3/4 6/4 9/4 ");
+}
+
+#[derive(Template)]
+#[template(
+    source = "{% for a in outer %}{% for b in a.chars() %}{{ b }}{{ loop.length }}{% endfor %}\
+              {% if loop.last %}({{ loop.length }}){% endif %}{% endfor %}",
+    ext = "txt"
+)]
+struct ForLengthNested<'a> {
+    outer: &'a [&'a str],
+}
+
+#[test]
+fn test_for_length_nested() {
+    let t = ForLengthNested {
+        outer: &["ab", "cde"],
+    };
+    assert_eq!(t.render().unwrap(), "a2b2c3d3e3(2)");
+}
+
+struct Item {
+    name: &'static str,
+    children: Vec<Item>,
+}
+
+#[derive(Template)]
+#[template(
+    source = "<ul>{% for item in items recursive %}<li>{{ item.name }}:{{ loop.index }}\
+        {%- if !item.children.is_empty() %}<ul>{{ loop(item.children) }}</ul>{% endif %}</li>\
+        {%- endfor %}</ul>",
+    ext = "html"
+)]
+struct RecursiveLoop {
+    items: Vec<Item>,
+}
+
+#[test]
+fn test_recursive_loop() {
+    let leaf = |name| Item {
+        name,
+        children: vec![],
+    };
+    let t = RecursiveLoop {
+        items: vec![
+            Item {
+                name: "a",
+                children: vec![
+                    leaf("b"),
+                    Item {
+                        name: "c",
+                        children: vec![leaf("d")],
+                    },
+                ],
+            },
+            leaf("<e>"),
+        ],
+    };
+    assert_eq!(
+        t.render().unwrap(),
+        "<ul><li>a:1<ul><li>b:1</li><li>c:2<ul><li>d:1</li></ul></li></ul></li>\
+         <li>&lt;e&gt;:2</li></ul>"
+    );
+}
+
+// The body of a recursive loop can update the variables declared with `let mut` outside of it,
+// and render `once` blocks.
+#[derive(Template)]
+#[template(
+    source = "{% let mut count = 0 %}{% for item in items recursive %}\
+        {%- once %}[{% endonce %}{% let count = count + 1 %}{{ item.name }}\
+        {%- if !item.children.is_empty() %}({{ loop(item.children) }}){% endif %}\
+        {%- endfor %}] {{ count }}",
+    ext = "txt"
+)]
+struct RecursiveLoopState {
+    items: Vec<Item>,
+}
+
+#[test]
+fn test_recursive_loop_state() {
+    let leaf = |name| Item {
+        name,
+        children: vec![],
+    };
+    let t = RecursiveLoopState {
+        items: vec![
+            Item {
+                name: "a",
+                children: vec![
+                    leaf("b"),
+                    Item {
+                        name: "c",
+                        children: vec![leaf("d")],
+                    },
+                ],
+            },
+            leaf("e"),
+        ],
+    };
+    assert_eq!(t.render().unwrap(), "[a(bc(d))e] 5");
+}
+
+struct Point {
+    x: u32,
+    y: u32,
+}
+
+#[derive(Template)]
+#[template(
+    source = "{% for (key, (a, b)) in pairs %}{{ key }}={{ a }},{{ b }} {% endfor %}\
+              {% for Point { x, y: height } in points %}({{ x }}, {{ height }}){% endfor %}",
+    ext = "txt"
+)]
+struct ForDestructuringTemplate<'a> {
+    pairs: Vec<(&'a str, (u32, char))>,
+    points: Vec<Point>,
+}
+
+#[test]
+fn test_for_destructuring() {
+    let t = ForDestructuringTemplate {
+        pairs: vec![("a", (1, 'x')), ("b", (2, 'y'))],
+        points: vec![Point { x: 1, y: 2 }, Point { x: 3, y: 4 }],
+    };
+    assert_eq!(t.render().unwrap(), "a=1,x b=2,y (1, 2)(3, 4)");
+}
+
+struct Countdown(std::cell::Cell<u32>);
+
+impl Countdown {
+    fn next(&self) -> Option<u32> {
+        let n = self.0.get();
+        self.0.set(n.saturating_sub(1));
+        (n > 0).then_some(n)
+    }
+}
+
+#[derive(Template)]
+#[template(
+    source = "{% while let Some(n) = countdown.next() %}{% if n|deref == 2 %}{% continue %}{% endif %}{{ n }} {% endwhile %}
+{%- while true %}liftoff{% break %}{% endwhile %}",
+    ext = "txt"
+)]
+struct While {
+    countdown: Countdown,
+}
+
+#[test]
+fn test_while() {
+    let t = While {
+        countdown: Countdown(std::cell::Cell::new(4)),
+    };
+    assert_eq!(t.render().unwrap(), "4 3 1 liftoff");
+}
+
+#[derive(Template)]
+#[template(
+    source = "{% while let Some(n) = countdown.next() %}{{ n }}{% endwhile %}",
+    ext = "txt",
+    config = "test_while_limit.toml"
+)]
+struct WhileLimit {
+    countdown: Countdown,
+}
+
+#[test]
+fn test_while_limit() {
+    let t = WhileLimit {
+        countdown: Countdown(std::cell::Cell::new(3)),
+    };
+    assert_eq!(t.render().unwrap(), "321");
+
+    let t = WhileLimit {
+        countdown: Countdown(std::cell::Cell::new(4)),
+    };
+    assert_eq!(
+        t.render().unwrap_err().without_context().to_string(),
+        "`while` loop exceeded the iteration limit of 3"
+    );
+}
@@ -34,6 +34,18 @@ fn test_import() {
     assert_eq!(t.render().unwrap(), "foo foo foo");
 }
 
+#[derive(Template)]
+#[template(path = "from-import.html")]
+struct FromImportTemplate<'a> {
+    s: &'a str,
+}
+
+#[test]
+fn test_from_import() {
+    let t = FromImportTemplate { s: "foo" };
+    assert_eq!(t.render().unwrap(), "foo foo foo|foo foo|foo");
+}
+
 #[derive(Template)]
 #[template(path = "deep-nested-macro.html")]
 struct NestedTemplate;
@@ -170,3 +182,249 @@ struct TrailingComma;
 fn test_trailing_comma() {
     assert_eq!(TrailingComma.render().unwrap(), "hihihihihi");
 }
+
+#[derive(Template)]
+#[template(
+    source = r#"{% macro badge(label) -%}
+<span class="badge">{{ label }}</span>
+{%- endmacro %}
+{%- macro tag(value, kind) -%}
+<{{ kind }}>{{ value }}</{{ kind }}>
+{%- endmacro -%}
+
+{{ name|badge }} {{ name|lower|badge }} {{- count|tag("code") }}
+{%- for item in items %} {{ item|badge }}{% endfor %}"#,
+    ext = "html"
+)]
+struct MacroAsFilter<'a> {
+    name: &'a str,
+    count: usize,
+    items: &'a [&'a str],
+}
+
+#[test]
+fn test_macro_as_filter() {
+    let t = MacroAsFilter {
+        name: "<New>",
+        count: 3,
+        items: &["a", "b"],
+    };
+    assert_eq!(
+        t.render().unwrap(),
+        "<span class=\"badge\">&lt;New&gt;</span> <span class=\"badge\">&lt;new&gt;</span>\
+         <code>3</code> <span class=\"badge\">a</span> <span class=\"badge\">b</span>"
+    );
+}
+
+#[derive(Template)]
+#[template(
+    source = r#"{% macro input(name, type="text", value="") -%}
+<input name="{{ name }}" type="{{ type }}" value="{{ value }}">
+{%- endmacro -%}
+
+{%- call input("a") %}
+{% call input("b", "password") %}
+{% call input("c", value=value) %}
+{% call input(name="d", type="hidden", value="x") -%}
+"#,
+    ext = "html"
+)]
+struct MacroDefaultArguments<'a> {
+    value: &'a str,
+}
+
+#[test]
+fn test_default_arguments() {
+    let t = MacroDefaultArguments { value: "v" };
+    assert_eq!(
+        t.render().unwrap(),
+        "\
+<input name=\"a\" type=\"text\" value=\"\">
+<input name=\"b\" type=\"password\" value=\"\">
+<input name=\"c\" type=\"text\" value=\"v\">
+<input name=\"d\" type=\"hidden\" value=\"x\">"
+    );
+}
+
+#[derive(Template)]
+#[template(
+    source = r#"{% macro card(title) -%}
+<div class="card"><h1>{{ title }}</h1>{{ caller() }}</div>
+{%- endmacro -%}
+{%- macro list(items) -%}
+<ul>{% for item in items %}<li>{{ caller(item, loop.index) }}</li>{% endfor %}</ul>
+{%- endmacro -%}
+
+{%- let title = title -%}
+{%- call() card("Card") -%}
+    <p>{{ title }}</p>
+{%- endcall %}
+{% call(item, index) list(items) -%}
+    {{ index }}: {% call() card(item) %}{{ item }} in {{ title }}{% endcall %}
+{%- endcall %}"#,
+    ext = "html"
+)]
+struct MacroCaller<'a> {
+    title: &'a str,
+    items: &'a [&'a str],
+}
+
+#[test]
+fn test_caller() {
+    let t = MacroCaller {
+        title: "<body>",
+        items: &["a", "b"],
+    };
+    assert_eq!(
+        t.render().unwrap(),
+        "<div class=\"card\"><h1>Card</h1><p>&lt;body&gt;</p></div>\n\
+         <ul><li>1: <div class=\"card\"><h1>a</h1>a in &lt;body&gt;</div></li>\
+         <li>2: <div class=\"card\"><h1>b</h1>b in &lt;body&gt;</div></li></ul>"
+    );
+}
+
+struct TreeNode {
+    name: &'static str,
+    children: Vec<TreeNode>,
+}
+
+#[derive(Template)]
+#[template(
+    source = r#"{% macro tree(node, depth=0) -%}
+{{ node.name }}({{ depth }}){% if !node.children.is_empty() %}[
+{%- for child in node.children %}{% call tree(child, depth + 1) %}{% endfor -%}
+]{% endif %}
+{%- endmacro -%}
+
+{%- macro even(n) %}{% if n > 0 %}e{% call odd(n - 1) %}{% endif %}{% endmacro -%}
+{%- macro odd(n) %}{% if n > 0 %}o{% call even(n - 1) %}{% endif %}{% endmacro -%}
+
+{%- macro names(node) %}{{ caller(node.name) }}
+{%- for child in node.children %}{% call names(child) %}{% endfor %}
+{%- endmacro -%}
+
+{%- call tree(root) %} {% call even(4) %} {% call(name) names(root) %}<{{ name }}>{% endcall -%}"#,
+    ext = "txt"
+)]
+struct RecursiveMacro {
+    root: TreeNode,
+}
+
+#[test]
+fn test_recursive_macro() {
+    let leaf = |name| TreeNode {
+        name,
+        children: vec![],
+    };
+    let t = RecursiveMacro {
+        root: TreeNode {
+            name: "a",
+            children: vec![
+                TreeNode {
+                    name: "b",
+                    children: vec![leaf("c")],
+                },
+                leaf("d"),
+            ],
+        },
+    };
+    assert_eq!(
+        t.render().unwrap(),
+        "a(0)[b(1)[c(2)]d(1)] eoeo <a><b><c><d>"
+    );
+
+    let mut root = leaf("z");
+    for _ in 0..100 {
+        root = TreeNode {
+            name: "y",
+            children: vec![root],
+        };
+    }
+    assert_eq!(
+        RecursiveMacro { root }
+            .render()
+            .unwrap_err()
+            .without_context()
+            .to_string(),
+        "macro `tree` exceeded the recursion limit of 64"
+    );
+}
+
+// A recursive macro can update the variables declared with `let mut` outside of it, and render
+// `once` blocks.
+#[derive(Template)]
+#[template(
+    source = r#"{% macro tree(node) -%}
+{%- once %}<script src="tree.js"></script>{% endonce -%}
+{%- let count = count + 1 -%}
+<li>{{ node.name }}{% for child in node.children %}{% call tree(child) %}{% endfor %}</li>
+{%- endmacro -%}
+
+{%- let mut count = 0 -%}
+{%- call tree(root) %} {{ count }}"#,
+    ext = "html"
+)]
+struct RecursiveMacroState {
+    root: TreeNode,
+}
+
+#[test]
+fn test_recursive_macro_state() {
+    let leaf = |name| TreeNode {
+        name,
+        children: vec![],
+    };
+    let t = RecursiveMacroState {
+        root: TreeNode {
+            name: "a",
+            children: vec![
+                TreeNode {
+                    name: "b",
+                    children: vec![leaf("c")],
+                },
+                leaf("d"),
+            ],
+        },
+    };
+    assert_eq!(
+        t.render().unwrap(),
+        r#"<script src="tree.js"></script><li>a<li>b<li>c</li></li><li>d</li></li> 4"#
+    );
+}
+
+#[derive(Template)]
+#[template(
+    source = r#"{% macro link(url, label, class="plain") -%}
+<a href="{{ url }}" class="{{ class }}">{{ label }}</a>
+{%- endmacro -%}
+{% call link(*pair) %} {% call link(*items, class="menu") %} {% call link("/", *rest) %}"#,
+    ext = "html"
+)]
+struct SpreadMacro<'a> {
+    pair: (&'a str, &'a str),
+    items: Vec<&'a str>,
+    rest: [&'a str; 1],
+}
+
+#[test]
+fn test_spread_macro_arguments() {
+    let t = SpreadMacro {
+        pair: ("/a", "A"),
+        items: vec!["/b", "B"],
+        rest: ["Home"],
+    };
+    assert_eq!(
+        t.render().unwrap(),
+        r#"<a href="/a" class="plain">A</a> <a href="/b" class="menu">B</a> <a href="/" class="plain">Home</a>"#
+    );
+
+    let t = SpreadMacro {
+        pair: ("/a", "A"),
+        items: vec!["/b"],
+        rest: ["Home"],
+    };
+    assert_eq!(
+        t.render().unwrap_err().without_context().to_string(),
+        "too few items to spread into macro argument #2"
+    );
+}
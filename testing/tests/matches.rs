@@ -224,3 +224,144 @@ fn test_match_enum_or() {
     };
     assert_eq!(template.render().unwrap(), "The card is red\n");
 }
+
+#[derive(Template)]
+#[template(
+    ext = "txt",
+    source = r#"
+{%- match self.lookup(id) -%}
+    {%- when Some with (n @ (1 or 2)) -%} small {{ n }}
+    {%- when found @ Some with (_) -%} found {{ found.unwrap() }}
+    {%- when None -%} missing
+{%- endmatch -%}
+{%- match a + b -%}
+    {%- when 3 -%} /three
+    {%- when sum -%} /{{ sum }}
+{%- endmatch -%}"#
+)]
+struct MatchExprScrutinee {
+    id: usize,
+    a: u32,
+    b: u32,
+    lookups: std::cell::Cell<usize>,
+}
+
+impl MatchExprScrutinee {
+    fn lookup(&self, id: &usize) -> Option<u32> {
+        self.lookups.set(self.lookups.get() + 1);
+        [1, 2, 30].get(*id).copied()
+    }
+}
+
+#[test]
+fn test_match_expr_scrutinee() {
+    let s = MatchExprScrutinee {
+        id: 1,
+        a: 1,
+        b: 2,
+        lookups: Default::default(),
+    };
+    assert_eq!(s.render().unwrap(), "small 2/three");
+    assert_eq!(s.lookups.get(), 1);
+
+    let s = MatchExprScrutinee {
+        id: 2,
+        a: 2,
+        b: 2,
+        lookups: Default::default(),
+    };
+    assert_eq!(s.render().unwrap(), "found 30/4");
+    assert_eq!(s.lookups.get(), 1);
+
+    let s = MatchExprScrutinee {
+        id: 5,
+        a: 0,
+        b: 0,
+        lookups: Default::default(),
+    };
+    assert_eq!(s.render().unwrap(), "missing/0");
+}
+
+#[derive(Template)]
+#[template(
+    source = "{% match item %}\
+              {% when Some(n) if n|deref > 10 %}big {{ n }}\
+              {% when Some(n) if n is even %}even {{ n }}\
+              {% when Some(n) %}{{ n }}\
+              {% when None %}none\
+              {% endmatch %}",
+    ext = "txt"
+)]
+struct MatchGuardTemplate {
+    item: Option<u32>,
+}
+
+#[test]
+fn test_match_guard() {
+    let render = |item| MatchGuardTemplate { item }.render().unwrap();
+    assert_eq!(render(Some(12)), "big 12");
+    assert_eq!(render(Some(4)), "even 4");
+    assert_eq!(render(Some(3)), "3");
+    assert_eq!(render(None), "none");
+}
+
+#[derive(Template)]
+#[template(
+    source = "{% match n %}\
+              {% when ..=0 %}none\
+              {% when n @ 1..=9 %}digit {{ n }}\
+              {% when 10 or 20..=29 %}ten or twenties\
+              {% when n @ 100.. %}large {{ n }}\
+              {% else %}other\
+              {% endmatch %} \
+              {% match c %}{% when Some(c @ 'a'..='z') %}lower {{ c }}{% else %}not lower{% endmatch %}",
+    ext = "txt"
+)]
+struct MatchRangeTemplate {
+    n: i32,
+    c: Option<char>,
+}
+
+#[test]
+fn test_match_range() {
+    let render = |n, c| MatchRangeTemplate { n, c }.render().unwrap();
+    assert_eq!(render(-1, Some('a')), "none lower a");
+    assert_eq!(render(7, Some('A')), "digit 7 not lower");
+    assert_eq!(render(10, None), "ten or twenties not lower");
+    assert_eq!(render(25, None), "ten or twenties not lower");
+    assert_eq!(render(50, None), "other not lower");
+    assert_eq!(render(100, None), "large 100 not lower");
+}
+
+#[allow(dead_code)]
+enum Status {
+    Active,
+    Pending(u32),
+    Closed,
+}
+
+#[derive(Template)]
+#[template(
+    source = "{% for status in statuses %}\
+              {% if status matches Status::Active | Status::Pending(1..) %}o{% else %}x{% endif %}\
+              {% endfor %} {{ code matches 'a'..='f' or 'A' }}",
+    ext = "txt"
+)]
+struct MatchesExprTemplate {
+    statuses: Vec<Status>,
+    code: char,
+}
+
+#[test]
+fn test_matches_expr() {
+    let t = MatchesExprTemplate {
+        statuses: vec![
+            Status::Active,
+            Status::Pending(0),
+            Status::Pending(3),
+            Status::Closed,
+        ],
+        code: 'A',
+    };
+    assert_eq!(t.render().unwrap(), "oxox true");
+}
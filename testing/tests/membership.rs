@@ -0,0 +1,35 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use askama::Template;
+
+#[derive(Template)]
+#[template(
+    source = r#"{% if "b" in names %}b{% endif %}{% if "z" not in names %} !z{% endif %}
+{%- if 2 in numbers %} 2{% endif %}{% if numbers[0] in set %} 1{% endif %}
+{%- if "key" in map %} key{% endif %}{% if name|deref in map %} {{ name }}{% endif %}
+{%- if 3 not in tree %} !3{% endif %}
+{%- if "ell" in name %} ell{% endif %}{% if 'x' not in name %} !x{% endif %}
+{%- for n in numbers if n|deref not in set %} {{ n }}{% endfor %}"#,
+    ext = "txt"
+)]
+struct MembershipTemplate<'a> {
+    names: Vec<String>,
+    numbers: &'a [i32],
+    set: HashSet<i32>,
+    map: HashMap<String, i32>,
+    tree: BTreeSet<u8>,
+    name: &'a str,
+}
+
+#[test]
+fn test_membership() {
+    let t = MembershipTemplate {
+        names: vec!["a".into(), "b".into()],
+        numbers: &[1, 2, 3],
+        set: HashSet::from([1, 3]),
+        map: HashMap::from([("key".into(), 1)]),
+        tree: BTreeSet::from([1, 2]),
+        name: "hello",
+    };
+    assert_eq!(t.render().unwrap(), "b !z 2 1 key !3 ell !x 2");
+}
@@ -34,6 +34,22 @@ fn test_self_raw_identifier_method() {
     assert_eq!(t.render().unwrap(), "foo");
 }
 
+#[derive(Template)]
+#[template(
+    source = "{{ s.parse::<u32>().unwrap_or(0) + 1 }} {{ s.chars().rev().collect::<String>() }} \
+              {{ std::mem::size_of::<u16>() }}",
+    ext = "txt"
+)]
+struct TurbofishTemplate<'a> {
+    s: &'a str,
+}
+
+#[test]
+fn test_turbofish() {
+    assert_eq!(TurbofishTemplate { s: "41" }.render().unwrap(), "42 14 2");
+    assert_eq!(TurbofishTemplate { s: "x" }.render().unwrap(), "1 x 2");
+}
+
 #[derive(Template)]
 #[template(source = "{{ self.get_s() }} {{ t.get_s() }}", ext = "txt")]
 struct NestedSelfMethodTemplate<'a> {
@@ -0,0 +1,59 @@
+use askama::Template;
+
+#[derive(Template)]
+#[template(
+    source = "{% for i in 0..3 %}{% once %}<ul>{% endonce %}<li>{{ i }}</li>{% endfor %}",
+    ext = "html"
+)]
+struct OnceLoop;
+
+#[test]
+fn test_once_loop() {
+    assert_eq!(
+        OnceLoop.render().unwrap(),
+        "<ul><li>0</li><li>1</li><li>2</li>"
+    );
+    // Every render starts over.
+    assert_eq!(
+        OnceLoop.render().unwrap(),
+        "<ul><li>0</li><li>1</li><li>2</li>"
+    );
+}
+
+#[derive(Template)]
+#[template(
+    source = r#"{%- macro button(label) -%}
+{% once %}<script src="button.js"></script>{% endonce %}<button>{{ label }}</button>
+{%- endmacro -%}
+{% call button("a") %}{% for label in labels %}{% call button(label) %}{% endfor %}"#,
+    ext = "html"
+)]
+struct OnceMacro<'a> {
+    labels: &'a [&'a str],
+}
+
+#[test]
+fn test_once_macro() {
+    let t = OnceMacro {
+        labels: &["b", "c"],
+    };
+    assert_eq!(
+        t.render().unwrap(),
+        r#"<script src="button.js"></script><button>a</button><button>b</button><button>c</button>"#
+    );
+}
+
+#[derive(Template)]
+#[template(
+    source = r#"{% include "once-included.html" %}{% once %}<b></b>{% endonce %}{% include "once-included.html" %}"#,
+    ext = "html"
+)]
+struct OnceInclude;
+
+#[test]
+fn test_once_include() {
+    assert_eq!(
+        OnceInclude.render().unwrap(),
+        "<style></style><i></i><b></b><i></i>"
+    );
+}
@@ -61,3 +61,21 @@ fn test_short_circuit() {
     let t = ShortCircuitTemplate {};
     assert_eq!(t.render().unwrap(), "truetrue");
 }
+
+#[derive(Template)]
+#[template(
+    source = "{% for x in values.iter().copied() %}{% if 0 <= x < 10 %}{{ x }}{% endif %}{% endfor %} \
+              {{ 1 < 2 <= 2 != 3 }} {{ 3 > 2 > 2 }}",
+    ext = "txt"
+)]
+struct ChainedComparisonTemplate {
+    values: Vec<i32>,
+}
+
+#[test]
+fn test_chained_comparison() {
+    let t = ChainedComparisonTemplate {
+        values: vec![-1, 0, 5, 10, 9],
+    };
+    assert_eq!(t.render().unwrap(), "059 true false");
+}
@@ -0,0 +1,95 @@
+use askama::Template;
+
+struct Address {
+    city: String,
+    zip: Option<u32>,
+}
+
+struct User {
+    name: String,
+    address: Option<Address>,
+}
+
+impl User {
+    fn new(name: &str, address: Option<Address>) -> Self {
+        Self {
+            name: name.to_owned(),
+            address,
+        }
+    }
+}
+
+fn address(city: &str, zip: Option<u32>) -> Option<Address> {
+    Some(Address {
+        city: city.to_owned(),
+        zip,
+    })
+}
+
+#[derive(Template)]
+#[template(source = "[{{ user?.address?.city }}]", ext = "html")]
+struct CityTemplate {
+    user: Option<User>,
+}
+
+#[test]
+fn test_optional_chain() {
+    let t = CityTemplate {
+        user: Some(User::new("Ann", address("Rome & Co", None))),
+    };
+    assert_eq!(t.render().unwrap(), "[Rome &amp; Co]");
+
+    let t = CityTemplate {
+        user: Some(User::new("Bob", None)),
+    };
+    assert_eq!(t.render().unwrap(), "[]");
+
+    let t = CityTemplate { user: None };
+    assert_eq!(t.render().unwrap(), "[]");
+}
+
+#[derive(Template)]
+#[template(
+    source = "{{ user?.name.len() }}/\
+              {% if let Some(zip) = user?.address?.zip %}{{ zip|deref|unwrap_or(0) }}{% else %}-{% endif %}/\
+              {% if let Some(city) = user?.address?.city %}{{ city }}{% else %}unknown{% endif %}",
+    ext = "txt"
+)]
+struct ChainTemplate<'a> {
+    user: &'a Option<User>,
+}
+
+#[test]
+fn test_optional_chain_expressions() {
+    let user = Some(User::new("Carol", address("Oslo", Some(150))));
+    assert_eq!(
+        ChainTemplate { user: &user }.render().unwrap(),
+        "5/150/Oslo"
+    );
+
+    let user = Some(User::new("Dan", address("Oslo", None)));
+    assert_eq!(ChainTemplate { user: &user }.render().unwrap(), "3/0/Oslo");
+
+    assert_eq!(
+        ChainTemplate { user: &None }.render().unwrap(),
+        "/-/unknown"
+    );
+}
+
+#[derive(Template)]
+#[template(source = "{{ (self.parse()?).to_string().len() }}", ext = "txt")]
+struct TryAttrTemplate<'a> {
+    s: &'a str,
+}
+
+impl TryAttrTemplate<'_> {
+    fn parse(&self) -> Result<u32, std::num::ParseIntError> {
+        self.s.parse()
+    }
+}
+
+#[test]
+fn test_try_then_attr() {
+    assert_eq!(TryAttrTemplate { s: "1234" }.render().unwrap(), "4");
+    assert!(TryAttrTemplate { s: "x" }.render().is_err());
+}
@@ -0,0 +1,53 @@
+use askama::Template;
+
+#[derive(Template)]
+#[template(
+    source = r#"<p>{{ user.name }}</p>{% if show %}{{ "a < b"|upper }}{% endif %}{{ count + 1 }}"#,
+    ext = "html",
+    render_debug = true
+)]
+struct Page<'a> {
+    user: User<'a>,
+    show: bool,
+    count: u32,
+}
+
+struct User<'a> {
+    name: &'a str,
+}
+
+#[test]
+fn test_render_debug() {
+    let t = Page {
+        user: User { name: "" },
+        show: true,
+        count: 1,
+    };
+    assert_eq!(t.render().unwrap(), "<p></p>A &lt; B2");
+    assert_eq!(
+        t.render_debug().unwrap(),
+        "<p>[[user.name = ]]</p>\
+         [[&quot;a &lt; b&quot;|upper = A &lt; B]]\
+         [[count + 1 = 2]]"
+    );
+}
+
+#[derive(Template)]
+#[template(
+    source = "{% for (i, x) in items.iter().enumerate() %}{{ x|safe }}{{ i }}{% endfor %}",
+    ext = "txt",
+    render_debug = true
+)]
+struct Loop<'a> {
+    items: &'a [&'a str],
+}
+
+#[test]
+fn test_render_debug_loop() {
+    let t = Loop { items: &["a", "b"] };
+    assert_eq!(t.render().unwrap(), "a0b1");
+    assert_eq!(
+        t.render_debug().unwrap(),
+        "[[x|safe = a]][[i = 0]][[x|safe = b]][[i = 1]]"
+    );
+}
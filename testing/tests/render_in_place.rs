@@ -37,3 +37,61 @@ fn test_render_in_place() {
        "Section 1: A=A\nB=B\nSection 2: C=C\nD=D\nSection 3 for:\n* A=1\nB=2\n* A=A\nB=B\n* A=a\nB=b\n"
     );
 }
+
+#[derive(Template)]
+#[template(source = "<p>{{ child }}</p>", ext = "html")]
+struct HtmlParent<C: Template> {
+    child: C,
+}
+
+#[derive(Template)]
+#[template(source = "<b>{{ name }}</b>", ext = "html")]
+struct HtmlChild<'a> {
+    name: &'a str,
+}
+
+#[derive(Template)]
+#[template(source = "<b>{{ name }}</b>", ext = "txt")]
+struct TextChild<'a> {
+    name: &'a str,
+}
+
+#[test]
+fn test_render_in_place_no_double_escaping() {
+    let t = HtmlParent {
+        child: HtmlChild { name: "a&b" },
+    };
+    assert_eq!(t.render().unwrap(), "<p><b>a&amp;b</b></p>");
+}
+
+#[test]
+fn test_render_in_place_escapes_other_mime_type() {
+    let t = HtmlParent {
+        child: TextChild { name: "a&b" },
+    };
+    assert_eq!(t.render().unwrap(), "<p>&lt;b&gt;a&amp;b&lt;/b&gt;</p>");
+}
+
+#[derive(Template)]
+#[template(source = r#"{{ "child"|fail }}"#, ext = "html")]
+struct FailingChild;
+
+mod filters {
+    pub fn fail<T>(_: T) -> ::askama::Result<&'static str> {
+        Err(::askama::Error::Custom("child failed".into()))
+    }
+}
+
+#[test]
+fn test_render_in_place_error_propagation() {
+    let t = HtmlParent {
+        child: FailingChild,
+    };
+    match t.render() {
+        Err(askama::Error::Filter {
+            name: "fail",
+            error,
+        }) => assert_eq!(error.to_string(), "child failed"),
+        res => panic!("unexpected result: {res:?}"),
+    }
+}
@@ -0,0 +1,38 @@
+#![cfg(feature = "serde-json")]
+
+use askama::Template;
+use serde_json::json;
+
+#[derive(Template)]
+#[template(source = "<b>{{ name }}</b>", ext = "html", serialize = "string")]
+struct Fragment<'a> {
+    name: &'a str,
+}
+
+#[derive(Template)]
+#[template(source = "<p>{{ body }}</p>", ext = "html", serialize = "object")]
+struct Preview<'a> {
+    body: &'a str,
+}
+
+#[test]
+fn test_serialize_string() {
+    let fragment = Fragment { name: "a & b" };
+    assert_eq!(
+        serde_json::to_value(&fragment).unwrap(),
+        json!("<b>a &amp; b</b>"),
+    );
+    assert_eq!(
+        serde_json::to_value(json!({ "fragment": fragment })).unwrap(),
+        json!({ "fragment": "<b>a &amp; b</b>" }),
+    );
+}
+
+#[test]
+fn test_serialize_object() {
+    let preview = Preview { body: "<hi>" };
+    assert_eq!(
+        serde_json::to_value(&preview).unwrap(),
+        json!({ "html": "<p>&lt;hi&gt;</p>", "mime_type": "text/html; charset=utf-8" }),
+    );
+}
@@ -159,6 +159,46 @@ fn test_literals_escape() {
     );
 }
 
+#[derive(Template)]
+#[template(
+    source = r###"{{ r"a\b" }} {{ r#"say "hi""# }} {{ "x"|fmt(r#"{:?}"#) }} {{ "a\
+                  b" }}"###,
+    ext = "txt"
+)]
+struct RawStringTemplate;
+
+#[test]
+fn test_raw_strings() {
+    assert_eq!(
+        RawStringTemplate.render().unwrap(),
+        r#"a\b say "hi" "x" ab"#
+    );
+}
+
+#[derive(Template)]
+#[template(
+    source = r#"{{ f"Hello, {name}!" }} {{ f"{price:.2} for {items.len()} <items>" }} {{ f"{{{name|upper}}}\u{2665}" }}"#,
+    ext = "html"
+)]
+struct FormatStrTemplate<'a> {
+    name: &'a str,
+    price: f64,
+    items: Vec<u32>,
+}
+
+#[test]
+fn test_format_str() {
+    let t = FormatStrTemplate {
+        name: "<b>",
+        price: 1.5,
+        items: vec![1, 2],
+    };
+    assert_eq!(
+        t.render().unwrap(),
+        "Hello, &lt;b&gt;! 1.50 for 2 &lt;items&gt; {&lt;B&gt;}\u{2665}"
+    );
+}
+
 struct Holder {
     a: usize,
 }
@@ -405,6 +445,61 @@ fn test_index() {
     assert_eq!(t.render().unwrap(), "baz");
 }
 
+#[derive(Template)]
+#[template(
+    source = "{{ items[-1] }} {{ items[-3] }} {{ items[1] }} {{ names[-2] }}",
+    ext = "txt"
+)]
+struct NegativeIndexTemplate<'a> {
+    items: [u8; 3],
+    names: Vec<&'a str>,
+}
+
+#[test]
+fn test_negative_index() {
+    let t = NegativeIndexTemplate {
+        items: [1, 2, 3],
+        names: vec!["a", "b"],
+    };
+    assert_eq!(t.render().unwrap(), "3 1 2 a");
+}
+
+#[test]
+#[should_panic(expected = "index -2 is out of bounds for a length of 1")]
+fn test_negative_index_out_of_bounds() {
+    let t = NegativeIndexTemplate {
+        items: [1, 2, 3],
+        names: vec!["a"],
+    };
+    let _ = t.render();
+}
+
+struct Temperatures([&'static str; 3]);
+
+impl std::ops::Index<i32> for Temperatures {
+    type Output = str;
+
+    fn index(&self, degrees: i32) -> &str {
+        self.0[(degrees.signum() + 1) as usize]
+    }
+}
+
+#[derive(Template)]
+#[template(source = "{{ t[-n] }} {{ t[n] }} {{ t[0] }}", ext = "txt")]
+struct SignedIndexTemplate {
+    t: Temperatures,
+    n: i32,
+}
+
+#[test]
+fn test_signed_index() {
+    let t = SignedIndexTemplate {
+        t: Temperatures(["cold", "zero", "warm"]),
+        n: 5,
+    };
+    assert_eq!(t.render().unwrap(), "cold warm zero");
+}
+
 #[derive(Template)]
 #[template(source = "foo", ext = "txt")]
 struct Empty;
@@ -447,6 +542,21 @@ fn test_raw_ws() {
     assert_eq!(template.render().unwrap(), "<{{hello}}>\n<{{bye}}>");
 }
 
+#[derive(Template)]
+#[template(
+    source = r#"{% raw "EOF" %}{% raw %}{{ x }}{% endraw %}{% endraw "OTHER" %}{%- endraw "EOF" -%} !"#,
+    ext = "txt"
+)]
+struct RawTemplateMarker;
+
+#[test]
+fn test_raw_marker() {
+    assert_eq!(
+        RawTemplateMarker.render().unwrap(),
+        r#"{% raw %}{{ x }}{% endraw %}{% endraw "OTHER" %}!"#
+    );
+}
+
 mod without_import_on_derive {
     #[derive(askama::Template)]
     #[template(source = "foo", ext = "txt")]
@@ -46,3 +46,15 @@ fn test_super_size_hint() {
 
     assert_eq!(T::SIZE_HINT, 5);
 }
+
+#[test]
+fn test_component_size_hint() {
+    #[derive(Template)]
+    #[template(source = "[{{ body }}]", ext = "txt")]
+    struct Wrapper {
+        body: askama::Slot,
+    }
+
+    // The body is written by the component, which has its own size hint.
+    test_size!("{% component Wrapper %}12345{% endcomponent %}", 3);
+}
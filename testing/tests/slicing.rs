@@ -0,0 +1,36 @@
+use askama::Template;
+
+#[derive(Template)]
+#[template(
+    source = "{{ items[1..3]|join(\",\") }} {{ items[2..]|join(\",\") }} {{ items[..=1]|join(\",\") }} \
+              {{ items[3..10]|join(\",\") }}|{{ items[5..]|join(\",\") }}| {{ name[..5] }} {{ name[2..4] }} \
+              {{ name[7..] }}|{{ name[start..2] }}| {{ items[start..]|join(\",\") }}",
+    ext = "txt"
+)]
+struct SliceTemplate<'a> {
+    items: Vec<u32>,
+    name: &'a str,
+    start: usize,
+}
+
+#[test]
+fn test_slicing() {
+    let t = SliceTemplate {
+        items: vec![1, 2, 3, 4],
+        name: "héllo wörld",
+        start: 3,
+    };
+    assert_eq!(t.render().unwrap(), "2,3 3,4 1,2 4|| héllo ll örld|| 4");
+}
+
+#[derive(Template)]
+#[template(source = "{{ name[..3] }}", ext = "txt", no_panic = true)]
+struct SliceNoPanicTemplate<'a> {
+    name: &'a str,
+}
+
+#[test]
+fn test_slicing_no_panic() {
+    let t = SliceNoPanicTemplate { name: "ab" };
+    assert_eq!(t.render().unwrap(), "ab");
+}
@@ -0,0 +1,72 @@
+use std::borrow::Cow;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use askama::Template;
+
+struct User {
+    name: String,
+    tags: Vec<&'static str>,
+}
+
+impl User {
+    fn greeting(&self) -> String {
+        format!("Hello {}", self.name)
+    }
+}
+
+#[derive(Template)]
+#[template(
+    source = "{{ user.name }}|{{ user.greeting() }}|{{ user.name.len() }}
+{%- for tag in user.tags %} {{ tag }}{% endfor %}|
+{%- for x in arc %}{{ x }}{% endfor %}|
+{%- for x in rc %}{{ x }}{% endfor %}|
+{%- for x in boxed %}{{ x }}{% endfor %}|
+{%- for x in cow %}{{ x }}{% endfor %}|
+{%- for user in users %}{{ user.name }}{% for tag in user.tags %}{{ tag }}{% endfor %}{% endfor %}",
+    ext = "txt"
+)]
+struct SmartPointers<'a> {
+    user: Arc<User>,
+    arc: Arc<Vec<u32>>,
+    rc: Rc<[u32]>,
+    boxed: Box<[u32]>,
+    cow: Cow<'a, [u32]>,
+    users: Vec<Rc<User>>,
+}
+
+#[test]
+fn test_smart_pointers() {
+    let t = SmartPointers {
+        user: Arc::new(User {
+            name: "foo".to_owned(),
+            tags: vec!["a", "b"],
+        }),
+        arc: Arc::new(vec![1, 2]),
+        rc: Rc::from(vec![3, 4]),
+        boxed: vec![5, 6].into_boxed_slice(),
+        cow: Cow::Borrowed(&[7, 8]),
+        users: vec![Rc::new(User {
+            name: "bar".to_owned(),
+            tags: vec!["c"],
+        })],
+    };
+    assert_eq!(t.render().unwrap(), "foo|Hello foo|3 a b|12|34|56|78|barc");
+}
+
+#[derive(Template)]
+#[template(
+    source = "{% for x in items %}{{ x }}{% else %}empty{% endfor %}",
+    ext = "txt"
+)]
+struct ArcLoopElse {
+    items: Arc<Vec<u32>>,
+}
+
+#[test]
+fn test_smart_pointer_loop_else() {
+    let t = ArcLoopElse {
+        items: Arc::new(vec![]),
+    };
+    assert_eq!(t.render().unwrap(), "empty");
+}
@@ -0,0 +1,31 @@
+use askama::Template;
+
+// The parent template uses the "legacy" syntax configured for its directory, while the child
+// template uses the default syntax.
+#[derive(Template)]
+#[template(
+    source = r#"{% extends "syntax-legacy/base.html" %}{% block content %}{{ title }}!{% endblock %}"#,
+    ext = "html",
+    config = "test_syntax_dirs.toml"
+)]
+struct ChildWithDefaultSyntax<'a> {
+    title: &'a str,
+}
+
+#[test]
+fn test_extends_other_syntax() {
+    let t = ChildWithDefaultSyntax { title: "foo" };
+    assert_eq!(t.render().unwrap(), "<title>foo</title>\nfoo!");
+}
+
+#[derive(Template)]
+#[template(path = "syntax-legacy/base.html", config = "test_syntax_dirs.toml")]
+struct Base<'a> {
+    title: &'a str,
+}
+
+#[test]
+fn test_syntax_of_directory() {
+    let t = Base { title: "foo" };
+    assert_eq!(t.render().unwrap(), "<title>foo</title>\n");
+}
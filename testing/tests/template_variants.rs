@@ -0,0 +1,92 @@
+use askama::{Template, TemplateVariants};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Theme {
+    Light,
+    Dark,
+    HighContrast,
+}
+
+#[derive(Template)]
+#[template(path = "themes/light.html")]
+#[template_variant(key = "Theme::Dark | Theme::HighContrast", path = "themes/dark.html")]
+struct Page<'a> {
+    title: &'a str,
+}
+
+#[test]
+fn test_template_variants() {
+    let page = Page { title: "Hello" };
+    assert_eq!(
+        page.render().unwrap(),
+        r#"<body class="light">Hello</body>"#
+    );
+    assert_eq!(
+        page.render_variant(&Theme::Light).unwrap(),
+        r#"<body class="light">Hello</body>"#
+    );
+    assert_eq!(
+        page.render_variant(&Theme::Dark).unwrap(),
+        r#"<body class="dark">HELLO</body>"#
+    );
+    assert_eq!(
+        page.render_variant(&Theme::HighContrast).unwrap(),
+        r#"<body class="dark">HELLO</body>"#
+    );
+
+    let mut buf = String::new();
+    page.render_variant_into(&Theme::Dark, &mut buf).unwrap();
+    assert_eq!(buf, r#"<body class="dark">HELLO</body>"#);
+}
+
+#[derive(Template)]
+#[template(source = "{{ count }} item(s)", ext = "txt")]
+#[template_variant(key = "Lang::Fr", path = "themes/light.html")]
+struct Generic<T: std::fmt::Display> {
+    count: T,
+    title: &'static str,
+}
+
+mod lang {
+    pub enum Lang {
+        En,
+        Fr,
+    }
+}
+use lang::Lang;
+
+#[test]
+fn test_template_variants_generic() {
+    let t = Generic {
+        count: 3,
+        title: "trois",
+    };
+    assert_eq!(t.render_variant(&Lang::En).unwrap(), "3 item(s)");
+    assert_eq!(
+        t.render_variant(&Lang::Fr).unwrap(),
+        r#"<body class="light">trois</body>"#
+    );
+
+    // The variant is escaped according to its own extension, not the main template's `ext`
+    let t = Generic {
+        count: 3,
+        title: "<b>",
+    };
+    assert_eq!(t.render_variant(&Lang::En).unwrap(), "3 item(s)");
+    assert_eq!(
+        t.render_variant(&Lang::Fr).unwrap(),
+        r#"<body class="light">&lt;b&gt;</body>"#
+    );
+}
+
+#[test]
+fn test_template_variants_size_hint() {
+    assert_eq!(
+        <Generic<u8> as TemplateVariants<Lang>>::variant_size_hint(&Lang::En),
+        <Generic<u8> as Template>::SIZE_HINT
+    );
+    assert_ne!(
+        <Generic<u8> as TemplateVariants<Lang>>::variant_size_hint(&Lang::Fr),
+        <Generic<u8> as Template>::SIZE_HINT
+    );
+}
@@ -0,0 +1,26 @@
+use askama::Template;
+
+#[derive(Template)]
+#[template(
+    source = "{{ total : f64 }} {{ count : &usize }} {{ name : str }} {{ title : std::borrow::Cow<str> }} {{ names.len() : usize }}",
+    ext = "html"
+)]
+struct TypeHintsTemplate<'a> {
+    total: f64,
+    count: &'a usize,
+    name: String,
+    title: std::borrow::Cow<'a, str>,
+    names: Vec<&'a str>,
+}
+
+#[test]
+fn test_type_hints() {
+    let t = TypeHintsTemplate {
+        total: 1.5,
+        count: &3,
+        name: "<b>".into(),
+        title: "<i>".into(),
+        names: vec!["a", "b"],
+    };
+    assert_eq!(t.render().unwrap(), "1.5 3 &lt;b&gt; &lt;i&gt; 2");
+}
@@ -0,0 +1,14 @@
+use askama::Template;
+
+#[derive(Template)]
+#[template(source = "{% const LIMIT = limit * 2 %}{{ LIMIT }}", ext = "txt")]
+struct FieldValue {
+    limit: usize,
+}
+
+#[derive(Template)]
+#[template(source = "{% if true %}{% const LIMIT = 2 %}{% endif %}", ext = "txt")]
+struct NotTopLevel;
+
+fn main() {
+}
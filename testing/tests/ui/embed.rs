@@ -0,0 +1,10 @@
+use askama::Template;
+
+#[derive(Template)]
+#[template(source = r#"{% embed "embed-card.html" %}
+  Hello {% block body %}{% endblock %}
+{% endembed %}"#, ext = "html")]
+struct ContentOutsideBlocks;
+
+fn main() {
+}
@@ -0,0 +1,27 @@
+use askama::Template;
+
+mod filters {
+    pub fn double(s: &str) -> ::askama::Result<String> {
+        Ok(format!("{s}{s}"))
+    }
+}
+
+#[derive(Template)]
+#[template(source = "{{ a|double }} {{ a|double(2) }}", ext = "txt")]
+struct InconsistentArguments<'a> {
+    a: &'a str,
+}
+
+#[derive(Template)]
+#[template(source = "{{ a|tripple }}", ext = "txt")]
+struct UnknownFilter<'a> {
+    a: &'a str,
+}
+
+#[derive(Template)]
+#[template(source = "{{ a|double(2) }}", ext = "txt")]
+struct WrongArgumentCount<'a> {
+    a: &'a str,
+}
+
+fn main() {}
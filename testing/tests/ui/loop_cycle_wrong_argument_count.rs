@@ -2,7 +2,7 @@ use askama::Template;
 
 #[derive(Template)]
 #[template(
-    source = r#"{% for v in values %}{{ loop.cycle("r", "g", "b") }}{{ v }},{% endfor %}"#,
+    source = r#"{% for v in values %}{{ loop.cycle() }}{{ v }},{% endfor %}"#,
     ext = "txt"
 )]
 struct ForCycle<'a> {
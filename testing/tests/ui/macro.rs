@@ -23,5 +23,53 @@ struct InvalidNumberOfArgs2;
 {%- call thrice(1, 2) -%}", ext = "html")]
 struct InvalidNumberOfArgs3;
 
+#[derive(Template)]
+#[template(source = "{%- macro badge(label) -%}
+<b>{{ label }}</b>
+{%- endmacro -%}
+
+{{ 1|badge|upper }}", ext = "html")]
+struct MacroAsFilterNotLast;
+
+#[derive(Template)]
+#[template(source = "{%- macro badge(label) -%}
+<b>{{ label }}</b>
+{%- endmacro -%}
+
+{{ 1|badge(2) }}", ext = "html")]
+struct MacroAsFilterArgs;
+
+#[derive(Template)]
+#[template(source = "{%- macro input(name, type=\"text\") -%}
+{{ name }} {{ type }}
+{%- endmacro -%}
+
+{%- call input() -%}", ext = "html")]
+struct MissingDefaultlessArg;
+
+#[derive(Template)]
+#[template(source = "{%- macro input(name, type=\"text\") -%}
+{{ name }} {{ type }}
+{%- endmacro -%}
+
+{%- call input(type=\"password\") -%}", ext = "html")]
+struct MissingNamedArg;
+
+#[derive(Template)]
+#[template(source = "{%- macro card() -%}
+{{ caller(1) }}
+{%- endmacro -%}
+
+{%- call() card() %}body{% endcall -%}", ext = "html")]
+struct CallerArgs;
+
+#[derive(Template)]
+#[template(source = "{%- macro list(n) -%}
+{{ caller() }}{% call() list(n) %}{% endcall %}
+{%- endmacro -%}
+
+{%- call() list(1) %}item{% endcall -%}", ext = "html")]
+struct RecursiveCaller;
+
 fn main() {
 }
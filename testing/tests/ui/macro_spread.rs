@@ -0,0 +1,14 @@
+use askama::Template;
+
+#[derive(Template)]
+#[template(source = "{%- macro pair(a, b) -%}
+{{ a }} {{ b }}
+{%- endmacro -%}
+
+{%- call pair(*items, 1) -%}", ext = "html")]
+struct SpreadNotLast {
+    items: Vec<u32>,
+}
+
+fn main() {
+}
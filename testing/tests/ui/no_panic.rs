@@ -0,0 +1,39 @@
+use askama::Template;
+
+#[derive(Template)]
+#[template(source = "{{ items[0] }}", ext = "txt", no_panic = true)]
+struct Index<'a> {
+    items: &'a [u32],
+}
+
+#[derive(Template)]
+#[template(source = "{{ name.as_ref().unwrap() }}", ext = "txt", no_panic = true)]
+struct Unwrap {
+    name: Option<String>,
+}
+
+#[derive(Template)]
+#[template(source = "{% if x > 3 %}{{ x|abs }}{% endif %}", ext = "txt", no_panic = true)]
+struct Abs {
+    x: i32,
+}
+
+#[derive(Template)]
+#[template(source = "{{ todo!() }}", ext = "txt", no_panic = true)]
+struct Todo;
+
+#[derive(Template)]
+#[template(source = "{{ total / count }}", ext = "txt", no_panic = true)]
+struct Div {
+    total: u32,
+    count: u32,
+}
+
+#[derive(Template)]
+#[template(source = "{{ total % -1 }}", ext = "txt", no_panic = true)]
+struct Rem {
+    total: i32,
+}
+
+fn main() {
+}
@@ -0,0 +1,30 @@
+use askama::Template;
+
+#[derive(Template)]
+#[template(
+    source = "{% for item in items %}{{ loop(item) }}{% endfor %}",
+    ext = "txt"
+)]
+struct NotRecursive {
+    items: Vec<u32>,
+}
+
+#[derive(Template)]
+#[template(
+    source = "{% for item in items recursive %}{% for x in item %}{{ loop(x) }}{% endfor %}{% endfor %}",
+    ext = "txt"
+)]
+struct InnerLoop {
+    items: Vec<Vec<u32>>,
+}
+
+#[derive(Template)]
+#[template(
+    source = "{% for item in items recursive %}{{ loop(item)|upper }}{% endfor %}",
+    ext = "txt"
+)]
+struct InExpression {
+    items: Vec<u32>,
+}
+
+fn main() {}
@@ -0,0 +1,28 @@
+use askama::Template;
+
+#[derive(Template)]
+#[template(source = r#"{% for x in xs|selectattr(name) %}{% endfor %}"#, ext = "txt")]
+struct AttrNotLiteral<'a> {
+    xs: &'a [(bool,)],
+    name: &'a str,
+}
+
+#[derive(Template)]
+#[template(source = r#"{% for x in xs|rejectattr("0 1") %}{% endfor %}"#, ext = "txt")]
+struct AttrNotField<'a> {
+    xs: &'a [(bool,)],
+}
+
+#[derive(Template)]
+#[template(source = r#"{% for x in xs|selectattr("0", "in", 1) %}{% endfor %}"#, ext = "txt")]
+struct UnknownOperator<'a> {
+    xs: &'a [(u32,)],
+}
+
+#[derive(Template)]
+#[template(source = r#"{% for x in xs|selectattr("0", ">") %}{% endfor %}"#, ext = "txt")]
+struct MissingValue<'a> {
+    xs: &'a [(u32,)],
+}
+
+fn main() {}
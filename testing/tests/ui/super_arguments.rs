@@ -0,0 +1,14 @@
+use askama::Template;
+
+#[derive(Template)]
+#[template(source = r#"{% extends "super-args-mid.html" %}
+{% block greeting %}{% call super("hi") %}{% endblock %}"#, ext = "txt")]
+struct PositionalArgument;
+
+#[derive(Template)]
+#[template(source = r#"{% extends "super-args-mid.html" %}
+{% block greeting %}{% call super.super.super() %}{% endblock %}"#, ext = "txt")]
+struct TooManyLevels;
+
+fn main() {
+}
@@ -0,0 +1,35 @@
+use askama::Template;
+
+enum Theme {
+    Light,
+    Dark,
+}
+
+enum Lang {
+    En,
+}
+
+#[derive(Template)]
+#[template(path = "themes/light.html")]
+#[template_variant(key = "Theme::Dark", path = "themes/dark.html")]
+#[template_variant(key = "Lang::En", path = "themes/dark.html")]
+struct DifferentKeyTypes<'a> {
+    title: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "themes/light.html")]
+#[template_variant(key = "Dark", path = "themes/dark.html")]
+struct KeyWithoutType<'a> {
+    title: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "themes/light.html")]
+#[template_variant(key = "Theme::Dark", path = "themes/dark.html")]
+#[template_variant(key = "Theme::Light | Theme::Dark", path = "themes/light.html")]
+struct DuplicatedKey<'a> {
+    title: &'a str,
+}
+
+fn main() {}
@@ -0,0 +1,15 @@
+use askama::Template;
+
+#[derive(Template)]
+#[template(source = "{{ total : f64 }}", ext = "txt")]
+struct Mismatch {
+    total: i32,
+}
+
+#[derive(Template)]
+#[template(source = "{{ total : }}", ext = "txt")]
+struct Missing {
+    total: i32,
+}
+
+fn main() {}
@@ -131,3 +131,120 @@ fn test_decl_assign_range() {
     let t = DeclAssignRange;
     assert_eq!(t.render().unwrap(), "1");
 }
+
+#[derive(Template)]
+#[template(
+    source = r#"{% set greeting -%}
+    Hello {{ name }}{% for _ in 0..2 %}!{% endfor %}
+{%- endset -%}
+<p>{{ greeting }}</p><h1>{{ greeting }}</h1>"#,
+    ext = "html"
+)]
+struct SetBlock<'a> {
+    name: &'a str,
+}
+
+#[test]
+fn test_set_block() {
+    let t = SetBlock { name: "<b>" };
+    assert_eq!(
+        t.render().unwrap(),
+        "<p>Hello &lt;b&gt;!!</p><h1>Hello &lt;b&gt;!!</h1>"
+    );
+}
+
+#[derive(Template)]
+#[template(
+    source = "{% let v %}{% if cond %}{% set v %}a{% endset %}{% else %}{% set v %}b{% endset %}{% endif %}{{ v }}",
+    ext = "txt"
+)]
+struct SetBlockDecl {
+    cond: bool,
+}
+
+#[test]
+fn test_set_block_decl() {
+    assert_eq!(SetBlockDecl { cond: true }.render().unwrap(), "a");
+    assert_eq!(SetBlockDecl { cond: false }.render().unwrap(), "b");
+}
+
+#[derive(Template)]
+#[template(
+    source = "{% with a = b, b = a %}{{ a }}{{ b }}{% let c = 3 %}{% endwith %}{{ a }}{{ b }}{{ c }}",
+    ext = "txt"
+)]
+struct With {
+    a: u32,
+    b: u32,
+    c: u32,
+}
+
+#[test]
+fn test_with() {
+    let t = With { a: 1, b: 2, c: 4 };
+    assert_eq!(t.render().unwrap(), "21124");
+}
+
+#[derive(Template)]
+#[template(
+    source = "{% let mut total = 0 %}{% let mut seen %}{% let seen = false %}
+{%- for item in items %}{% let total = total + item %}{% if total > 5 %}{% set seen = true %}{% endif %}{% endfor -%}
+{{ total }} {{ seen }}",
+    ext = "txt"
+)]
+struct LetMut<'a> {
+    items: &'a [u32],
+}
+
+#[test]
+fn test_let_mut() {
+    let t = LetMut { items: &[1, 2, 3] };
+    assert_eq!(t.render().unwrap(), "6 true");
+    let t = LetMut { items: &[1, 2] };
+    assert_eq!(t.render().unwrap(), "3 false");
+}
+
+struct Counter(std::cell::Cell<u32>);
+
+impl Counter {
+    fn incr(&self) -> u32 {
+        self.0.set(self.0.get() + 1);
+        self.0.get()
+    }
+}
+
+#[derive(Template)]
+#[template(
+    source = "{% for _ in 0..3 %}{% do counter.incr() %}{% endfor %}{{ counter.0.get() }}",
+    ext = "txt"
+)]
+struct Do {
+    counter: Counter,
+}
+
+#[test]
+fn test_do() {
+    let t = Do {
+        counter: Counter(std::cell::Cell::new(0)),
+    };
+    assert_eq!(t.render().unwrap(), "3");
+}
+
+#[derive(Template)]
+#[template(
+    source = "{% const max = 2 %}{% const SEP = \", \" %}\
+              {% for item in items %}{% if loop.index <= max %}{{ item }}{{ SEP }}{% endif %}{% endfor %}\
+              {% let max = \"more\" %}{{ max }}",
+    ext = "txt"
+)]
+struct Const<'a> {
+    items: &'a [&'a str],
+}
+
+#[test]
+fn test_const() {
+    let t = Const {
+        items: &["a", "b", "c"],
+    };
+    assert_eq!(t.render().unwrap(), "a, b, more");
+}
@@ -140,3 +140,54 @@ fn test_template_whitespace_config() {
     );
     test_template_ws_config!("test_minimize.toml", "suppress", "\n1{# #}\n\n\n2", "\n12");
 }
+
+#[test]
+fn test_linebreak_whitespace() {
+    test_template!(
+        "1 \n\n{%^ if true ^%}\n \n2\t{%^ endif ^%}\r\n 3",
+        "1\n\n2\n3"
+    );
+    test_template!(" 1 {{^ 2 ^}} 3 ", " 123 ");
+    test_template_minimize!("1 \n\n{%^ if true %}  2{% endif %}", "1\n 2");
+    test_template_ws_config!("test_trim.toml", "linebreak", "1 \n {# #}  2", "1\n2");
+}
+
+#[test]
+fn test_whitespace_marker_precedence() {
+    // A marker on either tag takes precedence over the configuration.
+    test_template_config!("test_trim.toml", "1{# +#}  {# #}2", "1  2");
+    test_template_config!("test_trim.toml", "1{# #}  {#+ #}2", "1  2");
+    test_template_minimize!("1{# ^#} \n \n {# #}2", "1\n2");
+    // Of two markers, the one removing the most whitespace wins.
+    test_template!("1{# ~#} \n \n {#+ #}2", "1\n2");
+    test_template!("1{# +#} \n \n {#~ #}2", "1\n2");
+    test_template!("1{# ~#} \n \n {#^ #}2", "1\n2");
+    test_template!("1{# ~#}  {#^ #}2", "12");
+    test_template!("1{# ^#} \n \n {#- #}2", "12");
+    test_template!("1{# -#} \n \n {#^ #}2", "12");
+}
+
+#[derive(Template)]
+#[template(
+    source = "<ul>
+  {% for item in items %}
+    <li>{{ item }}</li>
+  {% endfor %}
+</ul>
+  {{ items.len() }} items",
+    ext = "txt",
+    trim_blocks = true,
+    lstrip_blocks = true
+)]
+struct TrimBlocks<'a> {
+    items: &'a [&'a str],
+}
+
+#[test]
+fn test_trim_blocks() {
+    let t = TrimBlocks { items: &["a", "b"] };
+    assert_eq!(
+        t.render().unwrap(),
+        "<ul>\n    <li>a</li>\n    <li>b</li>\n</ul>\n  2 items"
+    );
+}